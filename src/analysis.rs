@@ -0,0 +1,586 @@
+// a pass over a rom before it runs: following the code from the vectors through every branch, jump
+// and call marks which prg bytes are instructions and which are data the code reads, so the debugger
+// doesn't disassemble tables as code. which windows of $8000-$FFFF the board banks comes from the
+// mapper number. a jump into a switchable window can land in any bank that fits it, so each is
+// tried, and kept unless the path runs into a jam, a BRK, an unofficial opcode or known data. the
+// tries of a round are spread over threads
+use crate::Emulator;
+use crate::cartridge::Cartridge;
+use crate::disasm::{Instruction, Operand};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::thread;
+
+// instructions reading their operand, a rom address in the others is a board register write
+const READS: [&str; 15] = [
+    "LDA", "LDX", "LDY", "LAX", "LAS", "ADC", "SBC", "AND", "ORA", "EOR", "CMP", "CPX", "CPY",
+    "BIT", "NOP",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteKind {
+    Unknown,
+    // the opcode of an instruction the code reaches, and the operand bytes after it
+    Code,
+    Operand,
+    // read by the code or a vector
+    Data,
+}
+
+// where a part of the prg rom shows up on the cpu bus, fixed to a bank or switched at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub start: u16,
+    pub size: usize,
+    pub fixed: Option<usize>,
+}
+
+impl Window {
+    fn contains(&self, address: u16) -> bool {
+        (address as usize).wrapping_sub(self.start as usize) < self.size
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reason {
+    Unofficial,
+    Jam,
+    Break,
+    // starts inside another instruction, like the BIT skip trick or code run from the wrong place
+    Overlap,
+    // runs into bytes a vector or a load reads, which the code isn't followed past
+    IntoData,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Unofficial => "unofficial opcode",
+            Reason::Jam => "jams the cpu",
+            Reason::Break => "BRK, often data run as code",
+            Reason::Overlap => "overlaps another instruction",
+            Reason::IntoData => "runs into data",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suspicious {
+    pub address: u16,
+    pub offset: usize,
+    pub instruction: Instruction,
+    pub reason: Reason,
+}
+
+// where a vector points, offset is None when that is outside the rom or in a switchable window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub name: &'static str,
+    pub address: u16,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub mapper: u16,
+    // false for boards the layout isn't known for, which are taken to bank $8000 and fix $C000
+    pub known_layout: bool,
+    pub windows: Vec<Window>,
+    // the smallest window, what unreachable_banks counts in
+    pub bank_size: usize,
+    pub entry_points: Vec<EntryPoint>,
+    // addresses in switchable windows the code jumps to, with the number of banks code was found at
+    pub banked_entries: BTreeMap<u16, usize>,
+    pub suspicious: Vec<Suspicious>,
+    kinds: Vec<ByteKind>,
+}
+
+// a switchable window with the bank taken to be in it, code in a window stays in its bank
+type Context = (usize, usize);
+
+enum Place {
+    Rom(usize, Context),
+    // in a switchable window other than the one the code runs from
+    Banked(usize),
+    Outside,
+}
+
+// what a trace found, merged into the analysis once the round of traces it is in is done
+#[derive(Default)]
+struct Trace {
+    code: Vec<([usize; 3], Instruction)>,
+    data: Vec<usize>,
+    banked: Vec<(usize, u16)>,
+    suspicious: Vec<Suspicious>,
+    // a try at a bank that ran into something code doesn't
+    failed: bool,
+}
+
+struct Walker<'a> {
+    windows: &'a [Window],
+    prg: &'a [u8],
+    kinds: &'a [ByteKind],
+}
+
+impl Walker<'_> {
+    fn place(&self, address: u16, (current, bank): Context) -> Place {
+        let Some(index) = self
+            .windows
+            .iter()
+            .position(|window| window.contains(address))
+        else {
+            return Place::Outside;
+        };
+        let window = self.windows[index];
+        let bank = match window.fixed {
+            Some(bank) => bank,
+            None if index == current => bank,
+            None => return Place::Banked(index),
+        };
+        let banks = (self.prg.len() / window.size).max(1);
+        let offset = (bank % banks) * window.size + (address - window.start) as usize;
+        Place::Rom(offset % self.prg.len(), (index, bank))
+    }
+
+    // follows the code from address, a try at a bank gives up at the first sign of data
+    fn trace(&self, address: u16, context: Context, trying: bool) -> Trace {
+        let mut trace = Trace::default();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![(address, context)];
+        while let Some((address, context)) = pending.pop() {
+            let (offset, context) = match self.place(address, context) {
+                Place::Rom(offset, context) => (offset, context),
+                Place::Banked(window) => {
+                    trace.banked.push((window, address));
+                    continue;
+                }
+                Place::Outside => continue,
+            };
+            if self.kinds[offset] == ByteKind::Code || !visited.insert(offset) {
+                continue;
+            }
+            let mut offsets = [offset; 3];
+            let mut bytes = Vec::with_capacity(3);
+            for (index, slot) in offsets.iter_mut().enumerate() {
+                match self.place(address.wrapping_add(index as u16), context) {
+                    Place::Rom(offset, _) => {
+                        *slot = offset;
+                        bytes.push(self.prg[offset]);
+                    }
+                    _ => break,
+                }
+            }
+            // an operand cut off by the end of the window
+            let Some(instruction) = Instruction::decode(&bytes, address) else {
+                trace.failed = trying;
+                if trying {
+                    return trace;
+                }
+                continue;
+            };
+            let offsets_used = &offsets[..instruction.size()];
+            let reason = match instruction.mnemonic {
+                _ if offsets_used
+                    .iter()
+                    .any(|&offset| self.kinds[offset] == ByteKind::Data) =>
+                {
+                    Some(Reason::IntoData)
+                }
+                "HLT" => Some(Reason::Jam),
+                "BRK" => Some(Reason::Break),
+                _ if !instruction.official => Some(Reason::Unofficial),
+                _ if self.kinds[offset] == ByteKind::Operand
+                    || offsets_used[1..]
+                        .iter()
+                        .any(|&offset| self.kinds[offset] == ByteKind::Code) =>
+                {
+                    Some(Reason::Overlap)
+                }
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                if trying && reason != Reason::Overlap {
+                    trace.failed = true;
+                }
+                trace.suspicious.push(Suspicious {
+                    address,
+                    offset,
+                    instruction,
+                    reason,
+                });
+            }
+            if trace.failed {
+                return trace;
+            }
+            if reason == Some(Reason::IntoData) {
+                continue;
+            }
+            trace.code.push((offsets, instruction));
+            if matches!(reason, Some(Reason::Jam | Reason::Break)) {
+                continue;
+            }
+
+            let next = address.wrapping_add(instruction.size() as u16);
+            match (instruction.operand, instruction.mnemonic) {
+                (_, "RTS" | "RTI") => {}
+                (Operand::Indirect, _) => {
+                    for byte in 0..2 {
+                        if let Place::Rom(offset, _) =
+                            self.place(instruction.value.wrapping_add(byte), context)
+                        {
+                            trace.data.push(offset);
+                        }
+                    }
+                }
+                (Operand::Absolute, "JMP") => pending.push((instruction.value, context)),
+                (Operand::Absolute, "JSR") | (Operand::Relative, _) => {
+                    pending.push((next, context));
+                    pending.push((instruction.target().unwrap(), context));
+                }
+                (operand, mnemonic) => {
+                    if matches!(
+                        operand,
+                        Operand::Absolute | Operand::AbsoluteX | Operand::AbsoluteY
+                    ) && READS.contains(&mnemonic)
+                        && let Place::Rom(offset, _) = self.place(instruction.value, context)
+                    {
+                        trace.data.push(offset);
+                    }
+                    pending.push((next, context));
+                }
+            }
+        }
+        trace
+    }
+
+    // every try of a round, on as many threads as there are cores
+    fn try_all(&self, tries: &[(u16, Context)]) -> Vec<Trace> {
+        let threads = thread::available_parallelism()
+            .map_or(1, usize::from)
+            .min(tries.len());
+        if threads <= 1 {
+            return tries
+                .iter()
+                .map(|&(address, context)| self.trace(address, context, true))
+                .collect();
+        }
+        thread::scope(|scope| {
+            let handles: Vec<_> = tries
+                .chunks(tries.len().div_ceil(threads))
+                .map(|tries| {
+                    scope.spawn(move || {
+                        tries
+                            .iter()
+                            .map(|&(address, context)| self.trace(address, context, true))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("a trace panicked"))
+                .collect()
+        })
+    }
+}
+
+// the windows a board starts out with, and whether the mapper is one that is known
+fn layout(mapper: u16, prg_size: usize) -> (Vec<Window>, bool) {
+    let last = |size: usize| (prg_size / size).max(1) - 1;
+    let fixed = |start, size, bank| Window {
+        start,
+        size,
+        fixed: Some(bank),
+    };
+    let switchable = |start, size| Window {
+        start,
+        size,
+        fixed: None,
+    };
+    match mapper {
+        0 | 3 => (vec![fixed(0x8000, 0x8000, 0)], true),
+        // the last bank of the first 256K, the half 512K boards start in
+        1 => (
+            vec![
+                switchable(0x8000, 0x4000),
+                fixed(0xC000, 0x4000, last(0x4000).min(0x0F)),
+            ],
+            true,
+        ),
+        2 => (
+            vec![
+                switchable(0x8000, 0x4000),
+                fixed(0xC000, 0x4000, last(0x4000)),
+            ],
+            true,
+        ),
+        // prg mode 0, which the mmc3 powers on in
+        4 => (
+            vec![
+                switchable(0x8000, 0x2000),
+                switchable(0xA000, 0x2000),
+                fixed(0xC000, 0x2000, last(0x2000).saturating_sub(1)),
+                fixed(0xE000, 0x2000, last(0x2000)),
+            ],
+            true,
+        ),
+        5 => (
+            vec![
+                switchable(0x8000, 0x2000),
+                switchable(0xA000, 0x2000),
+                switchable(0xC000, 0x2000),
+                fixed(0xE000, 0x2000, last(0x2000)),
+            ],
+            true,
+        ),
+        24 | 26 => (
+            vec![
+                switchable(0x8000, 0x4000),
+                switchable(0xC000, 0x2000),
+                fixed(0xE000, 0x2000, last(0x2000)),
+            ],
+            true,
+        ),
+        _ => (
+            vec![
+                switchable(0x8000, 0x4000),
+                fixed(0xC000, 0x4000, last(0x4000)),
+            ],
+            false,
+        ),
+    }
+}
+
+impl Analysis {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let prg = &cartridge.prg_rom[..];
+        let mapper = cartridge.header.mapper;
+        let (windows, known_layout) = layout(mapper, prg.len());
+        let mut analysis = Analysis {
+            mapper,
+            known_layout,
+            bank_size: windows.iter().map(|window| window.size).min().unwrap(),
+            windows,
+            entry_points: Vec::new(),
+            banked_entries: BTreeMap::new(),
+            suspicious: Vec::new(),
+            kinds: vec![ByteKind::Unknown; prg.len()],
+        };
+        if prg.is_empty() {
+            return analysis;
+        }
+
+        let windows = analysis.windows.clone();
+        let vectors = windows.len() - 1;
+        let context = (vectors, 0);
+        let mut vector_bytes = Vec::new();
+        for (name, vector) in [("nmi", 0xFFFA), ("reset", 0xFFFC), ("irq", 0xFFFE)] {
+            let walker = analysis.walker(prg);
+            let (Place::Rom(low, _), Place::Rom(high, _)) = (
+                walker.place(vector, context),
+                walker.place(vector + 1, context),
+            ) else {
+                continue;
+            };
+            let address = u16::from_le_bytes([prg[low], prg[high]]);
+            let offset = match walker.place(address, context) {
+                Place::Rom(offset, _) => Some(offset),
+                _ => None,
+            };
+            analysis.entry_points.push(EntryPoint {
+                name,
+                address,
+                offset,
+            });
+            vector_bytes.extend([low, high]);
+        }
+        for offset in vector_bytes {
+            analysis.kinds[offset] = ByteKind::Data;
+        }
+        let mut pending = Vec::new();
+        for entry in analysis.entry_points.clone() {
+            let trace = analysis.walker(prg).trace(entry.address, context, false);
+            analysis.merge(trace, &mut pending);
+        }
+
+        // rounds of trying the jumps into switchable windows in every bank, until no new ones turn up
+        let mut tried = BTreeSet::new();
+        while !pending.is_empty() {
+            let tries: Vec<(u16, Context)> = pending
+                .drain(..)
+                .filter(|entry| tried.insert(*entry))
+                .flat_map(|(window, address): (usize, u16)| {
+                    let banks = (prg.len() / windows[window].size).max(1);
+                    (0..banks).map(move |bank| (address, (window, bank)))
+                })
+                .collect();
+            let traces = analysis.walker(prg).try_all(&tries);
+            for (&(address, _), trace) in tries.iter().zip(traces) {
+                let found = analysis.banked_entries.entry(address).or_default();
+                if trace.failed || trace.code.is_empty() {
+                    continue;
+                }
+                *found += 1;
+                analysis.merge(trace, &mut pending);
+            }
+        }
+        analysis
+            .suspicious
+            .sort_by_key(|suspicious| (suspicious.offset, suspicious.reason));
+        analysis
+            .suspicious
+            .dedup_by_key(|suspicious| (suspicious.offset, suspicious.reason));
+        analysis
+    }
+
+    fn walker<'a>(&'a self, prg: &'a [u8]) -> Walker<'a> {
+        Walker {
+            windows: &self.windows,
+            prg,
+            kinds: &self.kinds,
+        }
+    }
+
+    fn merge(&mut self, trace: Trace, pending: &mut Vec<(usize, u16)>) {
+        for (offsets, instruction) in trace.code {
+            self.kinds[offsets[0]] = ByteKind::Code;
+            for &offset in &offsets[1..instruction.size()] {
+                if self.kinds[offset] != ByteKind::Code {
+                    self.kinds[offset] = ByteKind::Operand;
+                }
+            }
+        }
+        for offset in trace.data {
+            if self.kinds[offset] == ByteKind::Unknown {
+                self.kinds[offset] = ByteKind::Data;
+            }
+        }
+        self.suspicious.extend(trace.suspicious);
+        pending.extend(trace.banked);
+    }
+
+    // what the byte at an offset into prg rom was found to be
+    pub fn kind(&self, offset: usize) -> ByteKind {
+        self.kinds.get(offset).copied().unwrap_or(ByteKind::Unknown)
+    }
+
+    pub fn count(&self, kind: ByteKind) -> usize {
+        self.kinds.iter().filter(|&&found| found == kind).count()
+    }
+
+    pub fn bank_count(&self) -> usize {
+        (self.kinds.len() / self.bank_size).max(1)
+    }
+
+    // banks of bank_size without an instruction the code was followed to
+    pub fn unreachable_banks(&self) -> Vec<usize> {
+        (0..self.bank_count())
+            .filter(|&bank| {
+                let start = bank * self.bank_size;
+                let end = (start + self.bank_size).min(self.kinds.len());
+                !self.kinds[start.min(end)..end].contains(&ByteKind::Code)
+            })
+            .collect()
+    }
+}
+
+// mapper 2, 128K prg in 16K banks
+//   $8000-$BFFF  16K switchable
+//   $C000-$FFFF  16K fixed to bank 7
+// entry points
+//   reset  $C000  bank 7
+//   $8000  code in 6 banks
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "mapper {}{}, {}K prg in {}K banks",
+            self.mapper,
+            if self.known_layout {
+                ""
+            } else {
+                " (layout guessed)"
+            },
+            self.kinds.len() / 1024,
+            self.bank_size / 1024
+        )?;
+        for window in &self.windows {
+            let end = window.start as usize + window.size - 1;
+            write!(
+                f,
+                "  ${:04X}-${end:04X}  {}K ",
+                window.start,
+                window.size / 1024
+            )?;
+            match window.fixed {
+                Some(bank) => {
+                    let banks = (self.kinds.len() / window.size).max(1);
+                    writeln!(f, "fixed to bank {}", bank % banks)?
+                }
+                None => writeln!(f, "switchable")?,
+            }
+        }
+        writeln!(f, "entry points")?;
+        for entry in &self.entry_points {
+            write!(f, "  {:<6} ${:04X}", entry.name, entry.address)?;
+            match entry.offset {
+                Some(offset) => writeln!(f, "  bank {}", offset / self.bank_size)?,
+                None => writeln!(f, "  outside the fixed rom")?,
+            }
+        }
+        for (address, banks) in &self.banked_entries {
+            writeln!(f, "  ${address:04X}  code in {banks} banks")?;
+        }
+        writeln!(
+            f,
+            "{} bytes of code, {} of data and {} unknown",
+            self.count(ByteKind::Code) + self.count(ByteKind::Operand),
+            self.count(ByteKind::Data),
+            self.count(ByteKind::Unknown)
+        )?;
+        let unreachable: Vec<String> = self
+            .unreachable_banks()
+            .iter()
+            .map(usize::to_string)
+            .collect();
+        if !unreachable.is_empty() {
+            writeln!(f, "unreachable banks: {}", unreachable.join(", "))?;
+        }
+        if !self.suspicious.is_empty() {
+            writeln!(f, "suspicious")?;
+        }
+        for suspicious in &self.suspicious {
+            let instruction = suspicious.instruction.to_string();
+            writeln!(
+                f,
+                "  ${:04X}  bank {:<3} {:02X} {instruction:<12} {}",
+                suspicious.address,
+                suspicious.offset / self.bank_size,
+                suspicious.instruction.opcode,
+                suspicious.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Emulator {
+    // the analysis of the rom loaded, for the debugger's disassembly
+    pub fn set_analysis(&mut self, analysis: Analysis) {
+        self.analysis = Some(Box::new(analysis));
+    }
+
+    pub fn analysis(&self) -> Option<&Analysis> {
+        self.analysis.as_deref()
+    }
+
+    // true when the analysis found the rom byte at address to be data or inside an instruction
+    pub(crate) fn analyzed_as_data(&self, address: u16) -> bool {
+        match (&self.analysis, self.mapper.prg_rom_offset(address)) {
+            (Some(analysis), Some(offset)) => {
+                matches!(analysis.kind(offset), ByteKind::Data | ByteKind::Operand)
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,1089 @@
+use crate::clock::Region;
+use crate::expansion::{Expansion, ExpansionLevels};
+use crate::savestate::{StateError, ensure, savestate_fields};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+const NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+const DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// frame counter steps in cpu cycles since the sequence (re)started, the dendy counts like ntsc
+const FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+// the five channels as the mixer sees them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "pulse1",
+            Channel::Pulse2 => "pulse2",
+            Channel::Triangle => "triangle",
+            Channel::Noise => "noise",
+            Channel::Dmc => "dmc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Channel::ALL
+            .into_iter()
+            .find(|channel| channel.name().eq_ignore_ascii_case(name))
+    }
+
+    // the highest level the channel's dac takes
+    pub fn max_level(self) -> u8 {
+        match self {
+            Channel::Dmc => 127,
+            _ => 15,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant_volume = value & 0x10 != 0;
+        self.period = value & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn volume(&self) -> u8 {
+        if self.constant_volume {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index >> 3) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halted && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+}
+
+struct Pulse {
+    // pulse 1 negates with ones' complement, pulse 2 with two's complement
+    ones_complement: bool,
+    envelope: Envelope,
+    length: LengthCounter,
+    duty: u8,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halted = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length.load(value);
+                self.sequence_step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = change + self.ones_complement as u16;
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.sequence_step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Triangle {
+    length: LengthCounter,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length.halted = self.control;
+                self.linear_reload_value = value & 0x7F;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length.load(value);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length.value > 0 && self.linear_counter > 0 {
+                self.sequence_step = (self.sequence_step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequence_step as usize]
+    }
+}
+
+struct Noise {
+    envelope: Envelope,
+    length: LengthCounter,
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+}
+
+impl Noise {
+    fn new() -> Self {
+        Noise {
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            short_mode: false,
+            timer_period: NOISE_PERIODS[0],
+            timer: 0,
+            shift_register: 1,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, periods: &[u16; 16]) {
+        match register {
+            0 => {
+                self.length.halted = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                self.timer_period = periods[(value & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(value);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0 || self.shift_register & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    rate: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    interrupt: bool,
+}
+
+impl Dmc {
+    fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            rate: DMC_RATES[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            interrupt: false,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, rates: &[u16; 16]) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                self.rate = rates[(value & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.interrupt = false;
+                }
+            }
+            1 => self.output_level = value & 0x7F,
+            2 => self.sample_address = 0xC000 + value as u16 * 64,
+            _ => self.sample_length = value as u16 * 16 + 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+        if !self.silence {
+            if self.shift_register & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+            self.shift_register >>= 1;
+        }
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn fill_sample_buffer(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        // the address wraps from $FFFF back around to $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+}
+
+// first order filter, used for the high and low passes of the nes output stage
+struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Filter {
+    fn high_pass(sample_rate: u32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter {
+            high_pass: true,
+            alpha: rc / (rc + dt),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn low_pass(sample_rate: u32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Filter {
+            high_pass: false,
+            alpha: dt / (rc + dt),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = if self.high_pass {
+            self.alpha * (self.previous_output + input - self.previous_input)
+        } else {
+            self.previous_output + self.alpha * (input - self.previous_output)
+        };
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    cycle: u64,
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_interrupt: bool,
+    // writes to $4017 take effect 3 or 4 cycles later depending on the cpu cycle parity
+    pending_frame_counter_write: Option<(u8, u8)>,
+
+    // the region and sample rate are host settings, they are not part of a save state
+    region: Region,
+    sample_rate: u32,
+    // emulated time per real time, the output is resampled so it keeps up. None mutes it
+    speed: Option<f64>,
+    cycles_per_sample: f64,
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    // the channel outputs last mixed and their level, the outputs rarely change from one cycle to the next
+    mix_key: u32,
+    mix_level: f32,
+    // how loud each channel is mixed in, by Channel, and the ones left out altogether
+    volumes: [f32; 5],
+    muted: [bool; 5],
+    // the same for the sound chips on the cartridge, by Expansion
+    expansion_volumes: [f32; 2],
+    // the level of every channel at each output sample, while something is watching
+    levels: Option<Vec<[u8; 5]>>,
+    filters: [Filter; 3],
+    // mixed output at the configured sample rate, drained by the audio backend
+    samples: Vec<f32>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            cycle: 0,
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_interrupt: false,
+            pending_frame_counter_write: None,
+            region: Region::Ntsc,
+            sample_rate,
+            speed: Some(1.0),
+            cycles_per_sample: Region::Ntsc.cpu_clock_rate() / sample_rate as f64,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            mix_key: u32::MAX,
+            mix_level: 0.0,
+            volumes: [1.0; 5],
+            muted: [false; 5],
+            expansion_volumes: [1.0; 2],
+            levels: None,
+            filters: [
+                Filter::high_pass(sample_rate, 90.0),
+                Filter::high_pass(sample_rate, 440.0),
+                Filter::low_pass(sample_rate, 14_000.0),
+            ],
+            samples: Vec::with_capacity(sample_rate as usize / 30),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // resamples to a new output rate, the filters are rebuilt so their state starts over
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.filters = [
+            Filter::high_pass(sample_rate, 90.0),
+            Filter::high_pass(sample_rate, 440.0),
+            Filter::low_pass(sample_rate, 14_000.0),
+        ];
+        self.set_speed(self.speed);
+    }
+
+    // at twice the speed every sample covers twice the cpu cycles, so the sound keeps pace
+    // with the picture an octave up. None produces no samples at all
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        self.speed = speed;
+        self.cycles_per_sample =
+            self.region.cpu_clock_rate() * speed.unwrap_or(1.0) / self.sample_rate as f64;
+    }
+
+    pub fn channel_volume(&self, channel: Channel) -> f32 {
+        self.volumes[channel as usize]
+    }
+
+    // scales what the channel puts into the mix, 0 silences it and 1 is how the console mixes it
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.volumes[channel as usize] = volume.clamp(0.0, 1.0);
+        self.mix_key = u32::MAX;
+    }
+
+    pub fn expansion_volume(&self, expansion: Expansion) -> f32 {
+        self.expansion_volumes[expansion as usize]
+    }
+
+    // like set_channel_volume for a cartridge's sound chip, 0 leaves it out
+    pub fn set_expansion_volume(&mut self, expansion: Expansion, volume: f32) {
+        self.expansion_volumes[expansion as usize] = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        !self.muted[channel as usize]
+    }
+
+    // a channel turned off is left out of the mix but keeps its volume for when it comes back
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.muted[channel as usize] = !enabled;
+        self.mix_key = u32::MAX;
+    }
+
+    // keeps the level of every channel alongside the samples, for drawing them
+    pub fn set_record_levels(&mut self, record: bool) {
+        self.levels = record.then(Vec::new);
+    }
+
+    // the levels since the last take_levels, one per output sample in Channel order
+    pub fn take_levels(&mut self) -> Vec<[u8; 5]> {
+        self.levels.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // the pitch a channel is playing at, None for the noise and dmc and channels that are silent
+    pub fn channel_frequency(&self, channel: Channel) -> Option<f64> {
+        let clock = self.region.cpu_clock_rate();
+        match channel {
+            Channel::Pulse1 | Channel::Pulse2 => {
+                let pulse = if channel == Channel::Pulse1 {
+                    &self.pulse1
+                } else {
+                    &self.pulse2
+                };
+                (pulse.length.value > 0 && !pulse.muted() && pulse.envelope.volume() > 0)
+                    .then(|| clock / (16.0 * (pulse.timer_period as f64 + 1.0)))
+            }
+            Channel::Triangle => {
+                let triangle = &self.triangle;
+                // periods under 2 are ultrasonic, games use them to silence the channel
+                (triangle.length.value > 0
+                    && triangle.linear_counter > 0
+                    && triangle.timer_period >= 2)
+                    .then(|| clock / (32.0 * (triangle.timer_period as f64 + 1.0)))
+            }
+            Channel::Noise | Channel::Dmc => None,
+        }
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        let value = (self.pulse1.length.value > 0) as u8
+            | ((self.pulse2.length.value > 0) as u8) << 1
+            | ((self.triangle.length.value > 0) as u8) << 2
+            | ((self.noise.length.value > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_interrupt as u8) << 6
+            | (self.dmc.interrupt as u8) << 7;
+        self.frame_interrupt = false;
+        value
+    }
+
+    // address is the full cpu address in $4000..=$4017
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(address & 0x03, value),
+            0x4004..=0x4007 => self.pulse2.write(address & 0x03, value),
+            0x4008..=0x400B => self.triangle.write(address & 0x03, value),
+            0x400C..=0x400F => {
+                let periods = match self.region {
+                    Region::Pal => &PAL_NOISE_PERIODS,
+                    Region::Ntsc | Region::Dendy => &NOISE_PERIODS,
+                };
+                self.noise.write(address & 0x03, value, periods);
+            }
+            0x4010..=0x4013 => {
+                let rates = match self.region {
+                    Region::Pal => &PAL_DMC_RATES,
+                    Region::Ntsc | Region::Dendy => &DMC_RATES,
+                };
+                self.dmc.write(address & 0x03, value, rates);
+            }
+            0x4015 => {
+                self.pulse1.length.set_enabled(value & 0x01 != 0);
+                self.pulse2.length.set_enabled(value & 0x02 != 0);
+                self.triangle.length.set_enabled(value & 0x04 != 0);
+                self.noise.length.set_enabled(value & 0x08 != 0);
+                if value & 0x10 == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+                self.dmc.interrupt = false;
+            }
+            0x4017 => {
+                self.irq_inhibit = value & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_interrupt = false;
+                }
+                let delay = if self.cycle % 2 == 1 { 4 } else { 3 };
+                self.pending_frame_counter_write = Some((value, delay));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn frame_irq_pending(&self) -> bool {
+        self.frame_interrupt
+    }
+
+    pub fn dmc_irq_pending(&self) -> bool {
+        self.dmc.interrupt
+    }
+
+    // the samples produced since the last take_samples
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    // drops the samples from length on with their levels, for frames run again that were heard
+    // the first time
+    pub(crate) fn truncate_samples(&mut self, length: usize) {
+        let dropped = self.samples.len().saturating_sub(length);
+        self.samples.truncate(length);
+        if let Some(levels) = &mut self.levels {
+            levels.truncate(levels.len().saturating_sub(dropped));
+        }
+    }
+
+    // address of the next dmc sample byte when the reader needs one
+    pub fn dmc_sample_request(&self) -> Option<u16> {
+        if self.dmc.sample_buffer.is_none() && self.dmc.bytes_remaining > 0 {
+            Some(self.dmc.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub fn dmc_fill_sample(&mut self, value: u8) {
+        self.dmc.fill_sample_buffer(value);
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if let Some((value, delay)) = self.pending_frame_counter_write {
+            if delay == 0 {
+                self.pending_frame_counter_write = None;
+                self.five_step_mode = value & 0x80 != 0;
+                self.frame_cycle = 0;
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            } else {
+                self.pending_frame_counter_write = Some((value, delay - 1));
+            }
+        }
+
+        let [step_1, step_2, step_3, step_4, step_5] = match self.region {
+            Region::Pal => PAL_FRAME_STEPS,
+            Region::Ntsc | Region::Dendy => FRAME_STEPS,
+        };
+        self.frame_cycle += 1;
+        match self.frame_cycle {
+            cycle if cycle == step_1 || cycle == step_3 => self.clock_quarter_frame(),
+            cycle
+                if cycle == step_2
+                    || (cycle == step_4 && !self.five_step_mode)
+                    || (cycle == step_5 && self.five_step_mode) =>
+            {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            _ => {}
+        }
+
+        // the frame interrupt is raised over the last three cycles of the 4 step sequence
+        if !self.five_step_mode
+            && !self.irq_inhibit
+            && (step_4 - 1..=step_4 + 1).contains(&self.frame_cycle)
+        {
+            self.frame_interrupt = true;
+        }
+        let sequence_length = if self.five_step_mode {
+            step_5 + 1
+        } else {
+            step_4 + 1
+        };
+        if self.frame_cycle >= sequence_length {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn mix(&mut self) -> f32 {
+        let (pulse1, pulse2) = (self.pulse1.output(), self.pulse2.output());
+        let (triangle, noise) = (self.triangle.output(), self.noise.output());
+        let key = u32::from_le_bytes([
+            pulse1 | pulse2 << 4,
+            triangle | noise << 4,
+            self.dmc.output_level,
+            0,
+        ]);
+        if key != self.mix_key {
+            self.mix_key = key;
+            let levels = [pulse1, pulse2, triangle, noise, self.dmc.output_level];
+            self.mix_level = self.mix_levels(levels);
+        }
+        self.mix_level
+    }
+
+    // the console's nonlinear mix of the channel levels, each scaled by its volume first
+    fn mix_levels(&self, levels: [u8; 5]) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc] = std::array::from_fn(|index| {
+            if self.muted[index] {
+                0.0
+            } else {
+                levels[index] as f32 * self.volumes[index]
+            }
+        });
+        let pulse = pulse1 + pulse2;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+        pulse_out + tnd_out
+    }
+
+    // advances the apu by one cpu cycle, expansion is what the cartridge's sound chips put out
+    pub fn step(&mut self, expansion: ExpansionLevels) {
+        self.clock_frame_counter();
+        self.triangle.clock_timer();
+        // pulse and noise timers run at half the cpu clock
+        if self.cycle % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+        self.dmc.clock_timer();
+        self.cycle += 1;
+        if self.speed.is_none() {
+            return;
+        }
+
+        // box filter every cpu cycle that falls into the current output sample
+        // the chips on the cartridge add to the mix linearly
+        let expansion: f32 = expansion
+            .iter()
+            .zip(self.expansion_volumes)
+            .map(|(level, volume)| level * volume)
+            .sum();
+        self.sample_sum += self.mix() + expansion;
+        self.sample_count += 1;
+        self.sample_clock += 1.0;
+        if self.sample_clock >= self.cycles_per_sample {
+            self.sample_clock -= self.cycles_per_sample;
+            let mut sample = self.sample_sum / self.sample_count as f32;
+            for filter in &mut self.filters {
+                sample = filter.process(sample);
+            }
+            self.samples.push(sample);
+            if let Some(levels) = &mut self.levels {
+                levels.push([
+                    self.pulse1.output(),
+                    self.pulse2.output(),
+                    self.triangle.output(),
+                    self.noise.output(),
+                    self.dmc.output_level,
+                ]);
+            }
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+}
+
+savestate_fields!(Envelope {
+    start,
+    looping,
+    constant_volume,
+    period,
+    divider,
+    decay
+} checked);
+savestate_fields!(LengthCounter {
+    enabled,
+    halted,
+    value
+});
+savestate_fields!(Pulse {
+    envelope,
+    length,
+    duty,
+    sequence_step,
+    timer_period,
+    timer,
+    sweep_enabled,
+    sweep_period,
+    sweep_negate,
+    sweep_shift,
+    sweep_reload,
+    sweep_divider,
+} checked);
+savestate_fields!(Triangle {
+    length,
+    control,
+    linear_reload_value,
+    linear_counter,
+    linear_reload,
+    sequence_step,
+    timer_period,
+    timer,
+} checked);
+savestate_fields!(Noise {
+    envelope,
+    length,
+    short_mode,
+    timer_period,
+    timer,
+    shift_register
+});
+savestate_fields!(Dmc {
+    irq_enabled,
+    looping,
+    rate,
+    timer,
+    output_level,
+    sample_address,
+    sample_length,
+    current_address,
+    bytes_remaining,
+    sample_buffer,
+    shift_register,
+    bits_remaining,
+    silence,
+    interrupt,
+} checked);
+savestate_fields!(Filter {
+    previous_input,
+    previous_output
+} checked);
+// the sample rate and the samples not yet drained belong to the host, not the console
+savestate_fields!(Apu {
+    pulse1,
+    pulse2,
+    triangle,
+    noise,
+    dmc,
+    cycle,
+    frame_cycle,
+    five_step_mode,
+    irq_inhibit,
+    frame_interrupt,
+    pending_frame_counter_write,
+    sample_clock,
+    sample_sum,
+    sample_count,
+    filters,
+} checked);
+
+// a loaded state is held to the ranges the register writes keep each field in
+impl Envelope {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            self.period < 16 && self.divider < 16 && self.decay < 16,
+            "envelope",
+        )
+    }
+}
+
+impl Pulse {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            (self.duty as usize) < DUTY_TABLE.len() && self.sequence_step < 8,
+            "pulse duty step",
+        )?;
+        ensure(self.timer_period <= 0x07FF, "pulse period")?;
+        ensure(
+            self.sweep_period < 8 && self.sweep_divider < 8 && self.sweep_shift < 8,
+            "pulse sweep",
+        )
+    }
+}
+
+impl Triangle {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            (self.sequence_step as usize) < TRIANGLE_SEQUENCE.len(),
+            "triangle sequence step",
+        )?;
+        ensure(self.timer_period <= 0x07FF, "triangle period")?;
+        ensure(
+            self.linear_reload_value < 0x80 && self.linear_counter < 0x80,
+            "triangle linear counter",
+        )
+    }
+}
+
+impl Dmc {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(self.rate > 0, "dmc rate")?;
+        ensure((1..=8).contains(&self.bits_remaining), "dmc bit count")?;
+        ensure(self.output_level < 0x80, "dmc output level")
+    }
+}
+
+impl Filter {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            self.previous_input.is_finite() && self.previous_output.is_finite(),
+            "filter",
+        )
+    }
+}
+
+impl Apu {
+    // a sample clock far ahead would put out a sample every cycle until it caught up
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            (0.0..65536.0).contains(&self.sample_clock) && self.sample_sum.is_finite(),
+            "sample clock",
+        )
+    }
+}
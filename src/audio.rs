@@ -0,0 +1,181 @@
+// sound output through a player reading raw 16 bit mono samples on its stdin: pacat for pulseaudio
+// and pipewire, or aplay for alsa, the same way capture.rs hands frames to ffmpeg. a thread feeds
+// the player so a full pipe never stalls the frame, and counts what the player takes. once the pipe
+// and the player's buffer are full that count moves with the sound device's own clock, which is
+// what wait paces the frames on
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// how much sound the players are asked to keep in their own buffer
+const PLAYER_LATENCY_MS: u32 = 50;
+// samples are handed to the player this many at a time, so the backlog is seen to drop in steps
+// shorter than a frame
+const CHUNK: usize = 256;
+
+// the players tried in order, with the arguments for raw s16le mono at a sample rate
+fn players(sample_rate: u32) -> [(&'static str, Vec<String>); 2] {
+    [
+        (
+            "pacat",
+            vec![
+                "--playback".to_string(),
+                "--raw".to_string(),
+                "--format=s16le".to_string(),
+                "--channels=1".to_string(),
+                format!("--rate={sample_rate}"),
+                format!("--latency-msec={PLAYER_LATENCY_MS}"),
+                "--client-name=ntsc-nes".to_string(),
+            ],
+        ),
+        (
+            "aplay",
+            vec![
+                "-q".to_string(),
+                "-t".to_string(),
+                "raw".to_string(),
+                "-f".to_string(),
+                "S16_LE".to_string(),
+                "-c".to_string(),
+                "1".to_string(),
+                "-r".to_string(),
+                sample_rate.to_string(),
+                format!("--buffer-time={}", PLAYER_LATENCY_MS * 1000),
+            ],
+        ),
+    ]
+}
+
+#[derive(Default)]
+struct Queue {
+    // encoded samples the thread has not given the player yet
+    bytes: Vec<u8>,
+    // samples pushed that the player has not taken, those in bytes and the chunk being written
+    backlog: usize,
+    closed: bool,
+    // why the player stopped taking samples
+    error: Option<io::Error>,
+}
+
+pub struct AudioOutput {
+    name: &'static str,
+    player: Child,
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    writer: Option<JoinHandle<()>>,
+    sample_rate: u32,
+}
+
+impl AudioOutput {
+    // starts the first player there is, at the rate the apu resamples to
+    pub fn open(sample_rate: u32) -> io::Result<Self> {
+        for (name, arguments) in players(sample_rate) {
+            let spawned = Command::new(name)
+                .args(&arguments)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn();
+            let mut player = match spawned {
+                Ok(player) => player,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(io::Error::new(error.kind(), format!("{name}: {error}"))),
+            };
+            let stdin = player.stdin.take().expect("stdin is piped");
+            let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+            let writer = thread::spawn({
+                let queue = queue.clone();
+                move || feed(stdin, &queue)
+            });
+            return Ok(AudioOutput {
+                name,
+                player,
+                queue,
+                writer: Some(writer),
+                sample_rate,
+            });
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "sound needs pacat or aplay",
+        ))
+    }
+
+    // queues the samples of a frame for the player, an error once it has stopped taking them
+    pub fn push(&mut self, samples: &[f32]) -> io::Result<()> {
+        let (queue, wake) = &*self.queue;
+        let mut queue = queue.lock().expect("the feeding thread does not panic");
+        if let Some(error) = queue.error.take() {
+            return Err(io::Error::new(
+                error.kind(),
+                format!("{} stopped: {error}", self.name),
+            ));
+        }
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            queue.bytes.extend(value.to_le_bytes());
+        }
+        queue.backlog += samples.len();
+        wake.notify_all();
+        Ok(())
+    }
+
+    // blocks until the player has taken all but limit of the sound pushed. the player takes it as
+    // the device plays, so a device running slower than the frames holds them back here
+    pub fn wait(&self, limit: Duration) {
+        let limit = (limit.as_secs_f64() * self.sample_rate as f64) as usize;
+        let (queue, wake) = &*self.queue;
+        let queue = queue.lock().expect("the feeding thread does not panic");
+        let _queue = wake
+            .wait_while(queue, |queue| {
+                queue.backlog > limit && queue.error.is_none()
+            })
+            .expect("the feeding thread does not panic");
+    }
+}
+
+// hands the queued samples to the player a chunk at a time until the output is dropped
+fn feed(mut stdin: ChildStdin, queue: &(Mutex<Queue>, Condvar)) {
+    let (queue, wake) = queue;
+    loop {
+        let chunk: Vec<u8> = {
+            let queue = queue.lock().expect("pushing does not panic");
+            let mut queue = wake
+                .wait_while(queue, |queue| queue.bytes.is_empty() && !queue.closed)
+                .expect("pushing does not panic");
+            if queue.closed {
+                return;
+            }
+            let length = queue.bytes.len().min(CHUNK * 2);
+            queue.bytes.drain(..length).collect()
+        };
+        let result = stdin.write_all(&chunk);
+        let mut queue = queue.lock().expect("pushing does not panic");
+        queue.backlog = queue.backlog.saturating_sub(chunk.len() / 2);
+        if let Err(error) = result {
+            queue.error = Some(error);
+            queue.bytes.clear();
+            queue.backlog = 0;
+            wake.notify_all();
+            return;
+        }
+        wake.notify_all();
+    }
+}
+
+// the sound still buffered is cut off rather than played out after the session ends
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        {
+            let (queue, wake) = &*self.queue;
+            let mut queue = queue.lock().expect("the feeding thread does not panic");
+            queue.closed = true;
+            wake.notify_all();
+        }
+        let _ = self.player.kill();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        let _ = self.player.wait();
+    }
+}
@@ -0,0 +1,67 @@
+use crate::Emulator;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+impl Emulator {
+    // battery backed prg ram, None when the cartridge has no battery
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.mapper.battery_ram()
+    }
+
+    // for hosts that keep the save themselves and write into it directly
+    pub fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.mapper.battery_ram_mut()
+    }
+
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        if let Some(ram) = self.mapper.battery_ram_mut() {
+            let length = ram.len().min(data.len());
+            ram[..length].copy_from_slice(&data[..length]);
+        }
+    }
+
+    // backs the battery ram with a file, loading it when it already exists
+    pub fn set_save_file(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+        if self.battery_ram().is_none() {
+            return Ok(());
+        }
+        let data = read_save_file(&path)?;
+        self.attach_save_file(path, data);
+        Ok(())
+    }
+
+    // backs the battery ram with a file already read, data is None when it doesn't exist yet
+    pub(crate) fn attach_save_file(&mut self, path: PathBuf, data: Option<Vec<u8>>) {
+        if self.battery_ram().is_none() {
+            return;
+        }
+        if let Some(data) = data {
+            self.load_battery_ram(&data);
+        }
+        self.flushed_battery_ram = self.battery_ram().unwrap_or_default().to_vec();
+        self.save_file = Some(path);
+    }
+
+    // writes the battery ram to the save file if it changed since the last flush
+    pub fn flush_save_file(&mut self) -> io::Result<()> {
+        let (Some(path), Some(ram)) = (&self.save_file, self.mapper.battery_ram()) else {
+            return Ok(());
+        };
+        if ram != self.flushed_battery_ram.as_slice() {
+            fs::write(path, ram)?;
+            self.flushed_battery_ram = ram.to_vec();
+        }
+        Ok(())
+    }
+}
+
+// what a save file holds, None when there is none yet
+pub(crate) fn read_save_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,140 @@
+use crate::Emulator;
+use crate::savestate::savestate_fields;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IrqSource {
+    FrameCounter = 0x01,
+    Dmc = 0x02,
+    Mapper = 0x04,
+}
+
+// the /NMI and /IRQ lines of the cpu, driven by the ppu, apu and cartridge
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InterruptLines {
+    // nmi is edge triggered, so it stays latched until the cpu services it
+    nmi: bool,
+    // irq is level triggered and stays asserted while any source holds it
+    irq: u8,
+}
+
+impl InterruptLines {
+    pub fn request_nmi(&mut self) {
+        self.nmi = true;
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.irq |= source as u8;
+        } else {
+            self.irq &= !(source as u8);
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq != 0
+    }
+}
+
+impl Emulator {
+    // a cpu read, which takes one cycle, plus any cycles a dmc fetch halts it for
+    pub(crate) fn read(&mut self, address: u16) -> u8 {
+        if self.dma.dmc {
+            self.run_dmc_dma();
+        }
+        self.tick();
+        let value = self.read_untimed(address);
+        if !self.hooks.reads.is_empty() {
+            self.run_read_hooks(address, value);
+        }
+        value
+    }
+
+    pub(crate) fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        if !self.hooks.writes.is_empty() {
+            self.run_write_hooks(address, value);
+        }
+        self.write_untimed(address, value);
+        if !self.frozen.is_empty() {
+            self.refreeze(address);
+        }
+    }
+
+    // unmapped addresses return the last value on the data bus
+    pub(crate) fn read_untimed(&mut self, address: u16) -> u8 {
+        let value = match address {
+            // 2K of ram mirrored up to $1FFF
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize],
+            // 8 ppu registers mirrored up to $3FFF
+            0x2000..=0x3FFF => self.ppu.read_register(&mut *self.mapper, address & 0x07),
+            // bit 5 of $4015 is not driven
+            0x4015 => self.apu.read_status() | (self.open_bus & 0x20),
+            // the pads only drive the low bits
+            0x4017 if self.zapper.is_some() => self.read_zapper() | (self.open_bus & 0xE0),
+            0x4016 | 0x4017 if self.four_score.connected => {
+                let port = (address - 0x4016) as usize;
+                self.four_score.read(port, &mut self.controllers) | (self.open_bus & 0xE0)
+            }
+            0x4016 | 0x4017 => {
+                self.controllers[(address - 0x4016) as usize].read() | (self.open_bus & 0xE0)
+            }
+            0x4000..=0x401F => self.open_bus,
+            0x4020..=0xFFFF => self.mapper.prg_read(address).unwrap_or(self.open_bus),
+        };
+        let value = if self.cheats.is_empty() {
+            value
+        } else {
+            self.apply_cheats(address, value)
+        };
+        self.open_bus = value;
+        value
+    }
+
+    pub(crate) fn write_untimed(&mut self, address: u16, value: u8) {
+        self.open_bus = value;
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize] = value,
+            0x2000..=0x3FFF => self
+                .ppu
+                .write_register(&mut *self.mapper, address & 0x07, value),
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(address, value),
+            0x4016 => {
+                // the strobe line is shared by both ports
+                for controller in &mut self.controllers {
+                    controller.write_strobe(value);
+                }
+                self.four_score.write_strobe(value);
+            }
+            0x4014 => self.dma.oam_page = Some(value),
+            // the disabled cpu test registers
+            0x4018..=0x401F => {}
+            0x4020..=0xFFFF => self.mapper.prg_write(address, value),
+        }
+    }
+
+    // samples the interrupt outputs of every device into the cpu lines
+    pub(crate) fn update_interrupt_lines(&mut self) {
+        if self.ppu.take_nmi() {
+            self.interrupts.request_nmi();
+        }
+        self.interrupts
+            .set_irq(IrqSource::FrameCounter, self.apu.frame_irq_pending());
+        self.interrupts
+            .set_irq(IrqSource::Dmc, self.apu.dmc_irq_pending());
+        self.interrupts
+            .set_irq(IrqSource::Mapper, self.mapper.irq_pending());
+    }
+
+    pub(crate) fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read(address);
+        let high = self.read(address.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+}
+
+savestate_fields!(InterruptLines { nmi, irq });
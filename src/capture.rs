@@ -0,0 +1,253 @@
+// records the picture and sound of a session to a video file, one video frame per emulated frame.
+// .y4m files are written here, with the sound in a .wav next to them. anything else is encoded
+// losslessly by ffmpeg: the frames are piped to it while the sound goes to a .wav, and the two
+// are muxed into the file asked for once the recording ends
+use ntsc_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// frame rates are written as a fraction over this
+const FRAME_RATE_DENOMINATOR: u64 = 1000;
+
+enum VideoSink {
+    Y4m(BufWriter<File>),
+    Ffmpeg {
+        encoder: Child,
+        frames: ChildStdin,
+        // what the video is encoded to until it is muxed with the sound into output
+        video: PathBuf,
+        output: PathBuf,
+    },
+}
+
+pub struct Capture {
+    video: VideoSink,
+    audio: WavWriter,
+    // the .wav is only an intermediate file for ffmpeg
+    audio_path: PathBuf,
+    // per frame conversion buffer
+    planes: Vec<u8>,
+}
+
+impl Capture {
+    pub fn create(path: &Path, frame_rate: f64, sample_rate: u32) -> io::Result<Self> {
+        let rate = (frame_rate * FRAME_RATE_DENOMINATOR as f64).round() as u64;
+        let y4m = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("y4m"));
+        let (video, audio_path) = if y4m {
+            let mut file = BufWriter::new(File::create(path)?);
+            writeln!(
+                file,
+                "YUV4MPEG2 W{SCREEN_WIDTH} H{SCREEN_HEIGHT} F{rate}:{FRAME_RATE_DENOMINATOR} Ip A1:1 C444"
+            )?;
+            (VideoSink::Y4m(file), path.with_extension("wav"))
+        } else {
+            let video = intermediate(path, "video.mkv");
+            let mut encoder = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error"])
+                .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+                .args(["-s", &format!("{SCREEN_WIDTH}x{SCREEN_HEIGHT}")])
+                .args(["-framerate", &format!("{rate}/{FRAME_RATE_DENOMINATOR}")])
+                .args(["-i", "-", "-c:v", "ffv1"])
+                .arg(&video)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|error| {
+                    io::Error::new(
+                        error.kind(),
+                        format!("only .y4m is recorded without ffmpeg: {error}"),
+                    )
+                })?;
+            let frames = encoder.stdin.take().expect("stdin is piped");
+            let sink = VideoSink::Ffmpeg {
+                encoder,
+                frames,
+                video,
+                output: path.to_path_buf(),
+            };
+            (sink, intermediate(path, "audio.wav"))
+        };
+        Ok(Capture {
+            video,
+            audio: WavWriter::create(&audio_path, sample_rate)?,
+            audio_path,
+            planes: Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 3),
+        })
+    }
+
+    // a 256x240 RGBA8 frame and the samples the frame produced
+    pub fn push_frame(&mut self, rgba: &[u8], samples: &[f32]) -> io::Result<()> {
+        match &mut self.video {
+            VideoSink::Y4m(file) => {
+                rgb_to_yuv444(rgba, &mut self.planes);
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&self.planes)?;
+            }
+            VideoSink::Ffmpeg { frames, .. } => frames.write_all(rgba)?,
+        }
+        self.audio.write(samples)
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.audio.finish()?;
+        match self.video {
+            VideoSink::Y4m(mut file) => file.flush(),
+            VideoSink::Ffmpeg {
+                mut encoder,
+                frames,
+                video,
+                output,
+            } => {
+                drop(frames);
+                check(encoder.wait()?)?;
+                // ffv1 and flac keep it lossless in matroska, other containers get ffmpeg's defaults
+                let mkv = output
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("mkv"));
+                let mut mux = Command::new("ffmpeg");
+                mux.args(["-y", "-loglevel", "error", "-i"])
+                    .arg(&video)
+                    .arg("-i")
+                    .arg(&self.audio_path)
+                    .args(["-map", "0:v", "-map", "1:a"]);
+                if mkv {
+                    mux.args(["-c:v", "copy", "-c:a", "flac"]);
+                }
+                check(mux.arg(&output).status()?)?;
+                fs::remove_file(&video)?;
+                fs::remove_file(&self.audio_path)
+            }
+        }
+    }
+}
+
+// out.mkv becomes out.<suffix>
+fn intermediate(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.with_extension("").into_os_string();
+    name.push(".");
+    name.push(suffix);
+    name.into()
+}
+
+fn check(status: std::process::ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("ffmpeg failed: {status}")))
+    }
+}
+
+// full planes of Y, Cb and Cr in the studio range of bt.601, as y4m players expect
+fn rgb_to_yuv444(rgba: &[u8], planes: &mut Vec<u8>) {
+    let pixels = rgba.len() / 4;
+    planes.clear();
+    planes.resize(pixels * 3, 0);
+    let (luma, chroma) = planes.split_at_mut(pixels);
+    let (blue, red) = chroma.split_at_mut(pixels);
+    for (index, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        luma[index] = (16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0).round() as u8;
+        blue[index] = (128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0).round() as u8;
+        red[index] = (128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0).round() as u8;
+    }
+}
+
+// 16 bit mono pcm, the sizes in the header are filled in by finish
+pub struct WavWriter {
+    file: BufWriter<File>,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"RIFF\0\0\0\0WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // pcm, one channel
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * 2).to_le_bytes())?;
+        // bytes per frame and bits per sample
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data\0\0\0\0")?;
+        Ok(WavWriter { file, samples: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let data = self.samples * 2;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + data).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn y4m_captures_hold_each_frame_and_the_sound_beside_them() {
+        let directory =
+            std::env::temp_dir().join(format!("ntsc-nes-capture-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("out.y4m");
+        let pixels = SCREEN_WIDTH * SCREEN_HEIGHT;
+        let mut capture = Capture::create(&path, 60.0988, 44_100).unwrap();
+        capture.push_frame(&vec![0xFF; pixels * 4], &[0.5]).unwrap();
+        capture
+            .push_frame(&vec![0x00; pixels * 4], &[-1.0, 2.0])
+            .unwrap();
+        capture.finish().unwrap();
+
+        let video = fs::read(&path).unwrap();
+        let header = b"YUV4MPEG2 W256 H240 F60099:1000 Ip A1:1 C444\n";
+        assert_eq!(video[..header.len()], *header);
+        let frames = &video[header.len()..];
+        assert_eq!(frames.len(), 2 * (6 + pixels * 3));
+        // white and black in the studio range, with no colour either way
+        for (frame, luma) in frames.chunks(6 + pixels * 3).zip([235, 16]) {
+            assert_eq!(frame[..6], *b"FRAME\n");
+            let (y, chroma) = frame[6..].split_at(pixels);
+            assert!(y.iter().all(|&value| value == luma));
+            assert!(chroma.iter().all(|&value| value == 128));
+        }
+
+        let wav = fs::read(path.with_extension("wav")).unwrap();
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(wav[..4], *b"RIFF");
+        assert_eq!(wav[4..8], 42u32.to_le_bytes());
+        assert_eq!(wav[24..28], 44_100u32.to_le_bytes());
+        assert_eq!(wav[40..44], 6u32.to_le_bytes());
+        // out of range samples are clamped
+        let samples: Vec<i16> = wav[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(samples, [16383, -32767, 32767]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn colours_convert_to_bt601() {
+        let mut planes = Vec::new();
+        rgb_to_yuv444(&[0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0xFF], &mut planes);
+        // y of red then blue, cb of both, cr of both
+        assert_eq!(planes, [81, 41, 90, 240, 240, 110]);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::crc::crc32;
+use crate::ppu::Mirroring;
+use bytes::BytesMut;
+use std::fmt;
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const CHR_ROM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    PlayChoice10,
+    Extended,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    HeaderTooShort(usize),
+    BadMagic([u8; 4]),
+    Truncated { expected: usize, actual: usize },
+    UnsupportedMapper(u16),
+    NoPrgRom,
+    // an NES 2.0 size in exponent notation too large to address
+    SizeOverflow,
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::HeaderTooShort(length) => {
+                write!(
+                    f,
+                    "file is {length} bytes, too short for a 16 byte iNES header"
+                )
+            }
+            RomError::BadMagic(magic) => {
+                write!(f, "missing \"NES\\x1A\" signature, found {magic:02x?}")
+            }
+            RomError::Truncated { expected, actual } => write!(
+                f,
+                "header declares {expected} bytes of rom data but the file only has {actual}"
+            ),
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+            RomError::NoPrgRom => write!(f, "header declares no prg rom"),
+            RomError::SizeOverflow => write!(f, "header declares a rom size too large to load"),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomHeader {
+    pub format: RomFormat,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub console_type: ConsoleType,
+    pub timing: Timing,
+    // ram sizes in bytes, for iNES files only prg_ram_size is known
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+}
+
+impl RomHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, RomError> {
+        if data.len() < HEADER_SIZE {
+            return Err(RomError::HeaderTooShort(data.len()));
+        }
+        let header = &data[..HEADER_SIZE];
+        if header[0..4] != *b"NES\x1A" {
+            return Err(RomError::BadMagic([
+                header[0], header[1], header[2], header[3],
+            ]));
+        }
+
+        let format = if header[7] & 0x0C == 0x08 {
+            RomFormat::Nes2
+        } else {
+            RomFormat::INes
+        };
+        let mirroring = if header[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if header[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let battery = header[6] & 0x02 != 0;
+        let trainer = header[6] & 0x04 != 0;
+        let console_type = match header[7] & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::PlayChoice10,
+            _ => ConsoleType::Extended,
+        };
+
+        match format {
+            RomFormat::Nes2 => Ok(RomHeader {
+                format,
+                prg_rom_size: Self::nes2_rom_size(header[4], header[9] & 0x0F, PRG_ROM_BANK_SIZE)
+                    .ok_or(RomError::SizeOverflow)?,
+                chr_rom_size: Self::nes2_rom_size(header[5], header[9] >> 4, CHR_ROM_BANK_SIZE)
+                    .ok_or(RomError::SizeOverflow)?,
+                mirroring,
+                battery,
+                trainer,
+                mapper: (header[6] >> 4) as u16
+                    | (header[7] & 0xF0) as u16
+                    | ((header[8] & 0x0F) as u16) << 8,
+                submapper: header[8] >> 4,
+                console_type,
+                timing: match header[12] & 0x03 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                },
+                prg_ram_size: Self::nes2_ram_size(header[10] & 0x0F),
+                prg_nvram_size: Self::nes2_ram_size(header[10] >> 4),
+                chr_ram_size: Self::nes2_ram_size(header[11] & 0x0F),
+                chr_nvram_size: Self::nes2_ram_size(header[11] >> 4),
+            }),
+            RomFormat::INes => {
+                // old dumping tools left signatures like "DiskDude!" in bytes 7-15, which makes flags 7 garbage
+                let mapper_high = if header[12..16].iter().all(|&byte| byte == 0) {
+                    header[7] & 0xF0
+                } else {
+                    0
+                };
+                Ok(RomHeader {
+                    format,
+                    prg_rom_size: header[4] as usize * PRG_ROM_BANK_SIZE,
+                    chr_rom_size: header[5] as usize * CHR_ROM_BANK_SIZE,
+                    mirroring,
+                    battery,
+                    trainer,
+                    mapper: ((header[6] >> 4) | mapper_high) as u16,
+                    submapper: 0,
+                    console_type,
+                    timing: if header[9] & 0x01 != 0 {
+                        Timing::Pal
+                    } else {
+                        Timing::Ntsc
+                    },
+                    // a value of 0 means 8K for compatibility
+                    prg_ram_size: header[8].max(1) as usize * 0x2000,
+                    prg_nvram_size: 0,
+                    chr_ram_size: if header[5] == 0 { 0x2000 } else { 0 },
+                    chr_nvram_size: 0,
+                })
+            }
+        }
+    }
+
+    // none when the size doesn't fit a usize
+    fn nes2_rom_size(lsb: u8, msb: u8, bank_size: usize) -> Option<usize> {
+        if msb == 0x0F {
+            // exponent-multiplier notation: 2^E * (MM * 2 + 1) bytes
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = (lsb & 0x03) as usize * 2 + 1;
+            2usize.checked_pow(exponent)?.checked_mul(multiplier)
+        } else {
+            (((msb as usize) << 8) | lsb as usize).checked_mul(bank_size)
+        }
+    }
+
+    fn nes2_ram_size(shift: u8) -> usize {
+        if shift == 0 { 0 } else { 64 << shift }
+    }
+}
+
+pub struct Cartridge {
+    pub header: RomHeader,
+    pub prg_rom: BytesMut,
+    pub chr_rom: BytesMut,
+}
+
+impl Cartridge {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RomError> {
+        let header = RomHeader::parse(data)?;
+        if header.prg_rom_size == 0 {
+            return Err(RomError::NoPrgRom);
+        }
+        let trainer_size = if header.trainer { TRAINER_SIZE } else { 0 };
+        let expected = (HEADER_SIZE + trainer_size)
+            .checked_add(header.prg_rom_size)
+            .and_then(|size| size.checked_add(header.chr_rom_size))
+            .ok_or(RomError::SizeOverflow)?;
+        if data.len() < expected {
+            return Err(RomError::Truncated {
+                expected: expected - HEADER_SIZE,
+                actual: data.len() - HEADER_SIZE,
+            });
+        }
+
+        // the trainer sits between the header and the prg data
+        let prg_start = HEADER_SIZE + trainer_size;
+        let chr_start = prg_start + header.prg_rom_size;
+        Ok(Cartridge {
+            prg_rom: BytesMut::from(&data[prg_start..chr_start]),
+            chr_rom: BytesMut::from(&data[chr_start..chr_start + header.chr_rom_size]),
+            header,
+        })
+    }
+
+    // the crc32 of the prg rom followed by the chr rom, what tells roms apart in a save state
+    pub fn crc32(&self) -> u32 {
+        crc32(&[&self.prg_rom[..], &self.chr_rom[..]].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an NES 2.0 header with the size bytes given, followed by data bytes
+    fn nes2_file(prg_lsb: u8, chr_lsb: u8, msbs: u8, data: usize) -> Vec<u8> {
+        let mut file = vec![
+            b'N', b'E', b'S', 0x1A, prg_lsb, chr_lsb, 0x00, 0x08, 0x00, msbs,
+        ];
+        file.resize(HEADER_SIZE + data, 0);
+        file
+    }
+
+    #[test]
+    fn nes2_exponent_sizes_are_read() {
+        // 2^14 * 1 bytes of prg, 2^13 * 3 bytes of chr
+        let cartridge =
+            Cartridge::from_bytes(&nes2_file(14 << 2, 13 << 2 | 1, 0xFF, 0x4000 + 0x6000));
+        let cartridge = cartridge.unwrap();
+        assert_eq!(cartridge.header.prg_rom_size, 0x4000);
+        assert_eq!(cartridge.header.chr_rom_size, 0x6000);
+    }
+
+    #[test]
+    fn nes2_sizes_too_large_to_address_are_rejected() {
+        // 2^63 * 7 bytes does not fit a usize
+        let error = RomHeader::parse(&nes2_file(63 << 2 | 3, 0, 0x0F, 0)).unwrap_err();
+        assert!(matches!(error, RomError::SizeOverflow));
+        // 2^63 bytes each fit, but not together
+        let error = Cartridge::from_bytes(&nes2_file(63 << 2, 63 << 2, 0xFF, 0)).err();
+        assert!(matches!(error, Some(RomError::SizeOverflow)));
+        // plain bank counts are fine and only too much for the file
+        let error = Cartridge::from_bytes(&nes2_file(0xFF, 0xFF, 0xEE, 0)).err();
+        assert!(matches!(error, Some(RomError::Truncated { .. })));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::Emulator;
+use std::fmt;
+
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+// a patched cpu read: the byte at address reads as value, if it currently reads as compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheatError {
+    // game genie codes are 6 or 8 letters
+    BadLength(usize),
+    BadLetter(char),
+    // not a game genie code and not address:value or address?compare:value either
+    BadFormat(String),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatError::BadLength(length) => {
+                write!(f, "game genie codes have 6 or 8 letters, not {length}")
+            }
+            CheatError::BadLetter(letter) => write!(f, "{letter} is not a game genie letter"),
+            CheatError::BadFormat(code) => write!(
+                f,
+                "{code} is neither a game genie code nor address:value or address?compare:value"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+impl Cheat {
+    // game genie codes only ever patch prg rom, so they land in $8000-$FFFF
+    pub fn from_game_genie(code: &str) -> Result<Self, CheatError> {
+        let letters = code
+            .chars()
+            .map(|letter| {
+                GAME_GENIE_LETTERS
+                    .iter()
+                    .position(|&candidate| candidate == letter.to_ascii_uppercase() as u8)
+                    .map(|index| index as u16)
+                    .ok_or(CheatError::BadLetter(letter))
+            })
+            .collect::<Result<Vec<u16>, _>>()?;
+        let n = |index: usize| letters[index];
+        if letters.len() != 6 && letters.len() != 8 {
+            return Err(CheatError::BadLength(letters.len()));
+        }
+        let address = 0x8000
+            | ((n(3) & 7) << 12)
+            | ((n(5) & 7) << 8)
+            | ((n(4) & 8) << 8)
+            | ((n(2) & 7) << 4)
+            | ((n(1) & 8) << 4)
+            | (n(4) & 7)
+            | (n(3) & 8);
+        let data_low = if letters.len() == 6 { n(5) } else { n(7) };
+        let value = ((n(1) & 7) << 4) | ((n(0) & 8) << 4) | (n(0) & 7) | (data_low & 8);
+        let compare = (letters.len() == 8)
+            .then(|| (((n(7) & 7) << 4) | ((n(6) & 8) << 4) | (n(6) & 7) | (n(5) & 8)) as u8);
+        Ok(Cheat {
+            address,
+            value: value as u8,
+            compare,
+        })
+    }
+
+    // hex address:value, or address?compare:value, with optional $ prefixes
+    pub fn from_raw(code: &str) -> Result<Self, CheatError> {
+        let bad_format = || CheatError::BadFormat(code.to_string());
+        let hex = |text: &str| u16::from_str_radix(text.trim_start_matches('$'), 16).ok();
+        let (target, value) = code.split_once(':').ok_or_else(bad_format)?;
+        let (address, compare) = match target.split_once('?') {
+            Some((address, compare)) => (address, Some(compare)),
+            None => (target, None),
+        };
+        let byte = |text: &str| hex(text).and_then(|value| u8::try_from(value).ok());
+        Ok(Cheat {
+            address: hex(address).ok_or_else(bad_format)?,
+            value: byte(value).ok_or_else(bad_format)?,
+            compare: match compare {
+                Some(compare) => Some(byte(compare).ok_or_else(bad_format)?),
+                None => None,
+            },
+        })
+    }
+
+    // either kind of code, told apart by the separators of the raw ones
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        if code.contains(':') {
+            Cheat::from_raw(code)
+        } else {
+            Cheat::from_game_genie(code)
+        }
+    }
+
+    fn apply(&self, address: u16, value: u8) -> u8 {
+        if address == self.address && self.compare.is_none_or(|compare| compare == value) {
+            self.value
+        } else {
+            value
+        }
+    }
+}
+
+// one code per line, anything after it is a description and # starts a comment line,
+// errors carry the 1 based line number
+pub fn parse_cheat_file(text: &str) -> Result<Vec<Cheat>, (usize, CheatError)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let code = line.split_whitespace().next()?;
+            (!code.starts_with('#')).then(|| Cheat::parse(code).map_err(|error| (index + 1, error)))
+        })
+        .collect()
+}
+
+impl Emulator {
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    pub fn remove_cheat(&mut self, cheat: Cheat) {
+        self.cheats.retain(|active| *active != cheat);
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub(crate) fn apply_cheats(&self, address: u16, value: u8) -> u8 {
+        self.cheats
+            .iter()
+            .fold(value, |value, cheat| cheat.apply(address, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn six_letter_codes_replace_a_byte() {
+        // infinite lives in super mario bros
+        let cheat = Cheat::from_game_genie("SXIOPO").unwrap();
+        let expected = Cheat {
+            address: 0x91D9,
+            value: 0xAD,
+            compare: None,
+        };
+        assert_eq!(cheat, expected);
+        assert_eq!(Cheat::parse("sxiopo"), Ok(expected));
+        assert_eq!(cheat.apply(0x91D9, 0xDE), 0xAD);
+        assert_eq!(cheat.apply(0x91DA, 0xDE), 0xDE);
+    }
+
+    #[test]
+    fn eight_letter_codes_only_replace_the_byte_they_compare_with() {
+        let cheat = Cheat::from_game_genie("SLXPLOVS").unwrap();
+        let expected = Cheat {
+            address: 0x9123,
+            value: 0xBD,
+            compare: Some(0xDE),
+        };
+        assert_eq!(cheat, expected);
+        assert_eq!(cheat.apply(0x9123, 0xDE), 0xBD);
+        // another bank in the same window
+        assert_eq!(cheat.apply(0x9123, 0x00), 0x00);
+    }
+
+    #[test]
+    fn codes_with_other_letters_or_lengths_are_refused() {
+        assert_eq!(
+            Cheat::from_game_genie("SXIOPB"),
+            Err(CheatError::BadLetter('B'))
+        );
+        assert_eq!(
+            Cheat::from_game_genie("SXIOP"),
+            Err(CheatError::BadLength(5))
+        );
+        assert_eq!(Cheat::parse("91D9=AD"), Err(CheatError::BadLetter('9')));
+        assert_eq!(
+            Cheat::parse("91D9:1AD"),
+            Err(CheatError::BadFormat("91D9:1AD".to_string()))
+        );
+    }
+}
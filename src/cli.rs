@@ -0,0 +1,618 @@
+// command line parsing, options given here win over the config file
+use ntsc_nes::clock::Region;
+use ntsc_nes::power::RamInit;
+use ntsc_nes::video::VideoFilter;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const USAGE: &str = "\
+usage: ntsc-nes <rom> [options]
+       ntsc-nes <file.nsf> [--track <n>] [--record <file.wav>]
+       ntsc-nes disasm <rom>      with the labels of the .nl and .dbg files next to it
+       ntsc-nes analyze <rom> [--patch <file>]
+                                  the code reachable from the vectors, the banks and odd opcodes
+       ntsc-nes <rom> --bench [--frames <n>]
+       ntsc-nes --raw <file> [--load-addr <addr>] [--entry <addr>] [--char-out]
+
+options:
+  --scale <n>               integer scale of the picture
+  --terminal                draw the picture in the terminal with coloured half blocks instead of
+                            on the framebuffer, which is also what happens when there is none
+  --filter <rgb|ntsc|svideo>
+  --palette <file.pal>      colour the picture with a 64 or 512 colour palette
+  --no-sprite-limit         draw every sprite on a line instead of the first 8, for less flicker
+  --region <ntsc|pal|dendy> override the region from the rom header
+  --ram-init <ff|00|alternating|random>
+                            what ram holds at power on, $FF bytes when not given. any two hex
+                            digits fill it with that byte
+  --headless                run without a display and print ram when done
+  --frames <n>              stop after n frames, with --headless or --bench. for an nsf a frame is
+                            a call of its play routine
+  --track <n>               the song of an nsf to start with, counted from 1
+  --bench                   run the rom as fast as possible and report the emulated frame rate
+  --stats                   log the frame rate, host frame time, buffered audio, cpu and ppu cycles
+                            and dropped frames to stderr once a second. i shows them on screen
+  --config <file>           read settings from file instead of ~/.config/ntsc-nes/config.toml
+  --cheat <code>            apply a game genie or address:value code, can be repeated
+  --cheats <file>           read codes from file instead of the .cht file next to the rom
+  --patch <file>            apply an .ips or .bps patch to the rom as it loads, instead of the one
+                            named like the rom next to it
+  --script <file.lua>       run a lua script using fceux's emu, memory, gui and joypad functions,
+                            can be repeated
+  --play <file.fm2>         replay the input movie in file
+  --golden-record <file>    with --headless, write checkpoints of the cpu, ppu, ram and picture to
+                            file every --golden-every frames
+  --golden-verify <file>    with --headless, check the run against the checkpoints in file and
+                            stop at the first that differs with a dump of the state there
+  --golden-every <n>        frames between checkpoints, 60 by default
+  --record <file.fm2>       record input to file, m restarts the recording from the current state
+  --record <file>           record picture and sound to a .y4m (and .wav), or through ffmpeg to .mkv
+                            and other video files
+  --record <file.wav>       record the sound of an nsf
+  --screenshot <file.png>   save the last frame to file when the run ends
+  --raw-frame               also save the colour indices of screenshots to .raw files
+  --zapper                  plug a zapper into port 2, aimed with the mouse
+  --four-score              plug a four score into both ports for players 3 and 4
+  --debug                   start the debugger repl, with the rom analysed so data isn't
+                            disassembled as code
+  --trace <file>            log every instruction like nestest.log, - for stdout
+  --symbols <file>          read labels from an .nl or ca65 .dbg file instead of the ones next to
+                            the rom, can be repeated
+  --netplay <host:port>     join the netplay session of a host as player 2
+  --netplay-listen <port>   host a netplay session as player 1, waiting for a peer to connect
+  --netplay-delay <n>       frames of input delay for both players when hosting, 2 by default
+  --netplay-rollback <n>    frames to run ahead of the peer's input on a guess, 0 waits for it
+  --raw <file>              run a headerless 6502 program instead of a rom, a flat .prg that starts
+                            with its load address unless --load-addr is given
+  --load-addr <addr>        where the whole --raw file goes, in $0000-$07FF or from $4020 up
+  --entry <addr>            where the --raw program starts instead of its reset vector
+  --char-out                print what the --raw program writes to $F001
+  -h, --help";
+
+// which side of a netplay session to be
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetplayRole {
+    Join(String),
+    Host(u16),
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub scale: Option<usize>,
+    pub filter: Option<VideoFilter>,
+    pub palette: Option<PathBuf>,
+    pub no_sprite_limit: bool,
+    pub stats: bool,
+    pub region: Option<Region>,
+    pub ram_init: Option<RamInit>,
+    pub headless: bool,
+    pub terminal: bool,
+    // run without video or audio and time it
+    pub bench: bool,
+    pub frames: Option<usize>,
+    pub track: Option<u8>,
+    pub config: Option<PathBuf>,
+    pub debug: bool,
+    pub trace: Option<String>,
+    pub symbols: Vec<PathBuf>,
+    pub cheats: Option<PathBuf>,
+    pub cheat_codes: Vec<String>,
+    pub patch: Option<PathBuf>,
+    pub scripts: Vec<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub golden_record: Option<PathBuf>,
+    pub golden_verify: Option<PathBuf>,
+    pub golden_interval: Option<u64>,
+    pub record: Option<PathBuf>,
+    // a video file for --record with anything but .fm2
+    pub capture: Option<PathBuf>,
+    pub zapper: bool,
+    pub four_score: bool,
+    pub screenshot: Option<PathBuf>,
+    pub raw_frame: bool,
+    pub netplay: Option<NetplayRole>,
+    pub netplay_delay: Option<u8>,
+    pub netplay_rollback: u8,
+    // a headerless program in place of the rom, and how to load and run it
+    pub raw: bool,
+    pub load_address: Option<u16>,
+    pub entry: Option<u16>,
+    pub char_out: bool,
+}
+
+// $ and 0x prefixed addresses are hex, plain ones decimal
+fn parse_address(option: &str, text: &str) -> Result<u16, String> {
+    let address = match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    };
+    address.ok_or_else(|| format!("{option} expects an address like 0x8000, not {text}"))
+}
+
+pub enum Command {
+    Run(Box<Options>),
+    Disassemble(PathBuf),
+    // the rom and the patch it is analysed with
+    Analyze(PathBuf, Option<PathBuf>),
+    Help,
+}
+
+pub fn parse_filter(name: &str) -> Option<VideoFilter> {
+    match name.to_ascii_lowercase().as_str() {
+        "rgb" => Some(VideoFilter::Rgb),
+        "ntsc" | "composite" => Some(VideoFilter::Ntsc),
+        "svideo" | "s-video" => Some(VideoFilter::SVideo),
+        _ => None,
+    }
+}
+
+// random is seeded from the clock, so every run starts differently
+pub fn parse_ram_init(name: &str) -> Option<RamInit> {
+    match name.to_ascii_lowercase().as_str() {
+        "alternating" => Some(RamInit::Alternating),
+        "random" => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Some(RamInit::Random(now.as_nanos() as u64))
+        }
+        hex if hex.len() == 2 => u8::from_str_radix(hex, 16).ok().map(RamInit::Fill),
+        _ => None,
+    }
+}
+
+pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut arguments = arguments.into_iter();
+    let mut options = Options::default();
+    let mut rom = None;
+    while let Some(argument) = arguments.next() {
+        let mut value = |option: &str| {
+            arguments
+                .next()
+                .ok_or_else(|| format!("{option} needs a value"))
+        };
+        let number = |option: &str, text: String| {
+            text.parse::<usize>()
+                .map_err(|_| format!("{option} expects a number, not {text}"))
+        };
+        match argument.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--scale" => {
+                let scale = number("--scale", value("--scale")?)?;
+                if scale == 0 {
+                    return Err("--scale must be at least 1".to_string());
+                }
+                options.scale = Some(scale);
+            }
+            "--filter" => {
+                let name = value("--filter")?;
+                options.filter =
+                    Some(parse_filter(&name).ok_or_else(|| format!("unknown filter {name}"))?);
+            }
+            "--palette" => options.palette = Some(value("--palette")?.into()),
+            "--region" => {
+                options.region = Some(match value("--region")?.as_str() {
+                    "ntsc" => Region::Ntsc,
+                    "pal" => Region::Pal,
+                    "dendy" => Region::Dendy,
+                    name => return Err(format!("unknown region {name}")),
+                });
+            }
+            "--headless" => options.headless = true,
+            "--terminal" => options.terminal = true,
+            "--bench" => options.bench = true,
+            "--frames" => options.frames = Some(number("--frames", value("--frames")?)?),
+            "--track" => {
+                let text = value("--track")?;
+                let track = text
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&track| track > 0)
+                    .ok_or_else(|| format!("--track expects a song number, not {text}"))?;
+                options.track = Some(track);
+            }
+            "--config" => options.config = Some(value("--config")?.into()),
+            "--play" => options.play = Some(value("--play")?.into()),
+            "--record" => {
+                let path = PathBuf::from(value("--record")?);
+                let movie = path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("fm2"));
+                if movie {
+                    options.record = Some(path);
+                } else {
+                    options.capture = Some(path);
+                }
+            }
+            "--no-sprite-limit" => options.no_sprite_limit = true,
+            "--stats" => options.stats = true,
+            "--golden-record" => options.golden_record = Some(value("--golden-record")?.into()),
+            "--golden-verify" => options.golden_verify = Some(value("--golden-verify")?.into()),
+            "--golden-every" => {
+                let interval = number("--golden-every", value("--golden-every")?)?;
+                if interval == 0 {
+                    return Err("--golden-every must be at least 1".to_string());
+                }
+                options.golden_interval = Some(interval as u64);
+            }
+            "--ram-init" => {
+                let name = value("--ram-init")?;
+                options.ram_init = Some(
+                    parse_ram_init(&name).ok_or_else(|| format!("unknown ram pattern {name}"))?,
+                );
+            }
+            "--zapper" => options.zapper = true,
+            "--four-score" => options.four_score = true,
+            "--screenshot" => options.screenshot = Some(value("--screenshot")?.into()),
+            "--raw-frame" => options.raw_frame = true,
+            "--debug" => options.debug = true,
+            "--trace" => options.trace = Some(value("--trace")?),
+            "--netplay" => options.netplay = Some(NetplayRole::Join(value("--netplay")?)),
+            "--netplay-listen" => {
+                let text = value("--netplay-listen")?;
+                let port = text
+                    .parse::<u16>()
+                    .map_err(|_| format!("--netplay-listen expects a port, not {text}"))?;
+                options.netplay = Some(NetplayRole::Host(port));
+            }
+            "--netplay-delay" | "--netplay-rollback" => {
+                let text = value(&argument)?;
+                let frames = text
+                    .parse::<u8>()
+                    .map_err(|_| format!("{argument} expects a number of frames, not {text}"))?;
+                if argument == "--netplay-delay" {
+                    options.netplay_delay = Some(frames);
+                } else {
+                    options.netplay_rollback = frames;
+                }
+            }
+            "--cheat" => options.cheat_codes.push(value("--cheat")?),
+            "--cheats" => options.cheats = Some(value("--cheats")?.into()),
+            "--patch" => options.patch = Some(value("--patch")?.into()),
+            "--script" => options.scripts.push(value("--script")?.into()),
+            "--symbols" => options.symbols.push(value("--symbols")?.into()),
+            "--raw" => {
+                if rom.is_some() {
+                    return Err("--raw takes the place of the rom".to_string());
+                }
+                options.raw = true;
+                rom = Some(value("--raw")?);
+            }
+            "--load-addr" => {
+                options.load_address = Some(parse_address(&argument, &value(&argument)?)?);
+            }
+            "--entry" => options.entry = Some(parse_address(&argument, &value(&argument)?)?),
+            "--char-out" => options.char_out = true,
+            option if option.starts_with('-') && option != "-" => {
+                return Err(format!("unknown option {option}"));
+            }
+            "disasm" if rom.is_none() => {
+                let path = value("disasm")?;
+                if let Some(extra) = arguments.next() {
+                    return Err(format!("unexpected argument {extra}"));
+                }
+                return Ok(Command::Disassemble(path.into()));
+            }
+            "analyze" if rom.is_none() => {
+                let path = value("analyze")?;
+                // a --patch before analyze counts as much as one after the rom
+                let mut patch = options.patch.take();
+                while let Some(extra) = arguments.next() {
+                    if extra != "--patch" {
+                        return Err(format!("unexpected argument {extra}"));
+                    }
+                    patch = Some(arguments.next().ok_or("--patch needs a value")?.into());
+                }
+                return Ok(Command::Analyze(path.into(), patch));
+            }
+            _ if rom.is_none() => rom = Some(argument),
+            _ => return Err(format!("unexpected argument {argument}")),
+        }
+    }
+    if options.frames.is_some() && !options.headless && !options.bench {
+        return Err("--frames only works with --headless or --bench".to_string());
+    }
+    let raw_only = options.load_address.is_some() || options.entry.is_some() || options.char_out;
+    if raw_only && !options.raw {
+        return Err("--load-addr, --entry and --char-out go with --raw".to_string());
+    }
+    if options.patch.is_some() && options.raw {
+        return Err("--patch goes with a rom, not --raw".to_string());
+    }
+    // the program is loaded into ram, which powering on again would fill over
+    if options.ram_init.is_some() && options.raw {
+        return Err("--ram-init goes with a rom, not --raw".to_string());
+    }
+    if options.terminal && (options.headless || options.bench) {
+        return Err("--terminal needs a display, not --headless or --bench".to_string());
+    }
+    let golden = options.golden_record.is_some() || options.golden_verify.is_some();
+    if golden && !options.headless {
+        return Err("--golden-record and --golden-verify go with --headless".to_string());
+    }
+    if options.golden_record.is_some() && options.golden_verify.is_some() {
+        return Err("--golden-record and --golden-verify cannot be used together".to_string());
+    }
+    if golden && options.capture.is_some() {
+        return Err("a golden run cannot be recorded to a video at the same time".to_string());
+    }
+    if options.golden_interval.is_some() && options.golden_record.is_none() {
+        return Err("--golden-every goes with --golden-record".to_string());
+    }
+    if options.play.is_some() && options.record.is_some() {
+        return Err("--play and --record cannot be used together".to_string());
+    }
+    if options.record.is_some() && options.headless {
+        return Err("recording a .fm2 movie needs a display".to_string());
+    }
+    if options.four_score && options.zapper {
+        return Err("the four score and the zapper both need port 2".to_string());
+    }
+    if options.netplay.is_some() {
+        if options.four_score {
+            return Err("netplay only carries players 1 and 2".to_string());
+        }
+        if options.headless || options.bench || options.debug {
+            return Err("netplay needs a display".to_string());
+        }
+        if options.play.is_some() || options.record.is_some() {
+            return Err("movies cannot be played or recorded during netplay".to_string());
+        }
+    }
+    if options.netplay_delay.is_some() && !matches!(options.netplay, Some(NetplayRole::Host(_))) {
+        return Err("--netplay-delay is set by the host, with --netplay-listen".to_string());
+    }
+    options.rom = rom.ok_or("no rom given")?.into();
+    Ok(Command::Run(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn run(line: &str) -> Options {
+        match parse(arguments(line)) {
+            Ok(Command::Run(options)) => *options,
+            Ok(_) => panic!("{line} is not a run"),
+            Err(message) => panic!("{line}: {message}"),
+        }
+    }
+
+    fn error(line: &str) -> String {
+        match parse(arguments(line)) {
+            Err(message) => message,
+            Ok(_) => panic!("{line} was accepted"),
+        }
+    }
+
+    #[test]
+    fn every_flag_sets_its_option() {
+        let options = run(
+            "game.nes --scale 3 --filter svideo --palette smooth.pal --no-sprite-limit \
+             --region dendy --ram-init 5A --stats --config my.toml --debug --trace - \
+             --symbols a.nl --symbols b.dbg --cheat SXIOPO --cheat 0300:FF --cheats game.cht \
+             --patch fix.ips --script a.lua --script b.lua --play run.fm2 \
+             --screenshot shot.png --raw-frame --zapper",
+        );
+        assert_eq!(options.rom, PathBuf::from("game.nes"));
+        assert_eq!(options.scale, Some(3));
+        assert_eq!(options.filter, Some(VideoFilter::SVideo));
+        assert_eq!(options.palette, Some("smooth.pal".into()));
+        assert!(options.no_sprite_limit && options.stats && options.debug && options.zapper);
+        assert_eq!(options.region, Some(Region::Dendy));
+        assert_eq!(options.ram_init, Some(RamInit::Fill(0x5A)));
+        assert_eq!(options.config, Some("my.toml".into()));
+        assert_eq!(options.trace.as_deref(), Some("-"));
+        assert_eq!(options.symbols, [PathBuf::from("a.nl"), "b.dbg".into()]);
+        assert_eq!(options.cheat_codes, ["SXIOPO", "0300:FF"]);
+        assert_eq!(options.cheats, Some("game.cht".into()));
+        assert_eq!(options.patch, Some("fix.ips".into()));
+        assert_eq!(options.scripts, [PathBuf::from("a.lua"), "b.lua".into()]);
+        assert_eq!(options.play, Some("run.fm2".into()));
+        assert_eq!(options.screenshot, Some("shot.png".into()));
+        assert!(options.raw_frame);
+        assert!(!options.headless && !options.bench && !options.four_score);
+
+        let options =
+            run("game.nes --headless --frames 600 --golden-record run.golden --golden-every 30");
+        assert!(options.headless);
+        assert_eq!(options.frames, Some(600));
+        assert_eq!(options.golden_record, Some("run.golden".into()));
+        assert_eq!(options.golden_interval, Some(30));
+        let options = run("game.nes --headless --golden-verify run.golden --filter composite");
+        assert_eq!(options.golden_verify, Some("run.golden".into()));
+        assert_eq!(options.filter, Some(VideoFilter::Ntsc));
+        let options = run("game.nes --bench --frames 100 --ram-init alternating");
+        assert!(options.bench);
+        assert_eq!(options.ram_init, Some(RamInit::Alternating));
+
+        // --record is a movie as .fm2 and a capture otherwise
+        let options = run("game.nes --record run.FM2 --four-score --terminal");
+        assert_eq!(options.record, Some("run.FM2".into()));
+        assert_eq!(options.capture, None);
+        assert!(options.four_score && options.terminal);
+        let options = run("music.nsf --track 3 --record song.wav --region pal");
+        assert_eq!(options.track, Some(3));
+        assert_eq!(options.capture, Some("song.wav".into()));
+        assert_eq!(options.record, None);
+        assert_eq!(options.region, Some(Region::Pal));
+
+        let options = run("game.nes --netplay-listen 4000 --netplay-delay 3 --netplay-rollback 4");
+        assert_eq!(options.netplay, Some(NetplayRole::Host(4000)));
+        assert_eq!(options.netplay_delay, Some(3));
+        assert_eq!(options.netplay_rollback, 4);
+        let options = run("game.nes --netplay example.com:4000");
+        assert_eq!(
+            options.netplay,
+            Some(NetplayRole::Join("example.com:4000".into()))
+        );
+
+        // addresses are hex with $ or 0x, decimal without
+        let options = run("--raw program.prg --load-addr $0200 --entry 0x0210 --char-out");
+        assert!(options.raw && options.char_out);
+        assert_eq!(options.rom, PathBuf::from("program.prg"));
+        assert_eq!(options.load_address, Some(0x0200));
+        assert_eq!(options.entry, Some(0x0210));
+        let options = run("--raw program.prg --load-addr 512");
+        assert_eq!(options.load_address, Some(512));
+    }
+
+    #[test]
+    fn subcommands_and_help_are_commands_of_their_own() {
+        assert!(matches!(parse(arguments("-h")), Ok(Command::Help)));
+        assert!(matches!(
+            parse(arguments("game.nes --help")),
+            Ok(Command::Help)
+        ));
+        let Ok(Command::Disassemble(path)) = parse(arguments("disasm game.nes")) else {
+            panic!("disasm is not a disassembly");
+        };
+        assert_eq!(path, PathBuf::from("game.nes"));
+        let Ok(Command::Analyze(path, None)) = parse(arguments("analyze game.nes")) else {
+            panic!("analyze is not an analysis");
+        };
+        assert_eq!(path, PathBuf::from("game.nes"));
+        for line in [
+            "analyze game.nes --patch fix.ips",
+            "--patch fix.ips analyze game.nes",
+        ] {
+            let Ok(Command::Analyze(path, Some(patch))) = parse(arguments(line)) else {
+                panic!("{line} is not an analysis with a patch");
+            };
+            assert_eq!((path, patch), ("game.nes".into(), "fix.ips".into()));
+        }
+        assert_eq!(error("analyze game.nes --patch"), "--patch needs a value");
+        assert_eq!(
+            error("analyze game.nes --headless"),
+            "unexpected argument --headless"
+        );
+        assert_eq!(error("disasm game.nes more"), "unexpected argument more");
+        assert_eq!(error("analyze"), "analyze needs a value");
+        // after a rom they are file names
+        assert_eq!(error("game.nes disasm"), "unexpected argument disasm");
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        let cases = [
+            ("", "no rom given"),
+            ("--headless", "no rom given"),
+            ("game.nes --scale", "--scale needs a value"),
+            ("game.nes --scale 0", "--scale must be at least 1"),
+            ("game.nes --scale big", "--scale expects a number, not big"),
+            ("game.nes --filter vhs", "unknown filter vhs"),
+            ("game.nes --region secam", "unknown region secam"),
+            ("game.nes --ram-init zz", "unknown ram pattern zz"),
+            ("game.nes --track 0", "--track expects a song number, not 0"),
+            (
+                "game.nes --headless --golden-record g --golden-every 0",
+                "--golden-every must be at least 1",
+            ),
+            (
+                "game.nes --netplay-listen port",
+                "--netplay-listen expects a port, not port",
+            ),
+            (
+                "game.nes --netplay-rollback 300",
+                "--netplay-rollback expects a number of frames, not 300",
+            ),
+            (
+                "--raw a.prg --load-addr $10000",
+                "--load-addr expects an address like 0x8000, not $10000",
+            ),
+            ("game.nes --raw a.prg", "--raw takes the place of the rom"),
+            ("game.nes --bogus", "unknown option --bogus"),
+            ("game.nes other.nes", "unexpected argument other.nes"),
+        ];
+        for (line, message) in cases {
+            assert_eq!(error(line), message, "{line}");
+        }
+    }
+
+    #[test]
+    fn options_that_do_not_go_together_are_refused() {
+        let cases = [
+            (
+                "game.nes --frames 10",
+                "--frames only works with --headless or --bench",
+            ),
+            (
+                "game.nes --entry $C000",
+                "--load-addr, --entry and --char-out go with --raw",
+            ),
+            (
+                "game.nes --char-out",
+                "--load-addr, --entry and --char-out go with --raw",
+            ),
+            (
+                "--raw a.prg --patch fix.ips",
+                "--patch goes with a rom, not --raw",
+            ),
+            (
+                "--raw a.prg --ram-init 00",
+                "--ram-init goes with a rom, not --raw",
+            ),
+            (
+                "game.nes --bench --terminal",
+                "--terminal needs a display, not --headless or --bench",
+            ),
+            (
+                "game.nes --golden-record g",
+                "--golden-record and --golden-verify go with --headless",
+            ),
+            (
+                "game.nes --headless --golden-record g --golden-verify h",
+                "--golden-record and --golden-verify cannot be used together",
+            ),
+            (
+                "game.nes --headless --golden-verify g --record out.y4m",
+                "a golden run cannot be recorded to a video at the same time",
+            ),
+            (
+                "game.nes --headless --golden-verify g --golden-every 5",
+                "--golden-every goes with --golden-record",
+            ),
+            (
+                "game.nes --play a.fm2 --record b.fm2",
+                "--play and --record cannot be used together",
+            ),
+            (
+                "game.nes --headless --record b.fm2",
+                "recording a .fm2 movie needs a display",
+            ),
+            (
+                "game.nes --zapper --four-score",
+                "the four score and the zapper both need port 2",
+            ),
+            (
+                "game.nes --netplay h:1 --four-score",
+                "netplay only carries players 1 and 2",
+            ),
+            ("game.nes --netplay h:1 --debug", "netplay needs a display"),
+            (
+                "game.nes --netplay-listen 1 --headless",
+                "netplay needs a display",
+            ),
+            (
+                "game.nes --netplay h:1 --play a.fm2",
+                "movies cannot be played or recorded during netplay",
+            ),
+            (
+                "game.nes --netplay h:1 --netplay-delay 2",
+                "--netplay-delay is set by the host, with --netplay-listen",
+            ),
+            (
+                "game.nes --netplay-delay 2",
+                "--netplay-delay is set by the host, with --netplay-listen",
+            ),
+        ];
+        for (line, message) in cases {
+            assert_eq!(error(line), message, "{line}");
+        }
+    }
+}
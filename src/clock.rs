@@ -0,0 +1,196 @@
+use crate::Emulator;
+use crate::cartridge::Timing;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 8.0;
+
+// the console the game runs on, which sets the clock dividers and the length of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    // the famiclone: a pal picture with ntsc like cpu timing and a late vblank
+    Dendy,
+}
+
+impl Region {
+    // multi region games run as ntsc
+    pub fn from_timing(timing: Timing) -> Self {
+        match timing {
+            Timing::Ntsc | Timing::MultiRegion => Region::Ntsc,
+            Timing::Pal => Region::Pal,
+            Timing::Dendy => Region::Dendy,
+        }
+    }
+
+    pub fn master_clock_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 21_477_272.0,
+            Region::Pal | Region::Dendy => 26_601_712.0,
+        }
+    }
+
+    // master clock cycles per cpu cycle
+    pub fn cpu_divider(self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    // master clock cycles per ppu dot
+    pub fn ppu_divider(self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_rate(self) -> f64 {
+        self.master_clock_rate() / self.cpu_divider() as f64
+    }
+
+    // scanlines per frame, the last one is the pre-render line
+    pub fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // the scanline whose dot 1 sets the vblank flag
+    pub fn vblank_scanline(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // frames per second, ntsc drops a dot every other frame
+    pub fn frame_rate(self) -> f64 {
+        let dots = 341.0 * self.scanlines() as f64 - if self == Region::Ntsc { 0.5 } else { 0.0 };
+        self.master_clock_rate() / (dots * self.ppu_divider() as f64)
+    }
+}
+
+// how fast emulated time runs against real time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    // a multiple of real time between MIN_SPEED and MAX_SPEED
+    Multiplier(f64),
+    // as fast as the host can go, with the sound muted
+    Unlimited,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::Multiplier(1.0)
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::Multiplier(multiplier) => write!(f, "{multiplier}x"),
+            Speed::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+impl Emulator {
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // the header picks the region when the rom is loaded, this overrides it
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+    }
+
+    // advances every chip by one cpu cycle, the cpu calls it before each bus access
+    pub(crate) fn tick(&mut self) {
+        let ppu_divider = self.region.ppu_divider();
+        self.master_clock += self.region.cpu_divider();
+        let mut dots = 0;
+        while self.ppu_clock + ppu_divider <= self.master_clock {
+            self.ppu_clock += ppu_divider;
+            dots += 1;
+        }
+        self.ppu.run(&mut *self.mapper, dots);
+        if !self.hooks.scanlines.is_empty() {
+            self.check_scanline();
+        }
+        self.mapper.cpu_cycle();
+        self.apu.step(self.mapper.audio());
+        if self.apu.dmc_sample_request().is_some() {
+            self.dma.dmc = true;
+        }
+    }
+
+    // cpu cycles since power on
+    pub fn cycles(&self) -> u64 {
+        self.master_clock / self.region.cpu_divider()
+    }
+
+    // ppu dots since power on
+    pub fn ppu_dots(&self) -> u64 {
+        self.ppu_clock / self.region.ppu_divider()
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    // paces wait_for_frame and resamples the audio to match, multipliers are clamped to the supported range
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = match speed {
+            Speed::Multiplier(multiplier) => {
+                Speed::Multiplier(multiplier.clamp(MIN_SPEED, MAX_SPEED))
+            }
+            Speed::Unlimited => Speed::Unlimited,
+        };
+        self.apu.set_speed(match self.speed {
+            Speed::Multiplier(multiplier) => Some(multiplier),
+            Speed::Unlimited => None,
+        });
+    }
+
+    // the real time a frame takes at the current speed, None when unlimited.
+    // with an nsf loaded a frame is the time between calls of its play routine
+    pub fn frame_duration(&self) -> Option<Duration> {
+        let frame_rate = match &self.nsf {
+            Some(player) => player.play_rate(self.region),
+            None => self.region.frame_rate(),
+        };
+        match self.speed {
+            Speed::Multiplier(multiplier) => {
+                Some(Duration::from_secs_f64(1.0 / (frame_rate * multiplier)))
+            }
+            Speed::Unlimited => None,
+        }
+    }
+
+    // sleeps until the next frame is due, called once a frame. after a stall the deadlines
+    // start over from now instead of running frames back to back to catch up
+    pub fn wait_for_frame(&mut self) {
+        let Some(duration) = self.frame_duration() else {
+            self.next_frame = None;
+            return;
+        };
+        let now = Instant::now();
+        let next_frame = self.next_frame.map_or(now, |deadline| deadline + duration);
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+            self.next_frame = Some(next_frame);
+        } else {
+            self.next_frame = Some(now);
+        }
+    }
+}
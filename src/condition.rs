@@ -0,0 +1,64 @@
+// running until something happens, for test suites and fuzzers driving the emulator headlessly.
+// conditions are looked at before the first instruction and after every one, so a run that has a
+// frame limit among its conditions always ends, and the same rom and input always stop at the same
+// instruction
+use crate::Emulator;
+
+pub enum Condition {
+    // frames completed since the run started
+    Frames(u64),
+    // the next instruction is at this address
+    ProgramCounter(u16),
+    // a cpu address, read without side effects, holds this value
+    Memory(u16, u8),
+    Custom(Box<dyn FnMut(&mut Emulator) -> bool>),
+    // whichever of these comes first
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn custom(check: impl FnMut(&mut Emulator) -> bool + 'static) -> Self {
+        Condition::Custom(Box::new(check))
+    }
+
+    fn met(&mut self, emulator: &mut Emulator, start_frame: u64) -> bool {
+        match self {
+            Condition::Frames(frames) => emulator.frame_count() - start_frame >= *frames,
+            Condition::ProgramCounter(address) => emulator.cpu().program_counter == *address,
+            Condition::Memory(address, value) => emulator.peek(*address) == *value,
+            Condition::Custom(check) => check(emulator),
+            Condition::Any(conditions) => conditions
+                .iter_mut()
+                .any(|condition| condition.met(emulator, start_frame)),
+        }
+    }
+}
+
+// why run_until returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Met,
+    // the cpu hit a HLT before the condition was met
+    Halted,
+}
+
+impl Emulator {
+    // runs instruction by instruction until condition is met or the cpu halts. frames are begun and
+    // ended like step_frame does, a frame stopped in the middle of is finished by the next one run
+    pub fn run_until(&mut self, mut condition: Condition) -> Stop {
+        let start_frame = self.frame_count();
+        loop {
+            if condition.met(self, start_frame) {
+                return Stop::Met;
+            }
+            if self.cpu.halted {
+                return Stop::Halted;
+            }
+            self.begin_frame();
+            self.step_instruction();
+            if self.ppu.frame_complete || self.cpu.halted {
+                self.end_frame();
+            }
+        }
+    }
+}
@@ -0,0 +1,542 @@
+// settings from ~/.config/ntsc-nes/config.toml, for example
+//
+//   [video]
+//   filter = "ntsc"
+//   scale = 3
+//   palette = "~/nes/smooth.pal"
+//   # or decode one from the ntsc signal
+//   hue = -5.0
+//   saturation = 1.2
+//   brightness = 0.0
+//   # draw every sprite on a line rather than the first 8
+//   sprite_limit = false
+//
+//   [system]
+//   # what ram holds at power on: "ff", "00", any other byte, "alternating" or "random"
+//   ram_init = "random"
+//
+//   [audio]
+//   sample_rate = 48000
+//   # how loud each channel is mixed in from 0 to 1, and the ones left out
+//   triangle = 0.8
+//   mute = ["noise"]
+//   # and the vrc6 and disk system sound chips' own channels
+//   fds = 0.5
+//
+//   [rewind]
+//   memory = 64
+//
+//   [cheats]
+//   directory = "~/nes/cheats"
+//
+//   [keys]
+//   a = "k"
+//   b = "j"
+//   select = ["tab", "space"]
+//   turbo_a = "i"
+//   turbo_b = "u"
+//
+//   # players 2 to 4 have no keys of their own until bound
+//   [keys2]
+//   a = "e"
+//
+//   # presses a second of the turbo buttons
+//   [turbo]
+//   a = 20
+//   b = 10
+//
+//   # the js device numbers of the players' gamepads, and what their buttons and axes press.
+//   # d remaps the buttons from the frontend and writes them back here
+//   [gamepad]
+//   players = [0, 1]
+//   deadzone = 0.4
+//   a = 2
+//   b = 1
+//   up = ["axis1-", "axis7-"]
+use crate::cli::{Options, parse_filter, parse_ram_init};
+#[cfg(feature = "frontend")]
+use crate::frontend::{PadButton, TerminalKey};
+#[cfg(feature = "frontend")]
+use crate::gamepad::{GamepadInput, GamepadMap, REMAP_ORDER};
+use ntsc_nes::apu::Channel;
+#[cfg(feature = "frontend")]
+use ntsc_nes::controller::{Button, Player};
+use ntsc_nes::expansion::Expansion;
+use ntsc_nes::palette::NtscParameters;
+use ntsc_nes::power::RamInit;
+use ntsc_nes::video::VideoFilter;
+use std::env;
+use std::fs;
+#[cfg(feature = "frontend")]
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+#[cfg(feature = "frontend")]
+use toml_edit::{ImDocument, Value};
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub filter: Option<VideoFilter>,
+    pub scale: Option<usize>,
+    // a .pal file to colour the picture with
+    pub palette: Option<PathBuf>,
+    // set when any of hue, saturation or brightness is, the others keep their defaults
+    pub ntsc_palette: Option<NtscParameters>,
+    pub sprite_limit: Option<bool>,
+    pub ram_init: Option<RamInit>,
+    pub sample_rate: Option<u32>,
+    pub channel_volumes: Vec<(Channel, f32)>,
+    pub muted_channels: Vec<Channel>,
+    pub expansion_volumes: Vec<(Expansion, f32)>,
+    // megabytes of rewind history, 0 turns rewinding off
+    pub rewind_memory: Option<usize>,
+    // where <rom name>.cht files are looked for instead of next to the rom
+    pub cheat_directory: Option<PathBuf>,
+    // buttons rebound by the [keys] to [keys4] tables, each replaces all default keys of its button
+    #[cfg(feature = "frontend")]
+    pub bindings: Vec<(PadButton, Vec<TerminalKey>)>,
+    #[cfg(feature = "frontend")]
+    pub turbo_rates: Vec<(Button, f64)>,
+    // buttons rebound by the [gamepad] table, like the keys
+    #[cfg(feature = "frontend")]
+    pub gamepad_bindings: Vec<(Button, Vec<GamepadInput>)>,
+    #[cfg(feature = "frontend")]
+    pub gamepad_deadzone: Option<f32>,
+    // the js device of each player's gamepad in player order
+    #[cfg(feature = "frontend")]
+    pub gamepad_devices: Option<Vec<u8>>,
+}
+
+impl Config {
+    // fills in the settings the command line left out, what it gives wins over the file. the scale
+    // stays apart, the frontend takes either but only a --scale warns when there is no display
+    pub fn merge_into(&self, options: &mut Options) {
+        options.filter = options.filter.or(self.filter);
+        if options.palette.is_none() {
+            options.palette.clone_from(&self.palette);
+        }
+        options.ram_init = options.ram_init.or(self.ram_init);
+        options.no_sprite_limit |= self.sprite_limit == Some(false);
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ntsc-nes").join("config.toml"))
+}
+
+pub fn load(path: &Path) -> Result<Config, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    parse(&text).map_err(|error| format!("{}: {error}", path.display()))
+}
+
+fn setting<'a>(document: &'a DocumentMut, table: &str, key: &str) -> Option<&'a Item> {
+    document.get(table).and_then(|table| table.get(key))
+}
+
+fn positive(document: &DocumentMut, table: &str, key: &str) -> Result<Option<i64>, String> {
+    match setting(document, table, key) {
+        None => Ok(None),
+        Some(item) => match item.as_integer() {
+            Some(value @ 1..) => Ok(Some(value)),
+            _ => Err(format!("{table}.{key} must be a positive integer")),
+        },
+    }
+}
+
+fn float(document: &DocumentMut, table: &str, key: &str) -> Result<Option<f32>, String> {
+    match setting(document, table, key) {
+        None => Ok(None),
+        Some(item) => item
+            .as_float()
+            .or_else(|| item.as_integer().map(|value| value as f64))
+            .map(|value| Some(value as f32))
+            .ok_or_else(|| format!("{table}.{key} must be a number")),
+    }
+}
+
+// paths may start with ~/ for the home directory
+fn path(item: &Item, what: &str) -> Result<PathBuf, String> {
+    let path = item
+        .as_str()
+        .ok_or_else(|| format!("{what} must be a path"))?;
+    Ok(match path.strip_prefix("~/") {
+        Some(relative) => env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(relative),
+        None => path.into(),
+    })
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let document: DocumentMut = text.parse().map_err(|error| format!("{error}"))?;
+    let mut config = Config::default();
+    if let Some(item) = setting(&document, "video", "filter") {
+        let name = item.as_str().ok_or("video.filter must be a string")?;
+        config.filter = Some(parse_filter(name).ok_or_else(|| format!("unknown filter {name}"))?);
+    }
+    config.scale = positive(&document, "video", "scale")?.map(|scale| scale as usize);
+    if let Some(item) = setting(&document, "video", "palette") {
+        config.palette = Some(path(item, "video.palette")?);
+    }
+    let hue = float(&document, "video", "hue")?;
+    let saturation = float(&document, "video", "saturation")?;
+    let brightness = float(&document, "video", "brightness")?;
+    if hue.is_some() || saturation.is_some() || brightness.is_some() {
+        let defaults = NtscParameters::default();
+        config.ntsc_palette = Some(NtscParameters {
+            hue: hue.unwrap_or(defaults.hue),
+            saturation: saturation.unwrap_or(defaults.saturation),
+            brightness: brightness.unwrap_or(defaults.brightness),
+        });
+    }
+    if let Some(item) = setting(&document, "video", "sprite_limit") {
+        config.sprite_limit = Some(
+            item.as_bool()
+                .ok_or("video.sprite_limit must be true or false")?,
+        );
+    }
+    if let Some(item) = setting(&document, "system", "ram_init") {
+        let name = item.as_str().ok_or("system.ram_init must be a string")?;
+        config.ram_init =
+            Some(parse_ram_init(name).ok_or_else(|| format!("unknown ram pattern {name}"))?);
+    }
+    config.sample_rate = positive(&document, "audio", "sample_rate")?
+        .map(|rate| u32::try_from(rate).map_err(|_| "audio.sample_rate is too large"))
+        .transpose()?;
+    for channel in Channel::ALL {
+        if let Some(volume) = float(&document, "audio", channel.name())? {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(format!("audio.{} must be from 0 to 1", channel.name()));
+            }
+            config.channel_volumes.push((channel, volume));
+        }
+    }
+    for chip in Expansion::ALL {
+        if let Some(volume) = float(&document, "audio", chip.name())? {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(format!("audio.{} must be from 0 to 1", chip.name()));
+            }
+            config.expansion_volumes.push((chip, volume));
+        }
+    }
+    if let Some(item) = setting(&document, "audio", "mute") {
+        let names: Vec<&str> = match item.as_array() {
+            Some(array) => array
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Option<_>>(),
+            None => item.as_str().map(|name| vec![name]),
+        }
+        .ok_or("audio.mute must be a channel or a list of them")?;
+        for name in names {
+            let channel =
+                Channel::from_name(name).ok_or_else(|| format!("unknown channel {name}"))?;
+            config.muted_channels.push(channel);
+        }
+    }
+    if let Some(item) = setting(&document, "rewind", "memory") {
+        let megabytes = item
+            .as_integer()
+            .and_then(|megabytes| usize::try_from(megabytes).ok())
+            .ok_or("rewind.memory must be a number of megabytes")?;
+        config.rewind_memory = Some(megabytes);
+    }
+    if let Some(item) = setting(&document, "cheats", "directory") {
+        config.cheat_directory = Some(path(item, "cheats.directory")?);
+    }
+    #[cfg(feature = "frontend")]
+    for (table, player) in [
+        ("keys", Player::One),
+        ("keys2", Player::Two),
+        ("keys3", Player::Three),
+        ("keys4", Player::Four),
+    ] {
+        let Some(keys) = document.get(table).and_then(Item::as_table_like) else {
+            continue;
+        };
+        for (name, item) in keys.iter() {
+            let what = format!("{table}.{name}");
+            let pad = match name.strip_prefix("turbo_") {
+                Some(name) => PadButton {
+                    turbo: true,
+                    ..PadButton::new(player, button(name, &what)?)
+                },
+                None => PadButton::new(player, button(name, &what)?),
+            };
+            config.bindings.push((pad, key_list(&what, item)?));
+        }
+    }
+    #[cfg(feature = "frontend")]
+    if let Some(rates) = document.get("turbo").and_then(Item::as_table_like) {
+        for (name, _) in rates.iter() {
+            let what = format!("turbo.{name}");
+            let button = button(name, &what)?;
+            match float(&document, "turbo", name)? {
+                Some(rate) if rate > 0.0 => config.turbo_rates.push((button, rate as f64)),
+                _ => {
+                    return Err(format!(
+                        "{what} must be a positive number of presses a second"
+                    ));
+                }
+            }
+        }
+    }
+    #[cfg(feature = "frontend")]
+    if let Some(gamepad) = document.get("gamepad").and_then(Item::as_table_like) {
+        for (name, item) in gamepad.iter() {
+            match name {
+                "deadzone" => match float(&document, "gamepad", name)? {
+                    Some(deadzone) if (0.0..1.0).contains(&deadzone) => {
+                        config.gamepad_deadzone = Some(deadzone);
+                    }
+                    _ => return Err("gamepad.deadzone must be from 0 up to 1".to_string()),
+                },
+                "players" => {
+                    let devices: Option<Vec<u8>> = item.as_array().and_then(|array| {
+                        array
+                            .iter()
+                            .map(|device| device.as_integer()?.try_into().ok())
+                            .collect()
+                    });
+                    match devices {
+                        Some(devices) if devices.len() <= 4 => {
+                            config.gamepad_devices = Some(devices);
+                        }
+                        _ => {
+                            return Err("gamepad.players must be a list of up to 4 device numbers"
+                                .to_string());
+                        }
+                    }
+                }
+                _ => {
+                    let what = format!("gamepad.{name}");
+                    let button = button(name, &what)?;
+                    config
+                        .gamepad_bindings
+                        .push((button, gamepad_inputs(&what, item)?));
+                }
+            }
+        }
+    }
+    Ok(config)
+}
+
+// a button number, an "axis<n>-" or "axis<n>+" direction, or a list of them
+#[cfg(feature = "frontend")]
+fn gamepad_inputs(what: &str, item: &Item) -> Result<Vec<GamepadInput>, String> {
+    let invalid = || format!("{what} must be button numbers or axis directions like \"axis0-\"");
+    let input = |value: &Value| match (value.as_integer(), value.as_str()) {
+        (Some(number), _) => u8::try_from(number).ok().map(GamepadInput::Button),
+        (_, Some(name)) => GamepadInput::from_axis_name(name),
+        _ => None,
+    };
+    match item {
+        Item::Value(Value::Array(array)) => array
+            .iter()
+            .map(|value| input(value).ok_or_else(invalid))
+            .collect(),
+        Item::Value(value) => Ok(vec![input(value).ok_or_else(invalid)?]),
+        _ => Err(invalid()),
+    }
+}
+
+// writes the buttons of map into the [gamepad] table of the config at path, leaving the rest of
+// the file as it was. without the display feature toml_edit can't write a document back, so the
+// lines are edited in the text where the parser found them
+#[cfg(feature = "frontend")]
+pub fn save_gamepad_map(path: &Path, map: &GamepadMap) -> Result<(), String> {
+    let failed = |error: &dyn std::fmt::Display| format!("{}: {error}", path.display());
+    let mut text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(failed(&error)),
+    };
+    let lines: String = map
+        .inputs()
+        .into_iter()
+        .map(|(button, inputs)| format!("{} = {}\n", button_name(button), input_list(&inputs)))
+        .collect();
+    let document = ImDocument::parse(text.as_str()).map_err(|error| failed(&error))?;
+    match document.as_table().get("gamepad") {
+        Some(Item::Table(table)) if table.span().is_some() => {
+            let line_start =
+                |offset: usize| text[..offset].rfind('\n').map_or(0, |index| index + 1);
+            let line_end = |offset: usize| {
+                text[offset..]
+                    .find('\n')
+                    .map_or(text.len(), |index| offset + index + 1)
+            };
+            let mut old: Vec<Range<usize>> = REMAP_ORDER
+                .iter()
+                .filter_map(|button| {
+                    let (key, item) = table.get_key_value(button_name(*button))?;
+                    Some(line_start(key.span()?.start)..line_end(item.span()?.end))
+                })
+                .collect();
+            // the new lines go right under the header, the old ones all come after it
+            let header = line_end(table.span().expect("checked above").start);
+            old.sort_by_key(|range| std::cmp::Reverse(range.start));
+            drop(document);
+            for range in old {
+                text.replace_range(range, "");
+            }
+            text.insert_str(header, &lines);
+        }
+        Some(_) => return Err(failed(&"gamepad has to be a [gamepad] table to save into")),
+        None => {
+            drop(document);
+            if !text.is_empty() {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push('\n');
+            }
+            text.push_str("[gamepad]\n");
+            text.push_str(&lines);
+        }
+    }
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|error| failed(&error))?;
+    }
+    fs::write(path, text).map_err(|error| failed(&error))
+}
+
+// a toml value of inputs the way gamepad_inputs reads them
+#[cfg(feature = "frontend")]
+fn input_list(inputs: &[GamepadInput]) -> String {
+    let names: Vec<String> = inputs
+        .iter()
+        .map(|input| match input {
+            GamepadInput::Button(number) => number.to_string(),
+            GamepadInput::Axis(axis, negative) => {
+                format!("\"axis{axis}{}\"", if *negative { '-' } else { '+' })
+            }
+        })
+        .collect();
+    match names.as_slice() {
+        [name] => name.clone(),
+        names => format!("[{}]", names.join(", ")),
+    }
+}
+
+#[cfg(feature = "frontend")]
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::A => "a",
+        Button::B => "b",
+        Button::Select => "select",
+        Button::Start => "start",
+        Button::Up => "up",
+        Button::Down => "down",
+        Button::Left => "left",
+        Button::Right => "right",
+    }
+}
+
+#[cfg(feature = "frontend")]
+fn button(name: &str, what: &str) -> Result<Button, String> {
+    Ok(match name {
+        "a" => Button::A,
+        "b" => Button::B,
+        "select" => Button::Select,
+        "start" => Button::Start,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "left" => Button::Left,
+        "right" => Button::Right,
+        _ => return Err(format!("{what} is not a controller button")),
+    })
+}
+
+// a key name or a list of them
+#[cfg(feature = "frontend")]
+fn key_list(what: &str, item: &Item) -> Result<Vec<TerminalKey>, String> {
+    let names: Vec<&str> = match (item.as_str(), item.as_array()) {
+        (Some(name), _) => vec![name],
+        (None, Some(array)) => array.iter().filter_map(|value| value.as_str()).collect(),
+        (None, None) => {
+            return Err(format!("{what} must be a key name or a list of them"));
+        }
+    };
+    names
+        .into_iter()
+        .map(|name| TerminalKey::from_name(name).ok_or_else(|| format!("unknown key {name}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, Command};
+
+    fn options(line: &str) -> Options {
+        match cli::parse(line.split_whitespace().map(String::from)) {
+            Ok(Command::Run(options)) => *options,
+            _ => panic!("{line} is not a run"),
+        }
+    }
+
+    #[test]
+    fn the_command_line_wins_over_the_file() {
+        let config = parse(
+            "[video]\nfilter = \"ntsc\"\npalette = \"/nes/smooth.pal\"\nsprite_limit = false\n\
+             [system]\nram_init = \"00\"\n",
+        )
+        .unwrap();
+        // what the command line leaves out comes from the file
+        let mut merged = options("game.nes");
+        config.merge_into(&mut merged);
+        assert_eq!(merged.filter, Some(VideoFilter::Ntsc));
+        assert_eq!(merged.palette, Some("/nes/smooth.pal".into()));
+        assert_eq!(merged.ram_init, Some(RamInit::Fill(0x00)));
+        assert!(merged.no_sprite_limit);
+
+        let mut merged = options("game.nes --filter rgb --palette mine.pal --ram-init alternating");
+        config.merge_into(&mut merged);
+        assert_eq!(merged.filter, Some(VideoFilter::Rgb));
+        assert_eq!(merged.palette, Some("mine.pal".into()));
+        assert_eq!(merged.ram_init, Some(RamInit::Alternating));
+
+        // an empty file changes nothing, and the sprite limit is only ever turned off
+        let mut merged = options("game.nes --no-sprite-limit");
+        parse("").unwrap().merge_into(&mut merged);
+        assert_eq!(
+            (merged.filter, merged.palette, merged.ram_init),
+            (None, None, None)
+        );
+        assert!(merged.no_sprite_limit);
+        let mut merged = options("game.nes");
+        parse("[video]\nsprite_limit = true\n")
+            .unwrap()
+            .merge_into(&mut merged);
+        assert!(!merged.no_sprite_limit);
+    }
+
+    #[test]
+    fn settings_of_the_wrong_kind_are_refused() {
+        let cases = [
+            (
+                "[video]\nscale = 0",
+                "video.scale must be a positive integer",
+            ),
+            ("[video]\nfilter = 3", "video.filter must be a string"),
+            ("[video]\nfilter = \"vhs\"", "unknown filter vhs"),
+            (
+                "[audio]\ntriangle = 1.5",
+                "audio.triangle must be from 0 to 1",
+            ),
+            ("[audio]\nmute = [\"cowbell\"]", "unknown channel cowbell"),
+            (
+                "[rewind]\nmemory = -1",
+                "rewind.memory must be a number of megabytes",
+            ),
+        ];
+        for (text, message) in cases {
+            assert_eq!(parse(text).unwrap_err(), message, "{text}");
+        }
+    }
+}
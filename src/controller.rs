@@ -0,0 +1,185 @@
+use crate::savestate::savestate_fields;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    One,
+    Two,
+    // on the four score behind ports 1 and 2
+    Three,
+    Four,
+}
+
+impl Player {
+    pub fn port(self) -> usize {
+        self as usize
+    }
+}
+
+// the discriminant is the bit of the button in the shift register, A is read out first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Button {
+    A = 0x01,
+    B = 0x02,
+    Select = 0x04,
+    Start = 0x08,
+    Up = 0x10,
+    Down = 0x20,
+    Left = 0x40,
+    Right = 0x80,
+}
+
+// everything that reaches the console in one frame, pads in the bit order of Button
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameInput {
+    // players 3 and 4 are only read with a four score plugged in
+    pub buttons: [u8; 4],
+    // pressed before the frame, power cycles are run as resets for now
+    pub reset: bool,
+    pub power: bool,
+}
+
+// presses a second of turbo buttons without a rate of their own
+pub const DEFAULT_TURBO_RATE: f64 = 15.0;
+
+// turbo buttons press and release themselves while they are held, counted in frames so movies and
+// netplay see the same pattern a game does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Autofire {
+    frame_rate: f64,
+    // frames each button stays pressed and then released, indexed by its bit
+    periods: [u32; 8],
+    frame: u32,
+}
+
+impl Autofire {
+    pub fn new(frame_rate: f64) -> Self {
+        Autofire {
+            frame_rate,
+            periods: [turbo_period(frame_rate, DEFAULT_TURBO_RATE); 8],
+            frame: 0,
+        }
+    }
+
+    // at most one press every other frame, the fastest a game polling once a frame can see
+    pub fn set_rate(&mut self, button: Button, rate: f64) {
+        self.periods[(button as u8).trailing_zeros() as usize] =
+            turbo_period(self.frame_rate, rate);
+    }
+
+    pub fn rate(&self, button: Button) -> f64 {
+        let period = self.periods[(button as u8).trailing_zeros() as usize];
+        self.frame_rate / (period * 2) as f64
+    }
+
+    // which of the turbo buttons held on each pad are down this frame, then moves to the next one
+    pub fn next_frame(&mut self, held: [u8; 4]) -> [u8; 4] {
+        let mut down = 0;
+        for (bit, period) in self.periods.iter().enumerate() {
+            if (self.frame / period).is_multiple_of(2) {
+                down |= 1 << bit;
+            }
+        }
+        self.frame = self.frame.wrapping_add(1);
+        held.map(|buttons| buttons & down)
+    }
+}
+
+fn turbo_period(frame_rate: f64, rate: f64) -> u32 {
+    (frame_rate / rate / 2.0).round().max(1.0) as u32
+}
+
+// where input comes from frame by frame: the keyboard, a movie, a netplay peer
+pub trait InputSource {
+    // None once the source has run out, a finished movie for example
+    fn next_frame(&mut self) -> Option<FrameInput>;
+}
+
+// standard joypad: a 4021 shift register that latches the buttons while the strobe is high
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Controller {
+    buttons: u8,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.buttons |= button as u8;
+        } else {
+            self.buttons &= !(button as u8);
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    // returns the next button state in bit 0
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let value = self.shift & 0x01;
+        // official pads report 1 once all eight buttons have been shifted out
+        self.shift = (self.shift >> 1) | 0x80;
+        value
+    }
+}
+
+savestate_fields!(Controller {
+    buttons,
+    shift,
+    strobe
+});
+
+// read out lowest bit first after the two pads of port 1 and port 2
+const FOUR_SCORE_SIGNATURES: [u8; 2] = [0x08, 0x04];
+
+// the four score adapter: each port shifts out its own pad, then the pad of player 3 or 4, then a
+// signature byte games check for, and 1 after that
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FourScore {
+    // host side like the zapper, the reads are console state
+    pub(crate) connected: bool,
+    strobe: bool,
+    // bits read from each port since the strobe went low
+    reads: [u8; 2],
+}
+
+impl FourScore {
+    pub(crate) fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.reads = [0; 2];
+        }
+    }
+
+    pub(crate) fn read(&mut self, port: usize, controllers: &mut [Controller; 4]) -> u8 {
+        if self.strobe {
+            return controllers[port].read();
+        }
+        let read = self.reads[port];
+        self.reads[port] = read.saturating_add(1);
+        match read {
+            0..8 => controllers[port].read(),
+            8..16 => controllers[port + 2].read(),
+            16..24 => FOUR_SCORE_SIGNATURES[port] >> (read - 16) & 0x01,
+            _ => 1,
+        }
+    }
+}
+
+savestate_fields!(FourScore { strobe, reads });
@@ -0,0 +1,2077 @@
+use crate::Emulator;
+use crate::savestate::savestate_fields;
+use num_enum::FromPrimitive;
+
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+pub struct Cpu {
+    pub flags: StatusFlags,
+    pub program_counter: u16,
+    // the stack lives at $0100-$01FF, SP wraps within it
+    pub stack_pointer: u8,
+    pub halted: bool,
+    pub reg_a: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    // the I flag as seen by the interrupt poll at the end of the last instruction
+    pub(crate) poll_interrupt_disable: bool,
+    pub(crate) page_crossed: bool,
+}
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(u8)]
+pub enum Opcode {
+    HLT = 0x02,
+
+    ADC_Immediate = 0x69,
+    ADC_ZeroPage = 0x65,
+    ADC_ZeroPageX = 0x75,
+    ADC_Absolute = 0x6D,
+    ADC_AbsoluteX = 0x7D,
+    ADC_AbsoluteY = 0x79,
+    ADC_IndirectX = 0x61,
+    ADC_IndirectY = 0x71,
+
+    AND_Immediate = 0x29,
+    AND_ZeroPage = 0x25,
+    AND_ZeroPageX = 0x35,
+    AND_Absolute = 0x2D,
+    AND_AbsoluteX = 0x3D,
+    AND_AbsoluteY = 0x39,
+    AND_IndirectX = 0x21,
+    AND_IndirectY = 0x31,
+
+    ASL_Accumulator = 0x0A,
+    ASL_ZeroPage = 0x06,
+    ASL_ZeroPageX = 0x16,
+    ASL_Absolute = 0x0E,
+    ASL_AbsoluteX = 0x1E,
+
+    BIT_ZeroPage = 0x24,
+    BIT_Absolute = 0x2C,
+
+    BPL = 0x10,
+    BMI = 0x30,
+    BVC = 0x50,
+    BVS = 0x70,
+    BCC = 0x90,
+    BCS = 0xB0,
+    BNE = 0xD0,
+    BEQ = 0xF0,
+
+    BRK = 0x00,
+
+    CLC = 0x18,
+    CLD = 0xD8,
+    CLI = 0x58,
+    CLV = 0xB8,
+    SEC = 0x38,
+    SED = 0xF8,
+    SEI = 0x78,
+
+    CMP_Immediate = 0xC9,
+    CMP_ZeroPage = 0xC5,
+    CMP_ZeroPageX = 0xD5,
+    CMP_Absolute = 0xCD,
+    CMP_AbsoluteX = 0xDD,
+    CMP_AbsoluteY = 0xD9,
+    CMP_IndirectX = 0xC1,
+    CMP_IndirectY = 0xD1,
+
+    CPX_Immediate = 0xE0,
+    CPX_ZeroPage = 0xE4,
+    CPX_Absolute = 0xEC,
+
+    CPY_Immediate = 0xC0,
+    CPY_ZeroPage = 0xC4,
+    CPY_Absolute = 0xCC,
+
+    DEC_ZeroPage = 0xC6,
+    DEC_ZeroPageX = 0xD6,
+    DEC_Absolute = 0xCE,
+    DEC_AbsoluteX = 0xDE,
+
+    DEX = 0xCA,
+    DEY = 0x88,
+
+    EOR_Immediate = 0x49,
+    EOR_ZeroPage = 0x45,
+    EOR_ZeroPageX = 0x55,
+    EOR_Absolute = 0x4D,
+    EOR_AbsoluteX = 0x5D,
+    EOR_AbsoluteY = 0x59,
+    EOR_IndirectX = 0x41,
+    EOR_IndirectY = 0x51,
+
+    INC_ZeroPage = 0xE6,
+    INC_ZeroPageX = 0xF6,
+    INC_Absolute = 0xEE,
+    INC_AbsoluteX = 0xFE,
+
+    INX = 0xE8,
+    INY = 0xC8,
+
+    JMP_Absolute = 0x4C,
+    JMP_Indirect = 0x6C,
+    JSR = 0x20,
+    RTS = 0x60,
+    RTI = 0x40,
+
+    LDA_Immediate = 0xA9,
+    LDA_ZeroPage = 0xA5,
+    LDA_ZeroPageX = 0xB5,
+    LDA_Absolute = 0xAD,
+    LDA_AbsoluteX = 0xBD,
+    LDA_AbsoluteY = 0xB9,
+    LDA_IndirectX = 0xA1,
+    LDA_IndirectY = 0xB1,
+
+    LDX_Immediate = 0xA2,
+    LDX_ZeroPage = 0xA6,
+    LDX_ZeroPageY = 0xB6,
+    LDX_Absolute = 0xAE,
+    LDX_AbsoluteY = 0xBE,
+
+    LDY_Immediate = 0xA0,
+    LDY_ZeroPage = 0xA4,
+    LDY_ZeroPageX = 0xB4,
+    LDY_Absolute = 0xAC,
+    LDY_AbsoluteX = 0xBC,
+
+    LSR_Accumulator = 0x4A,
+    LSR_ZeroPage = 0x46,
+    LSR_ZeroPageX = 0x56,
+    LSR_Absolute = 0x4E,
+    LSR_AbsoluteX = 0x5E,
+
+    NOP = 0xEA,
+
+    ORA_Immediate = 0x09,
+    ORA_ZeroPage = 0x05,
+    ORA_ZeroPageX = 0x15,
+    ORA_Absolute = 0x0D,
+    ORA_AbsoluteX = 0x1D,
+    ORA_AbsoluteY = 0x19,
+    ORA_IndirectX = 0x01,
+    ORA_IndirectY = 0x11,
+
+    PHA = 0x48,
+    PHP = 0x08,
+    PLA = 0x68,
+    PLP = 0x28,
+
+    ROL_Accumulator = 0x2A,
+    ROL_ZeroPage = 0x26,
+    ROL_ZeroPageX = 0x36,
+    ROL_Absolute = 0x2E,
+    ROL_AbsoluteX = 0x3E,
+
+    ROR_Accumulator = 0x6A,
+    ROR_ZeroPage = 0x66,
+    ROR_ZeroPageX = 0x76,
+    ROR_Absolute = 0x6E,
+    ROR_AbsoluteX = 0x7E,
+
+    SBC_Immediate = 0xE9,
+    SBC_ZeroPage = 0xE5,
+    SBC_ZeroPageX = 0xF5,
+    SBC_Absolute = 0xED,
+    SBC_AbsoluteX = 0xFD,
+    SBC_AbsoluteY = 0xF9,
+    SBC_IndirectX = 0xE1,
+    SBC_IndirectY = 0xF1,
+
+    STA_ZeroPage = 0x85,
+    STA_ZeroPageX = 0x95,
+    STA_Absolute = 0x8D,
+    STA_AbsoluteX = 0x9D,
+    STA_AbsoluteY = 0x99,
+    STA_IndirectX = 0x81,
+    STA_IndirectY = 0x91,
+
+    STX_ZeroPage = 0x86,
+    STX_ZeroPageY = 0x96,
+    STX_Absolute = 0x8E,
+
+    STY_ZeroPage = 0x84,
+    STY_ZeroPageX = 0x94,
+    STY_Absolute = 0x8C,
+
+    TAX = 0xAA,
+    TAY = 0xA8,
+    TSX = 0xBA,
+    TXA = 0x8A,
+    TXS = 0x9A,
+    TYA = 0x98,
+
+    // unofficial opcodes, every byte value decodes to something on the 2A03
+    SLO_ZeroPage = 0x07,
+    SLO_ZeroPageX = 0x17,
+    SLO_Absolute = 0x0F,
+    SLO_AbsoluteX = 0x1F,
+    SLO_AbsoluteY = 0x1B,
+    SLO_IndirectX = 0x03,
+    SLO_IndirectY = 0x13,
+
+    RLA_ZeroPage = 0x27,
+    RLA_ZeroPageX = 0x37,
+    RLA_Absolute = 0x2F,
+    RLA_AbsoluteX = 0x3F,
+    RLA_AbsoluteY = 0x3B,
+    RLA_IndirectX = 0x23,
+    RLA_IndirectY = 0x33,
+
+    SRE_ZeroPage = 0x47,
+    SRE_ZeroPageX = 0x57,
+    SRE_Absolute = 0x4F,
+    SRE_AbsoluteX = 0x5F,
+    SRE_AbsoluteY = 0x5B,
+    SRE_IndirectX = 0x43,
+    SRE_IndirectY = 0x53,
+
+    RRA_ZeroPage = 0x67,
+    RRA_ZeroPageX = 0x77,
+    RRA_Absolute = 0x6F,
+    RRA_AbsoluteX = 0x7F,
+    RRA_AbsoluteY = 0x7B,
+    RRA_IndirectX = 0x63,
+    RRA_IndirectY = 0x73,
+
+    DCP_ZeroPage = 0xC7,
+    DCP_ZeroPageX = 0xD7,
+    DCP_Absolute = 0xCF,
+    DCP_AbsoluteX = 0xDF,
+    DCP_AbsoluteY = 0xDB,
+    DCP_IndirectX = 0xC3,
+    DCP_IndirectY = 0xD3,
+
+    ISC_ZeroPage = 0xE7,
+    ISC_ZeroPageX = 0xF7,
+    ISC_Absolute = 0xEF,
+    ISC_AbsoluteX = 0xFF,
+    ISC_AbsoluteY = 0xFB,
+    ISC_IndirectX = 0xE3,
+    ISC_IndirectY = 0xF3,
+
+    SAX_ZeroPage = 0x87,
+    SAX_ZeroPageY = 0x97,
+    SAX_Absolute = 0x8F,
+    SAX_IndirectX = 0x83,
+
+    LAX_ZeroPage = 0xA7,
+    LAX_ZeroPageY = 0xB7,
+    LAX_Absolute = 0xAF,
+    LAX_AbsoluteY = 0xBF,
+    LAX_IndirectX = 0xA3,
+    LAX_IndirectY = 0xB3,
+    LAX_Immediate = 0xAB,
+
+    ANC_Immediate = 0x0B,
+    ANC_2B = 0x2B,
+    ALR_Immediate = 0x4B,
+    ARR_Immediate = 0x6B,
+    AXS_Immediate = 0xCB,
+    XAA_Immediate = 0x8B,
+    SBC_EB = 0xEB,
+
+    SHA_AbsoluteY = 0x9F,
+    SHA_IndirectY = 0x93,
+    SHX_AbsoluteY = 0x9E,
+    SHY_AbsoluteX = 0x9C,
+    TAS_AbsoluteY = 0x9B,
+    LAS_AbsoluteY = 0xBB,
+
+    NOP_1A = 0x1A,
+    NOP_3A = 0x3A,
+    NOP_5A = 0x5A,
+    NOP_7A = 0x7A,
+    NOP_DA = 0xDA,
+    NOP_FA = 0xFA,
+    NOP_80 = 0x80,
+    NOP_82 = 0x82,
+    NOP_89 = 0x89,
+    NOP_C2 = 0xC2,
+    NOP_E2 = 0xE2,
+    NOP_04 = 0x04,
+    NOP_44 = 0x44,
+    NOP_64 = 0x64,
+    NOP_14 = 0x14,
+    NOP_34 = 0x34,
+    NOP_54 = 0x54,
+    NOP_74 = 0x74,
+    NOP_D4 = 0xD4,
+    NOP_F4 = 0xF4,
+    NOP_0C = 0x0C,
+    NOP_1C = 0x1C,
+    NOP_3C = 0x3C,
+    NOP_5C = 0x5C,
+    NOP_7C = 0x7C,
+    NOP_DC = 0xDC,
+    NOP_FC = 0xFC,
+
+    HLT_12 = 0x12,
+    HLT_22 = 0x22,
+    HLT_32 = 0x32,
+    HLT_42 = 0x42,
+    HLT_52 = 0x52,
+    HLT_62 = 0x62,
+    HLT_72 = 0x72,
+    HLT_92 = 0x92,
+    HLT_B2 = 0xB2,
+    HLT_D2 = 0xD2,
+    HLT_F2 = 0xF2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    IndirectX,
+    IndirectY,
+}
+
+pub struct StatusFlags {
+    pub carry_flag: bool,
+    pub zero_flag: bool,
+    pub interrupt_disable_flag: bool,
+    pub decimal_flag: bool,
+    pub overflow_flag: bool,
+    pub negative_flag: bool,
+}
+
+impl StatusFlags {
+    // bit 5 always reads back as set, bit 4 (B) only exists on the stack copy
+    pub fn to_byte(&self, break_flag: bool) -> u8 {
+        (self.carry_flag as u8)
+            | (self.zero_flag as u8) << 1
+            | (self.interrupt_disable_flag as u8) << 2
+            | (self.decimal_flag as u8) << 3
+            | (break_flag as u8) << 4
+            | 1 << 5
+            | (self.overflow_flag as u8) << 6
+            | (self.negative_flag as u8) << 7
+    }
+
+    pub fn set_from_byte(&mut self, value: u8) {
+        self.carry_flag = value & 0x01 != 0;
+        self.zero_flag = value & 0x02 != 0;
+        self.interrupt_disable_flag = value & 0x04 != 0;
+        self.decimal_flag = value & 0x08 != 0;
+        self.overflow_flag = value & 0x40 != 0;
+        self.negative_flag = value & 0x80 != 0;
+    }
+
+    fn set_zero_negative(&mut self, value: u8) {
+        self.zero_flag = value == 0;
+        self.negative_flag = value >= 0x80;
+    }
+}
+
+impl Emulator {
+    fn stack_address(&self) -> u16 {
+        0x0100 | self.cpu.stack_pointer as u16
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(self.stack_address(), value);
+        self.cpu.stack_pointer = self.cpu.stack_pointer.wrapping_sub(1);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.cpu.stack_pointer = self.cpu.stack_pointer.wrapping_add(1);
+        self.read(self.stack_address())
+    }
+
+    // pulls spend a cycle reading the top of the stack before SP moves, and the reset sequence reads where it would push
+    pub(crate) fn stack_read(&mut self) {
+        self.read(self.stack_address());
+    }
+
+    // the cycle after the opcode fetch of a one byte instruction reads the next byte and throws it away
+    fn idle_read(&mut self) {
+        self.read(self.cpu.program_counter);
+    }
+
+    pub(crate) fn push_word(&mut self, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.push(high);
+        self.push(low);
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        let low = self.pull();
+        let high = self.pull();
+        u16::from_le_bytes([low, high])
+    }
+
+    // shared by BRK, IRQ and NMI, only BRK pushes the status with the B flag set.
+    // BRK has fetched its padding byte by now, IRQ and NMI read the next opcode twice without taking it
+    pub(crate) fn interrupt(&mut self, vector: u16, break_flag: bool) -> usize {
+        if !break_flag {
+            self.idle_read();
+            self.idle_read();
+        }
+        self.push_word(self.cpu.program_counter);
+        self.push(self.cpu.flags.to_byte(break_flag));
+        self.cpu.flags.interrupt_disable_flag = true;
+        self.cpu.poll_interrupt_disable = true;
+        // an nmi that arrives while a BRK or IRQ is pushing hijacks its vector fetch. the ppu's
+        // edge only reaches the lines between instructions otherwise, so look for it here
+        if self.ppu.take_nmi() {
+            self.interrupts.request_nmi();
+        }
+        let vector = if vector == IRQ_VECTOR && self.interrupts.take_nmi() {
+            NMI_VECTOR
+        } else {
+            vector
+        };
+        self.cpu.program_counter = self.read_word(vector);
+        7
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let value = self.read(self.cpu.program_counter);
+        self.cpu.program_counter = self.cpu.program_counter.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let low = self.fetch_byte();
+        let high = self.fetch_byte();
+        u16::from_le_bytes([low, high])
+    }
+
+    // consumes the operand bytes and returns the effective address
+    fn operand_address(&mut self, mode: AddressingMode) -> u16 {
+        use AddressingMode::*;
+        self.cpu.page_crossed = false;
+        match mode {
+            Immediate => {
+                let address = self.cpu.program_counter;
+                self.cpu.program_counter = self.cpu.program_counter.wrapping_add(1);
+                address
+            }
+            ZeroPage => self.fetch_byte() as u16,
+            // indexing in the zero page takes a cycle that reads the unindexed address
+            ZeroPageX => {
+                let base = self.fetch_byte();
+                self.read(base as u16);
+                base.wrapping_add(self.cpu.reg_x) as u16
+            }
+            ZeroPageY => {
+                let base = self.fetch_byte();
+                self.read(base as u16);
+                base.wrapping_add(self.cpu.reg_y) as u16
+            }
+            Absolute => self.fetch_word(),
+            AbsoluteX => {
+                let base = self.fetch_word();
+                self.indexed(base, self.cpu.reg_x)
+            }
+            AbsoluteY => {
+                let base = self.fetch_word();
+                self.indexed(base, self.cpu.reg_y)
+            }
+            IndirectX => {
+                // the pointer itself never leaves the zero page
+                let base = self.fetch_byte();
+                self.read(base as u16);
+                let pointer = base.wrapping_add(self.cpu.reg_x);
+                let low = self.read(pointer as u16);
+                let high = self.read(pointer.wrapping_add(1) as u16);
+                u16::from_le_bytes([low, high])
+            }
+            IndirectY => {
+                let pointer = self.fetch_byte();
+                let low = self.read(pointer as u16);
+                let high = self.read(pointer.wrapping_add(1) as u16);
+                self.indexed(u16::from_le_bytes([low, high]), self.cpu.reg_y)
+            }
+        }
+    }
+
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        self.cpu.page_crossed = (base & 0xFF00) != (address & 0xFF00);
+        address
+    }
+
+    fn read_operand(&mut self, mode: AddressingMode) -> u8 {
+        let address = self.operand_address(mode);
+        if self.cpu.page_crossed {
+            // the first read happens before the carry reaches the high byte, which costs a cycle
+            self.read(address.wrapping_sub(0x100));
+        }
+        self.read(address)
+    }
+
+    // stores and read-modify-writes can't skip the cycle that fixes the high byte, so
+    // indexed ones always read the address before the fix, whether or not the page changed
+    fn write_address(&mut self, mode: AddressingMode) -> u16 {
+        let address = self.operand_address(mode);
+        if matches!(
+            mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY
+        ) {
+            let unfixed = if self.cpu.page_crossed {
+                address.wrapping_sub(0x100)
+            } else {
+                address
+            };
+            self.read(unfixed);
+        }
+        address
+    }
+
+    // the unmodified value is written back while the operation runs, then the result
+    fn read_modify_write(&mut self, address: u16, operation: fn(&mut Self, u8) -> u8) -> u8 {
+        let value = self.read(address);
+        self.write(address, value);
+        let result = operation(self, value);
+        self.write(address, result);
+        result
+    }
+
+    // SHA, SHX, SHY and TAS store value & (H + 1), H being the high byte of the base address,
+    // and when indexing crosses a page the stored value also replaces the high byte of the target
+    fn store_high_and(&mut self, mode: AddressingMode, index: u8, value: u8) {
+        let address = self.write_address(mode);
+        let base_high = (address.wrapping_sub(index as u16) >> 8) as u8;
+        let result = value & base_high.wrapping_add(1);
+        let address = if self.cpu.page_crossed {
+            ((result as u16) << 8) | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.write(address, result);
+    }
+
+    fn branch(&mut self, condition: bool) -> usize {
+        let offset = self.fetch_byte() as i8;
+        if condition {
+            // a taken branch reads the next opcode while adding the offset, and the
+            // target before the carry into the high byte when it crosses a page
+            self.idle_read();
+            let target = self.cpu.program_counter.wrapping_add(offset as u16);
+            let page_crossed = (target & 0xFF00) != (self.cpu.program_counter & 0xFF00);
+            if page_crossed {
+                self.read((self.cpu.program_counter & 0xFF00) | (target & 0x00FF));
+            }
+            self.cpu.program_counter = target;
+            if page_crossed { 4 } else { 3 }
+        } else {
+            2
+        }
+    }
+
+    fn lda(&mut self, value: u8) {
+        self.cpu.reg_a = value;
+        self.cpu.flags.set_zero_negative(value);
+    }
+
+    fn ldx(&mut self, value: u8) {
+        self.cpu.reg_x = value;
+        self.cpu.flags.set_zero_negative(value);
+    }
+
+    fn ldy(&mut self, value: u8) {
+        self.cpu.reg_y = value;
+        self.cpu.flags.set_zero_negative(value);
+    }
+
+    fn adc(&mut self, value: u8) {
+        // the 2A03 has no decimal mode, the D flag is stored but ignored
+        let sum = self.cpu.reg_a as u16 + value as u16 + self.cpu.flags.carry_flag as u16;
+        let result = sum as u8;
+        self.cpu.flags.carry_flag = sum > 0xFF;
+        self.cpu.flags.overflow_flag = (self.cpu.reg_a ^ result) & (value ^ result) & 0x80 != 0;
+        self.lda(result);
+    }
+
+    fn sbc(&mut self, value: u8) {
+        self.adc(!value);
+    }
+
+    fn and(&mut self, value: u8) {
+        self.lda(self.cpu.reg_a & value);
+    }
+
+    fn ora(&mut self, value: u8) {
+        self.lda(self.cpu.reg_a | value);
+    }
+
+    fn eor(&mut self, value: u8) {
+        self.lda(self.cpu.reg_a ^ value);
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.cpu.flags.carry_flag = register >= value;
+        self.cpu
+            .flags
+            .set_zero_negative(register.wrapping_sub(value));
+    }
+
+    fn bit(&mut self, value: u8) {
+        self.cpu.flags.zero_flag = self.cpu.reg_a & value == 0;
+        self.cpu.flags.overflow_flag = value & 0x40 != 0;
+        self.cpu.flags.negative_flag = value & 0x80 != 0;
+    }
+
+    fn asl(&mut self, value: u8) -> u8 {
+        let result = value << 1;
+        self.cpu.flags.carry_flag = value & 0x80 != 0;
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    fn lsr(&mut self, value: u8) -> u8 {
+        let result = value >> 1;
+        self.cpu.flags.carry_flag = value & 0x01 != 0;
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    fn rol(&mut self, value: u8) -> u8 {
+        let result = (value << 1) | self.cpu.flags.carry_flag as u8;
+        self.cpu.flags.carry_flag = value & 0x80 != 0;
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    fn ror(&mut self, value: u8) -> u8 {
+        let result = (value >> 1) | (self.cpu.flags.carry_flag as u8) << 7;
+        self.cpu.flags.carry_flag = value & 0x01 != 0;
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    fn inc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    fn dec(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.cpu.flags.set_zero_negative(result);
+        result
+    }
+
+    pub(crate) fn emulate_cpu(&mut self) -> usize {
+        use AddressingMode::*;
+        use Opcode::*;
+        let opcode = Opcode::from(self.fetch_byte());
+        let interrupt_disable = self.cpu.flags.interrupt_disable_flag;
+        let cycles: usize;
+        match opcode {
+            // the unofficial KIL opcodes jam the cpu until the next reset
+            HLT | HLT_12 | HLT_22 | HLT_32 | HLT_42 | HLT_52 | HLT_62 | HLT_72 | HLT_92
+            | HLT_B2 | HLT_D2 | HLT_F2 => {
+                self.cpu.halted = true;
+                cycles = 1;
+            }
+
+            ADC_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.adc(value);
+                cycles = 2;
+            }
+            ADC_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.adc(value);
+                cycles = 3;
+            }
+            ADC_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.adc(value);
+                cycles = 4;
+            }
+            ADC_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.adc(value);
+                cycles = 4;
+            }
+            ADC_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.adc(value);
+                cycles = 4;
+            }
+            ADC_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.adc(value);
+                cycles = 4;
+            }
+            ADC_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.adc(value);
+                cycles = 6;
+            }
+            ADC_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.adc(value);
+                cycles = 5;
+            }
+
+            AND_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.and(value);
+                cycles = 2;
+            }
+            AND_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.and(value);
+                cycles = 3;
+            }
+            AND_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.and(value);
+                cycles = 4;
+            }
+            AND_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.and(value);
+                cycles = 4;
+            }
+            AND_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.and(value);
+                cycles = 4;
+            }
+            AND_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.and(value);
+                cycles = 4;
+            }
+            AND_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.and(value);
+                cycles = 6;
+            }
+            AND_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.and(value);
+                cycles = 5;
+            }
+
+            ASL_Accumulator => {
+                self.idle_read();
+                self.cpu.reg_a = self.asl(self.cpu.reg_a);
+                cycles = 2;
+            }
+            ASL_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::asl);
+                cycles = 5;
+            }
+            ASL_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::asl);
+                cycles = 6;
+            }
+            ASL_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::asl);
+                cycles = 6;
+            }
+            ASL_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::asl);
+                cycles = 7;
+            }
+
+            BIT_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.bit(value);
+                cycles = 3;
+            }
+            BIT_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.bit(value);
+                cycles = 4;
+            }
+
+            BPL => cycles = self.branch(!self.cpu.flags.negative_flag),
+            BMI => cycles = self.branch(self.cpu.flags.negative_flag),
+            BVC => cycles = self.branch(!self.cpu.flags.overflow_flag),
+            BVS => cycles = self.branch(self.cpu.flags.overflow_flag),
+            BCC => cycles = self.branch(!self.cpu.flags.carry_flag),
+            BCS => cycles = self.branch(self.cpu.flags.carry_flag),
+            BNE => cycles = self.branch(!self.cpu.flags.zero_flag),
+            BEQ => cycles = self.branch(self.cpu.flags.zero_flag),
+
+            BRK => {
+                // BRK skips a padding byte, so the return address is opcode + 2
+                self.fetch_byte();
+                cycles = self.interrupt(IRQ_VECTOR, true);
+            }
+
+            CLC => {
+                self.idle_read();
+                self.cpu.flags.carry_flag = false;
+                cycles = 2;
+            }
+            CLD => {
+                self.idle_read();
+                self.cpu.flags.decimal_flag = false;
+                cycles = 2;
+            }
+            CLI => {
+                self.idle_read();
+                self.cpu.flags.interrupt_disable_flag = false;
+                cycles = 2;
+            }
+            CLV => {
+                self.idle_read();
+                self.cpu.flags.overflow_flag = false;
+                cycles = 2;
+            }
+            SEC => {
+                self.idle_read();
+                self.cpu.flags.carry_flag = true;
+                cycles = 2;
+            }
+            SED => {
+                self.idle_read();
+                self.cpu.flags.decimal_flag = true;
+                cycles = 2;
+            }
+            SEI => {
+                self.idle_read();
+                self.cpu.flags.interrupt_disable_flag = true;
+                cycles = 2;
+            }
+
+            CMP_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 2;
+            }
+            CMP_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 3;
+            }
+            CMP_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 4;
+            }
+            CMP_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 4;
+            }
+            CMP_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 4;
+            }
+            CMP_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 4;
+            }
+            CMP_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 6;
+            }
+            CMP_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 5;
+            }
+
+            CPX_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.compare(self.cpu.reg_x, value);
+                cycles = 2;
+            }
+            CPX_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.compare(self.cpu.reg_x, value);
+                cycles = 3;
+            }
+            CPX_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.compare(self.cpu.reg_x, value);
+                cycles = 4;
+            }
+
+            CPY_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.compare(self.cpu.reg_y, value);
+                cycles = 2;
+            }
+            CPY_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.compare(self.cpu.reg_y, value);
+                cycles = 3;
+            }
+            CPY_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.compare(self.cpu.reg_y, value);
+                cycles = 4;
+            }
+
+            DEC_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::dec);
+                cycles = 5;
+            }
+            DEC_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::dec);
+                cycles = 6;
+            }
+            DEC_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::dec);
+                cycles = 6;
+            }
+            DEC_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::dec);
+                cycles = 7;
+            }
+
+            DEX => {
+                self.idle_read();
+                self.cpu.reg_x = self.dec(self.cpu.reg_x);
+                cycles = 2;
+            }
+            DEY => {
+                self.idle_read();
+                self.cpu.reg_y = self.dec(self.cpu.reg_y);
+                cycles = 2;
+            }
+
+            EOR_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.eor(value);
+                cycles = 2;
+            }
+            EOR_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.eor(value);
+                cycles = 3;
+            }
+            EOR_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.eor(value);
+                cycles = 4;
+            }
+            EOR_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.eor(value);
+                cycles = 4;
+            }
+            EOR_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.eor(value);
+                cycles = 4;
+            }
+            EOR_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.eor(value);
+                cycles = 4;
+            }
+            EOR_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.eor(value);
+                cycles = 6;
+            }
+            EOR_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.eor(value);
+                cycles = 5;
+            }
+
+            INC_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::inc);
+                cycles = 5;
+            }
+            INC_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::inc);
+                cycles = 6;
+            }
+            INC_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::inc);
+                cycles = 6;
+            }
+            INC_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::inc);
+                cycles = 7;
+            }
+
+            INX => {
+                self.idle_read();
+                self.cpu.reg_x = self.inc(self.cpu.reg_x);
+                cycles = 2;
+            }
+            INY => {
+                self.idle_read();
+                self.cpu.reg_y = self.inc(self.cpu.reg_y);
+                cycles = 2;
+            }
+
+            JMP_Absolute => {
+                self.cpu.program_counter = self.fetch_word();
+                cycles = 3;
+            }
+            JMP_Indirect => {
+                // hardware bug: the high byte is fetched without carrying into the pointer's page
+                let pointer = self.fetch_word();
+                let low = self.read(pointer);
+                let high = self.read((pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF));
+                self.cpu.program_counter = u16::from_le_bytes([low, high]);
+                cycles = 5;
+            }
+            JSR => {
+                // the high byte of the target is fetched last, so the pushed
+                // address points at the last byte of the instruction
+                let low = self.fetch_byte();
+                self.stack_read();
+                self.push_word(self.cpu.program_counter);
+                let high = self.fetch_byte();
+                self.cpu.program_counter = u16::from_le_bytes([low, high]);
+                cycles = 6;
+            }
+            RTS => {
+                self.idle_read();
+                self.stack_read();
+                self.cpu.program_counter = self.pull_word();
+                // the increment past the JSR takes a cycle of its own
+                self.idle_read();
+                self.cpu.program_counter = self.cpu.program_counter.wrapping_add(1);
+                cycles = 6;
+            }
+            RTI => {
+                self.idle_read();
+                self.stack_read();
+                let status = self.pull();
+                self.cpu.flags.set_from_byte(status);
+                self.cpu.program_counter = self.pull_word();
+                cycles = 6;
+            }
+
+            LDA_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.lda(value);
+                cycles = 2;
+            }
+            LDA_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.lda(value);
+                cycles = 3;
+            }
+            LDA_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.lda(value);
+                cycles = 4;
+            }
+            LDA_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.lda(value);
+                cycles = 4;
+            }
+            LDA_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.lda(value);
+                cycles = 4;
+            }
+            LDA_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.lda(value);
+                cycles = 4;
+            }
+            LDA_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.lda(value);
+                cycles = 6;
+            }
+            LDA_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.lda(value);
+                cycles = 5;
+            }
+
+            LDX_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.ldx(value);
+                cycles = 2;
+            }
+            LDX_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.ldx(value);
+                cycles = 3;
+            }
+            LDX_ZeroPageY => {
+                let value = self.read_operand(ZeroPageY);
+                self.ldx(value);
+                cycles = 4;
+            }
+            LDX_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.ldx(value);
+                cycles = 4;
+            }
+            LDX_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.ldx(value);
+                cycles = 4;
+            }
+
+            LDY_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.ldy(value);
+                cycles = 2;
+            }
+            LDY_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.ldy(value);
+                cycles = 3;
+            }
+            LDY_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.ldy(value);
+                cycles = 4;
+            }
+            LDY_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.ldy(value);
+                cycles = 4;
+            }
+            LDY_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.ldy(value);
+                cycles = 4;
+            }
+
+            LSR_Accumulator => {
+                self.idle_read();
+                self.cpu.reg_a = self.lsr(self.cpu.reg_a);
+                cycles = 2;
+            }
+            LSR_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::lsr);
+                cycles = 5;
+            }
+            LSR_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::lsr);
+                cycles = 6;
+            }
+            LSR_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::lsr);
+                cycles = 6;
+            }
+            LSR_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::lsr);
+                cycles = 7;
+            }
+
+            NOP => {
+                self.idle_read();
+                cycles = 2;
+            }
+
+            ORA_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.ora(value);
+                cycles = 2;
+            }
+            ORA_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.ora(value);
+                cycles = 3;
+            }
+            ORA_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.ora(value);
+                cycles = 4;
+            }
+            ORA_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.ora(value);
+                cycles = 4;
+            }
+            ORA_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.ora(value);
+                cycles = 4;
+            }
+            ORA_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.ora(value);
+                cycles = 4;
+            }
+            ORA_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.ora(value);
+                cycles = 6;
+            }
+            ORA_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.ora(value);
+                cycles = 5;
+            }
+
+            PHA => {
+                self.idle_read();
+                self.push(self.cpu.reg_a);
+                cycles = 3;
+            }
+            PHP => {
+                self.idle_read();
+                self.push(self.cpu.flags.to_byte(true));
+                cycles = 3;
+            }
+            PLA => {
+                self.idle_read();
+                self.stack_read();
+                let value = self.pull();
+                self.lda(value);
+                cycles = 4;
+            }
+            PLP => {
+                self.idle_read();
+                self.stack_read();
+                let status = self.pull();
+                self.cpu.flags.set_from_byte(status);
+                cycles = 4;
+            }
+
+            ROL_Accumulator => {
+                self.idle_read();
+                self.cpu.reg_a = self.rol(self.cpu.reg_a);
+                cycles = 2;
+            }
+            ROL_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::rol);
+                cycles = 5;
+            }
+            ROL_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::rol);
+                cycles = 6;
+            }
+            ROL_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::rol);
+                cycles = 6;
+            }
+            ROL_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::rol);
+                cycles = 7;
+            }
+
+            ROR_Accumulator => {
+                self.idle_read();
+                self.cpu.reg_a = self.ror(self.cpu.reg_a);
+                cycles = 2;
+            }
+            ROR_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.read_modify_write(address, Self::ror);
+                cycles = 5;
+            }
+            ROR_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.read_modify_write(address, Self::ror);
+                cycles = 6;
+            }
+            ROR_Absolute => {
+                let address = self.write_address(Absolute);
+                self.read_modify_write(address, Self::ror);
+                cycles = 6;
+            }
+            ROR_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.read_modify_write(address, Self::ror);
+                cycles = 7;
+            }
+
+            SBC_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.sbc(value);
+                cycles = 2;
+            }
+            SBC_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.sbc(value);
+                cycles = 3;
+            }
+            SBC_ZeroPageX => {
+                let value = self.read_operand(ZeroPageX);
+                self.sbc(value);
+                cycles = 4;
+            }
+            SBC_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.sbc(value);
+                cycles = 4;
+            }
+            SBC_AbsoluteX => {
+                let value = self.read_operand(AbsoluteX);
+                self.sbc(value);
+                cycles = 4;
+            }
+            SBC_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.sbc(value);
+                cycles = 4;
+            }
+            SBC_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.sbc(value);
+                cycles = 6;
+            }
+            SBC_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.sbc(value);
+                cycles = 5;
+            }
+
+            STA_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.write(address, self.cpu.reg_a);
+                cycles = 3;
+            }
+            STA_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.write(address, self.cpu.reg_a);
+                cycles = 4;
+            }
+            STA_Absolute => {
+                let address = self.write_address(Absolute);
+                self.write(address, self.cpu.reg_a);
+                cycles = 4;
+            }
+            STA_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                self.write(address, self.cpu.reg_a);
+                cycles = 5;
+            }
+            STA_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                self.write(address, self.cpu.reg_a);
+                cycles = 5;
+            }
+            STA_IndirectX => {
+                let address = self.write_address(IndirectX);
+                self.write(address, self.cpu.reg_a);
+                cycles = 6;
+            }
+            STA_IndirectY => {
+                let address = self.write_address(IndirectY);
+                self.write(address, self.cpu.reg_a);
+                cycles = 6;
+            }
+
+            STX_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.write(address, self.cpu.reg_x);
+                cycles = 3;
+            }
+            STX_ZeroPageY => {
+                let address = self.write_address(ZeroPageY);
+                self.write(address, self.cpu.reg_x);
+                cycles = 4;
+            }
+            STX_Absolute => {
+                let address = self.write_address(Absolute);
+                self.write(address, self.cpu.reg_x);
+                cycles = 4;
+            }
+
+            STY_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.write(address, self.cpu.reg_y);
+                cycles = 3;
+            }
+            STY_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                self.write(address, self.cpu.reg_y);
+                cycles = 4;
+            }
+            STY_Absolute => {
+                let address = self.write_address(Absolute);
+                self.write(address, self.cpu.reg_y);
+                cycles = 4;
+            }
+
+            TAX => {
+                self.idle_read();
+                self.ldx(self.cpu.reg_a);
+                cycles = 2;
+            }
+            TAY => {
+                self.idle_read();
+                self.ldy(self.cpu.reg_a);
+                cycles = 2;
+            }
+            TSX => {
+                self.idle_read();
+                self.ldx(self.cpu.stack_pointer);
+                cycles = 2;
+            }
+            TXA => {
+                self.idle_read();
+                self.lda(self.cpu.reg_x);
+                cycles = 2;
+            }
+            TXS => {
+                self.idle_read();
+                // the only transfer that leaves the flags alone
+                self.cpu.stack_pointer = self.cpu.reg_x;
+                cycles = 2;
+            }
+            TYA => {
+                self.idle_read();
+                self.lda(self.cpu.reg_y);
+                cycles = 2;
+            }
+
+            SLO_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 5;
+            }
+            SLO_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 6;
+            }
+            SLO_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 6;
+            }
+            SLO_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 7;
+            }
+            SLO_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 7;
+            }
+            SLO_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 8;
+            }
+            SLO_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::asl);
+                self.ora(value);
+                cycles = 8;
+            }
+            RLA_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 5;
+            }
+            RLA_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 6;
+            }
+            RLA_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 6;
+            }
+            RLA_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 7;
+            }
+            RLA_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 7;
+            }
+            RLA_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 8;
+            }
+            RLA_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::rol);
+                self.and(value);
+                cycles = 8;
+            }
+            SRE_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 5;
+            }
+            SRE_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 6;
+            }
+            SRE_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 6;
+            }
+            SRE_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 7;
+            }
+            SRE_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 7;
+            }
+            SRE_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 8;
+            }
+            SRE_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::lsr);
+                self.eor(value);
+                cycles = 8;
+            }
+            RRA_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 5;
+            }
+            RRA_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 6;
+            }
+            RRA_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 6;
+            }
+            RRA_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 7;
+            }
+            RRA_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 7;
+            }
+            RRA_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 8;
+            }
+            RRA_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::ror);
+                self.adc(value);
+                cycles = 8;
+            }
+            DCP_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 5;
+            }
+            DCP_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 6;
+            }
+            DCP_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 6;
+            }
+            DCP_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 7;
+            }
+            DCP_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 7;
+            }
+            DCP_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 8;
+            }
+            DCP_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::dec);
+                self.compare(self.cpu.reg_a, value);
+                cycles = 8;
+            }
+            ISC_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 5;
+            }
+            ISC_ZeroPageX => {
+                let address = self.write_address(ZeroPageX);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 6;
+            }
+            ISC_Absolute => {
+                let address = self.write_address(Absolute);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 6;
+            }
+            ISC_AbsoluteX => {
+                let address = self.write_address(AbsoluteX);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 7;
+            }
+            ISC_AbsoluteY => {
+                let address = self.write_address(AbsoluteY);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 7;
+            }
+            ISC_IndirectX => {
+                let address = self.write_address(IndirectX);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 8;
+            }
+            ISC_IndirectY => {
+                let address = self.write_address(IndirectY);
+                let value = self.read_modify_write(address, Self::inc);
+                self.sbc(value);
+                cycles = 8;
+            }
+            SAX_ZeroPage => {
+                let address = self.write_address(ZeroPage);
+                self.write(address, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 3;
+            }
+            SAX_ZeroPageY => {
+                let address = self.write_address(ZeroPageY);
+                self.write(address, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 4;
+            }
+            SAX_Absolute => {
+                let address = self.write_address(Absolute);
+                self.write(address, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 4;
+            }
+            SAX_IndirectX => {
+                let address = self.write_address(IndirectX);
+                self.write(address, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 6;
+            }
+            LAX_ZeroPage => {
+                let value = self.read_operand(ZeroPage);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 3;
+            }
+            LAX_ZeroPageY => {
+                let value = self.read_operand(ZeroPageY);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 4;
+            }
+            LAX_Absolute => {
+                let value = self.read_operand(Absolute);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 4;
+            }
+            LAX_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 4;
+            }
+            LAX_IndirectX => {
+                let value = self.read_operand(IndirectX);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 6;
+            }
+            LAX_IndirectY => {
+                let value = self.read_operand(IndirectY);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 5;
+            }
+            LAX_Immediate => {
+                // unstable on hardware, the value ORed into A before the AND differs between chips.
+                // this assumes $EE like XAA
+                let value = (self.cpu.reg_a | 0xEE) & self.read_operand(Immediate);
+                self.lda(value);
+                self.cpu.reg_x = value;
+                cycles = 2;
+            }
+            ANC_Immediate | ANC_2B => {
+                let value = self.read_operand(Immediate);
+                self.and(value);
+                self.cpu.flags.carry_flag = self.cpu.flags.negative_flag;
+                cycles = 2;
+            }
+            ALR_Immediate => {
+                let value = self.read_operand(Immediate);
+                self.cpu.reg_a = self.lsr(self.cpu.reg_a & value);
+                cycles = 2;
+            }
+            ARR_Immediate => {
+                let value = self.read_operand(Immediate);
+                let result = self.ror(self.cpu.reg_a & value);
+                self.cpu.reg_a = result;
+                self.cpu.flags.carry_flag = result & 0x40 != 0;
+                self.cpu.flags.overflow_flag = ((result >> 6) ^ (result >> 5)) & 0x01 != 0;
+                cycles = 2;
+            }
+            AXS_Immediate => {
+                let value = self.read_operand(Immediate);
+                let register = self.cpu.reg_a & self.cpu.reg_x;
+                self.compare(register, value);
+                self.cpu.reg_x = register.wrapping_sub(value);
+                cycles = 2;
+            }
+            XAA_Immediate => {
+                // unstable like LAX #imm, this assumes the common $EE magic constant
+                let value = self.read_operand(Immediate);
+                self.lda((self.cpu.reg_a | 0xEE) & self.cpu.reg_x & value);
+                cycles = 2;
+            }
+            SBC_EB => {
+                let value = self.read_operand(Immediate);
+                self.sbc(value);
+                cycles = 2;
+            }
+            SHA_AbsoluteY => {
+                self.store_high_and(AbsoluteY, self.cpu.reg_y, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 5;
+            }
+            SHA_IndirectY => {
+                self.store_high_and(IndirectY, self.cpu.reg_y, self.cpu.reg_a & self.cpu.reg_x);
+                cycles = 6;
+            }
+            SHX_AbsoluteY => {
+                self.store_high_and(AbsoluteY, self.cpu.reg_y, self.cpu.reg_x);
+                cycles = 5;
+            }
+            SHY_AbsoluteX => {
+                self.store_high_and(AbsoluteX, self.cpu.reg_x, self.cpu.reg_y);
+                cycles = 5;
+            }
+            TAS_AbsoluteY => {
+                self.cpu.stack_pointer = self.cpu.reg_a & self.cpu.reg_x;
+                self.store_high_and(AbsoluteY, self.cpu.reg_y, self.cpu.stack_pointer);
+                cycles = 5;
+            }
+            LAS_AbsoluteY => {
+                let value = self.read_operand(AbsoluteY) & self.cpu.stack_pointer;
+                self.lda(value);
+                self.cpu.reg_x = value;
+                self.cpu.stack_pointer = value;
+                cycles = 4;
+            }
+            NOP_1A | NOP_3A | NOP_5A | NOP_7A | NOP_DA | NOP_FA => {
+                self.idle_read();
+                cycles = 2;
+            }
+            // nops with an operand still perform the read, side effects on registers included
+            NOP_80 | NOP_82 | NOP_89 | NOP_C2 | NOP_E2 => {
+                self.read_operand(Immediate);
+                cycles = 2;
+            }
+            NOP_04 | NOP_44 | NOP_64 => {
+                self.read_operand(ZeroPage);
+                cycles = 3;
+            }
+            NOP_14 | NOP_34 | NOP_54 | NOP_74 | NOP_D4 | NOP_F4 => {
+                self.read_operand(ZeroPageX);
+                cycles = 4;
+            }
+            NOP_0C => {
+                self.read_operand(Absolute);
+                cycles = 4;
+            }
+            NOP_1C | NOP_3C | NOP_5C | NOP_7C | NOP_DC | NOP_FC => {
+                self.read_operand(AbsoluteX);
+                cycles = 4;
+            }
+        }
+        // CLI, SEI and PLP change the I flag after the interrupt poll of their last cycle
+        self.cpu.poll_interrupt_disable = match opcode {
+            CLI | SEI | PLP => interrupt_disable,
+            _ => self.cpu.flags.interrupt_disable_flag,
+        };
+        cycles
+    }
+}
+
+savestate_fields!(StatusFlags {
+    carry_flag,
+    zero_flag,
+    interrupt_disable_flag,
+    decimal_flag,
+    overflow_flag,
+    negative_flag,
+});
+savestate_fields!(Cpu {
+    flags,
+    program_counter,
+    stack_pointer,
+    halted,
+    reg_a,
+    reg_x,
+    reg_y,
+    poll_interrupt_disable,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::IrqSource;
+    use crate::cartridge::Cartridge;
+
+    const NMI_HANDLER: u16 = 0xD000;
+    const IRQ_HANDLER: u16 = 0xE000;
+
+    // an nrom console about to run program from $C000, with nops everywhere else
+    fn console(program: &[u8]) -> Emulator {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0];
+        rom.resize(16, 0);
+        let mut prg = program.to_vec();
+        prg.resize(0x4000, 0xEA);
+        let [nmi_low, nmi_high] = NMI_HANDLER.to_le_bytes();
+        let [irq_low, irq_high] = IRQ_HANDLER.to_le_bytes();
+        prg[0x3FFA..].copy_from_slice(&[nmi_low, nmi_high, 0x00, 0xC0, irq_low, irq_high]);
+        rom.extend(prg);
+        Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap()
+    }
+
+    // the line is set again after every instruction from its sources, so hold it for this one
+    fn step_with_irq(emulator: &mut Emulator) -> usize {
+        emulator.interrupts.set_irq(IrqSource::Mapper, true);
+        emulator.step_instruction()
+    }
+
+    // the byte pushed offset bytes below the top of the stack
+    fn pushed(emulator: &Emulator, offset: u8) -> u8 {
+        emulator.ram[0x100 | emulator.cpu.stack_pointer.wrapping_add(offset) as usize]
+    }
+
+    #[test]
+    fn adc_and_sbc_set_carry_and_overflow() {
+        let mut emulator = console(&[]);
+        // a, operand, carry in, result, carry out, overflow
+        let sums = [
+            (0x50, 0x10, false, 0x60, false, false),
+            (0x50, 0x50, false, 0xA0, false, true),
+            (0x50, 0xD0, false, 0x20, true, false),
+            (0xD0, 0x90, false, 0x60, true, true),
+            (0xFF, 0x00, true, 0x00, true, false),
+        ];
+        for (a, operand, carry, result, carry_out, overflow) in sums {
+            emulator.cpu.reg_a = a;
+            emulator.cpu.flags.carry_flag = carry;
+            emulator.adc(operand);
+            let flags = &emulator.cpu.flags;
+            assert_eq!(emulator.cpu.reg_a, result, "{a:02X} + {operand:02X}");
+            assert_eq!(
+                flags.carry_flag, carry_out,
+                "carry of {a:02X} + {operand:02X}"
+            );
+            assert_eq!(
+                flags.overflow_flag, overflow,
+                "overflow of {a:02X} + {operand:02X}"
+            );
+            assert_eq!(flags.zero_flag, result == 0);
+            assert_eq!(flags.negative_flag, result >= 0x80);
+        }
+        // the carry in is the inverse of a borrow
+        let differences = [
+            (0x50, 0xF0, true, 0x60, false, false),
+            (0x50, 0xB0, true, 0xA0, false, true),
+            (0xD0, 0x70, true, 0x60, true, true),
+            (0x00, 0x01, true, 0xFF, false, false),
+            (0x05, 0x04, false, 0x00, true, false),
+        ];
+        for (a, operand, carry, result, carry_out, overflow) in differences {
+            emulator.cpu.reg_a = a;
+            emulator.cpu.flags.carry_flag = carry;
+            emulator.sbc(operand);
+            let flags = &emulator.cpu.flags;
+            assert_eq!(emulator.cpu.reg_a, result, "{a:02X} - {operand:02X}");
+            assert_eq!(
+                flags.carry_flag, carry_out,
+                "carry of {a:02X} - {operand:02X}"
+            );
+            assert_eq!(
+                flags.overflow_flag, overflow,
+                "overflow of {a:02X} - {operand:02X}"
+            );
+            assert_eq!(flags.zero_flag, result == 0);
+            assert_eq!(flags.negative_flag, result >= 0x80);
+        }
+    }
+
+    #[test]
+    fn crossing_a_page_costs_reads_a_cycle_and_stores_nothing_more() {
+        let mut program = vec![
+            0xA2, 0x01, // LDX #$01
+            0xBD, 0xFF, 0x02, // LDA $02FF,X
+            0xBD, 0x00, 0x02, // LDA $0200,X
+            0x9D, 0xFF, 0x02, // STA $02FF,X
+            0x9D, 0x00, 0x02, // STA $0200,X
+            0xA0, 0x00, // LDY #$00
+            0xD0, 0x00, // BNE, not taken
+            0xF0, 0x00, // BEQ, taken to the next instruction
+            0x4C, 0xF0, 0xC0, // JMP $C0F0
+        ];
+        program.resize(0xF0, 0xEA);
+        // BEQ from $C0F2 to $C112
+        program.extend([0xF0, 0x20]);
+        let mut emulator = console(&program);
+        let cycles: Vec<usize> = (0..10).map(|_| emulator.step_instruction()).collect();
+        assert_eq!(cycles, [2, 5, 4, 5, 5, 2, 2, 3, 3, 4]);
+        assert_eq!(emulator.cpu.program_counter, 0xC112);
+    }
+
+    #[test]
+    fn brk_pushes_the_b_flag_and_an_nmi_during_it_takes_the_vector() {
+        let mut emulator = console(&[0x00, 0x00]);
+        assert_eq!(emulator.step_instruction(), 7);
+        assert_eq!(emulator.cpu.program_counter, IRQ_HANDLER);
+        // the return address skips the padding byte
+        assert_eq!(pushed(&emulator, 1) & 0x30, 0x30);
+        assert_eq!([pushed(&emulator, 2), pushed(&emulator, 3)], [0x02, 0xC0]);
+
+        // nmis on, then nops and jumps until vblank begins a few cycles into a BRK at $C010
+        let mut program = vec![0xA9, 0x80, 0x8D, 0x00, 0x20, 0xEA, 0x4C, 0x05, 0xC0];
+        program.resize(0x10, 0xEA);
+        program.extend([0x00, 0x00]);
+        let mut emulator = console(&program);
+        let dots_to_vblank = |emulator: &Emulator| {
+            let (line, dot) = (emulator.ppu.scanline() as i64, emulator.ppu.dot() as i64);
+            (241 - line) * 341 + 1 - dot
+        };
+        while dots_to_vblank(&emulator) > 12 {
+            emulator.step_instruction();
+        }
+        emulator.cpu.program_counter = 0xC010;
+        assert_eq!(emulator.step_instruction(), 7);
+        assert_eq!(emulator.cpu.program_counter, NMI_HANDLER);
+        assert_eq!(pushed(&emulator, 1) & 0x30, 0x30);
+        // the nmi was taken by the BRK and does not come again
+        emulator.step_instruction();
+        assert_eq!(emulator.cpu.program_counter, NMI_HANDLER + 1);
+    }
+
+    #[test]
+    fn cli_sei_and_plp_change_the_i_flag_after_the_interrupt_poll() {
+        // CLI lets an irq in only after the instruction that follows it
+        let mut emulator = console(&[0x58, 0xEA, 0xEA]);
+        step_with_irq(&mut emulator);
+        step_with_irq(&mut emulator);
+        assert_eq!(emulator.cpu.program_counter, 0xC002);
+        assert_eq!(step_with_irq(&mut emulator), 7);
+        assert_eq!(emulator.cpu.program_counter, IRQ_HANDLER);
+
+        // an irq right after SEI is still taken, with I set in the pushed status
+        let mut emulator = console(&[0x58, 0xEA, 0x78, 0xEA]);
+        for _ in 0..3 {
+            emulator.step_instruction();
+        }
+        assert!(emulator.cpu.flags.interrupt_disable_flag);
+        step_with_irq(&mut emulator);
+        assert_eq!(emulator.cpu.program_counter, IRQ_HANDLER);
+        assert_eq!(pushed(&emulator, 1) & 0x34, 0x24);
+        assert_eq!([pushed(&emulator, 2), pushed(&emulator, 3)], [0x03, 0xC0]);
+
+        // PLP clearing I waits an instruction like CLI
+        let mut emulator = console(&[0xA9, 0x00, 0x48, 0x28, 0xEA, 0xEA]);
+        for _ in 0..3 {
+            emulator.step_instruction();
+        }
+        assert!(!emulator.cpu.flags.interrupt_disable_flag);
+        step_with_irq(&mut emulator);
+        assert_eq!(emulator.cpu.program_counter, 0xC005);
+        step_with_irq(&mut emulator);
+        assert_eq!(emulator.cpu.program_counter, IRQ_HANDLER);
+
+        // RTI restores I at once
+        let mut emulator = console(&[0xA9, 0xC0, 0x48, 0xA9, 0x10, 0x48, 0xA9, 0x00, 0x48, 0x40]);
+        for _ in 0..7 {
+            emulator.step_instruction();
+        }
+        assert_eq!(emulator.cpu.program_counter, 0xC010);
+        step_with_irq(&mut emulator);
+        assert_eq!(emulator.cpu.program_counter, IRQ_HANDLER);
+    }
+
+    #[test]
+    fn lax_immediate_ands_the_operand_with_a_or_ee() {
+        let mut emulator = console(&[0xA9, 0x00, 0xAB, 0xFF]);
+        emulator.step_instruction();
+        emulator.step_instruction();
+        assert_eq!((emulator.cpu.reg_a, emulator.cpu.reg_x), (0xEE, 0xEE));
+    }
+}
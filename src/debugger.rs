@@ -0,0 +1,182 @@
+use crate::Emulator;
+use crate::cpu::Cpu;
+use crate::hooks::HookId;
+use crate::script::ScriptApi;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // the cpu is about to execute the instruction at this address
+    Breakpoint(u16),
+    Watchpoint {
+        access: Access,
+        address: u16,
+        value: u8,
+    },
+    FrameComplete,
+    Halted,
+}
+
+// breakpoint and watchpoint sets, breakpoints are checked after each instruction and watchpoints by a
+// memory hook on each of their addresses
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    read_watchpoints: BTreeSet<u16>,
+    write_watchpoints: BTreeSet<u16>,
+    // the first watchpoint hit by the instruction being executed
+    hit: Option<StopReason>,
+    // the hooks of the watchpoints, made again when they change
+    hooks: Vec<HookId>,
+    changed: bool,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, access: Access, address: u16) {
+        self.changed |= self.watchpoints_mut(access).insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, access: Access, address: u16) -> bool {
+        let removed = self.watchpoints_mut(access).remove(&address);
+        self.changed |= removed;
+        removed
+    }
+
+    pub fn watchpoints(&self, access: Access) -> impl Iterator<Item = u16> + '_ {
+        match access {
+            Access::Read => self.read_watchpoints.iter().copied(),
+            Access::Write => self.write_watchpoints.iter().copied(),
+        }
+    }
+
+    fn watchpoints_mut(&mut self, access: Access) -> &mut BTreeSet<u16> {
+        match access {
+            Access::Read => &mut self.read_watchpoints,
+            Access::Write => &mut self.write_watchpoints,
+        }
+    }
+
+    fn watchpoint_hit(&mut self, access: Access, address: u16, value: u8) {
+        if self.hit.is_none() {
+            self.hit = Some(StopReason::Watchpoint {
+                access,
+                address,
+                value,
+            });
+        }
+    }
+}
+
+impl Emulator {
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    // reads memory without the side effects of a cpu read, io registers show the open bus
+    pub fn peek(&mut self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize],
+            0x4020..=0xFFFF => self.mapper.prg_read(address).unwrap_or(self.open_bus),
+            _ => self.open_bus,
+        }
+    }
+
+    // writes memory like the cpu would, registers included, without taking a cycle
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.write_untimed(address, value);
+    }
+
+    // runs one instruction and reports a watchpoint it hit, or a breakpoint at the next one
+    pub fn debug_step(&mut self) -> Option<StopReason> {
+        if self.cpu.halted {
+            return Some(StopReason::Halted);
+        }
+        if self.debugger.changed {
+            self.hook_watchpoints();
+        }
+        self.debugger.hit = None;
+        self.step_instruction();
+        if let Some(reason) = self.debugger.hit.take() {
+            return Some(reason);
+        }
+        let pc = self.cpu.program_counter;
+        self.debugger
+            .breakpoints
+            .contains(&pc)
+            .then_some(StopReason::Breakpoint(pc))
+    }
+
+    // resumes until a breakpoint, a watchpoint or a HLT, always executing at least one instruction
+    pub fn debug_continue(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.debug_step() {
+                return reason;
+            }
+        }
+    }
+
+    // like step_frame, but stops early for breakpoints and watchpoints. the next call carries on
+    // with a frame that was stopped partway
+    pub fn debug_frame(&mut self) -> StopReason {
+        self.begin_frame();
+        loop {
+            if let Some(reason) = self.debug_step() {
+                if reason == StopReason::Halted {
+                    self.end_frame();
+                }
+                return reason;
+            }
+            if self.ppu.frame_complete {
+                self.end_frame();
+                return StopReason::FrameComplete;
+            }
+        }
+    }
+
+    // one access hook per watched address
+    fn hook_watchpoints(&mut self) {
+        for id in std::mem::take(&mut self.debugger.hooks) {
+            self.remove_hook(id);
+        }
+        for access in [Access::Read, Access::Write] {
+            let addresses: Vec<u16> = self.debugger.watchpoints(access).collect();
+            for address in addresses {
+                let hit = move |api: &mut ScriptApi, address, value| {
+                    api.emulator.debugger.watchpoint_hit(access, address, value)
+                };
+                let id = match access {
+                    Access::Read => self.on_memory_read(address..=address, hit),
+                    Access::Write => self.on_memory_write(address..=address, hit),
+                };
+                self.debugger.hooks.push(id);
+            }
+        }
+        self.debugger.changed = false;
+    }
+}
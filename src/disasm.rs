@@ -0,0 +1,644 @@
+use crate::Emulator;
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Operand {
+    // bytes taken by the operand, not counting the opcode
+    pub fn size(self) -> usize {
+        use Operand::*;
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+        }
+    }
+}
+
+use Operand::*;
+
+// mnemonic, operand and whether the opcode is an official one, indexed by opcode byte
+const INSTRUCTIONS: [(&str, Operand, bool); 256] = [
+    ("BRK", Implied, true),     // $00
+    ("ORA", IndirectX, true),   // $01
+    ("HLT", Implied, false),    // $02
+    ("SLO", IndirectX, false),  // $03
+    ("NOP", ZeroPage, false),   // $04
+    ("ORA", ZeroPage, true),    // $05
+    ("ASL", ZeroPage, true),    // $06
+    ("SLO", ZeroPage, false),   // $07
+    ("PHP", Implied, true),     // $08
+    ("ORA", Immediate, true),   // $09
+    ("ASL", Accumulator, true), // $0A
+    ("ANC", Immediate, false),  // $0B
+    ("NOP", Absolute, false),   // $0C
+    ("ORA", Absolute, true),    // $0D
+    ("ASL", Absolute, true),    // $0E
+    ("SLO", Absolute, false),   // $0F
+    ("BPL", Relative, true),    // $10
+    ("ORA", IndirectY, true),   // $11
+    ("HLT", Implied, false),    // $12
+    ("SLO", IndirectY, false),  // $13
+    ("NOP", ZeroPageX, false),  // $14
+    ("ORA", ZeroPageX, true),   // $15
+    ("ASL", ZeroPageX, true),   // $16
+    ("SLO", ZeroPageX, false),  // $17
+    ("CLC", Implied, true),     // $18
+    ("ORA", AbsoluteY, true),   // $19
+    ("NOP", Implied, false),    // $1A
+    ("SLO", AbsoluteY, false),  // $1B
+    ("NOP", AbsoluteX, false),  // $1C
+    ("ORA", AbsoluteX, true),   // $1D
+    ("ASL", AbsoluteX, true),   // $1E
+    ("SLO", AbsoluteX, false),  // $1F
+    ("JSR", Absolute, true),    // $20
+    ("AND", IndirectX, true),   // $21
+    ("HLT", Implied, false),    // $22
+    ("RLA", IndirectX, false),  // $23
+    ("BIT", ZeroPage, true),    // $24
+    ("AND", ZeroPage, true),    // $25
+    ("ROL", ZeroPage, true),    // $26
+    ("RLA", ZeroPage, false),   // $27
+    ("PLP", Implied, true),     // $28
+    ("AND", Immediate, true),   // $29
+    ("ROL", Accumulator, true), // $2A
+    ("ANC", Immediate, false),  // $2B
+    ("BIT", Absolute, true),    // $2C
+    ("AND", Absolute, true),    // $2D
+    ("ROL", Absolute, true),    // $2E
+    ("RLA", Absolute, false),   // $2F
+    ("BMI", Relative, true),    // $30
+    ("AND", IndirectY, true),   // $31
+    ("HLT", Implied, false),    // $32
+    ("RLA", IndirectY, false),  // $33
+    ("NOP", ZeroPageX, false),  // $34
+    ("AND", ZeroPageX, true),   // $35
+    ("ROL", ZeroPageX, true),   // $36
+    ("RLA", ZeroPageX, false),  // $37
+    ("SEC", Implied, true),     // $38
+    ("AND", AbsoluteY, true),   // $39
+    ("NOP", Implied, false),    // $3A
+    ("RLA", AbsoluteY, false),  // $3B
+    ("NOP", AbsoluteX, false),  // $3C
+    ("AND", AbsoluteX, true),   // $3D
+    ("ROL", AbsoluteX, true),   // $3E
+    ("RLA", AbsoluteX, false),  // $3F
+    ("RTI", Implied, true),     // $40
+    ("EOR", IndirectX, true),   // $41
+    ("HLT", Implied, false),    // $42
+    ("SRE", IndirectX, false),  // $43
+    ("NOP", ZeroPage, false),   // $44
+    ("EOR", ZeroPage, true),    // $45
+    ("LSR", ZeroPage, true),    // $46
+    ("SRE", ZeroPage, false),   // $47
+    ("PHA", Implied, true),     // $48
+    ("EOR", Immediate, true),   // $49
+    ("LSR", Accumulator, true), // $4A
+    ("ALR", Immediate, false),  // $4B
+    ("JMP", Absolute, true),    // $4C
+    ("EOR", Absolute, true),    // $4D
+    ("LSR", Absolute, true),    // $4E
+    ("SRE", Absolute, false),   // $4F
+    ("BVC", Relative, true),    // $50
+    ("EOR", IndirectY, true),   // $51
+    ("HLT", Implied, false),    // $52
+    ("SRE", IndirectY, false),  // $53
+    ("NOP", ZeroPageX, false),  // $54
+    ("EOR", ZeroPageX, true),   // $55
+    ("LSR", ZeroPageX, true),   // $56
+    ("SRE", ZeroPageX, false),  // $57
+    ("CLI", Implied, true),     // $58
+    ("EOR", AbsoluteY, true),   // $59
+    ("NOP", Implied, false),    // $5A
+    ("SRE", AbsoluteY, false),  // $5B
+    ("NOP", AbsoluteX, false),  // $5C
+    ("EOR", AbsoluteX, true),   // $5D
+    ("LSR", AbsoluteX, true),   // $5E
+    ("SRE", AbsoluteX, false),  // $5F
+    ("RTS", Implied, true),     // $60
+    ("ADC", IndirectX, true),   // $61
+    ("HLT", Implied, false),    // $62
+    ("RRA", IndirectX, false),  // $63
+    ("NOP", ZeroPage, false),   // $64
+    ("ADC", ZeroPage, true),    // $65
+    ("ROR", ZeroPage, true),    // $66
+    ("RRA", ZeroPage, false),   // $67
+    ("PLA", Implied, true),     // $68
+    ("ADC", Immediate, true),   // $69
+    ("ROR", Accumulator, true), // $6A
+    ("ARR", Immediate, false),  // $6B
+    ("JMP", Indirect, true),    // $6C
+    ("ADC", Absolute, true),    // $6D
+    ("ROR", Absolute, true),    // $6E
+    ("RRA", Absolute, false),   // $6F
+    ("BVS", Relative, true),    // $70
+    ("ADC", IndirectY, true),   // $71
+    ("HLT", Implied, false),    // $72
+    ("RRA", IndirectY, false),  // $73
+    ("NOP", ZeroPageX, false),  // $74
+    ("ADC", ZeroPageX, true),   // $75
+    ("ROR", ZeroPageX, true),   // $76
+    ("RRA", ZeroPageX, false),  // $77
+    ("SEI", Implied, true),     // $78
+    ("ADC", AbsoluteY, true),   // $79
+    ("NOP", Implied, false),    // $7A
+    ("RRA", AbsoluteY, false),  // $7B
+    ("NOP", AbsoluteX, false),  // $7C
+    ("ADC", AbsoluteX, true),   // $7D
+    ("ROR", AbsoluteX, true),   // $7E
+    ("RRA", AbsoluteX, false),  // $7F
+    ("NOP", Immediate, false),  // $80
+    ("STA", IndirectX, true),   // $81
+    ("NOP", Immediate, false),  // $82
+    ("SAX", IndirectX, false),  // $83
+    ("STY", ZeroPage, true),    // $84
+    ("STA", ZeroPage, true),    // $85
+    ("STX", ZeroPage, true),    // $86
+    ("SAX", ZeroPage, false),   // $87
+    ("DEY", Implied, true),     // $88
+    ("NOP", Immediate, false),  // $89
+    ("TXA", Implied, true),     // $8A
+    ("XAA", Immediate, false),  // $8B
+    ("STY", Absolute, true),    // $8C
+    ("STA", Absolute, true),    // $8D
+    ("STX", Absolute, true),    // $8E
+    ("SAX", Absolute, false),   // $8F
+    ("BCC", Relative, true),    // $90
+    ("STA", IndirectY, true),   // $91
+    ("HLT", Implied, false),    // $92
+    ("SHA", IndirectY, false),  // $93
+    ("STY", ZeroPageX, true),   // $94
+    ("STA", ZeroPageX, true),   // $95
+    ("STX", ZeroPageY, true),   // $96
+    ("SAX", ZeroPageY, false),  // $97
+    ("TYA", Implied, true),     // $98
+    ("STA", AbsoluteY, true),   // $99
+    ("TXS", Implied, true),     // $9A
+    ("TAS", AbsoluteY, false),  // $9B
+    ("SHY", AbsoluteX, false),  // $9C
+    ("STA", AbsoluteX, true),   // $9D
+    ("SHX", AbsoluteY, false),  // $9E
+    ("SHA", AbsoluteY, false),  // $9F
+    ("LDY", Immediate, true),   // $A0
+    ("LDA", IndirectX, true),   // $A1
+    ("LDX", Immediate, true),   // $A2
+    ("LAX", IndirectX, false),  // $A3
+    ("LDY", ZeroPage, true),    // $A4
+    ("LDA", ZeroPage, true),    // $A5
+    ("LDX", ZeroPage, true),    // $A6
+    ("LAX", ZeroPage, false),   // $A7
+    ("TAY", Implied, true),     // $A8
+    ("LDA", Immediate, true),   // $A9
+    ("TAX", Implied, true),     // $AA
+    ("LAX", Immediate, false),  // $AB
+    ("LDY", Absolute, true),    // $AC
+    ("LDA", Absolute, true),    // $AD
+    ("LDX", Absolute, true),    // $AE
+    ("LAX", Absolute, false),   // $AF
+    ("BCS", Relative, true),    // $B0
+    ("LDA", IndirectY, true),   // $B1
+    ("HLT", Implied, false),    // $B2
+    ("LAX", IndirectY, false),  // $B3
+    ("LDY", ZeroPageX, true),   // $B4
+    ("LDA", ZeroPageX, true),   // $B5
+    ("LDX", ZeroPageY, true),   // $B6
+    ("LAX", ZeroPageY, false),  // $B7
+    ("CLV", Implied, true),     // $B8
+    ("LDA", AbsoluteY, true),   // $B9
+    ("TSX", Implied, true),     // $BA
+    ("LAS", AbsoluteY, false),  // $BB
+    ("LDY", AbsoluteX, true),   // $BC
+    ("LDA", AbsoluteX, true),   // $BD
+    ("LDX", AbsoluteY, true),   // $BE
+    ("LAX", AbsoluteY, false),  // $BF
+    ("CPY", Immediate, true),   // $C0
+    ("CMP", IndirectX, true),   // $C1
+    ("NOP", Immediate, false),  // $C2
+    ("DCP", IndirectX, false),  // $C3
+    ("CPY", ZeroPage, true),    // $C4
+    ("CMP", ZeroPage, true),    // $C5
+    ("DEC", ZeroPage, true),    // $C6
+    ("DCP", ZeroPage, false),   // $C7
+    ("INY", Implied, true),     // $C8
+    ("CMP", Immediate, true),   // $C9
+    ("DEX", Implied, true),     // $CA
+    ("AXS", Immediate, false),  // $CB
+    ("CPY", Absolute, true),    // $CC
+    ("CMP", Absolute, true),    // $CD
+    ("DEC", Absolute, true),    // $CE
+    ("DCP", Absolute, false),   // $CF
+    ("BNE", Relative, true),    // $D0
+    ("CMP", IndirectY, true),   // $D1
+    ("HLT", Implied, false),    // $D2
+    ("DCP", IndirectY, false),  // $D3
+    ("NOP", ZeroPageX, false),  // $D4
+    ("CMP", ZeroPageX, true),   // $D5
+    ("DEC", ZeroPageX, true),   // $D6
+    ("DCP", ZeroPageX, false),  // $D7
+    ("CLD", Implied, true),     // $D8
+    ("CMP", AbsoluteY, true),   // $D9
+    ("NOP", Implied, false),    // $DA
+    ("DCP", AbsoluteY, false),  // $DB
+    ("NOP", AbsoluteX, false),  // $DC
+    ("CMP", AbsoluteX, true),   // $DD
+    ("DEC", AbsoluteX, true),   // $DE
+    ("DCP", AbsoluteX, false),  // $DF
+    ("CPX", Immediate, true),   // $E0
+    ("SBC", IndirectX, true),   // $E1
+    ("NOP", Immediate, false),  // $E2
+    ("ISC", IndirectX, false),  // $E3
+    ("CPX", ZeroPage, true),    // $E4
+    ("SBC", ZeroPage, true),    // $E5
+    ("INC", ZeroPage, true),    // $E6
+    ("ISC", ZeroPage, false),   // $E7
+    ("INX", Implied, true),     // $E8
+    ("SBC", Immediate, true),   // $E9
+    ("NOP", Implied, true),     // $EA
+    ("SBC", Immediate, false),  // $EB
+    ("CPX", Absolute, true),    // $EC
+    ("SBC", Absolute, true),    // $ED
+    ("INC", Absolute, true),    // $EE
+    ("ISC", Absolute, false),   // $EF
+    ("BEQ", Relative, true),    // $F0
+    ("SBC", IndirectY, true),   // $F1
+    ("HLT", Implied, false),    // $F2
+    ("ISC", IndirectY, false),  // $F3
+    ("NOP", ZeroPageX, false),  // $F4
+    ("SBC", ZeroPageX, true),   // $F5
+    ("INC", ZeroPageX, true),   // $F6
+    ("ISC", ZeroPageX, false),  // $F7
+    ("SED", Implied, true),     // $F8
+    ("SBC", AbsoluteY, true),   // $F9
+    ("NOP", Implied, false),    // $FA
+    ("ISC", AbsoluteY, false),  // $FB
+    ("NOP", AbsoluteX, false),  // $FC
+    ("SBC", AbsoluteX, true),   // $FD
+    ("INC", AbsoluteX, true),   // $FE
+    ("ISC", AbsoluteX, false),  // $FF
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operand: Operand,
+    pub official: bool,
+    // the operand bytes as a little endian value
+    pub value: u16,
+}
+
+impl Instruction {
+    // decodes the instruction at the start of bytes, None when its operand is cut off
+    pub fn decode(bytes: &[u8], address: u16) -> Option<Self> {
+        let opcode = *bytes.first()?;
+        let (mnemonic, operand, official) = INSTRUCTIONS[opcode as usize];
+        let value = match operand.size() {
+            0 => 0,
+            1 => *bytes.get(1)? as u16,
+            _ => u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]),
+        };
+        Some(Instruction {
+            address,
+            opcode,
+            mnemonic,
+            operand,
+            official,
+            value,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        1 + self.operand.size()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let [low, high] = self.value.to_le_bytes();
+        [self.opcode, low, high][..self.size()].to_vec()
+    }
+
+    // where a branch, JMP or JSR goes, JMP ($xxxx) is left out because its target is only known at runtime
+    pub fn target(&self) -> Option<u16> {
+        match (self.operand, self.mnemonic) {
+            (Relative, _) => Some(
+                self.address
+                    .wrapping_add(2)
+                    .wrapping_add(self.value as u8 as i8 as u16),
+            ),
+            (Absolute, "JMP" | "JSR") => Some(self.value),
+            _ => None,
+        }
+    }
+
+    // the address the operand names, None for immediates and instructions without one
+    pub fn operand_address(&self) -> Option<u16> {
+        match self.operand {
+            Implied | Accumulator | Immediate => None,
+            Relative => self.target(),
+            _ => Some(self.value),
+        }
+    }
+
+    // like the Display text, with the address of the operand spelled as its label in symbols
+    pub fn format_with(&self, symbols: &Symbols) -> String {
+        let label = self
+            .operand_address()
+            .and_then(|address| symbols.label(address));
+        let operand = self.format_operand(label.as_deref());
+        if operand.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {operand}", self.mnemonic)
+        }
+    }
+
+    // the operand as assembler syntax, with target spelled as a label when one is given
+    pub fn format_operand(&self, label: Option<&str>) -> String {
+        let address = match label {
+            Some(label) => label.to_string(),
+            None if self.operand == Relative => format!("${:04X}", self.target().unwrap()),
+            None if self.operand.size() == 1 => format!("${:02X}", self.value),
+            None => format!("${:04X}", self.value),
+        };
+        match self.operand {
+            Implied => String::new(),
+            Accumulator => "A".to_string(),
+            Immediate => format!("#${:02X}", self.value),
+            ZeroPage | Absolute | Relative => address,
+            ZeroPageX | AbsoluteX => format!("{address},X"),
+            ZeroPageY | AbsoluteY => format!("{address},Y"),
+            Indirect => format!("({address})"),
+            IndirectX => format!("({address},X)"),
+            IndirectY => format!("({address}),Y"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.format_operand(None);
+        if operand.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {operand}", self.mnemonic)
+        }
+    }
+}
+
+// one entry of a linear sweep, bytes that do not form a whole instruction become data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Instruction(Instruction),
+    Data { address: u16, bytes: Vec<u8> },
+}
+
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        match Instruction::decode(&bytes[offset..], address) {
+            Some(instruction) => {
+                offset += instruction.size();
+                lines.push(Line::Instruction(instruction));
+            }
+            None => {
+                lines.push(Line::Data {
+                    address,
+                    bytes: bytes[offset..].to_vec(),
+                });
+                break;
+            }
+        }
+    }
+    lines
+}
+
+// a listing with addresses, raw bytes and the labels of symbols, branch and jump targets inside it
+// without one get an L_xxxx label
+pub fn listing(lines: &[Line], symbols: &Symbols) -> String {
+    let starts: BTreeSet<u16> = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Instruction(instruction) => Some(instruction.address),
+            Line::Data { .. } => None,
+        })
+        .collect();
+    let targets: BTreeSet<u16> = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Instruction(instruction) => instruction.target(),
+            Line::Data { .. } => None,
+        })
+        .filter(|target| starts.contains(target))
+        .collect();
+
+    let mut output = String::new();
+    for line in lines {
+        match line {
+            Line::Instruction(instruction) => {
+                let address = instruction.address;
+                if let Some(name) = symbols.address_label(address) {
+                    output += &format!("{name}:\n");
+                } else if targets.contains(&address) {
+                    output += &format!("L_{address:04X}:\n");
+                }
+                let bytes: Vec<String> = instruction
+                    .bytes()
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect();
+                let label = instruction
+                    .operand_address()
+                    .and_then(|address| symbols.label(address))
+                    .or_else(|| {
+                        instruction
+                            .target()
+                            .filter(|target| targets.contains(target))
+                            .map(|target| format!("L_{target:04X}"))
+                    });
+                let operand = instruction.format_operand(label.as_deref());
+                let marker = if instruction.official { ' ' } else { '*' };
+                let text = format!(
+                    "  {:04X}  {:<8} {marker}{} {operand}",
+                    instruction.address,
+                    bytes.join(" "),
+                    instruction.mnemonic
+                );
+                output += text.trim_end();
+                output.push('\n');
+            }
+            Line::Data { address, bytes } => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+                output += &format!("  {address:04X}            .db {}\n", bytes.join(","));
+            }
+        }
+    }
+    output
+}
+
+impl Emulator {
+    // decodes the instruction at address in live memory, reading it without side effects
+    pub fn disassemble_at(&mut self, address: u16) -> Instruction {
+        let bytes = [
+            self.peek(address),
+            self.peek(address.wrapping_add(1)),
+            self.peek(address.wrapping_add(2)),
+        ];
+        Instruction::decode(&bytes, address).unwrap()
+    }
+
+    // count lines of live memory from address like the debugger shows them, with the rom bytes the
+    // analysis found to be data or the middle of an instruction as data rather than decoded
+    pub fn disassemble_lines(&mut self, address: u16, count: usize) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut address = address;
+        while lines.len() < count {
+            if !self.analyzed_as_data(address) {
+                let instruction = self.disassemble_at(address);
+                address = address.wrapping_add(instruction.size() as u16);
+                lines.push(Line::Instruction(instruction));
+                continue;
+            }
+            let start = address;
+            let mut bytes = Vec::new();
+            while bytes.len() < 8 && (bytes.is_empty() || self.analyzed_as_data(address)) {
+                bytes.push(self.peek(address));
+                address = address.wrapping_add(1);
+            }
+            lines.push(Line::Data {
+                address: start,
+                bytes,
+            });
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], address: u16) -> Instruction {
+        Instruction::decode(bytes, address).unwrap()
+    }
+
+    #[test]
+    fn every_addressing_mode_decodes_and_formats() {
+        let cases: [(&[u8], Operand, &str); 13] = [
+            (&[0xEA], Implied, "NOP"),
+            (&[0x0A], Accumulator, "ASL A"),
+            (&[0xA9, 0x05], Immediate, "LDA #$05"),
+            (&[0xA5, 0x10], ZeroPage, "LDA $10"),
+            (&[0xB5, 0x10], ZeroPageX, "LDA $10,X"),
+            (&[0xB6, 0x10], ZeroPageY, "LDX $10,Y"),
+            (&[0xAD, 0x34, 0x12], Absolute, "LDA $1234"),
+            (&[0xBD, 0x34, 0x12], AbsoluteX, "LDA $1234,X"),
+            (&[0xB9, 0x34, 0x12], AbsoluteY, "LDA $1234,Y"),
+            (&[0x6C, 0x34, 0x12], Indirect, "JMP ($1234)"),
+            (&[0xA1, 0x20], IndirectX, "LDA ($20,X)"),
+            (&[0xB1, 0x20], IndirectY, "LDA ($20),Y"),
+            (&[0xD0, 0x03], Relative, "BNE $C005"),
+        ];
+        for (bytes, operand, text) in cases {
+            let instruction = decode(bytes, 0xC000);
+            assert_eq!(instruction.operand, operand, "{text}");
+            assert!(instruction.official, "{text}");
+            assert_eq!(instruction.size(), bytes.len(), "{text}");
+            assert_eq!(instruction.bytes(), bytes, "{text}");
+            assert_eq!(instruction.to_string(), text);
+        }
+
+        // an operand cut off is no instruction, and a sweep leaves it as data
+        assert_eq!(Instruction::decode(&[0xAD, 0x34], 0xC000), None);
+        let lines = disassemble(&[0xEA, 0xAD, 0x34], 0xC000);
+        assert_eq!(
+            lines[1],
+            Line::Data {
+                address: 0xC001,
+                bytes: vec![0xAD, 0x34]
+            }
+        );
+    }
+
+    #[test]
+    fn branches_and_jumps_know_their_targets() {
+        // forward, back onto itself and backwards past the start of a page
+        assert_eq!(decode(&[0xD0, 0x03], 0xC000).target(), Some(0xC005));
+        assert_eq!(decode(&[0xF0, 0xFE], 0xC010).target(), Some(0xC010));
+        assert_eq!(decode(&[0x10, 0x80], 0xC010).target(), Some(0xBF92));
+        // across the top of the address space
+        let branch = decode(&[0x90, 0x20], 0xFFF0);
+        assert_eq!(branch.target(), Some(0x0012));
+        assert_eq!(branch.to_string(), "BCC $0012");
+
+        assert_eq!(decode(&[0x4C, 0x00, 0xD0], 0xC000).target(), Some(0xD000));
+        assert_eq!(decode(&[0x20, 0x00, 0xD0], 0xC000).target(), Some(0xD000));
+        // where JMP ($xxxx) goes is read at runtime, the operand still names the pointer
+        let indirect = decode(&[0x6C, 0xFC, 0xFF], 0xC000);
+        assert_eq!(indirect.target(), None);
+        assert_eq!(indirect.operand_address(), Some(0xFFFC));
+        assert_eq!(decode(&[0xAD, 0x02, 0x20], 0xC000).target(), None);
+        assert_eq!(decode(&[0xA9, 0x02], 0xC000).operand_address(), None);
+
+        // a target inside the listing gets a label, one outside keeps its address
+        let listing = listing(
+            &disassemble(&[0xEA, 0xD0, 0xFD, 0x4C, 0x00, 0xD0], 0xC000),
+            &Symbols::new(),
+        );
+        assert_eq!(
+            listing,
+            "L_C000:\n  C000  EA        NOP\n  C001  D0 FD     BNE L_C000\n  \
+             C003  4C 00 D0  JMP $D000\n"
+        );
+    }
+
+    #[test]
+    fn unofficial_opcodes_decode_and_are_marked() {
+        let cases: [(&[u8], &str); 6] = [
+            (&[0x03, 0x20], "SLO ($20,X)"),
+            (&[0xA7, 0x10], "LAX $10"),
+            (&[0x80, 0x44], "NOP #$44"),
+            (&[0xEB, 0x01], "SBC #$01"),
+            (&[0x9E, 0x00, 0x03], "SHX $0300,Y"),
+            (&[0x02], "HLT"),
+        ];
+        for (bytes, text) in cases {
+            let instruction = decode(bytes, 0xC000);
+            assert!(!instruction.official, "{text}");
+            assert_eq!(instruction.size(), bytes.len(), "{text}");
+            assert_eq!(instruction.to_string(), text);
+        }
+        // every opcode decodes, 151 of them official
+        let official = (0..=0xFF)
+            .filter(|&opcode| decode(&[opcode, 0, 0], 0).official)
+            .count();
+        assert_eq!(official, 151);
+
+        let listing = listing(
+            &disassemble(&[0xA7, 0x10, 0xA5, 0x10], 0xC000),
+            &Symbols::new(),
+        );
+        assert_eq!(
+            listing,
+            "  C000  A7 10    *LAX $10\n  C002  A5 10     LDA $10\n"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use crate::Emulator;
+use crate::savestate::savestate_fields;
+
+// a pending transfer, started by a $4014 write or by the dmc running out of sample bytes
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Dma {
+    // the page to copy into oam once the writing instruction is done
+    pub(crate) oam_page: Option<u8>,
+    // the dmc wants a byte, the cpu gets halted at its next read
+    pub(crate) dmc: bool,
+}
+
+impl Emulator {
+    // 513 cycles, or 514 when the copy has to wait a cycle to start on a read cycle
+    pub(crate) fn run_oam_dma(&mut self, page: u8) {
+        // the cycle that halts the cpu
+        self.tick();
+        if self.cycles() % 2 == 1 {
+            self.tick();
+        }
+        for offset in 0..=0xFF {
+            self.tick();
+            let value = self.read_untimed(u16::from_be_bytes([page, offset]));
+            // a dmc fetch in the middle of the copy takes the read cycle and one to realign
+            if self.dma.dmc {
+                self.tick();
+                self.fetch_dmc_sample();
+                self.tick();
+            }
+            self.tick();
+            self.write_untimed(0x2004, value);
+        }
+    }
+
+    // the dmc halts the cpu on a read cycle, then takes a dummy cycle, an alignment cycle and the fetch,
+    // the halted cpu repeats its read but the side effects of that are not emulated
+    pub(crate) fn run_dmc_dma(&mut self) {
+        for _ in 0..3 {
+            self.tick();
+        }
+        self.tick();
+        self.fetch_dmc_sample();
+    }
+
+    fn fetch_dmc_sample(&mut self) {
+        self.dma.dmc = false;
+        if let Some(address) = self.apu.dmc_sample_request() {
+            let sample = self.read_untimed(address);
+            self.apu.dmc_fill_sample(sample);
+        }
+    }
+}
+
+savestate_fields!(Dma { oam_page, dmc });
@@ -0,0 +1,101 @@
+use crate::cartridge::RomError;
+use crate::nsf::NsfError;
+use crate::patch::PatchError;
+use crate::raw::RawError;
+use crate::symbols::SymbolError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// everything that can go wrong while loading a game, for frontends to report instead of panicking
+#[derive(Debug)]
+pub enum EmuError {
+    IoError(io::Error),
+    BadHeader(RomError),
+    UnsupportedMapper(u16),
+    RomTooSmall { expected: usize, actual: usize },
+    BadNsf(NsfError),
+    BadRaw(RawError),
+    BadPatch(PatchError),
+    BadSymbols(SymbolError),
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::IoError(error) => write!(f, "{error}"),
+            EmuError::BadHeader(error) => write!(f, "bad rom header: {error}"),
+            EmuError::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+            EmuError::RomTooSmall { expected, actual } => write!(
+                f,
+                "rom is {actual} bytes but its header needs at least {expected}"
+            ),
+            EmuError::BadNsf(error) => write!(f, "bad nsf file: {error}"),
+            EmuError::BadRaw(error) => write!(f, "bad raw program: {error}"),
+            EmuError::BadPatch(error) => write!(f, "bad patch: {error}"),
+            EmuError::BadSymbols(error) => write!(f, "bad symbol file: {error}"),
+        }
+    }
+}
+
+impl Error for EmuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmuError::IoError(error) => Some(error),
+            EmuError::BadHeader(error) => Some(error),
+            EmuError::BadNsf(error) => Some(error),
+            EmuError::BadRaw(error) => Some(error),
+            EmuError::BadPatch(error) => Some(error),
+            EmuError::BadSymbols(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmuError {
+    fn from(error: io::Error) -> Self {
+        EmuError::IoError(error)
+    }
+}
+
+impl From<NsfError> for EmuError {
+    fn from(error: NsfError) -> Self {
+        EmuError::BadNsf(error)
+    }
+}
+
+impl From<PatchError> for EmuError {
+    fn from(error: PatchError) -> Self {
+        EmuError::BadPatch(error)
+    }
+}
+
+impl From<RawError> for EmuError {
+    fn from(error: RawError) -> Self {
+        EmuError::BadRaw(error)
+    }
+}
+
+impl From<SymbolError> for EmuError {
+    fn from(error: SymbolError) -> Self {
+        EmuError::BadSymbols(error)
+    }
+}
+
+impl From<RomError> for EmuError {
+    fn from(error: RomError) -> Self {
+        match error {
+            // sizes here include the 16 byte header, unlike RomError::Truncated
+            RomError::HeaderTooShort(actual) => EmuError::RomTooSmall {
+                expected: 16,
+                actual,
+            },
+            RomError::Truncated { expected, actual } => EmuError::RomTooSmall {
+                expected: expected + 16,
+                actual: actual + 16,
+            },
+            RomError::UnsupportedMapper(mapper) => EmuError::UnsupportedMapper(mapper),
+            error => EmuError::BadHeader(error),
+        }
+    }
+}
@@ -0,0 +1,518 @@
+// the sound chips some cartridges carry besides the apu, mixed into its output by the console's
+// expansion audio pin. the vrc6 adds two pulse channels and a sawtooth, the famicom disk system a
+// 64 step wavetable with a modulator bending its pitch. the chips live on the board that has them,
+// which clocks them every cpu cycle and hands their levels to the apu for mixing. the vrc7's fm
+// synthesis isn't emulated, its music plays without those channels
+use crate::savestate::{StateError, ensure, savestate_fields};
+
+// an apu pulse channel at full volume, which a vrc6 pulse at full volume about matches
+const PULSE_LEVEL: f32 = 95.88 / (8128.0 / 15.0 + 100.0);
+const VRC6_LEVEL: f32 = PULSE_LEVEL / 15.0;
+// the disk system at full volume is about 2.4 times as loud as an apu pulse
+const FDS_LEVEL: f32 = PULSE_LEVEL * 2.4 / (63.0 * 32.0);
+// how far $4089 turns the disk system's wave down, out of 36
+const FDS_MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+// what each entry of the modulation table adds to the modulator's counter, 4 resets it
+const FDS_MODULATION_STEPS: [u8; 8] = [0, 1, 2, 4, 0, 0x7C, 0x7E, 0x7F];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expansion {
+    Vrc6,
+    Fds,
+}
+
+impl Expansion {
+    pub const ALL: [Expansion; 2] = [Expansion::Vrc6, Expansion::Fds];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Expansion::Vrc6 => "vrc6",
+            Expansion::Fds => "fds",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Expansion::ALL
+            .into_iter()
+            .find(|expansion| expansion.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// what each chip puts out, indexed by Expansion and already in the scale of the apu's mix
+pub type ExpansionLevels = [f32; 2];
+
+#[derive(Debug, Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    // ignores the duty and holds the volume
+    constant: bool,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.volume = value & 0x0F;
+                self.duty = (value >> 4) & 0x07;
+                self.constant = value & 0x80 != 0;
+            }
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0x80 != 0;
+                // turning the channel off starts its duty cycle over
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.constant || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    // 14 steps a wave, the accumulator grows on every other one
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        if !self.enabled {
+            return;
+        }
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    // the top 5 bits of the accumulator
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+// konami's vrc6, its registers at $9000-$9003, $A000-$A002 and $B000-$B002
+#[derive(Debug, Default)]
+pub struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Vrc6Sawtooth,
+    // $9003: every channel stopped, or their periods shortened by 4 or 8 bits
+    halted: bool,
+    shift: u8,
+}
+
+impl Vrc6Audio {
+    // address is the cpu address of a sound register with the board's wiring undone
+    pub fn write(&mut self, address: u16, value: u8) {
+        let register = address & 0x03;
+        match (address & 0xF000, register) {
+            (0x9000, 3) => {
+                self.halted = value & 0x01 != 0;
+                self.shift = if value & 0x04 != 0 {
+                    8
+                } else if value & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            (0x9000, _) => self.pulses[0].write(register, value),
+            (0xA000, 0..=2) => self.pulses[1].write(register, value),
+            (0xB000, 0..=2) => self.sawtooth.write(register, value),
+            _ => {}
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.halted {
+            return;
+        }
+        for pulse in &mut self.pulses {
+            pulse.clock(self.shift);
+        }
+        self.sawtooth.clock(self.shift);
+    }
+
+    pub fn output(&self) -> f32 {
+        let level = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        level as f32 * VRC6_LEVEL
+    }
+}
+
+// the volume envelope and the modulator's gain, which count up or down towards a limit of 32 at a
+// rate of their own times the master rate of $408A
+#[derive(Debug, Default)]
+struct FdsEnvelope {
+    speed: u8,
+    increase: bool,
+    // the gain is the speed bits as written, nothing changes it
+    off: bool,
+    gain: u8,
+    timer: u32,
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, value: u8, master_speed: u8) {
+        self.speed = value & 0x3F;
+        self.increase = value & 0x40 != 0;
+        self.off = value & 0x80 != 0;
+        if self.off {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    // true when the gain changed
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.off || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+}
+
+// the famicom disk system's sound, its registers at $4040-$408A
+#[derive(Debug)]
+pub struct FdsAudio {
+    wave: [u8; 64],
+    // the wave can only be written while this holds it still
+    wave_writable: bool,
+    wave_halted: bool,
+    envelopes_halted: bool,
+    master_volume: u8,
+    master_speed: u8,
+    volume: FdsEnvelope,
+    frequency: u16,
+    // the position in the wave, 65536 to a step of its 64
+    wave_position: u32,
+    modulation: FdsEnvelope,
+    modulation_table: [u8; 64],
+    modulation_position: u8,
+    modulation_halted: bool,
+    modulation_frequency: u16,
+    modulation_accumulator: u16,
+    // a 7 bit signed number
+    modulation_counter: u8,
+    // what the modulator adds to the wave's frequency, in two's complement
+    pitch: u16,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        FdsAudio {
+            wave: [0; 64],
+            wave_writable: false,
+            wave_halted: false,
+            envelopes_halted: false,
+            master_volume: 0,
+            // what the bios leaves in $408A
+            master_speed: 0xE8,
+            volume: FdsEnvelope::default(),
+            frequency: 0,
+            wave_position: 0,
+            modulation: FdsEnvelope::default(),
+            modulation_table: [0; 64],
+            modulation_position: 0,
+            modulation_halted: true,
+            modulation_frequency: 0,
+            modulation_accumulator: 0,
+            modulation_counter: 0,
+            pitch: 0,
+        }
+    }
+}
+
+impl FdsAudio {
+    // the wave and the two gains read back, bits 6 and 7 are the open bus of a $40xx address
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => Some(self.wave[(address - 0x4040) as usize] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_writable => {
+                self.wave[(address - 0x4040) as usize] = value & 0x3F;
+            }
+            0x4080 => self.volume.write(value, self.master_speed),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_halted = value & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_position = 0;
+                }
+                if self.envelopes_halted {
+                    self.volume.reset_timer(self.master_speed);
+                    self.modulation.reset_timer(self.master_speed);
+                }
+            }
+            0x4084 => {
+                self.modulation.write(value, self.master_speed);
+                self.update_pitch();
+            }
+            0x4085 => {
+                self.modulation_counter = value & 0x7F;
+                self.update_pitch();
+            }
+            0x4086 => {
+                self.modulation_frequency = (self.modulation_frequency & 0x0F00) | value as u16;
+            }
+            0x4087 => {
+                self.modulation_frequency =
+                    (self.modulation_frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.modulation_halted = value & 0x80 != 0;
+                if self.modulation_halted {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            // the table is written two entries at a time, and only while the modulator is held
+            0x4088 if self.modulation_halted => {
+                let position = self.modulation_position as usize;
+                self.modulation_table[position] = value & 0x07;
+                self.modulation_table[(position + 1) & 0x3F] = value & 0x07;
+                self.modulation_position = (self.modulation_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.master_volume = value & 0x03;
+                self.wave_writable = value & 0x80 != 0;
+            }
+            0x408A => self.master_speed = value,
+            _ => {}
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.wave_halted && !self.envelopes_halted {
+            self.volume.clock(self.master_speed);
+            if self.modulation.clock(self.master_speed) {
+                self.update_pitch();
+            }
+        }
+        if !self.modulation_halted && self.modulation_frequency > 0 {
+            let (accumulator, overflow) = self
+                .modulation_accumulator
+                .overflowing_add(self.modulation_frequency);
+            self.modulation_accumulator = accumulator;
+            if overflow {
+                let step = self.modulation_table[self.modulation_position as usize];
+                self.modulation_position = (self.modulation_position + 1) & 0x3F;
+                self.modulation_counter = if step == 4 {
+                    0
+                } else {
+                    self.modulation_counter
+                        .wrapping_add(FDS_MODULATION_STEPS[step as usize])
+                        & 0x7F
+                };
+                self.update_pitch();
+            }
+        }
+        if self.wave_halted || self.wave_writable {
+            return;
+        }
+        let frequency = self.frequency as i32 + self.pitch as i16 as i32;
+        if frequency > 0 {
+            self.wave_position = (self.wave_position + frequency as u32) & 0x3F_FFFF;
+        }
+    }
+
+    // the modulator's counter times its gain, rounded the way the chip does, scaled by the
+    // wave's frequency
+    fn update_pitch(&mut self) {
+        let counter = ((self.modulation_counter << 1) as i8 >> 1) as i32;
+        let mut product = counter * self.modulation.gain as i32;
+        let remainder = product & 0x0F;
+        product >>= 4;
+        if remainder > 0 && product & 0x80 == 0 {
+            product += if counter < 0 { -1 } else { 2 };
+        }
+        if product >= 192 {
+            product -= 256;
+        } else if product < -64 {
+            product += 256;
+        }
+        let mut pitch = product * self.frequency as i32;
+        let remainder = pitch & 0x3F;
+        pitch >>= 6;
+        if remainder >= 32 {
+            pitch += 1;
+        }
+        self.pitch = pitch as u16;
+    }
+
+    pub fn output(&self) -> f32 {
+        let sample = self.wave[(self.wave_position >> 16) as usize & 0x3F] as u32;
+        let gain = self.volume.gain.min(32) as u32;
+        let level = sample * gain * FDS_MASTER_VOLUMES[self.master_volume as usize] / 36;
+        level as f32 * FDS_LEVEL
+    }
+}
+
+savestate_fields!(Vrc6Pulse {
+    volume,
+    duty,
+    constant,
+    enabled,
+    period,
+    timer,
+    step
+} checked);
+savestate_fields!(Vrc6Sawtooth {
+    rate,
+    enabled,
+    period,
+    timer,
+    step,
+    accumulator
+} checked);
+savestate_fields!(Vrc6Audio {
+    pulses,
+    sawtooth,
+    halted,
+    shift
+} checked);
+savestate_fields!(FdsEnvelope {
+    speed,
+    increase,
+    off,
+    gain,
+    timer
+} checked);
+savestate_fields!(FdsAudio {
+    wave,
+    wave_writable,
+    wave_halted,
+    envelopes_halted,
+    master_volume,
+    master_speed,
+    volume,
+    frequency,
+    wave_position,
+    modulation,
+    modulation_table,
+    modulation_position,
+    modulation_halted,
+    modulation_frequency,
+    modulation_accumulator,
+    modulation_counter,
+    pitch,
+} checked);
+
+// a loaded state is held to the ranges the register writes keep each field in
+impl Vrc6Pulse {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            self.volume < 16 && self.duty < 8 && self.step < 16,
+            "vrc6 pulse",
+        )
+    }
+}
+
+impl Vrc6Sawtooth {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(self.rate < 0x40 && self.step < 14, "vrc6 sawtooth")
+    }
+}
+
+impl Vrc6Audio {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(matches!(self.shift, 0 | 4 | 8), "vrc6 period shift")
+    }
+}
+
+impl FdsEnvelope {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(self.speed < 0x40 && self.gain < 0x40, "fds envelope")
+    }
+}
+
+impl FdsAudio {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            (self.master_volume as usize) < FDS_MASTER_VOLUMES.len(),
+            "fds master volume",
+        )?;
+        ensure(
+            (self.modulation_position as usize) < self.modulation_table.len()
+                && self
+                    .modulation_table
+                    .iter()
+                    .all(|&step| (step as usize) < FDS_MODULATION_STEPS.len()),
+            "fds modulation table",
+        )?;
+        ensure(
+            self.wave.iter().all(|&sample| sample < 0x40) && self.modulation_counter < 0x80,
+            "fds wave",
+        )
+    }
+}
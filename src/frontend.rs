@@ -0,0 +1,1403 @@
+// interactive frontend: blits frames to the linux framebuffer console, or draws them in the terminal
+// where there is none, and reads the keyboard from the terminal
+use crate::audio::AudioOutput;
+use crate::capture::{Capture, WavWriter};
+use crate::config;
+use crate::gamepad::{self, Gamepad, GamepadEvent, GamepadInput, GamepadMap, REMAP_ORDER};
+use crate::terminal::TerminalScreen;
+use ntsc_nes::Emulator;
+use ntsc_nes::apu::Channel;
+use ntsc_nes::clock::{MAX_SPEED, MIN_SPEED, Speed};
+use ntsc_nes::controller::{Autofire, Button, FrameInput, InputSource, Player};
+use ntsc_nes::memory::MemorySpace;
+use ntsc_nes::movie::{Movie, MoviePlayer, Recorder};
+use ntsc_nes::netplay::Netplay;
+use ntsc_nes::overlay::{GLYPH_HEIGHT, GLYPH_WIDTH, Overlay};
+use ntsc_nes::palette::Palette;
+use ntsc_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use ntsc_nes::rewind::Rewind;
+use ntsc_nes::stats::Stats;
+use ntsc_nes::video::VideoFilter;
+use ntsc_nes::viewer::Image;
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// terminals only report key presses, so a key counts as held until its auto-repeat stops
+const HOLD_FRAMES: u8 = 8;
+// how often battery ram is written back to the .sav file
+const SAVE_FILE_INTERVAL: u32 = 300;
+// frames a click keeps the zapper trigger pulled, so games polling once a frame see it
+const TRIGGER_FRAMES: u8 = 3;
+const CROSSHAIR_COLOR: [u8; 4] = [0xFF, 0x40, 0x40, 0xC0];
+// the memory view shows a page of 16 rows of 16 bytes
+const MEMORY_PAGE: u16 = 0x100;
+const MEMORY_TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const MEMORY_BACKGROUND: [u8; 4] = [0x00, 0x00, 0x00, 0xC0];
+// the mixer draws each channel's level over a lane this high
+const MIXER_LANE: i32 = 40;
+const MIXER_WAVE_COLOR: [u8; 4] = [0x40, 0xFF, 0x80, 0xFF];
+const MIXER_MUTED_COLOR: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+const VOLUME_STEP: f32 = 0.1;
+// the sound queued for the player beyond this holds the next frame back until the device catches up
+const AUDIO_BACKLOG: Duration = Duration::from_millis(50);
+// turbo counts as held for this long after each press, longer than the delay before auto-repeat starts
+const TURBO_HOLD: Duration = Duration::from_millis(500);
+
+// a key as the terminal reports it, letters folded to lower case
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalKey {
+    Char(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl TerminalKey {
+    // "up", "down", "left", "right", "tab", "space", "enter" or a single character
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "up" => TerminalKey::Up,
+            "down" => TerminalKey::Down,
+            "left" => TerminalKey::Left,
+            "right" => TerminalKey::Right,
+            "tab" => TerminalKey::Char(b'\t'),
+            "space" => TerminalKey::Char(b' '),
+            "enter" | "return" => TerminalKey::Char(b'\r'),
+            name if name.len() == 1 && name.is_ascii() => TerminalKey::Char(name.as_bytes()[0]),
+            _ => return None,
+        })
+    }
+}
+
+// what a bound key presses: a button on one of the pads, held down or on turbo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PadButton {
+    pub player: Player,
+    pub button: Button,
+    pub turbo: bool,
+}
+
+impl PadButton {
+    pub const fn new(player: Player, button: Button) -> Self {
+        PadButton {
+            player,
+            button,
+            turbo: false,
+        }
+    }
+}
+
+// only player 1 has keys unless the config binds some
+const DEFAULT_BINDINGS: [(TerminalKey, PadButton); 9] = [
+    (TerminalKey::Up, PadButton::new(Player::One, Button::Up)),
+    (TerminalKey::Down, PadButton::new(Player::One, Button::Down)),
+    (TerminalKey::Left, PadButton::new(Player::One, Button::Left)),
+    (
+        TerminalKey::Right,
+        PadButton::new(Player::One, Button::Right),
+    ),
+    (
+        TerminalKey::Char(b'x'),
+        PadButton::new(Player::One, Button::A),
+    ),
+    (
+        TerminalKey::Char(b'z'),
+        PadButton::new(Player::One, Button::B),
+    ),
+    (
+        TerminalKey::Char(b'\t'),
+        PadButton::new(Player::One, Button::Select),
+    ),
+    (
+        TerminalKey::Char(b' '),
+        PadButton::new(Player::One, Button::Select),
+    ),
+    (
+        TerminalKey::Char(b'\r'),
+        PadButton::new(Player::One, Button::Start),
+    ),
+];
+
+pub struct Settings {
+    // None fits the largest integer scale on the screen
+    pub scale: Option<usize>,
+    // draw in the terminal even when there is a framebuffer
+    pub terminal: bool,
+    pub bindings: Vec<(TerminalKey, PadButton)>,
+    // presses a second of turbo buttons that don't go at the default rate
+    pub turbo_rates: Vec<(Button, f64)>,
+    pub gamepad_map: GamepadMap,
+    // the js device of each player's gamepad in player order
+    pub gamepad_devices: Vec<u8>,
+    // where a remapped gamepad is saved to
+    pub config_path: Option<PathBuf>,
+    // bytes of history for rewinding, 0 turns it off
+    pub rewind_budget: usize,
+    // screenshots are saved as <screenshot_base>-<frame>.png
+    pub screenshot_base: PathBuf,
+    // also save the colour indices of every screenshot to a .raw file
+    pub raw_screenshots: bool,
+    // named palettes o switches between, and the one the emulator starts with
+    pub palettes: Vec<(String, Palette)>,
+    pub palette: usize,
+    // log the frame timing once a second
+    pub stats: bool,
+}
+
+impl Settings {
+    // the default keys, with every button in rebound replaced by its new keys
+    pub fn new(
+        scale: Option<usize>,
+        rebound: &[(PadButton, Vec<TerminalKey>)],
+        rewind_budget: usize,
+    ) -> Self {
+        let mut bindings: Vec<(TerminalKey, PadButton)> = DEFAULT_BINDINGS
+            .into_iter()
+            .filter(|(_, button)| !rebound.iter().any(|(rebound, _)| rebound == button))
+            .collect();
+        for (button, keys) in rebound {
+            bindings.extend(keys.iter().map(|key| (*key, *button)));
+        }
+        Settings {
+            scale,
+            terminal: false,
+            bindings,
+            turbo_rates: Vec::new(),
+            gamepad_map: GamepadMap::default(),
+            gamepad_devices: vec![0, 1, 2, 3],
+            config_path: None,
+            rewind_budget,
+            screenshot_base: PathBuf::from("screenshot"),
+            raw_screenshots: false,
+            palettes: Vec::new(),
+            palette: 0,
+            stats: false,
+        }
+    }
+}
+
+// what happens to movies during the session
+pub enum MovieMode {
+    Off,
+    Play(MoviePlayer),
+    // the movie is written out by the caller once the session ends
+    Record(Movie),
+}
+
+// the pads driven by the keyboard and the gamepads
+struct HeldKeys {
+    // frames left for each button, indexed by player and by its bit
+    held: [[u8; 8]; 4],
+    turbo: [[u8; 8]; 4],
+    autofire: Autofire,
+    // what each player's gamepad holds down
+    gamepads: [u8; 4],
+    reset: bool,
+    power: bool,
+}
+
+impl HeldKeys {
+    fn new(autofire: Autofire) -> Self {
+        HeldKeys {
+            held: [[0; 8]; 4],
+            turbo: [[0; 8]; 4],
+            autofire,
+            gamepads: [0; 4],
+            reset: false,
+            power: false,
+        }
+    }
+
+    fn press(&mut self, pad: PadButton) {
+        let keys = if pad.turbo {
+            &mut self.turbo
+        } else {
+            &mut self.held
+        };
+        keys[pad.player.port()][(pad.button as u8).trailing_zeros() as usize] = HOLD_FRAMES;
+    }
+}
+
+// a remapped gamepad goes into the config so it is there next time
+fn save_gamepad_map(path: Option<&Path>, map: &GamepadMap) {
+    let Some(path) = path else {
+        eprint!("gamepad remapped\r\n");
+        return;
+    };
+    match config::save_gamepad_map(path, map) {
+        Ok(()) => eprint!("gamepad saved to {}\r\n", path.display()),
+        Err(error) => eprint!("error: {error}\r\n"),
+    }
+}
+
+// the buttons of each pad still held, counting a frame off every one
+fn release(keys: &mut [[u8; 8]; 4]) -> [u8; 4] {
+    let mut pads = [0; 4];
+    for (buttons, frames) in pads.iter_mut().zip(keys.iter_mut()) {
+        for (bit, frames) in frames.iter_mut().enumerate() {
+            if *frames > 0 {
+                *buttons |= 1 << bit;
+            }
+            *frames = frames.saturating_sub(1);
+        }
+    }
+    pads
+}
+
+impl InputSource for HeldKeys {
+    fn next_frame(&mut self) -> Option<FrameInput> {
+        let held = release(&mut self.held);
+        let fired = self.autofire.next_frame(release(&mut self.turbo));
+        Some(FrameInput {
+            buttons: std::array::from_fn(|pad| held[pad] | fired[pad] | self.gamepads[pad]),
+            reset: std::mem::take(&mut self.reset),
+            power: std::mem::take(&mut self.power),
+        })
+    }
+}
+
+enum Key {
+    Button(PadButton),
+    Pause,
+    // runs one frame and pauses
+    FrameAdvance,
+    // halves or doubles the speed
+    ChangeSpeed(i32),
+    // runs unlimited while held
+    Turbo,
+    Reset,
+    CycleFilter,
+    SelectSlot(usize),
+    SaveState,
+    LoadState,
+    Rewind,
+    RestartRecording,
+    // shows the cpu, ppu, oam and palette memory in turn over the picture, then nothing
+    CycleMemoryView,
+    // moves the memory view a page back or forward
+    MemoryPage(i32),
+    // shows the ppu viewers in turn instead of the picture, then the picture again
+    CycleViewer,
+    // picks the palette the pattern tables are drawn in
+    ViewerPalette(i32),
+    Screenshot,
+    CyclePalette,
+    // asks for the gamepad input of every button in turn and saves them to the config
+    RemapGamepad,
+    // shows every sound channel's wave, volume and note over the picture, or stops showing them
+    ToggleMixer,
+    // picks the channel the mixer keys change
+    NextChannel,
+    MuteChannel,
+    ChannelVolume(i32),
+    // shows the frame timing over the picture, or stops showing it
+    ToggleStats,
+    // turns the console off and on again
+    PowerCycle,
+    Quit,
+}
+
+struct Framebuffer {
+    device: File,
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    stride: usize,
+    scale: usize,
+    row: Vec<u8>,
+}
+
+impl Framebuffer {
+    fn open(scale: Option<usize>) -> io::Result<Self> {
+        let attribute = |name: &str| fs::read_to_string(format!("/sys/class/graphics/fb0/{name}"));
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let size = attribute("virtual_size")?;
+        let (width, height): (usize, usize) = size
+            .trim()
+            .split_once(',')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .ok_or_else(|| invalid("bad framebuffer size"))?;
+        let bits_per_pixel: usize = attribute("bits_per_pixel")?
+            .trim()
+            .parse()
+            .map_err(|_| invalid("bad framebuffer depth"))?;
+        if bits_per_pixel != 16 && bits_per_pixel != 32 {
+            return Err(invalid("only 16 and 32 bit framebuffers are supported"));
+        }
+        let stride = attribute("stride")?
+            .trim()
+            .parse()
+            .map_err(|_| invalid("bad framebuffer stride"))?;
+        let scale =
+            scale.unwrap_or_else(|| (width / SCREEN_WIDTH).min(height / SCREEN_HEIGHT).max(1));
+        Ok(Framebuffer {
+            device: OpenOptions::new().write(true).open("/dev/fb0")?,
+            width,
+            height,
+            bytes_per_pixel: bits_per_pixel / 8,
+            stride,
+            scale,
+            row: Vec::new(),
+        })
+    }
+
+    // frame is 256x240 RGBA8
+    fn present(&mut self, frame: &[u8]) -> io::Result<()> {
+        let columns = (SCREEN_WIDTH * self.scale).min(self.width);
+        let rows = (SCREEN_HEIGHT * self.scale).min(self.height);
+        let left = (self.width - columns) / 2;
+        let top = (self.height - rows) / 2;
+        for y in 0..rows {
+            let line = &frame[(y / self.scale) * SCREEN_WIDTH * 4..][..SCREEN_WIDTH * 4];
+            self.row.clear();
+            for x in 0..columns {
+                let pixel = &line[(x / self.scale) * 4..][..4];
+                let (red, green, blue) = (pixel[0], pixel[1], pixel[2]);
+                if self.bytes_per_pixel == 4 {
+                    self.row.extend_from_slice(&[blue, green, red, 0xFF]);
+                } else {
+                    let pixel =
+                        ((red as u16 >> 3) << 11) | ((green as u16 >> 2) << 5) | (blue as u16 >> 3);
+                    self.row.extend_from_slice(&pixel.to_le_bytes());
+                }
+            }
+            let offset = (top + y) * self.stride + left * self.bytes_per_pixel;
+            self.device.seek(SeekFrom::Start(offset as u64))?;
+            self.device.write_all(&self.row)?;
+        }
+        Ok(())
+    }
+}
+
+enum Display {
+    Framebuffer(Framebuffer),
+    Terminal(TerminalScreen),
+}
+
+impl Display {
+    // the framebuffer unless the terminal is asked for or there is no framebuffer to be had
+    fn open(settings: &Settings) -> io::Result<Self> {
+        if !settings.terminal {
+            match Framebuffer::open(settings.scale) {
+                Ok(framebuffer) => return Ok(Display::Framebuffer(framebuffer)),
+                Err(error) => eprintln!("no framebuffer ({error}), drawing in the terminal"),
+            }
+        }
+        Ok(Display::Terminal(TerminalScreen::open()?))
+    }
+
+    // display pixels per picture pixel, the terminal draws at most one cell per pixel
+    fn scale(&self) -> usize {
+        match self {
+            Display::Framebuffer(framebuffer) => framebuffer.scale,
+            Display::Terminal(_) => 1,
+        }
+    }
+
+    fn present(&mut self, frame: &[u8]) -> io::Result<()> {
+        match self {
+            Display::Framebuffer(framebuffer) => framebuffer.present(frame),
+            Display::Terminal(screen) => screen.present(frame),
+        }
+    }
+}
+
+// puts the terminal in raw mode for as long as it lives
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enable() -> io::Result<Self> {
+        stty(&["raw", "-echo"])?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = stty(&["sane"]);
+    }
+}
+
+fn stty(arguments: &[&str]) -> io::Result<()> {
+    let status = Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other("stty failed"))
+    }
+}
+
+fn spawn_keyboard() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let mut stdin = io::stdin();
+        while let Ok(count @ 1..) = stdin.read(&mut buffer) {
+            if sender.send(buffer[..count].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// the ps/2 packets of every mouse, three bytes of buttons and x and y movement each
+fn spawn_mouse() -> io::Result<Receiver<[u8; 3]>> {
+    let mut device = File::open("/dev/input/mice")?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut packet = [0; 3];
+        while device.read_exact(&mut packet).is_ok() {
+            if sender.send(packet).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+// the zapper follows the mouse across the picture, left button pulls the trigger and
+// holding the right one points the gun away from the screen
+struct Aim {
+    // in display pixels from the top left of the picture
+    x: i32,
+    y: i32,
+    trigger_frames: u8,
+    offscreen: bool,
+}
+
+impl Aim {
+    fn new(scale: usize) -> Self {
+        Aim {
+            x: (SCREEN_WIDTH * scale / 2) as i32,
+            y: (SCREEN_HEIGHT * scale / 2) as i32,
+            trigger_frames: 0,
+            offscreen: false,
+        }
+    }
+
+    fn update(&mut self, packet: [u8; 3], scale: usize) {
+        let [buttons, dx, dy] = packet;
+        // the sign bits of the movement live in the first byte, y grows upwards
+        let dx = dx as i32 - if buttons & 0x10 != 0 { 256 } else { 0 };
+        let dy = dy as i32 - if buttons & 0x20 != 0 { 256 } else { 0 };
+        self.x = (self.x + dx).clamp(0, (SCREEN_WIDTH * scale) as i32 - 1);
+        self.y = (self.y - dy).clamp(0, (SCREEN_HEIGHT * scale) as i32 - 1);
+        if buttons & 0x01 != 0 {
+            self.trigger_frames = TRIGGER_FRAMES;
+        }
+        self.offscreen = buttons & 0x02 != 0;
+    }
+
+    // the pixel aimed at
+    fn pixel(&self, scale: usize) -> (usize, usize) {
+        (self.x as usize / scale, self.y as usize / scale)
+    }
+}
+
+fn terminal_keys(input: &[u8]) -> Vec<TerminalKey> {
+    let mut keys = Vec::new();
+    let mut bytes = input.iter().copied();
+    while let Some(byte) = bytes.next() {
+        let key = match byte {
+            // arrow keys arrive as ESC [ A-D
+            0x1B => match (bytes.next(), bytes.next()) {
+                (Some(b'['), Some(b'A')) => TerminalKey::Up,
+                (Some(b'['), Some(b'B')) => TerminalKey::Down,
+                (Some(b'['), Some(b'C')) => TerminalKey::Right,
+                (Some(b'['), Some(b'D')) => TerminalKey::Left,
+                _ => continue,
+            },
+            b'\n' => TerminalKey::Char(b'\r'),
+            byte => TerminalKey::Char(byte.to_ascii_lowercase()),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+// controller bindings come first, so a rebound key shadows the hotkey it used to be
+fn parse_keys(input: &[u8], bindings: &[(TerminalKey, PadButton)]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    for key in terminal_keys(input) {
+        if let Some((_, pad)) = bindings.iter().find(|(bound, _)| *bound == key) {
+            keys.push(Key::Button(*pad));
+            continue;
+        }
+        let TerminalKey::Char(byte) = key else {
+            continue;
+        };
+        keys.push(match byte {
+            b'p' => Key::Pause,
+            b'n' => Key::FrameAdvance,
+            b'-' => Key::ChangeSpeed(-1),
+            b'=' | b'+' => Key::ChangeSpeed(1),
+            b't' => Key::Turbo,
+            b'r' => Key::Reset,
+            b'f' => Key::CycleFilter,
+            b'0'..=b'9' => Key::SelectSlot((byte - b'0') as usize),
+            b's' => Key::SaveState,
+            b'l' => Key::LoadState,
+            b'm' => Key::RestartRecording,
+            b'v' => Key::CycleMemoryView,
+            b'[' => Key::MemoryPage(-1),
+            b']' => Key::MemoryPage(1),
+            b'c' => Key::Screenshot,
+            b'o' => Key::CyclePalette,
+            b'd' => Key::RemapGamepad,
+            b'a' => Key::ToggleMixer,
+            b'w' => Key::NextChannel,
+            b'e' => Key::MuteChannel,
+            b'y' => Key::ChannelVolume(-1),
+            b'u' => Key::ChannelVolume(1),
+            b'i' => Key::ToggleStats,
+            b'h' => Key::PowerCycle,
+            b'g' => Key::CycleViewer,
+            b',' => Key::ViewerPalette(-1),
+            b'.' => Key::ViewerPalette(1),
+            // backspace
+            0x7F | 0x08 => Key::Rewind,
+            // ctrl-c is not turned into a signal in raw mode
+            b'q' | 0x03 => Key::Quit,
+            _ => continue,
+        });
+    }
+    keys
+}
+
+// a page of memory as a hex dump over the top of the picture, read without side effects
+fn draw_memory_view(emulator: &mut Emulator, space: MemorySpace, start: u16) {
+    let mut text = format!("{} memory\n", space.name());
+    let length = (MEMORY_PAGE as usize).min(space.size()) as u16;
+    for row in (0..length).step_by(16) {
+        let address = start.wrapping_add(row);
+        text.push_str(&format!("{address:04X}:"));
+        for offset in 0..16.min(length - row) {
+            let value = emulator.read_memory(space, address.wrapping_add(offset));
+            text.push_str(&format!(" {value:02X}"));
+        }
+        text.push('\n');
+    }
+    let lines = text.lines().count();
+    let overlay = emulator.overlay_mut();
+    let height = (lines * GLYPH_HEIGHT + 2) as u32;
+    overlay.rect(0, 0, SCREEN_WIDTH as u32, height, MEMORY_BACKGROUND, true);
+    overlay.text(2, 2, &text, MEMORY_TEXT_COLOR);
+}
+
+// in the bottom right corner, out of the way of the memory view and the mixer at the top
+fn draw_stats(emulator: &mut Emulator, stats: &Stats) {
+    let text = match stats.last() {
+        Some(last) => format!(
+            "{:.1} fps\nhost {:.2}ms\nmax {:.2}ms\naudio {:.1}ms\ncpu {}\nppu {}\ndropped {}",
+            stats.fps(),
+            stats.average_host_time().as_secs_f64() * 1000.0,
+            stats.max_host_time().as_secs_f64() * 1000.0,
+            last.buffered_audio.as_secs_f64() * 1000.0,
+            last.cpu_cycles,
+            last.ppu_dots,
+            stats.dropped()
+        ),
+        None => return,
+    };
+    let lines = text.lines().count();
+    let width = text.lines().map(str::len).max().unwrap_or(0) * GLYPH_WIDTH + 4;
+    let height = lines * GLYPH_HEIGHT + 2;
+    let (x, y) = (
+        (SCREEN_WIDTH - width) as i32,
+        (SCREEN_HEIGHT - height) as i32,
+    );
+    let overlay = emulator.overlay_mut();
+    overlay.rect(x, y, width as u32, height as u32, MEMORY_BACKGROUND, true);
+    overlay.text(x + 2, y + 2, &text, MEMORY_TEXT_COLOR);
+}
+
+// the note nearest to frequency, like A4 for 440Hz
+fn note_name(frequency: f64) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let note = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+    format!(
+        "{}{}",
+        NAMES[note.rem_euclid(12) as usize],
+        note.div_euclid(12) - 1
+    )
+}
+
+// "pulse1 80% A4", with the note left off while the channel plays none
+fn channel_status(emulator: &Emulator, channel: Channel) -> String {
+    let apu = emulator.apu();
+    let mut status = if apu.channel_enabled(channel) {
+        format!(
+            "{} {:.0}%",
+            channel.name(),
+            apu.channel_volume(channel) * 100.0
+        )
+    } else {
+        format!("{} off", channel.name())
+    };
+    if let Some(frequency) = apu.channel_frequency(channel) {
+        status.push(' ');
+        status.push_str(&note_name(frequency));
+    }
+    status
+}
+
+// a lane for each channel over the top of the picture with its level through the frame drawn
+// like an oscilloscope, the channel the mixer keys change is marked
+fn draw_mixer(emulator: &mut Emulator, levels: &[[u8; 5]], selected: Channel) {
+    let labels: Vec<String> = Channel::ALL
+        .into_iter()
+        .map(|channel| channel_status(emulator, channel))
+        .collect();
+    let enabled = Channel::ALL.map(|channel| emulator.apu().channel_enabled(channel));
+    let overlay = emulator.overlay_mut();
+    let height = MIXER_LANE as u32 * Channel::ALL.len() as u32;
+    overlay.rect(0, 0, SCREEN_WIDTH as u32, height, MEMORY_BACKGROUND, true);
+    for (index, channel) in Channel::ALL.into_iter().enumerate() {
+        let top = index as i32 * MIXER_LANE;
+        let marker = if channel == selected { "> " } else { "" };
+        overlay.text(
+            2,
+            top + 2,
+            &format!("{marker}{}", labels[index]),
+            MEMORY_TEXT_COLOR,
+        );
+        let color = if enabled[index] {
+            MIXER_WAVE_COLOR
+        } else {
+            MIXER_MUTED_COLOR
+        };
+        // the wave fills the lane under its label
+        let wave_top = top + GLYPH_HEIGHT as i32 + 4;
+        let wave_height = MIXER_LANE - GLYPH_HEIGHT as i32 - 6;
+        let max = channel.max_level() as i32;
+        for x in 0..SCREEN_WIDTH.min(levels.len()) {
+            let level = levels[x * levels.len() / SCREEN_WIDTH][index] as i32;
+            let y = wave_top + wave_height - level * wave_height / max;
+            overlay.rect(x as i32, y, 1, 1, color, true);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Viewer {
+    Nametables,
+    PatternTables,
+    Sprites,
+    Palette,
+}
+
+impl Viewer {
+    fn next(viewer: Option<Viewer>) -> Option<Viewer> {
+        match viewer {
+            None => Some(Viewer::Nametables),
+            Some(Viewer::Nametables) => Some(Viewer::PatternTables),
+            Some(Viewer::PatternTables) => Some(Viewer::Sprites),
+            Some(Viewer::Sprites) => Some(Viewer::Palette),
+            Some(Viewer::Palette) => None,
+        }
+    }
+}
+
+// scales image into the width x height area of a 256x240 RGBA8 frame at left, top
+fn blit(
+    frame: &mut [u8],
+    image: &Image,
+    palette: &Palette,
+    (left, top): (usize, usize),
+    (width, height): (usize, usize),
+) {
+    let rgba = image.to_rgba(palette);
+    for y in 0..height.min(SCREEN_HEIGHT - top) {
+        let source_y = y * image.height / height;
+        for x in 0..width.min(SCREEN_WIDTH - left) {
+            let source = (source_y * image.width + x * image.width / width) * 4;
+            let target = ((top + y) * SCREEN_WIDTH + left + x) * 4;
+            frame[target..target + 4].copy_from_slice(&rgba[source..source + 4]);
+        }
+    }
+}
+
+// a viewer in place of the picture, big things are shrunk to fit the 256x240 frame
+fn viewer_frame(emulator: &mut Emulator, viewer: Viewer, pattern_palette: u8) -> Vec<u8> {
+    let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+    let mut overlay = Overlay::default();
+    let white = [0xFF, 0xFF, 0xFF, 0xFF];
+    let label = match viewer {
+        Viewer::Nametables => {
+            // the four tables at half size, with the scroll of the next frame boxed
+            blit(
+                &mut frame,
+                &emulator.render_nametables(),
+                emulator.palette(),
+                (0, 0),
+                (256, 240),
+            );
+            let (x, y) = emulator.scroll();
+            let (x, y) = (x as i32 / 2, y as i32 / 2);
+            for (dx, dy) in [(0, 0), (256, 0), (0, 240), (256, 240)] {
+                overlay.rect(x - dx, y - dy, 128, 120, [0xFF, 0x40, 0x40, 0xFF], false);
+            }
+            "nametables".to_string()
+        }
+        Viewer::PatternTables => {
+            blit(
+                &mut frame,
+                &emulator.render_pattern_tables(pattern_palette),
+                emulator.palette(),
+                (0, 16),
+                (256, 128),
+            );
+            format!("pattern tables, palette {pattern_palette}  , and . pick it")
+        }
+        Viewer::Sprites => {
+            blit(
+                &mut frame,
+                &emulator.render_sprites(),
+                emulator.palette(),
+                (0, 16),
+                (256, 128),
+            );
+            // the first sprites decoded, there is only room for a quarter of them
+            let sprites = emulator.sprites();
+            let mut text = String::new();
+            for (index, sprite) in sprites.iter().enumerate().take(15) {
+                text.push_str(&format!(
+                    "{index:2} x:{:3} y:{:3} tile:{:02X} pal:{}{}{}{}\n",
+                    sprite.x,
+                    sprite.y,
+                    sprite.tile,
+                    sprite.palette,
+                    if sprite.behind_background {
+                        " behind"
+                    } else {
+                        ""
+                    },
+                    if sprite.flip_horizontal { " h" } else { "" },
+                    if sprite.flip_vertical { " v" } else { "" },
+                ));
+            }
+            overlay.text(2, 146, &text, white);
+            "sprites".to_string()
+        }
+        Viewer::Palette => {
+            blit(
+                &mut frame,
+                &emulator.render_palette(),
+                emulator.palette(),
+                (0, 16),
+                (256, 32),
+            );
+            let entries: Vec<String> = emulator
+                .palette_ram()
+                .iter()
+                .map(|entry| format!("{entry:02X}"))
+                .collect();
+            overlay.text(2, 52, &entries[..16].join(" "), white);
+            overlay.text(2, 60, &entries[16..].join(" "), white);
+            "palette".to_string()
+        }
+    };
+    overlay.text(2, 2, &label, white);
+    overlay.draw(&mut frame);
+    frame
+}
+
+// the state of an interactive session between frames, run a step at a time by run
+struct Session<'a> {
+    emulator: &'a mut Emulator,
+    settings: &'a Settings,
+    movie: &'a mut MovieMode,
+    // every frame shown goes here as well, picture and sound
+    capture: Option<&'a mut Capture>,
+    netplay: Option<&'a mut Netplay>,
+    audio: Option<AudioOutput>,
+    display: Display,
+    _terminal: RawTerminal,
+    keyboard: Receiver<Vec<u8>>,
+    mouse: Option<Receiver<[u8; 3]>>,
+    aim: Aim,
+    // the space and first address shown by the memory view while it is open
+    memory_view: Option<(MemorySpace, u16)>,
+    viewer: Option<Viewer>,
+    viewer_palette: u8,
+    mixer: bool,
+    stats: Rc<RefCell<Stats>>,
+    show_stats: bool,
+    channel: Channel,
+    palette: usize,
+    // frames past the display's rate are run but not shown when going faster than real time
+    frame_duration: Duration,
+    last_present: Instant,
+    pad: HeldKeys,
+    gamepad_events: Receiver<(usize, GamepadEvent)>,
+    gamepads: Vec<Option<Gamepad>>,
+    gamepad_map: GamepadMap,
+    // the inputs given so far while remapping
+    remap: Option<Vec<GamepadInput>>,
+    paused: bool,
+    advance: bool,
+    speed: f64,
+    turbo_until: Option<Instant>,
+    rewind: Option<Rewind>,
+    rewind_held: u8,
+    // quick save slots, kept for the lifetime of the session along with
+    // the length the recording had when each was saved
+    slots: [Option<(Vec<u8>, usize)>; 10],
+    slot: usize,
+    frames_since_flush: u32,
+}
+
+impl<'a> Session<'a> {
+    fn open(
+        emulator: &'a mut Emulator,
+        settings: &'a Settings,
+        movie: &'a mut MovieMode,
+        capture: Option<&'a mut Capture>,
+        netplay: Option<&'a mut Netplay>,
+    ) -> io::Result<Self> {
+        let display = Display::open(settings)?;
+        let terminal = RawTerminal::enable()?;
+        let mouse = if emulator.zapper_connected() {
+            Some(spawn_mouse().map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("the zapper needs /dev/input/mice: {error}"),
+                )
+            })?)
+        } else {
+            None
+        };
+        let mut autofire = Autofire::new(emulator.region().frame_rate());
+        for (button, rate) in &settings.turbo_rates {
+            autofire.set_rate(*button, *rate);
+        }
+        Ok(Session {
+            aim: Aim::new(display.scale()),
+            stats: Stats::attach(emulator),
+            frame_duration: Duration::from_secs_f64(1.0 / emulator.region().frame_rate()),
+            audio: open_audio(emulator),
+            emulator,
+            settings,
+            movie,
+            capture,
+            netplay,
+            display,
+            _terminal: terminal,
+            keyboard: spawn_keyboard(),
+            mouse,
+            memory_view: None,
+            viewer: None,
+            viewer_palette: 0,
+            mixer: false,
+            show_stats: false,
+            channel: Channel::Pulse1,
+            palette: settings.palette,
+            last_present: Instant::now(),
+            pad: HeldKeys::new(autofire),
+            gamepad_events: gamepad::spawn(&settings.gamepad_devices),
+            gamepads: settings.gamepad_devices.iter().map(|_| None).collect(),
+            gamepad_map: settings.gamepad_map.clone(),
+            remap: None,
+            paused: false,
+            advance: false,
+            speed: 1.0,
+            turbo_until: None,
+            rewind: (settings.rewind_budget > 0).then(|| Rewind::new(settings.rewind_budget)),
+            rewind_held: 0,
+            slots: Default::default(),
+            slot: 0,
+            frames_since_flush: 0,
+        })
+    }
+
+    // false once q has been pressed
+    fn read_keys(&mut self) -> bool {
+        let input: Vec<Vec<u8>> = self.keyboard.try_iter().collect();
+        for input in input {
+            for key in parse_keys(&input, &self.settings.bindings) {
+                if !self.press(key) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn press(&mut self, key: Key) -> bool {
+        let emulator = &mut *self.emulator;
+        match key {
+            Key::Button(button) => self.pad.press(button),
+            // the peer runs in lockstep, so nothing may stop or turn back the frames
+            Key::Pause
+            | Key::FrameAdvance
+            | Key::ChangeSpeed(_)
+            | Key::Turbo
+            | Key::LoadState
+            | Key::Rewind
+                if self.netplay.is_some() =>
+            {
+                eprint!("not during netplay\r\n");
+            }
+            Key::Pause => self.paused = !self.paused,
+            Key::FrameAdvance => {
+                self.paused = true;
+                self.advance = true;
+            }
+            // a recording takes the samples of each frame as they come, so it needs them at 1x
+            Key::ChangeSpeed(_) | Key::Turbo if self.capture.is_some() => {
+                eprint!("the speed stays at 1x while recording video\r\n");
+            }
+            Key::ChangeSpeed(step) => {
+                self.speed = (self.speed * 2f64.powi(step)).clamp(MIN_SPEED, MAX_SPEED);
+                eprint!("speed {}\r\n", Speed::Multiplier(self.speed));
+            }
+            Key::Turbo => self.turbo_until = Some(Instant::now() + TURBO_HOLD),
+            // pressed through the pad so a recording sees it
+            Key::Reset => self.pad.reset = true,
+            Key::PowerCycle => self.pad.power = true,
+            Key::CycleFilter => {
+                let next = match emulator.video_filter() {
+                    VideoFilter::Rgb => VideoFilter::Ntsc,
+                    VideoFilter::Ntsc => VideoFilter::SVideo,
+                    VideoFilter::SVideo => VideoFilter::Rgb,
+                };
+                emulator.set_video_filter(next);
+            }
+            Key::SelectSlot(index) => self.slot = index,
+            Key::SaveState => {
+                let frames = match &self.movie {
+                    MovieMode::Record(movie) => movie.len(),
+                    _ => 0,
+                };
+                self.slots[self.slot] = Some((emulator.save_state(), frames));
+            }
+            Key::LoadState => {
+                if let Some((state, frames)) = &self.slots[self.slot] {
+                    match emulator.load_state(state) {
+                        // the recording goes back to where the state was saved
+                        Ok(()) => {
+                            if let MovieMode::Record(movie) = &mut self.movie {
+                                movie.frames.truncate(*frames);
+                                movie.rerecord_count += 1;
+                            }
+                        }
+                        // raw mode needs the carriage return spelled out
+                        Err(error) => eprint!("error: {error}\r\n"),
+                    }
+                }
+            }
+            Key::Rewind => {
+                if self.rewind_held == 0
+                    && let MovieMode::Record(movie) = &mut self.movie
+                {
+                    movie.rerecord_count += 1;
+                }
+                self.rewind_held = HOLD_FRAMES;
+            }
+            // the recording starts over from a save state of this moment
+            Key::RestartRecording => {
+                if let MovieMode::Record(movie) = &mut self.movie {
+                    movie.frames.clear();
+                    movie.savestate = Some(emulator.save_state());
+                    if let Some(rewind) = &mut self.rewind {
+                        rewind.clear();
+                    }
+                    self.slots = Default::default();
+                    eprint!("recording from here\r\n");
+                }
+            }
+            Key::CycleMemoryView => {
+                self.memory_view = match self.memory_view {
+                    None => Some((MemorySpace::ALL[0], 0)),
+                    Some((space, _)) => MemorySpace::ALL
+                        .into_iter()
+                        .skip_while(|other| *other != space)
+                        .nth(1)
+                        .map(|next| (next, 0)),
+                };
+            }
+            Key::MemoryPage(pages) => {
+                if let Some((space, start)) = &mut self.memory_view {
+                    let size = space.size() as i32;
+                    let page = (MEMORY_PAGE as i32).min(size);
+                    *start = (*start as i32 + pages * page).rem_euclid(size) as u16;
+                }
+            }
+            Key::CycleViewer => self.viewer = Viewer::next(self.viewer),
+            Key::ViewerPalette(step) => {
+                self.viewer_palette = (self.viewer_palette as i32 + step).rem_euclid(8) as u8;
+            }
+            Key::Screenshot => {
+                let mut name = self.settings.screenshot_base.clone().into_os_string();
+                name.push(format!("-{}.png", emulator.frame_count()));
+                let path = PathBuf::from(name);
+                match emulator.save_screenshot(&path, self.settings.raw_screenshots) {
+                    Ok(()) => eprint!("saved {}\r\n", path.display()),
+                    Err(error) => eprint!("error: {}: {error}\r\n", path.display()),
+                }
+            }
+            Key::CyclePalette => {
+                let palettes = &self.settings.palettes;
+                if !palettes.is_empty() {
+                    self.palette = (self.palette + 1) % palettes.len();
+                    let (name, colors) = &palettes[self.palette];
+                    emulator.set_palette(colors.clone());
+                    eprint!("{name}\r\n");
+                }
+            }
+            Key::RemapGamepad if self.gamepads.iter().all(Option::is_none) => {
+                eprint!("no gamepad is connected\r\n");
+            }
+            Key::RemapGamepad => {
+                self.remap = Some(Vec::new());
+                eprint!("gamepad: press {:?}\r\n", REMAP_ORDER[0]);
+            }
+            Key::ToggleMixer => {
+                self.mixer = !self.mixer;
+                emulator.apu_mut().set_record_levels(self.mixer);
+            }
+            Key::NextChannel => {
+                self.channel = Channel::ALL[(self.channel as usize + 1) % Channel::ALL.len()];
+                eprint!("{}\r\n", channel_status(emulator, self.channel));
+            }
+            Key::MuteChannel => {
+                let enabled = emulator.apu().channel_enabled(self.channel);
+                emulator
+                    .apu_mut()
+                    .set_channel_enabled(self.channel, !enabled);
+                eprint!("{}\r\n", channel_status(emulator, self.channel));
+            }
+            Key::ChannelVolume(step) => {
+                let volume = emulator.apu().channel_volume(self.channel);
+                let volume = ((volume / VOLUME_STEP).round() + step as f32) * VOLUME_STEP;
+                emulator.apu_mut().set_channel_volume(self.channel, volume);
+                eprint!("{}\r\n", channel_status(emulator, self.channel));
+            }
+            Key::ToggleStats => self.show_stats = !self.show_stats,
+            Key::Quit => return false,
+        }
+        true
+    }
+
+    fn read_gamepads(&mut self) {
+        for (player, event) in self.gamepad_events.try_iter() {
+            match event {
+                GamepadEvent::Connected(name) => {
+                    eprint!("{name} connected as player {}\r\n", player + 1);
+                    self.gamepads[player] = Some(Gamepad::default());
+                }
+                GamepadEvent::Disconnected => {
+                    eprint!("the gamepad of player {} was unplugged\r\n", player + 1);
+                    self.gamepads[player] = None;
+                }
+                GamepadEvent::Changed(kind, number, value) => {
+                    let Some(gamepad) = &mut self.gamepads[player] else {
+                        continue;
+                    };
+                    let pressed = gamepad.update(kind, number, value, self.gamepad_map.deadzone);
+                    if let (Some(inputs), Some(input)) = (&mut self.remap, pressed)
+                        && !inputs.contains(&input)
+                    {
+                        inputs.push(input);
+                        match REMAP_ORDER.get(inputs.len()) {
+                            Some(next) => eprint!("gamepad: press {next:?}\r\n"),
+                            None => {
+                                self.gamepad_map.bindings =
+                                    inputs.iter().copied().zip(REMAP_ORDER).collect();
+                                self.remap = None;
+                                let path = self.settings.config_path.as_deref();
+                                save_gamepad_map(path, &self.gamepad_map);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        // the game sees nothing of the gamepads while they are remapped
+        for (held, gamepad) in self.pad.gamepads.iter_mut().zip(&self.gamepads) {
+            *held = match gamepad {
+                Some(gamepad) if self.remap.is_none() => gamepad.buttons(&self.gamepad_map),
+                _ => 0,
+            };
+        }
+    }
+
+    fn read_mouse(&mut self) {
+        let Some(mouse) = &self.mouse else {
+            return;
+        };
+        for packet in mouse.try_iter() {
+            self.aim.update(packet, self.display.scale());
+        }
+        let pixel = (!self.aim.offscreen).then(|| self.aim.pixel(self.display.scale()));
+        self.emulator.set_zapper(pixel, self.aim.trigger_frames > 0);
+        self.aim.trigger_frames = self.aim.trigger_frames.saturating_sub(1);
+    }
+
+    // runs the next frame, or a step back while rewinding. false when paused or halted
+    fn emulate(&mut self) -> io::Result<bool> {
+        let emulator = &mut *self.emulator;
+        let turbo = self.turbo_until.is_some_and(|until| Instant::now() < until);
+        let wanted = if turbo {
+            Speed::Unlimited
+        } else {
+            Speed::Multiplier(self.speed)
+        };
+        if emulator.speed() != wanted {
+            emulator.set_speed(wanted);
+        }
+
+        // while rewinding each frame goes back one and is run again to draw it,
+        // a movie being played cannot be rewound
+        let rewinding = !self.paused
+            && self.rewind_held > 0
+            && !matches!(self.movie, MovieMode::Play(_))
+            && self.rewind.as_mut().is_some_and(|rewind| {
+                rewind.rewind(emulator).unwrap_or_else(|error| {
+                    eprint!("error: rewind: {error}\r\n");
+                    false
+                })
+            });
+        self.rewind_held = self.rewind_held.saturating_sub(1);
+        let running = !self.paused || std::mem::take(&mut self.advance);
+        if !running || (!rewinding && emulator.halted()) {
+            return Ok(false);
+        }
+        if !rewinding
+            && self.netplay.is_none()
+            && let Some(rewind) = &mut self.rewind
+        {
+            rewind.push(emulator);
+        }
+        match &mut self.movie {
+            MovieMode::Off => match &mut self.netplay {
+                Some(netplay) => {
+                    let input = self.pad.next_frame().unwrap_or_default();
+                    netplay
+                        .step_frame(emulator, input)
+                        .map_err(|error| io::Error::other(format!("netplay: {error}")))?;
+                }
+                None => {
+                    emulator.step_frame_with(&mut self.pad);
+                }
+            },
+            MovieMode::Play(player) => {
+                if emulator.step_frame_with(player).is_none() {
+                    eprint!("movie finished\r\n");
+                    *self.movie = MovieMode::Off;
+                    emulator.step_frame_with(&mut self.pad);
+                }
+            }
+            MovieMode::Record(movie) => {
+                // the rewound frame and the one run again to draw it are recorded anew
+                if rewinding {
+                    movie.frames.truncate(movie.frames.len().saturating_sub(2));
+                }
+                let mut recorder = Recorder {
+                    source: &mut self.pad,
+                    movie,
+                };
+                emulator.step_frame_with(&mut recorder);
+            }
+        }
+        // what a raw program prints, between the lines a raw terminal needs carriage returns
+        let output = emulator.take_output();
+        if !output.is_empty() {
+            eprint!("{}", String::from_utf8_lossy(&output).replace('\n', "\r\n"));
+        }
+        let printed = emulator.take_script_output();
+        if !printed.is_empty() {
+            eprint!("{}", printed.replace('\n', "\r\n"));
+        }
+        self.frames_since_flush += 1;
+        if self.frames_since_flush == SAVE_FILE_INTERVAL {
+            self.frames_since_flush = 0;
+            emulator.flush_save_file()?;
+        }
+        Ok(true)
+    }
+
+    // the sound of the frame just run goes to the player, and is returned for a recording
+    fn audio(&mut self) -> Vec<f32> {
+        let samples = self.emulator.take_audio_samples();
+        play(&mut self.audio, &samples);
+        samples
+    }
+
+    // until the next frame is due. the sound device's clock holds frames back when it plays
+    // slower than the timer runs them
+    fn wait(&mut self) {
+        self.emulator.wait_for_frame();
+        if let Some(audio) = &self.audio
+            && self.emulator.frame_duration().is_some()
+        {
+            audio.wait(AUDIO_BACKLOG);
+        }
+    }
+
+    // the overlays, then the frame or the viewer when the display is due one, and the frame to a
+    // recording with its sound
+    fn present(&mut self, samples: &[f32]) -> io::Result<()> {
+        let emulator = &mut *self.emulator;
+        if self.mouse.is_some() && !self.aim.offscreen {
+            let (x, y) = self.aim.pixel(self.display.scale());
+            let overlay = emulator.overlay_mut();
+            overlay.rect(x as i32 - 3, y as i32, 7, 1, CROSSHAIR_COLOR, true);
+            overlay.rect(x as i32, y as i32 - 3, 1, 7, CROSSHAIR_COLOR, true);
+        }
+        if let Some((space, start)) = self.memory_view {
+            draw_memory_view(emulator, space, start);
+        }
+        if self.mixer {
+            let levels = emulator.apu_mut().take_levels();
+            draw_mixer(emulator, &levels, self.channel);
+        }
+        if self.show_stats {
+            draw_stats(emulator, &self.stats.borrow());
+        }
+        if self.settings.stats
+            && let Some(report) = self.stats.borrow_mut().take_report()
+        {
+            eprint!("{report}\r\n");
+        }
+        let present = match emulator.speed() {
+            Speed::Multiplier(multiplier) if multiplier <= 1.0 => true,
+            _ => self.last_present.elapsed() >= self.frame_duration,
+        };
+        if present {
+            self.last_present = Instant::now();
+        }
+        // the recording gets the picture even while a viewer is shown instead
+        if (present && self.viewer.is_none()) || self.capture.is_some() {
+            let frame = emulator.video_frame();
+            if let Some(capture) = &mut self.capture {
+                capture.push_frame(frame, samples)?;
+            }
+            if present && self.viewer.is_none() {
+                self.display.present(frame)?;
+            }
+        }
+        if present && let Some(viewer) = self.viewer {
+            let frame = viewer_frame(emulator, viewer, self.viewer_palette);
+            self.display.present(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run(
+    emulator: &mut Emulator,
+    settings: &Settings,
+    movie: &mut MovieMode,
+    capture: Option<&mut Capture>,
+    netplay: Option<&mut Netplay>,
+) -> io::Result<()> {
+    let mut session = Session::open(emulator, settings, movie, capture, netplay)?;
+    loop {
+        if !session.read_keys() {
+            return Ok(());
+        }
+        session.read_gamepads();
+        session.read_mouse();
+        if session.emulate()? {
+            let samples = session.audio();
+            session.present(&samples)?;
+        }
+        session.wait();
+    }
+}
+
+// sound goes out through pacat or aplay, a session without either is silent
+fn open_audio(emulator: &Emulator) -> Option<AudioOutput> {
+    match AudioOutput::open(emulator.sample_rate()) {
+        Ok(audio) => Some(audio),
+        Err(error) => {
+            eprint!("no sound: {error}\r\n");
+            None
+        }
+    }
+}
+
+// a player that stops taking samples is dropped, and the session goes on without sound
+fn play(audio: &mut Option<AudioOutput>, samples: &[f32]) {
+    if let Some(output) = audio
+        && let Err(error) = output.push(samples)
+    {
+        eprint!("error: {error}\r\n");
+        *audio = None;
+    }
+}
+
+// plays an nsf with no picture: left and right change the song, 1 to 5 turn the channels off and
+// on, p pauses and q quits. the sound is played and goes to wav as well
+pub fn play_music(emulator: &mut Emulator, mut wav: Option<&mut WavWriter>) -> io::Result<()> {
+    let mut audio = open_audio(emulator);
+    let _terminal = RawTerminal::enable()?;
+    let keyboard = spawn_keyboard();
+    let songs = emulator.nsf().map_or(0, |nsf| nsf.songs);
+    let mut paused = false;
+    let mut shown = None;
+    loop {
+        for input in keyboard.try_iter() {
+            for key in terminal_keys(&input) {
+                match key {
+                    TerminalKey::Left => emulator.previous_song(),
+                    TerminalKey::Right => emulator.next_song(),
+                    TerminalKey::Char(b'p') => paused = !paused,
+                    TerminalKey::Char(digit @ b'1'..=b'5') => {
+                        let channel = Channel::ALL[(digit - b'1') as usize];
+                        let enabled = emulator.apu().channel_enabled(channel);
+                        emulator.apu_mut().set_channel_enabled(channel, !enabled);
+                    }
+                    TerminalKey::Char(b'q' | 0x03) => {
+                        eprint!("\r\n");
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if !paused {
+            emulator.step_nsf();
+            let samples = emulator.take_audio_samples();
+            play(&mut audio, &samples);
+            if let Some(wav) = &mut wav {
+                wav.write(&samples)?;
+            }
+        }
+        let muted: Vec<&str> = Channel::ALL
+            .into_iter()
+            .filter(|channel| !emulator.apu().channel_enabled(*channel))
+            .map(Channel::name)
+            .collect();
+        let status = (emulator.song().unwrap_or(0), paused, muted);
+        if shown.as_ref() != Some(&status) {
+            let state = if paused { " paused" } else { "" };
+            let muted = if status.2.is_empty() {
+                String::new()
+            } else {
+                format!(", {} off", status.2.join(" "))
+            };
+            eprint!("\rsong {}/{songs}{state}{muted}\x1B[K", status.0);
+            shown = Some(status);
+        }
+        emulator.wait_for_frame();
+        if let Some(audio) = &audio {
+            audio.wait(AUDIO_BACKLOG);
+        }
+    }
+}
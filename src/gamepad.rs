@@ -0,0 +1,239 @@
+// physical gamepads through the linux joystick devices /dev/input/js*, which the kernel creates for
+// anything it knows as a joystick. each player's device is looked for again every second, so pads
+// can be plugged in and pulled out while a game runs
+use ntsc_nes::controller::Button;
+use std::fs::{self, File};
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+// struct js_event: u32 milliseconds, i16 value, u8 type, u8 number
+const EVENT_SIZE: usize = 8;
+const EVENT_BUTTON: u8 = 0x01;
+const EVENT_AXIS: u8 = 0x02;
+// marks the events a device sends when opened, telling where everything starts out
+const EVENT_INIT: u8 = 0x80;
+// how far out of 1 an axis has to be pushed to press its direction
+pub const DEFAULT_DEADZONE: f32 = 0.5;
+// the order the remapping asks for the buttons in
+pub const REMAP_ORDER: [Button; 8] = [
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+];
+
+// a button or one direction of an axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadInput {
+    Button(u8),
+    // the axis and whether it is pushed below its centre
+    Axis(u8, bool),
+}
+
+impl GamepadInput {
+    // "axis<n>-" or "axis<n>+", buttons are plain numbers
+    pub fn from_axis_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix("axis")?;
+        let (number, negative) = match (name.strip_suffix('-'), name.strip_suffix('+')) {
+            (Some(number), _) => (number, true),
+            (_, Some(number)) => (number, false),
+            _ => return None,
+        };
+        Some(GamepadInput::Axis(number.parse().ok()?, negative))
+    }
+}
+
+// what the buttons and axes of a gamepad press, the same for every player's
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadMap {
+    pub bindings: Vec<(GamepadInput, Button)>,
+    pub deadzone: f32,
+}
+
+impl Default for GamepadMap {
+    // laid out like an xbox pad: the d-pad hat and the left stick steer, b and a are the face
+    // buttons to the right, select and start are back and start
+    fn default() -> Self {
+        let mut bindings = vec![
+            (GamepadInput::Button(1), Button::A),
+            (GamepadInput::Button(0), Button::B),
+            (GamepadInput::Button(6), Button::Select),
+            (GamepadInput::Button(7), Button::Start),
+        ];
+        for axis in [0, 6] {
+            bindings.push((GamepadInput::Axis(axis, true), Button::Left));
+            bindings.push((GamepadInput::Axis(axis, false), Button::Right));
+        }
+        for axis in [1, 7] {
+            bindings.push((GamepadInput::Axis(axis, true), Button::Up));
+            bindings.push((GamepadInput::Axis(axis, false), Button::Down));
+        }
+        GamepadMap {
+            bindings,
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+impl GamepadMap {
+    // the default map with every button in rebound given its new inputs instead
+    pub fn new(rebound: &[(Button, Vec<GamepadInput>)], deadzone: Option<f32>) -> Self {
+        let mut map = GamepadMap::default();
+        map.bindings
+            .retain(|(_, button)| !rebound.iter().any(|(rebound, _)| rebound == button));
+        for (button, inputs) in rebound {
+            map.bindings
+                .extend(inputs.iter().map(|input| (*input, *button)));
+        }
+        map.deadzone = deadzone.unwrap_or(map.deadzone);
+        map
+    }
+
+    // the inputs of each button, in the order of REMAP_ORDER
+    pub fn inputs(&self) -> Vec<(Button, Vec<GamepadInput>)> {
+        REMAP_ORDER
+            .into_iter()
+            .map(|button| {
+                let inputs = self
+                    .bindings
+                    .iter()
+                    .filter(|(_, bound)| *bound == button)
+                    .map(|(input, _)| *input)
+                    .collect();
+                (button, inputs)
+            })
+            .collect()
+    }
+}
+
+pub enum GamepadEvent {
+    // with the name the device gives itself
+    Connected(String),
+    Disconnected,
+    // a js_event: its type, number and value
+    Changed(u8, u8, i16),
+}
+
+// watches the device numbered devices[n] for player n + 1
+pub fn spawn(devices: &[u8]) -> Receiver<(usize, GamepadEvent)> {
+    let (sender, receiver) = mpsc::channel();
+    for (player, &device) in devices.iter().enumerate() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            loop {
+                let path = format!("/dev/input/js{device}");
+                if let Ok(mut file) = File::open(&path) {
+                    let name =
+                        fs::read_to_string(format!("/sys/class/input/js{device}/device/name"))
+                            .map_or(path, |name| name.trim().to_string());
+                    if sender
+                        .send((player, GamepadEvent::Connected(name)))
+                        .is_err()
+                    {
+                        return;
+                    }
+                    let mut event = [0; EVENT_SIZE];
+                    while file.read_exact(&mut event).is_ok() {
+                        let value = i16::from_le_bytes([event[4], event[5]]);
+                        let changed = GamepadEvent::Changed(event[6], event[7], value);
+                        if sender.send((player, changed)).is_err() {
+                            return;
+                        }
+                    }
+                    if sender.send((player, GamepadEvent::Disconnected)).is_err() {
+                        return;
+                    }
+                }
+                thread::sleep(RESCAN_INTERVAL);
+            }
+        });
+    }
+    receiver
+}
+
+// where the buttons and axes of one gamepad are
+pub struct Gamepad {
+    buttons: [bool; 256],
+    axes: [i16; 256],
+    // axes that rest away from the centre, like the triggers of some pads, are never remapped to
+    off_centre: [bool; 256],
+}
+
+impl Default for Gamepad {
+    fn default() -> Self {
+        Gamepad {
+            buttons: [false; 256],
+            axes: [0; 256],
+            off_centre: [false; 256],
+        }
+    }
+}
+
+impl Gamepad {
+    // takes in an event, and returns the input it newly pressed
+    pub fn update(
+        &mut self,
+        kind: u8,
+        number: u8,
+        value: i16,
+        deadzone: f32,
+    ) -> Option<GamepadInput> {
+        let init = kind & EVENT_INIT != 0;
+        let index = number as usize;
+        match kind & !EVENT_INIT {
+            EVENT_BUTTON => {
+                let pressed = !self.buttons[index] && value != 0;
+                self.buttons[index] = value != 0;
+                (pressed && !init).then_some(GamepadInput::Button(number))
+            }
+            EVENT_AXIS => {
+                let was = [true, false]
+                    .map(|negative| self.pressed(GamepadInput::Axis(number, negative), deadzone));
+                self.axes[index] = value;
+                if init {
+                    self.off_centre[index] = beyond_deadzone(value, deadzone);
+                    return None;
+                }
+                if self.off_centre[index] {
+                    return None;
+                }
+                [true, false]
+                    .into_iter()
+                    .zip(was)
+                    .map(|(negative, was)| (GamepadInput::Axis(number, negative), was))
+                    .find(|&(input, was)| !was && self.pressed(input, deadzone))
+                    .map(|(input, _)| input)
+            }
+            _ => None,
+        }
+    }
+
+    fn pressed(&self, input: GamepadInput, deadzone: f32) -> bool {
+        match input {
+            GamepadInput::Button(number) => self.buttons[number as usize],
+            GamepadInput::Axis(number, negative) => {
+                let value = self.axes[number as usize];
+                beyond_deadzone(value, deadzone) && (value < 0) == negative
+            }
+        }
+    }
+
+    // the nes buttons pressed, in the bit order of Button
+    pub fn buttons(&self, map: &GamepadMap) -> u8 {
+        map.bindings
+            .iter()
+            .filter(|(input, _)| self.pressed(*input, map.deadzone))
+            .fold(0, |buttons, (_, button)| buttons | *button as u8)
+    }
+}
+
+fn beyond_deadzone(value: i16, deadzone: f32) -> bool {
+    (value as f32 / i16::MAX as f32).abs() > deadzone
+}
@@ -0,0 +1,290 @@
+// golden runs: checkpoints of the machine every few frames of a run, written to a file that a later
+// run of the same rom and input is checked against. the first checkpoint that differs stops the run
+// with both sides and the state there, which catches a refactor of the cpu or the ppu changing what
+// a game does long before anyone would see it. a checkpoint has the cpu registers and cycle count,
+// where the ppu is, and crc32s of work ram, the ppu's state and the picture
+use crate::Emulator;
+use crate::crc::crc32;
+use crate::savestate::{Savestate, StateWriter};
+use std::fmt;
+
+const HEADER: &str = "ntsc-nes golden run 1";
+pub const DEFAULT_INTERVAL: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GoldenError {
+    NotAGoldenRun,
+    // 1 based number of a line that is not a checkpoint
+    BadLine(usize),
+    // 1 based number of a checkpoint whose frame is not after the one before it
+    OutOfOrder(usize),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::NotAGoldenRun => {
+                write!(f, "not a golden run, it should start with \"{HEADER}\"")
+            }
+            GoldenError::BadLine(line) => write!(f, "line {line} is not a valid checkpoint"),
+            GoldenError::OutOfOrder(line) => write!(
+                f,
+                "the checkpoint on line {line} is not of a frame after the one before it"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub frame: u64,
+    pub cycles: u64,
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub scanline: u16,
+    pub dot: u16,
+    pub ram: u32,
+    pub ppu: u32,
+    pub picture: u32,
+}
+
+impl Checkpoint {
+    pub fn take(emulator: &Emulator) -> Self {
+        let cpu = emulator.cpu();
+        let mut ppu = StateWriter::new();
+        emulator.ppu.save(&mut ppu);
+        let picture: Vec<u8> = emulator
+            .framebuffer()
+            .iter()
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .collect();
+        Checkpoint {
+            frame: emulator.frame_count(),
+            cycles: emulator.cycles(),
+            pc: cpu.program_counter,
+            a: cpu.reg_a,
+            x: cpu.reg_x,
+            y: cpu.reg_y,
+            p: cpu.flags.to_byte(false),
+            sp: cpu.stack_pointer,
+            scanline: emulator.ppu.scanline(),
+            dot: emulator.ppu.dot(),
+            ram: crc32(emulator.ram()),
+            ppu: crc32(&ppu.into_bytes()),
+            picture: crc32(&picture),
+        }
+    }
+
+    // the fields by name, hex for all but the counts, as they are written to the file
+    fn fields(&self) -> [(&'static str, String); 13] {
+        [
+            ("frame", self.frame.to_string()),
+            ("cycles", self.cycles.to_string()),
+            ("pc", format!("{:04X}", self.pc)),
+            ("a", format!("{:02X}", self.a)),
+            ("x", format!("{:02X}", self.x)),
+            ("y", format!("{:02X}", self.y)),
+            ("p", format!("{:02X}", self.p)),
+            ("sp", format!("{:02X}", self.sp)),
+            ("line", self.scanline.to_string()),
+            ("dot", self.dot.to_string()),
+            ("ram", format!("{:08X}", self.ram)),
+            ("ppu", format!("{:08X}", self.ppu)),
+            ("picture", format!("{:08X}", self.picture)),
+        ]
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut checkpoint = Checkpoint::default();
+        let mut found = Vec::new();
+        for field in line.split_whitespace() {
+            let (key, value) = field.split_once('=')?;
+            if found.contains(&key) {
+                return None;
+            }
+            let hex = |value| u32::from_str_radix(value, 16).ok();
+            match key {
+                "frame" => checkpoint.frame = value.parse().ok()?,
+                "cycles" => checkpoint.cycles = value.parse().ok()?,
+                "pc" => checkpoint.pc = u16::from_str_radix(value, 16).ok()?,
+                "a" => checkpoint.a = u8::from_str_radix(value, 16).ok()?,
+                "x" => checkpoint.x = u8::from_str_radix(value, 16).ok()?,
+                "y" => checkpoint.y = u8::from_str_radix(value, 16).ok()?,
+                "p" => checkpoint.p = u8::from_str_radix(value, 16).ok()?,
+                "sp" => checkpoint.sp = u8::from_str_radix(value, 16).ok()?,
+                "line" => checkpoint.scanline = value.parse().ok()?,
+                "dot" => checkpoint.dot = value.parse().ok()?,
+                "ram" => checkpoint.ram = hex(value)?,
+                "ppu" => checkpoint.ppu = hex(value)?,
+                "picture" => checkpoint.picture = hex(value)?,
+                _ => return None,
+            }
+            found.push(key);
+        }
+        (found.len() == checkpoint.fields().len()).then_some(checkpoint)
+    }
+}
+
+// frame=60 cycles=1789773 pc=C123 a=00 x=01 y=02 p=24 sp=FD line=241 dot=9 ram=... ppu=... picture=...
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields()
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        write!(f, "{}", fields.join(" "))
+    }
+}
+
+// the first checkpoint of a run that isn't the recorded one, with the state the run got to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub expected: Checkpoint,
+    pub actual: Checkpoint,
+    pub dump: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "frame {} differs from the golden run",
+            self.expected.frame
+        )?;
+        writeln!(f, "  {:<8} {:<10} got", "", "expected")?;
+        for ((key, expected), (_, actual)) in
+            self.expected.fields().into_iter().zip(self.actual.fields())
+        {
+            let marker = if expected == actual { ' ' } else { '*' };
+            writeln!(f, "{marker} {key:<8} {expected:<10} {actual}")?;
+        }
+        write!(f, "{}", self.dump)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenRun {
+    // frames between checkpoints
+    pub interval: u64,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl GoldenRun {
+    pub fn new(interval: u64) -> Self {
+        GoldenRun {
+            interval: interval.max(1),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, GoldenError> {
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+        if lines.next() != Some(HEADER) {
+            return Err(GoldenError::NotAGoldenRun);
+        }
+        let interval = lines
+            .next()
+            .and_then(|line| line.strip_prefix("every "))
+            .and_then(|interval| interval.parse().ok())
+            .filter(|&interval| interval > 0)
+            .ok_or(GoldenError::BadLine(2))?;
+        let mut run = GoldenRun::new(interval);
+        for (index, line) in lines.enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let checkpoint = Checkpoint::parse(line).ok_or(GoldenError::BadLine(index + 3))?;
+            // verify finds checkpoints by a binary search on the frame
+            if run
+                .checkpoints
+                .last()
+                .is_some_and(|last| last.frame >= checkpoint.frame)
+            {
+                return Err(GoldenError::OutOfOrder(index + 3));
+            }
+            run.checkpoints.push(checkpoint);
+        }
+        Ok(run)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{HEADER}\nevery {}\n", self.interval);
+        for checkpoint in &self.checkpoints {
+            text += &format!("{checkpoint}\n");
+        }
+        text
+    }
+
+    // the frame of the last checkpoint, what a run being checked has to get to
+    pub fn last_frame(&self) -> u64 {
+        self.checkpoints
+            .last()
+            .map_or(0, |checkpoint| checkpoint.frame)
+    }
+
+    // called after each frame of a run being recorded, takes a checkpoint every interval frames
+    pub fn record(&mut self, emulator: &Emulator) {
+        if emulator.frame_count().is_multiple_of(self.interval) {
+            self.checkpoints.push(Checkpoint::take(emulator));
+        }
+    }
+
+    // called after each frame of a run being checked, compares it with the checkpoint recorded for
+    // the frame when there is one
+    pub fn verify(&self, emulator: &mut Emulator) -> Result<(), Box<Divergence>> {
+        let frame = emulator.frame_count();
+        let Ok(index) = self
+            .checkpoints
+            .binary_search_by_key(&frame, |checkpoint| checkpoint.frame)
+        else {
+            return Ok(());
+        };
+        let expected = self.checkpoints[index];
+        let actual = Checkpoint::take(emulator);
+        if actual == expected {
+            return Ok(());
+        }
+        Err(Box::new(Divergence {
+            expected,
+            actual,
+            dump: state_dump(emulator),
+        }))
+    }
+}
+
+// the registers, the ppu and the zero page and stack
+fn state_dump(emulator: &mut Emulator) -> String {
+    let cpu = emulator.cpu();
+    let mut dump = format!(
+        "cpu  pc:{:04X} a:{:02X} x:{:02X} y:{:02X} p:{:02X} sp:{:02X} cyc:{}\n",
+        cpu.program_counter,
+        cpu.reg_a,
+        cpu.reg_x,
+        cpu.reg_y,
+        cpu.flags.to_byte(false),
+        cpu.stack_pointer,
+        emulator.cycles()
+    );
+    let next = emulator.disassemble_at(emulator.cpu().program_counter);
+    dump += &format!("     next {}\n", next.format_with(emulator.symbols()));
+    dump += &format!(
+        "ppu  line:{} dot:{} ctrl:{:02X} mask:{:02X} dots:{}\n",
+        emulator.ppu.scanline(),
+        emulator.ppu.dot(),
+        emulator.ppu.ctrl(),
+        emulator.ppu.mask(),
+        emulator.ppu_dots()
+    );
+    for (row, bytes) in emulator.ram()[..0x200].chunks(16).enumerate() {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        dump += &format!("{:04X} {}\n", row * 16, bytes.join(" "));
+    }
+    dump
+}
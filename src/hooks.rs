@@ -0,0 +1,230 @@
+// callbacks on what the machine does, for embedders that want to watch it without changing the core:
+// frames, nmis, instructions, cpu memory accesses and scanlines. the debugger's watchpoints, the
+// trace log and scripts are all subscribers like any other, and an achievement checker needs no more
+// than a frame hook peeking at ram. callbacks get a ScriptApi, and hooks are host side and not part
+// of a save state
+use crate::Emulator;
+use crate::script::ScriptApi;
+use std::ops::RangeInclusive;
+
+// what a subscription returns, to remove it with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HookId(u64);
+
+type Callback = Box<dyn FnMut(&mut ScriptApi)>;
+// the address and the value read or about to be written
+type AccessCallback = Box<dyn FnMut(&mut ScriptApi, u16, u8)>;
+
+pub(crate) struct Hook<C> {
+    id: HookId,
+    // the addresses or scanlines the hook is for
+    range: RangeInclusive<u16>,
+    callback: C,
+}
+
+impl<C> Hook<C> {
+    fn new(id: HookId, range: RangeInclusive<u16>, callback: C) -> Self {
+        Hook {
+            id,
+            range,
+            callback,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    next_id: u64,
+    pub(crate) frame_start: Vec<Hook<Callback>>,
+    pub(crate) frame_end: Vec<Hook<Callback>>,
+    pub(crate) nmi: Vec<Hook<Callback>>,
+    pub(crate) instruction: Vec<Hook<Callback>>,
+    pub(crate) reads: Vec<Hook<AccessCallback>>,
+    pub(crate) writes: Vec<Hook<AccessCallback>>,
+    pub(crate) scanlines: Vec<Hook<Callback>>,
+    // the line the ppu was on when last looked at, so a scanline hook runs once per line
+    scanline: u16,
+    // removed from a callback while its list was taken out to run
+    removed: Vec<HookId>,
+}
+
+impl Hooks {
+    fn next_id(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+}
+
+impl Emulator {
+    // before the first instruction of every frame, after the input of the frame is applied
+    pub fn on_frame_start(&mut self, callback: impl FnMut(&mut ScriptApi) + 'static) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, 0..=0, Box::new(callback) as Callback);
+        self.hooks.frame_start.push(hook);
+        id
+    }
+
+    // once a frame is complete, when the ppu enters vblank
+    pub fn on_frame(&mut self, callback: impl FnMut(&mut ScriptApi) + 'static) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, 0..=0, Box::new(callback) as Callback);
+        self.hooks.frame_end.push(hook);
+        id
+    }
+
+    // when the cpu takes an nmi, before it pushes anything
+    pub fn on_nmi(&mut self, callback: impl FnMut(&mut ScriptApi) + 'static) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, 0..=0, Box::new(callback) as Callback);
+        self.hooks.nmi.push(hook);
+        id
+    }
+
+    // before every instruction the cpu runs, with pc at its opcode
+    pub fn on_instruction(&mut self, callback: impl FnMut(&mut ScriptApi) + 'static) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, 0..=0, Box::new(callback) as Callback);
+        self.hooks.instruction.push(hook);
+        id
+    }
+
+    // after the cpu reads from an address in range, with the value it read
+    pub fn on_memory_read(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: impl FnMut(&mut ScriptApi, u16, u8) + 'static,
+    ) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, range, Box::new(callback) as AccessCallback);
+        self.hooks.reads.push(hook);
+        id
+    }
+
+    // before the cpu writes to an address in range, with the value it writes
+    pub fn on_memory_write(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: impl FnMut(&mut ScriptApi, u16, u8) + 'static,
+    ) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, range, Box::new(callback) as AccessCallback);
+        self.hooks.writes.push(hook);
+        id
+    }
+
+    // at the start of scanline, counted from 0 at the first visible line up to the pre-render line
+    pub fn on_scanline(
+        &mut self,
+        scanline: u16,
+        callback: impl FnMut(&mut ScriptApi) + 'static,
+    ) -> HookId {
+        let id = self.hooks.next_id();
+        let hook = Hook::new(id, scanline..=scanline, Box::new(callback) as Callback);
+        if self.hooks.scanlines.is_empty() {
+            self.hooks.scanline = self.ppu.scanline();
+        }
+        self.hooks.scanlines.push(hook);
+        id
+    }
+
+    // a hook that is already gone is ignored, one removed from a callback runs no more after it
+    pub fn remove_hook(&mut self, id: HookId) {
+        let hooks = &mut self.hooks;
+        let found = remove(&mut hooks.frame_start, id)
+            | remove(&mut hooks.frame_end, id)
+            | remove(&mut hooks.nmi, id)
+            | remove(&mut hooks.instruction, id)
+            | remove(&mut hooks.reads, id)
+            | remove(&mut hooks.writes, id)
+            | remove(&mut hooks.scanlines, id);
+        if !found {
+            hooks.removed.push(id);
+        }
+    }
+
+    pub(crate) fn run_frame_start_hooks(&mut self) {
+        self.run_hooks(
+            |hooks| &mut hooks.frame_start,
+            0,
+            |callback, api| callback(api),
+        );
+    }
+
+    pub(crate) fn run_frame_end_hooks(&mut self) {
+        self.run_hooks(
+            |hooks| &mut hooks.frame_end,
+            0,
+            |callback, api| callback(api),
+        );
+    }
+
+    pub(crate) fn run_nmi_hooks(&mut self) {
+        self.run_hooks(|hooks| &mut hooks.nmi, 0, |callback, api| callback(api));
+    }
+
+    pub(crate) fn run_instruction_hooks(&mut self) {
+        self.run_hooks(
+            |hooks| &mut hooks.instruction,
+            0,
+            |callback, api| callback(api),
+        );
+    }
+
+    pub(crate) fn run_read_hooks(&mut self, address: u16, value: u8) {
+        self.run_hooks(
+            |hooks| &mut hooks.reads,
+            address,
+            |callback, api| callback(api, address, value),
+        );
+    }
+
+    pub(crate) fn run_write_hooks(&mut self, address: u16, value: u8) {
+        self.run_hooks(
+            |hooks| &mut hooks.writes,
+            address,
+            |callback, api| callback(api, address, value),
+        );
+    }
+
+    // called after the ppu runs, the hooks of a line run on the first cpu cycle inside it
+    pub(crate) fn check_scanline(&mut self) {
+        let scanline = self.ppu.scanline();
+        if scanline != self.hooks.scanline {
+            self.hooks.scanline = scanline;
+            self.run_hooks(
+                |hooks| &mut hooks.scanlines,
+                scanline,
+                |callback, api| callback(api),
+            );
+        }
+    }
+
+    // the list is taken out while it runs, so accesses made by a callback do not call it again.
+    // hooks added meanwhile go after it, and ones removed are dropped when it is put back
+    fn run_hooks<C>(
+        &mut self,
+        list: fn(&mut Hooks) -> &mut Vec<Hook<C>>,
+        key: u16,
+        mut call: impl FnMut(&mut C, &mut ScriptApi),
+    ) {
+        let mut hooks = std::mem::take(list(&mut self.hooks));
+        let mut api = ScriptApi { emulator: self };
+        for hook in &mut hooks {
+            if hook.range.contains(&key) {
+                call(&mut hook.callback, &mut api);
+            }
+        }
+        let added = std::mem::replace(list(&mut self.hooks), hooks);
+        list(&mut self.hooks).extend(added);
+        if !self.hooks.removed.is_empty() {
+            let removed = std::mem::take(&mut self.hooks.removed);
+            list(&mut self.hooks).retain(|hook| !removed.contains(&hook.id));
+        }
+    }
+}
+
+fn remove<C>(hooks: &mut Vec<Hook<C>>, id: HookId) -> bool {
+    let length = hooks.len();
+    hooks.retain(|hook| hook.id != id);
+    hooks.len() != length
+}
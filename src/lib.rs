@@ -0,0 +1,437 @@
+pub mod analysis;
+pub mod apu;
+mod battery;
+pub mod bus;
+pub mod cartridge;
+pub mod cheats;
+pub mod clock;
+pub mod condition;
+pub mod controller;
+pub mod cpu;
+mod crc;
+pub mod debugger;
+pub mod disasm;
+mod dma;
+pub mod error;
+pub mod expansion;
+pub mod golden;
+pub mod hooks;
+#[cfg(feature = "libretro")]
+mod libretro;
+pub mod lua;
+pub mod mapper;
+pub mod memory;
+pub mod movie;
+pub mod netplay;
+pub mod nsf;
+pub mod overlay;
+pub mod palette;
+pub mod patch;
+pub mod power;
+pub mod ppu;
+pub mod raw;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
+pub mod script;
+pub mod stats;
+pub mod symbols;
+mod trace;
+pub mod video;
+pub mod viewer;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub mod zapper;
+
+use analysis::Analysis;
+use apu::{Apu, DEFAULT_SAMPLE_RATE};
+use bus::InterruptLines;
+use cartridge::Cartridge;
+use cheats::Cheat;
+use clock::{Region, Speed};
+use condition::Condition;
+use controller::{Button, Controller, FourScore, FrameInput, InputSource, Player};
+use cpu::{Cpu, IRQ_VECTOR, NMI_VECTOR, StatusFlags};
+use debugger::Debugger;
+use dma::Dma;
+use error::EmuError;
+use hooks::{HookId, Hooks};
+use mapper::Mapper;
+use memory::MemorySpace;
+use nsf::NsfPlayer;
+use overlay::Overlay;
+use palette::Palette;
+use power::RamInit;
+use ppu::Ppu;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use symbols::Symbols;
+use video::{VideoFilter, VideoOutput};
+use zapper::Zapper;
+
+// what one step_frame produced. the samples are the ones this frame added to the audio buffer,
+// which still holds them until take_audio_samples
+#[derive(Debug, Clone, Copy)]
+pub struct FrameResult<'a> {
+    // 256x240 NES colour indices, see ppu::Ppu::frame_buffer
+    pub framebuffer: &'a [u16],
+    pub samples: &'a [f32],
+    // frame_count after the frame, and the cpu cycles it took
+    pub frame: u64,
+    pub cycles: u64,
+    // the cpu hit a HLT opcode before the frame was complete
+    pub halted: bool,
+}
+
+pub struct Emulator {
+    ram: [u8; 0x800],
+    cpu: Cpu,
+    ppu: Ppu,
+    apu: Apu,
+    mapper: Box<dyn Mapper>,
+    // crc32 of the cartridge's roms, or of the program or music loaded instead, in every save state
+    rom_crc: u32,
+    interrupts: InterruptLines,
+    dma: Dma,
+    // master clock cycles run so far, and how far the ppu has caught up with them
+    master_clock: u64,
+    ppu_clock: u64,
+    // the last value driven on the cpu data bus
+    open_bus: u8,
+    controllers: [Controller; 4],
+    four_score: FourScore,
+    // plugged into port 2 instead of the second pad, host side like the pads' buttons
+    zapper: Option<Zapper>,
+    video: VideoOutput,
+    // the .sav file behind the battery ram, and its contents as last written
+    save_file: Option<PathBuf>,
+    flushed_battery_ram: Vec<u8>,
+    debugger: Debugger,
+    // the instruction hook writing the nestest style log while tracing
+    trace: Option<HookId>,
+    region: Region,
+    // host side pacing, see clock::Speed
+    speed: Speed,
+    next_frame: Option<Instant>,
+    // codes patching what the cpu reads, not part of a save state
+    cheats: Vec<Cheat>,
+    // addresses held at a value from the memory viewer, host side like the cheats
+    frozen: BTreeMap<(MemorySpace, u16), u8>,
+    // frame, nmi, instruction, memory and scanline callbacks, see hooks.rs
+    hooks: Hooks,
+    // the hooks of the scripts added
+    scripts: Vec<HookId>,
+    // what lua scripts printed, see lua.rs
+    script_output: String,
+    overlay: Overlay,
+    // labels for the debugger, the trace log and the disassembly
+    symbols: Symbols,
+    // the music being played when an nsf is loaded instead of a cartridge
+    nsf: Option<Box<NsfPlayer>>,
+    // run_until stopped partway through a frame, the next frame run carries on with it
+    mid_frame: bool,
+    // what ram is filled with on power_cycle, and the state power_cycle goes back to
+    ram_init: RamInit,
+    power_on: Vec<u8>,
+    // times power_cycle ran, a rewind history of the run before one is of another console
+    power_cycles: u64,
+    // what the debugger's disassembly knows of the rom's code and data, see analysis.rs
+    analysis: Option<Box<Analysis>>,
+}
+
+impl Emulator {
+    pub fn new(cartridge: Cartridge) -> Result<Self, EmuError> {
+        let region = Region::from_timing(cartridge.header.timing);
+        let rom_crc = cartridge.crc32();
+        Ok(Self::with_mapper(
+            mapper::from_cartridge(cartridge)?,
+            region,
+            rom_crc,
+        ))
+    }
+
+    // rom_crc tells the states of what is loaded apart from those of other roms
+    pub(crate) fn with_mapper(mapper: Box<dyn Mapper>, region: Region, rom_crc: u32) -> Self {
+        let mut emulator = Emulator {
+            ram: [0xFF; 0x800],
+            cpu: Cpu {
+                //interrupt_disable_flag is the only one that is enabled by default
+                flags: StatusFlags {
+                    carry_flag: false,
+                    zero_flag: false,
+                    interrupt_disable_flag: true,
+                    decimal_flag: false,
+                    overflow_flag: false,
+                    negative_flag: false,
+                },
+                program_counter: 0,
+                stack_pointer: 0,
+                halted: false,
+                reg_a: 0,
+                reg_x: 0,
+                reg_y: 0,
+                poll_interrupt_disable: true,
+                page_crossed: false,
+            },
+            ppu: Ppu::new(),
+            apu: Apu::new(DEFAULT_SAMPLE_RATE),
+            mapper,
+            rom_crc,
+            interrupts: InterruptLines::default(),
+            dma: Dma::default(),
+            master_clock: 0,
+            ppu_clock: 0,
+            open_bus: 0,
+            controllers: [Controller::default(); 4],
+            four_score: FourScore::default(),
+            zapper: None,
+            video: VideoOutput::new(),
+            save_file: None,
+            flushed_battery_ram: Vec::new(),
+            debugger: Debugger::default(),
+            trace: None,
+            region: Region::Ntsc,
+            speed: Speed::default(),
+            next_frame: None,
+            cheats: Vec::new(),
+            frozen: BTreeMap::new(),
+            hooks: Hooks::default(),
+            scripts: Vec::new(),
+            script_output: String::new(),
+            overlay: Overlay::default(),
+            symbols: Symbols::new(),
+            nsf: None,
+            mid_frame: false,
+            ram_init: RamInit::default(),
+            power_on: Vec::new(),
+            power_cycles: 0,
+            analysis: None,
+        };
+        emulator.set_region(region);
+        emulator.take_power_on_state();
+        emulator.soft_reset();
+        emulator
+    }
+
+    // a whole .nes file, for hosts without a filesystem
+    pub fn from_rom_bytes(rom: &[u8]) -> Result<Self, EmuError> {
+        Self::new(Cartridge::from_bytes(rom)?)
+    }
+
+    // battery backed games get a .sav file next to the rom
+    pub fn load_rom(path: impl AsRef<Path>) -> Result<Self, EmuError> {
+        let path = path.as_ref();
+        let mut emulator = Self::from_rom_bytes(&fs::read(path)?)?;
+        emulator.set_save_file(path.with_extension("sav"))?;
+        Ok(emulator)
+    }
+
+    // runs one instruction, or the interrupt sequence when one is pending, and returns the cpu cycles it took
+    pub fn step_instruction(&mut self) -> usize {
+        // counted in master clock cycles, which saves a division per cycle
+        let divider = self.region.cpu_divider();
+        let start = self.master_clock;
+        let cycles = if self.interrupts.take_nmi() {
+            if !self.hooks.nmi.is_empty() {
+                self.run_nmi_hooks();
+            }
+            self.interrupt(NMI_VECTOR, false)
+        } else if self.interrupts.irq() && !self.cpu.poll_interrupt_disable {
+            self.interrupt(IRQ_VECTOR, false)
+        } else {
+            if !self.hooks.instruction.is_empty() {
+                self.run_instruction_hooks();
+            }
+            self.emulate_cpu()
+        };
+        // bus accesses already ran their cycles, the internal ones that touch no memory run here
+        let end = start + cycles as u64 * divider;
+        while self.master_clock < end {
+            self.tick();
+        }
+        if let Some(page) = self.dma.oam_page.take() {
+            self.run_oam_dma(page);
+        }
+        self.update_interrupt_lines();
+        ((self.master_clock - start) / divider) as usize
+    }
+
+    // runs until the ppu enters vblank, or until a HLT
+    pub fn step_frame(&mut self) -> FrameResult<'_> {
+        let (start_cycles, start_samples) = (self.cycles(), self.apu.samples().len());
+        self.begin_frame();
+        while !self.ppu.frame_complete && !self.cpu.halted {
+            self.step_instruction();
+        }
+        self.end_frame();
+        FrameResult {
+            framebuffer: self.ppu.frame_buffer(),
+            samples: &self.apu.samples()[start_samples..],
+            frame: self.ppu.frame(),
+            cycles: self.cycles() - start_cycles,
+            halted: self.cpu.halted,
+        }
+    }
+
+    // what happens before the first instruction of a frame, unless one is already underway
+    pub(crate) fn begin_frame(&mut self) {
+        if std::mem::replace(&mut self.mid_frame, true) {
+            return;
+        }
+        self.ppu.frame_complete = false;
+        self.overlay.clear();
+        if !self.frozen.is_empty() {
+            self.apply_freezes();
+        }
+        if !self.hooks.frame_start.is_empty() {
+            self.run_frame_start_hooks();
+        }
+    }
+
+    // once the frame is complete or the cpu halted
+    pub(crate) fn end_frame(&mut self) {
+        self.mid_frame = false;
+        if !self.hooks.frame_end.is_empty() {
+            self.run_frame_end_hooks();
+        }
+    }
+
+    // presses the buttons of one frame of input, and the reset or power button if it asks for it
+    pub fn apply_input(&mut self, input: FrameInput) {
+        if input.power {
+            self.power_cycle();
+        } else if input.reset {
+            self.soft_reset();
+        }
+        self.set_input(input.buttons);
+    }
+
+    // the pads of players 1 to 4 at once, each in set_buttons order
+    pub fn set_input(&mut self, buttons: [u8; 4]) {
+        for (controller, buttons) in self.controllers.iter_mut().zip(buttons) {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    // runs one frame with input from source, None without running when the source has run out
+    pub fn step_frame_with(&mut self, source: &mut dyn InputSource) -> Option<FrameResult<'_>> {
+        let input = source.next_frame()?;
+        self.apply_input(input);
+        Some(self.step_frame())
+    }
+
+    // runs until the cpu executes a HLT opcode
+    pub fn run(&mut self) {
+        while !self.cpu.halted {
+            self.step_instruction();
+        }
+    }
+
+    // runs at most frames frames, stopping early at a HLT, and returns whether the cpu halted
+    pub fn run_until_halt_or(&mut self, frames: usize) -> bool {
+        self.run_until(Condition::Frames(frames as u64));
+        self.cpu.halted
+    }
+
+    pub fn halted(&self) -> bool {
+        self.cpu.halted
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // frames that have reached vblank since power on
+    pub fn frame_count(&self) -> u64 {
+        self.ppu.frame()
+    }
+
+    // 256x240 NES colour indices, see ppu::Ppu::frame_buffer
+    pub fn framebuffer(&self) -> &[u16] {
+        self.ppu.frame_buffer()
+    }
+
+    pub fn sprite_limit(&self) -> bool {
+        self.ppu.sprite_limit()
+    }
+
+    // see Ppu::set_sprite_limit
+    pub fn set_sprite_limit(&mut self, limit: bool) {
+        self.ppu.set_sprite_limit(limit);
+    }
+
+    pub fn video_filter(&self) -> VideoFilter {
+        self.video.filter()
+    }
+
+    pub fn set_video_filter(&mut self, filter: VideoFilter) {
+        self.video.set_filter(filter);
+    }
+
+    pub fn palette(&self) -> &Palette {
+        self.video.palette()
+    }
+
+    // colours the frame from now on, with the rgb filter and everywhere colour indices become rgb
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.video.set_palette(palette);
+    }
+
+    // the current frame as 256x240 RGBA8, passed through the selected video filter, with the overlay on top
+    pub fn video_frame(&mut self) -> &[u8] {
+        let rgba = self
+            .video
+            .render(self.ppu.frame_buffer(), self.ppu.odd_frame());
+        self.overlay.draw(rgba);
+        rgba
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.apu.sample_rate()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    // the mixer: channel volumes, muting and the levels for drawing them
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn set_button(&mut self, player: Player, button: Button, pressed: bool) {
+        self.controllers[player.port()].set_button(button, pressed);
+    }
+
+    // buttons in shift order: A, B, Select, Start, Up, Down, Left, Right from bit 0 to bit 7
+    pub fn set_buttons(&mut self, player: Player, buttons: u8) {
+        self.controllers[player.port()].set_buttons(buttons);
+    }
+
+    // plugs a four score into both ports for players 3 and 4, or takes it out again
+    pub fn connect_four_score(&mut self, connected: bool) {
+        self.four_score.connected = connected;
+    }
+
+    pub fn four_score_connected(&self) -> bool {
+        self.four_score.connected
+    }
+}
@@ -0,0 +1,394 @@
+// the emulator as a libretro core, hand written against libretro.h api version 1. build it with
+//   cargo rustc --lib --release --no-default-features --features libretro --crate-type cdylib
+// and load the resulting libntsc_nes.so (or .dll/.dylib) in retroarch.
+// the frontend calls these from one thread, so the core lives in a thread local
+use crate::Emulator;
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::cheats::Cheat;
+use crate::clock::Region;
+use crate::controller::Player;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::cell::RefCell;
+use std::ffi::{CStr, c_char, c_uint, c_void};
+
+const API_VERSION: c_uint = 1;
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const DEVICE_JOYPAD: c_uint = 1;
+const REGION_NTSC: c_uint = 0;
+const REGION_PAL: c_uint = 1;
+const MEMORY_SAVE_RAM: c_uint = 0;
+const MEMORY_SYSTEM_RAM: c_uint = 2;
+// retropad ids in the order of the bits of a pad: A, B, Select, Start, Up, Down, Left, Right
+const JOYPAD_IDS: [c_uint; 8] = [8, 0, 2, 3, 4, 5, 6, 7];
+
+type EnvironmentCallback = extern "C" fn(command: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshCallback =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleCallback = extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchCallback = extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollCallback = extern "C" fn();
+type InputStateCallback =
+    extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[derive(Default)]
+struct Core {
+    emulator: Option<Emulator>,
+    environment: Option<EnvironmentCallback>,
+    video_refresh: Option<VideoRefreshCallback>,
+    audio_sample_batch: Option<AudioSampleBatchCallback>,
+    input_poll: Option<InputPollCallback>,
+    input_state: Option<InputStateCallback>,
+    // the frame converted to XRGB8888 and the audio as interleaved stereo, kept between frames
+    frame: Vec<u32>,
+    audio: Vec<i16>,
+}
+
+thread_local! {
+    static CORE: RefCell<Core> = RefCell::default();
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| f(&mut core.borrow_mut()))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_environment(callback: EnvironmentCallback) {
+    with_core(|core| core.environment = Some(callback));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshCallback) {
+    with_core(|core| core.video_refresh = Some(callback));
+}
+
+// samples go out a frame at a time through the batch callback
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleCallback) {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchCallback) {
+    with_core(|core| core.audio_sample_batch = Some(callback));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_poll(callback: InputPollCallback) {
+    with_core(|core| core.input_poll = Some(callback));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_state(callback: InputStateCallback) {
+    with_core(|core| core.input_state = Some(callback));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_init() {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| *core = Core::default());
+}
+
+/// # Safety
+/// info must point to a retro_system_info the frontend owns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    let library_version = concat!(env!("CARGO_PKG_VERSION"), "\0");
+    // SAFETY: the frontend passes a valid pointer, the strings are static
+    unsafe {
+        info.write(SystemInfo {
+            library_name: c"ntsc-nes".as_ptr(),
+            library_version: library_version.as_ptr().cast(),
+            valid_extensions: c"nes".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        });
+    }
+}
+
+/// # Safety
+/// info must point to a retro_system_av_info the frontend owns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    let region = with_core(|core| core.emulator.as_ref().map(Emulator::region)).unwrap_or_default();
+    // SAFETY: the frontend passes a valid pointer
+    unsafe {
+        info.write(SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: SCREEN_WIDTH as c_uint,
+                base_height: SCREEN_HEIGHT as c_uint,
+                max_width: SCREEN_WIDTH as c_uint,
+                max_height: SCREEN_HEIGHT as c_uint,
+                aspect_ratio: 4.0 / 3.0,
+            },
+            timing: SystemTiming {
+                fps: region.frame_rate(),
+                sample_rate: DEFAULT_SAMPLE_RATE as f64,
+            },
+        });
+    }
+}
+
+// both ports always hold a standard pad
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        if let Some(emulator) = &mut core.emulator {
+            emulator.soft_reset();
+        }
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_run() {
+    with_core(|core| {
+        let Some(emulator) = &mut core.emulator else {
+            return;
+        };
+        if let (Some(poll), Some(state)) = (core.input_poll, core.input_state) {
+            poll();
+            for (port, player) in [(0, Player::One), (1, Player::Two)] {
+                let buttons = JOYPAD_IDS
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &id)| state(port, DEVICE_JOYPAD, 0, id) != 0)
+                    .fold(0, |buttons, (bit, _)| buttons | 1 << bit);
+                emulator.set_buttons(player, buttons);
+            }
+        }
+        emulator.step_frame();
+
+        if let Some(video_refresh) = core.video_refresh {
+            for (pixel, rgba) in core
+                .frame
+                .iter_mut()
+                .zip(emulator.video_frame().chunks_exact(4))
+            {
+                *pixel = u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]]);
+            }
+            video_refresh(
+                core.frame.as_ptr().cast(),
+                SCREEN_WIDTH as c_uint,
+                SCREEN_HEIGHT as c_uint,
+                SCREEN_WIDTH * 4,
+            );
+        }
+
+        core.audio.clear();
+        for sample in emulator.take_audio_samples() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            core.audio.extend([sample, sample]);
+        }
+        if let Some(audio_sample_batch) = core.audio_sample_batch {
+            let mut written = 0;
+            // the frontend may take fewer frames than offered
+            while written < core.audio.len() / 2 {
+                let taken = audio_sample_batch(
+                    core.audio[written * 2..].as_ptr(),
+                    core.audio.len() / 2 - written,
+                );
+                if taken == 0 {
+                    break;
+                }
+                written += taken;
+            }
+        }
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(|core| {
+        core.emulator
+            .as_ref()
+            .map_or(0, |emulator| emulator.save_state().len())
+    })
+}
+
+/// # Safety
+/// data must point to size writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let Some(state) = with_core(|core| core.emulator.as_ref().map(Emulator::save_state)) else {
+        return false;
+    };
+    if state.len() > size {
+        return false;
+    }
+    // SAFETY: the frontend passes a buffer of size bytes, which holds the state
+    unsafe { std::ptr::copy_nonoverlapping(state.as_ptr(), data.cast(), state.len()) };
+    true
+}
+
+/// # Safety
+/// data must point to size readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    // SAFETY: the frontend passes a buffer of size bytes
+    let state = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), size) };
+    with_core(|core| {
+        core.emulator
+            .as_mut()
+            .is_some_and(|emulator| emulator.load_state(state).is_ok())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_cheat_reset() {
+    with_core(|core| {
+        if let Some(emulator) = &mut core.emulator {
+            emulator.clear_cheats();
+        }
+    });
+}
+
+/// # Safety
+/// code must be a nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_cheat_set(_index: c_uint, enabled: bool, code: *const c_char) {
+    if !enabled || code.is_null() {
+        return;
+    }
+    // SAFETY: the frontend passes a nul terminated string
+    let code = unsafe { CStr::from_ptr(code) }.to_string_lossy();
+    with_core(|core| {
+        if let Some(emulator) = &mut core.emulator {
+            // retroarch joins the codes of one cheat with +
+            for code in code.split('+') {
+                if let Ok(cheat) = Cheat::parse(code.trim()) {
+                    emulator.add_cheat(cheat);
+                }
+            }
+        }
+    });
+}
+
+/// # Safety
+/// game must be null or point to a retro_game_info whose data holds size bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    // SAFETY: the frontend passes a valid game info, need_fullpath is off so data is the rom
+    let Some(game) = (unsafe { game.as_ref() }) else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+    // SAFETY: as above
+    let rom = unsafe { std::slice::from_raw_parts(game.data.cast::<u8>(), game.size) };
+    let Ok(emulator) = Emulator::from_rom_bytes(rom) else {
+        return false;
+    };
+    with_core(|core| {
+        if let Some(environment) = core.environment {
+            let mut format = PIXEL_FORMAT_XRGB8888;
+            if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, (&raw mut format).cast()) {
+                return false;
+            }
+        }
+        core.frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        core.emulator = Some(emulator);
+        true
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _count: usize,
+) -> bool {
+    false
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| core.emulator = None);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_region() -> c_uint {
+    with_core(|core| match core.emulator.as_ref().map(Emulator::region) {
+        Some(Region::Pal) => REGION_PAL,
+        _ => REGION_NTSC,
+    })
+}
+
+// the frontend reads and writes the battery ram for .srm files and the system ram for achievements
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    with_core(|core| {
+        let memory = core.emulator.as_mut().and_then(|emulator| match id {
+            MEMORY_SAVE_RAM => emulator.battery_ram_mut(),
+            MEMORY_SYSTEM_RAM => Some(emulator.ram_mut()),
+            _ => None,
+        });
+        memory.map_or(std::ptr::null_mut(), |memory| memory.as_mut_ptr().cast())
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    with_core(|core| {
+        let Some(emulator) = &core.emulator else {
+            return 0;
+        };
+        match id {
+            MEMORY_SAVE_RAM => emulator.battery_ram().map_or(0, <[u8]>::len),
+            MEMORY_SYSTEM_RAM => emulator.ram().len(),
+            _ => 0,
+        }
+    })
+}
@@ -0,0 +1,2748 @@
+// lua scripts like the ones written for fceux: bots, trackers and practice tools. there is no lua
+// crate to embed, so this is an interpreter for the part of lua 5.1 those scripts use: nil,
+// booleans, numbers, strings, tables and closures, locals and globals, if, while, repeat, numeric
+// for and for in over pairs and ipairs, and the functions in LIBRARY. there are no metatables,
+// varargs, goto or coroutines, and so no emu.frameadvance: a script does its work in the functions
+// it registers
+//   emu.registerbefore(f)            f() at the start of every frame, after its input is applied
+//   emu.registerafter(f)             f() once the frame is complete
+//   memory.registerread(a, [n,] f)   f(address, value) after the cpu reads from a to a+n-1
+//   memory.registerwrite(a, [n,] f)  f(address, value) before the cpu writes there
+// and looks at and changes the machine from them through memory, gui and joypad. the first error
+// stops a script, and like the hooks it runs from a script is host side and not in a save state
+use crate::Emulator;
+use crate::controller::{Button, Player};
+use crate::hooks::HookId;
+use crate::overlay::Color;
+use crate::script::ScriptApi;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::{Bound, RangeInclusive};
+use std::rc::Rc;
+use std::str::Chars;
+
+// statements the main chunk or a callback may run before it is stopped as stuck in a loop
+const BUDGET: u64 = 10_000_000;
+// calls inside calls before a runaway recursion is stopped, well inside the 2M stack a thread
+// gets in a debug build
+const MAX_DEPTH: usize = 100;
+// bytes string.rep builds at most, a script asking for more would take the host's memory with it
+const MAX_STRING: usize = 1 << 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LuaError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LuaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for LuaError {}
+
+type Result<T> = std::result::Result<T, LuaError>;
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T> {
+    Err(LuaError {
+        line,
+        message: message.into(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(Rc<str>),
+    Number(f64),
+    Str(Rc<str>),
+    // keywords and operators
+    Symbol(&'static str),
+    End,
+}
+
+const KEYWORDS: [&str; 21] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+// longest first, so .. is not read as two dots
+const SYMBOLS: [&str; 26] = [
+    "...", "..", "==", "~=", "<=", ">=", "+", "-", "*", "/", "%", "^", "#", "<", ">", "=", "(",
+    ")", "{", "}", "[", "]", ";", ":", ",", ".",
+];
+
+struct Lexer<'a> {
+    source: &'a [u8],
+    position: usize,
+    line: usize,
+}
+
+// the tokens of source, each with the line it starts on
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let mut lexer = Lexer {
+        source: source.as_bytes(),
+        position: 0,
+        line: 1,
+    };
+    let mut tokens = Vec::new();
+    loop {
+        lexer.skip_space()?;
+        let line = lexer.line;
+        let token = lexer.token()?;
+        let end = token == Token::End;
+        tokens.push((token, line));
+        if end {
+            return Ok(tokens);
+        }
+    }
+}
+
+impl Lexer<'_> {
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.source.get(self.position + offset).copied()
+    }
+
+    fn bump(&mut self) -> u8 {
+        let byte = self.source[self.position];
+        self.position += 1;
+        if byte == b'\n' {
+            self.line += 1;
+        }
+        byte
+    }
+
+    // whitespace and -- comments, [[ ]] ones included
+    fn skip_space(&mut self) -> Result<()> {
+        loop {
+            match self.peek(0) {
+                Some(b' ' | b'\t' | b'\r' | b'\n') => {
+                    self.bump();
+                }
+                Some(b'-') if self.peek(1) == Some(b'-') => {
+                    self.position += 2;
+                    match self.long_bracket() {
+                        Some(level) => {
+                            self.long_string(level)?;
+                        }
+                        None => {
+                            while self.peek(0).is_some_and(|byte| byte != b'\n') {
+                                self.bump();
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // an opening [[ or [==[, taken with its level
+    fn long_bracket(&mut self) -> Option<usize> {
+        if self.peek(0) != Some(b'[') {
+            return None;
+        }
+        let mut level = 0;
+        while self.peek(1 + level) == Some(b'=') {
+            level += 1;
+        }
+        if self.peek(1 + level) != Some(b'[') {
+            return None;
+        }
+        self.position += level + 2;
+        Some(level)
+    }
+
+    // the text up to the closing bracket of level, without a newline right after the opening
+    fn long_string(&mut self, level: usize) -> Result<String> {
+        let line = self.line;
+        if self.peek(0) == Some(b'\r') {
+            self.bump();
+        }
+        if self.peek(0) == Some(b'\n') {
+            self.bump();
+        }
+        let mut text = Vec::new();
+        loop {
+            match self.peek(0) {
+                None => return error(line, "unfinished long string"),
+                Some(b']')
+                    if (1..=level).all(|offset| self.peek(offset) == Some(b'='))
+                        && self.peek(level + 1) == Some(b']') =>
+                {
+                    self.position += level + 2;
+                    return Ok(String::from_utf8_lossy(&text).into_owned());
+                }
+                Some(_) => text.push(self.bump()),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Token> {
+        let Some(byte) = self.peek(0) else {
+            return Ok(Token::End);
+        };
+        if byte.is_ascii_alphabetic() || byte == b'_' {
+            let start = self.position;
+            while self
+                .peek(0)
+                .is_some_and(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+            {
+                self.position += 1;
+            }
+            let word = String::from_utf8_lossy(&self.source[start..self.position]);
+            return Ok(match KEYWORDS.iter().find(|keyword| **keyword == word) {
+                Some(keyword) => Token::Symbol(keyword),
+                None => Token::Name(word.into()),
+            });
+        }
+        if byte.is_ascii_digit()
+            || (byte == b'.' && self.peek(1).is_some_and(|b| b.is_ascii_digit()))
+        {
+            return self.number();
+        }
+        if byte == b'"' || byte == b'\'' {
+            return self.string(byte);
+        }
+        if let Some(level) = self.long_bracket() {
+            return Ok(Token::Str(self.long_string(level)?.into()));
+        }
+        for symbol in SYMBOLS {
+            if self.source[self.position..].starts_with(symbol.as_bytes()) {
+                if symbol == "..." {
+                    return error(self.line, "varargs are not supported");
+                }
+                self.position += symbol.len();
+                return Ok(Token::Symbol(symbol));
+            }
+        }
+        error(
+            self.line,
+            format!("unexpected symbol {:?}", char::from(byte)),
+        )
+    }
+
+    // decimal with an optional fraction and exponent, or 0x hex
+    fn number(&mut self) -> Result<Token> {
+        let start = self.position;
+        let digits = |lexer: &mut Self, hex: bool| {
+            while lexer
+                .peek(0)
+                .is_some_and(|byte| byte.is_ascii_digit() || (hex && byte.is_ascii_hexdigit()))
+            {
+                lexer.position += 1;
+            }
+        };
+        let value = if self.peek(0) == Some(b'0') && matches!(self.peek(1), Some(b'x' | b'X')) {
+            self.position += 2;
+            digits(self, true);
+            let text = String::from_utf8_lossy(&self.source[start + 2..self.position]);
+            u64::from_str_radix(&text, 16)
+                .ok()
+                .map(|value| value as f64)
+        } else {
+            digits(self, false);
+            if self.peek(0) == Some(b'.') {
+                self.position += 1;
+                digits(self, false);
+            }
+            if matches!(self.peek(0), Some(b'e' | b'E')) {
+                self.position += 1;
+                if matches!(self.peek(0), Some(b'+' | b'-')) {
+                    self.position += 1;
+                }
+                digits(self, false);
+            }
+            String::from_utf8_lossy(&self.source[start..self.position])
+                .parse()
+                .ok()
+        };
+        // 3x is one malformed number rather than 3 and x
+        let run_on = self
+            .peek(0)
+            .is_some_and(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.');
+        match value {
+            Some(value) if !run_on => Ok(Token::Number(value)),
+            _ => error(self.line, "malformed number"),
+        }
+    }
+
+    fn string(&mut self, quote: u8) -> Result<Token> {
+        let line = self.line;
+        self.position += 1;
+        let mut text = Vec::new();
+        loop {
+            let Some(byte) = self.peek(0) else {
+                return error(line, "unfinished string");
+            };
+            self.position += 1;
+            match byte {
+                b'\n' => return error(line, "unfinished string"),
+                byte if byte == quote => break,
+                b'\\' => {
+                    let Some(escape) = self.peek(0) else {
+                        return error(line, "unfinished string");
+                    };
+                    self.bump();
+                    match escape {
+                        b'n' | b'\n' => text.push(b'\n'),
+                        b't' => text.push(b'\t'),
+                        b'r' => text.push(b'\r'),
+                        b'a' => text.push(7),
+                        b'b' => text.push(8),
+                        b'f' => text.push(12),
+                        b'v' => text.push(11),
+                        b'\\' | b'"' | b'\'' => text.push(escape),
+                        b'x' => {
+                            let digits = self.source.get(self.position..self.position + 2);
+                            let value = digits
+                                .and_then(|digits| std::str::from_utf8(digits).ok())
+                                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                            let Some(value) = value else {
+                                return error(self.line, "invalid escape sequence");
+                            };
+                            self.position += 2;
+                            text.push(value);
+                        }
+                        b'0'..=b'9' => {
+                            let mut value = u32::from(escape - b'0');
+                            for _ in 0..2 {
+                                match self.peek(0) {
+                                    Some(digit @ b'0'..=b'9') => {
+                                        value = value * 10 + u32::from(digit - b'0');
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            let Ok(value) = u8::try_from(value) else {
+                                return error(self.line, "escape sequence too large");
+                            };
+                            text.push(value);
+                        }
+                        _ => return error(self.line, "invalid escape sequence"),
+                    }
+                }
+                byte => text.push(byte),
+            }
+        }
+        Ok(Token::Str(String::from_utf8_lossy(&text).into()))
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Name(name) => format!("'{name}'"),
+        Token::Number(number) => format!("'{}'", number_to_string(*number)),
+        Token::Str(text) => format!("{text:?}"),
+        Token::Symbol(symbol) => format!("'{symbol}'"),
+        Token::End => "the end".to_string(),
+    }
+}
+
+type Block = Vec<Statement>;
+
+struct Statement {
+    line: usize,
+    kind: StatementKind,
+}
+
+enum StatementKind {
+    Local(Vec<Rc<str>>, Vec<Expr>),
+    // local function f, which can call itself
+    LocalFunction(Rc<str>, Rc<Function>),
+    // function a.b() is an assignment too
+    Assign(Vec<Expr>, Vec<Expr>),
+    Call(Expr),
+    Do(Block),
+    While(Expr, Block),
+    Repeat(Block, Expr),
+    If(Vec<(Expr, Block)>, Option<Block>),
+    NumericFor(NumericFor),
+    GenericFor(Vec<Rc<str>>, Vec<Expr>, Block),
+    Return(Vec<Expr>),
+    Break,
+}
+
+// for name = start, limit, step
+struct NumericFor {
+    name: Rc<str>,
+    start: Expr,
+    limit: Expr,
+    step: Option<Expr>,
+    body: Block,
+}
+
+enum Expr {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    Name(Rc<str>),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    // object:name(arguments)
+    Method(Box<Expr>, Rc<str>, Vec<Expr>),
+    Function(Rc<Function>),
+    Table(Vec<Field>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    // a call in parentheses gives only its first value
+    Paren(Box<Expr>),
+}
+
+enum Field {
+    // the next of 1, 2, 3...
+    Item(Expr),
+    Keyed(Expr, Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Negate,
+    Not,
+    Length,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+struct Function {
+    parameters: Vec<Rc<str>>,
+    body: Block,
+}
+
+// what a unary operator binds to the right of, tighter than everything but ^
+const UNARY_PRIORITY: u8 = 8;
+
+// the operator with its left and right priority, right associative when the right is lower
+fn binary_operator(token: &Token) -> Option<(BinaryOp, u8, u8)> {
+    let Token::Symbol(symbol) = token else {
+        return None;
+    };
+    Some(match *symbol {
+        "or" => (BinaryOp::Or, 1, 1),
+        "and" => (BinaryOp::And, 2, 2),
+        "==" => (BinaryOp::Equal, 3, 3),
+        "~=" => (BinaryOp::NotEqual, 3, 3),
+        "<" => (BinaryOp::Less, 3, 3),
+        "<=" => (BinaryOp::LessEqual, 3, 3),
+        ">" => (BinaryOp::Greater, 3, 3),
+        ">=" => (BinaryOp::GreaterEqual, 3, 3),
+        ".." => (BinaryOp::Concat, 5, 4),
+        "+" => (BinaryOp::Add, 6, 6),
+        "-" => (BinaryOp::Subtract, 6, 6),
+        "*" => (BinaryOp::Multiply, 7, 7),
+        "/" => (BinaryOp::Divide, 7, 7),
+        "%" => (BinaryOp::Modulo, 7, 7),
+        "^" => (BinaryOp::Power, 10, 9),
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    // loops the parser is inside in the current function, where break may go
+    loops: usize,
+}
+
+fn parse(source: &str) -> Result<Block> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        loops: 0,
+    };
+    let block = parser.block()?;
+    if *parser.peek() != Token::End {
+        return parser.unexpected("statement expected");
+    }
+    Ok(block)
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn peek_next(&self) -> &Token {
+        &self.tokens[(self.position + 1).min(self.tokens.len() - 1)].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn check(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Token::Symbol(found) if *found == symbol)
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = self.check(symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{symbol}' expected"))
+        }
+    }
+
+    fn unexpected<T>(&self, message: &str) -> Result<T> {
+        error(
+            self.line(),
+            format!("{message} near {}", describe_token(self.peek())),
+        )
+    }
+
+    fn name(&mut self) -> Result<Rc<str>> {
+        match self.peek() {
+            Token::Name(name) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => self.unexpected("name expected"),
+        }
+    }
+
+    fn at_block_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::End | Token::Symbol("end" | "else" | "elseif" | "until")
+        )
+    }
+
+    // statements up to the keyword ending the block, a return only as the last of them
+    fn block(&mut self) -> Result<Block> {
+        let mut block = Vec::new();
+        while !self.at_block_end() {
+            if self.check("return") {
+                let line = self.line();
+                self.position += 1;
+                let values = if self.at_block_end() || self.check(";") {
+                    Vec::new()
+                } else {
+                    self.expression_list()?
+                };
+                self.accept(";");
+                if !self.at_block_end() {
+                    return self.unexpected("'end' expected");
+                }
+                block.push(Statement {
+                    line,
+                    kind: StatementKind::Return(values),
+                });
+                break;
+            }
+            if let Some(statement) = self.statement()? {
+                block.push(statement);
+            }
+        }
+        Ok(block)
+    }
+
+    fn loop_body(&mut self) -> Result<Block> {
+        self.loops += 1;
+        let body = self.block();
+        self.loops -= 1;
+        body
+    }
+
+    fn statement(&mut self) -> Result<Option<Statement>> {
+        let line = self.line();
+        let kind = match self.peek().clone() {
+            Token::Symbol(";") => {
+                self.position += 1;
+                return Ok(None);
+            }
+            Token::Symbol("if") => {
+                self.position += 1;
+                let mut branches = Vec::new();
+                loop {
+                    let condition = self.expression()?;
+                    self.expect("then")?;
+                    branches.push((condition, self.block()?));
+                    if !self.accept("elseif") {
+                        break;
+                    }
+                }
+                let otherwise = if self.accept("else") {
+                    Some(self.block()?)
+                } else {
+                    None
+                };
+                self.expect("end")?;
+                StatementKind::If(branches, otherwise)
+            }
+            Token::Symbol("while") => {
+                self.position += 1;
+                let condition = self.expression()?;
+                self.expect("do")?;
+                let body = self.loop_body()?;
+                self.expect("end")?;
+                StatementKind::While(condition, body)
+            }
+            Token::Symbol("do") => {
+                self.position += 1;
+                let body = self.block()?;
+                self.expect("end")?;
+                StatementKind::Do(body)
+            }
+            Token::Symbol("repeat") => {
+                self.position += 1;
+                let body = self.loop_body()?;
+                self.expect("until")?;
+                StatementKind::Repeat(body, self.expression()?)
+            }
+            Token::Symbol("for") => {
+                self.position += 1;
+                self.for_statement()?
+            }
+            Token::Symbol("function") => {
+                self.position += 1;
+                // a.b.c, or a.b:c taking self first
+                let mut target = Expr::Name(self.name()?);
+                while self.accept(".") {
+                    let field = Expr::Str(self.name()?);
+                    target = Expr::Index(Box::new(target), Box::new(field));
+                }
+                let method = self.accept(":");
+                if method {
+                    let field = Expr::Str(self.name()?);
+                    target = Expr::Index(Box::new(target), Box::new(field));
+                }
+                let function = self.function_body(method)?;
+                StatementKind::Assign(vec![target], vec![Expr::Function(function)])
+            }
+            Token::Symbol("local") => {
+                self.position += 1;
+                if self.accept("function") {
+                    let name = self.name()?;
+                    StatementKind::LocalFunction(name, self.function_body(false)?)
+                } else {
+                    let mut names = vec![self.name()?];
+                    while self.accept(",") {
+                        names.push(self.name()?);
+                    }
+                    let values = if self.accept("=") {
+                        self.expression_list()?
+                    } else {
+                        Vec::new()
+                    };
+                    StatementKind::Local(names, values)
+                }
+            }
+            Token::Symbol("break") => {
+                self.position += 1;
+                if self.loops == 0 {
+                    return error(line, "break outside a loop");
+                }
+                StatementKind::Break
+            }
+            _ => {
+                let expression = self.suffixed_expression()?;
+                if self.check("=") || self.check(",") {
+                    let mut targets = vec![expression];
+                    while self.accept(",") {
+                        targets.push(self.suffixed_expression()?);
+                    }
+                    if !targets
+                        .iter()
+                        .all(|target| matches!(target, Expr::Name(_) | Expr::Index(..)))
+                    {
+                        return error(line, "cannot assign to that");
+                    }
+                    self.expect("=")?;
+                    StatementKind::Assign(targets, self.expression_list()?)
+                } else if matches!(expression, Expr::Call(..) | Expr::Method(..)) {
+                    StatementKind::Call(expression)
+                } else {
+                    return self.unexpected("syntax error");
+                }
+            }
+        };
+        Ok(Some(Statement { line, kind }))
+    }
+
+    fn for_statement(&mut self) -> Result<StatementKind> {
+        let name = self.name()?;
+        if self.accept("=") {
+            let start = self.expression()?;
+            self.expect(",")?;
+            let limit = self.expression()?;
+            let step = if self.accept(",") {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.expect("do")?;
+            let body = self.loop_body()?;
+            self.expect("end")?;
+            return Ok(StatementKind::NumericFor(NumericFor {
+                name,
+                start,
+                limit,
+                step,
+                body,
+            }));
+        }
+        let mut names = vec![name];
+        while self.accept(",") {
+            names.push(self.name()?);
+        }
+        self.expect("in")?;
+        let values = self.expression_list()?;
+        self.expect("do")?;
+        let body = self.loop_body()?;
+        self.expect("end")?;
+        Ok(StatementKind::GenericFor(names, values, body))
+    }
+
+    // the parameters and body after function, a break inside it can't leave a loop outside it
+    fn function_body(&mut self, method: bool) -> Result<Rc<Function>> {
+        self.expect("(")?;
+        let mut parameters = Vec::new();
+        if method {
+            parameters.push("self".into());
+        }
+        if !self.check(")") {
+            loop {
+                parameters.push(self.name()?);
+                if !self.accept(",") {
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.block();
+        self.loops = loops;
+        let body = body?;
+        self.expect("end")?;
+        Ok(Rc::new(Function { parameters, body }))
+    }
+
+    fn expression_list(&mut self) -> Result<Vec<Expr>> {
+        let mut list = vec![self.expression()?];
+        while self.accept(",") {
+            list.push(self.expression()?);
+        }
+        Ok(list)
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.subexpression(0)
+    }
+
+    // operators binding tighter than limit
+    fn subexpression(&mut self, limit: u8) -> Result<Expr> {
+        let unary = match self.peek() {
+            Token::Symbol("not") => Some(UnaryOp::Not),
+            Token::Symbol("-") => Some(UnaryOp::Negate),
+            Token::Symbol("#") => Some(UnaryOp::Length),
+            _ => None,
+        };
+        let mut left = match unary {
+            Some(operator) => {
+                self.position += 1;
+                let operand = self.subexpression(UNARY_PRIORITY)?;
+                Expr::Unary(operator, Box::new(operand))
+            }
+            None => self.simple_expression()?,
+        };
+        while let Some((operator, left_priority, right_priority)) = binary_operator(self.peek())
+            && left_priority > limit
+        {
+            self.position += 1;
+            let right = self.subexpression(right_priority)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn simple_expression(&mut self) -> Result<Expr> {
+        let expression = match self.peek().clone() {
+            Token::Number(number) => Expr::Number(number),
+            Token::Str(text) => Expr::Str(text),
+            Token::Symbol("nil") => Expr::Nil,
+            Token::Symbol("true") => Expr::Bool(true),
+            Token::Symbol("false") => Expr::Bool(false),
+            Token::Symbol("function") => {
+                self.position += 1;
+                return Ok(Expr::Function(self.function_body(false)?));
+            }
+            Token::Symbol("{") => return self.table(),
+            _ => return self.suffixed_expression(),
+        };
+        self.position += 1;
+        Ok(expression)
+    }
+
+    // a name or a parenthesised expression, then any fields, indexes and calls of it
+    fn suffixed_expression(&mut self) -> Result<Expr> {
+        let mut expression = match self.peek().clone() {
+            Token::Name(name) => {
+                self.position += 1;
+                Expr::Name(name)
+            }
+            Token::Symbol("(") => {
+                self.position += 1;
+                let inner = self.expression()?;
+                self.expect(")")?;
+                Expr::Paren(Box::new(inner))
+            }
+            _ => return self.unexpected("unexpected symbol"),
+        };
+        loop {
+            expression = match self.peek().clone() {
+                Token::Symbol(".") => {
+                    self.position += 1;
+                    let field = Expr::Str(self.name()?);
+                    Expr::Index(Box::new(expression), Box::new(field))
+                }
+                Token::Symbol("[") => {
+                    self.position += 1;
+                    let key = self.expression()?;
+                    self.expect("]")?;
+                    Expr::Index(Box::new(expression), Box::new(key))
+                }
+                Token::Symbol(":") => {
+                    self.position += 1;
+                    let name = self.name()?;
+                    Expr::Method(Box::new(expression), name, self.arguments()?)
+                }
+                Token::Symbol("(" | "{") | Token::Str(_) => {
+                    Expr::Call(Box::new(expression), self.arguments()?)
+                }
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    // (a, b), or a single table or string without the parentheses
+    fn arguments(&mut self) -> Result<Vec<Expr>> {
+        match self.peek().clone() {
+            Token::Str(text) => {
+                self.position += 1;
+                Ok(vec![Expr::Str(text)])
+            }
+            Token::Symbol("{") => Ok(vec![self.table()?]),
+            _ => {
+                self.expect("(")?;
+                if self.accept(")") {
+                    return Ok(Vec::new());
+                }
+                let arguments = self.expression_list()?;
+                self.expect(")")?;
+                Ok(arguments)
+            }
+        }
+    }
+
+    fn table(&mut self) -> Result<Expr> {
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        while !self.check("}") {
+            if self.accept("[") {
+                let key = self.expression()?;
+                self.expect("]")?;
+                self.expect("=")?;
+                fields.push(Field::Keyed(key, self.expression()?));
+            } else if let Token::Name(name) = self.peek().clone()
+                && *self.peek_next() == Token::Symbol("=")
+            {
+                self.position += 2;
+                fields.push(Field::Keyed(Expr::Str(name), self.expression()?));
+            } else {
+                fields.push(Field::Item(self.expression()?));
+            }
+            if !self.accept(",") && !self.accept(";") {
+                break;
+            }
+        }
+        self.expect("}")?;
+        Ok(Expr::Table(fields))
+    }
+}
+
+#[derive(Clone)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    Table(Rc<RefCell<Table>>),
+    Function(Rc<Closure>),
+    Builtin(Builtin),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::Table(_) => "table",
+            Value::Function(_) | Value::Builtin(_) => "function",
+        }
+    }
+
+    // numbers, and strings that read as one
+    fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Str(text) => parse_number(text),
+            _ => None,
+        }
+    }
+
+    // what .. takes, numbers and strings
+    fn to_text(&self) -> Option<Rc<str>> {
+        match self {
+            Value::Str(text) => Some(text.clone()),
+            Value::Number(number) => Some(number_to_string(*number).into()),
+            _ => None,
+        }
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Table(a), Value::Table(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// what tostring and print show
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(number) => write!(f, "{}", number_to_string(*number)),
+            Value::Str(text) => write!(f, "{text}"),
+            Value::Table(table) => write!(f, "table: {:p}", Rc::as_ptr(table)),
+            Value::Function(closure) => write!(f, "function: {:p}", Rc::as_ptr(closure)),
+            Value::Builtin(builtin) => write!(f, "function: builtin {}", builtin_name(*builtin)),
+        }
+    }
+}
+
+// integers without a fraction like lua prints them
+fn number_to_string(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < 1e15 {
+        format!("{}", number as i64)
+    } else if number.is_nan() {
+        "nan".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{number}")
+    }
+}
+
+// decimal or 0x hex, with space around it
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u64::from_str_radix(digits, 16)
+            .ok()
+            .map(|value| value as f64);
+    }
+    // not the inf and nan rust would read
+    if !text
+        .bytes()
+        .all(|byte| byte.is_ascii_digit() || b".eE+-".contains(&byte))
+    {
+        return None;
+    }
+    text.parse().ok()
+}
+
+// a value a table can be indexed by, anything but nil and nan. tables and functions are keys by
+// identity
+#[derive(Clone)]
+enum Key {
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    Builtin(Builtin),
+    Table(Rc<RefCell<Table>>),
+    Function(Rc<Closure>),
+}
+
+impl Key {
+    fn of(value: &Value) -> Option<Key> {
+        Some(match value {
+            Value::Nil => return None,
+            Value::Number(number) if number.is_nan() => return None,
+            // -0 and 0 are the same key
+            Value::Number(number) => Key::Number(number + 0.0),
+            Value::Bool(value) => Key::Bool(*value),
+            Value::Str(text) => Key::Str(text.clone()),
+            Value::Builtin(builtin) => Key::Builtin(*builtin),
+            Value::Table(table) => Key::Table(table.clone()),
+            Value::Function(closure) => Key::Function(closure.clone()),
+        })
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            Key::Bool(value) => Value::Bool(*value),
+            Key::Number(number) => Value::Number(*number),
+            Key::Str(text) => Value::Str(text.clone()),
+            Key::Builtin(builtin) => Value::Builtin(*builtin),
+            Key::Table(table) => Value::Table(table.clone()),
+            Key::Function(closure) => Value::Function(closure.clone()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Bool(_) => 0,
+            Key::Number(_) => 1,
+            Key::Str(_) => 2,
+            Key::Builtin(_) => 3,
+            Key::Table(_) => 4,
+            Key::Function(_) => 5,
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Key::Bool(a), Key::Bool(b)) => a.cmp(b),
+            (Key::Number(a), Key::Number(b)) => a.total_cmp(b),
+            (Key::Str(a), Key::Str(b)) => a.cmp(b),
+            (Key::Builtin(a), Key::Builtin(b)) => a.cmp(b),
+            (Key::Table(a), Key::Table(b)) => Rc::as_ptr(a).cmp(&Rc::as_ptr(b)),
+            (Key::Function(a), Key::Function(b)) => Rc::as_ptr(a).cmp(&Rc::as_ptr(b)),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+// ordered by key so pairs goes through a table the same way every run, as a movie needs
+#[derive(Default)]
+struct Table {
+    entries: BTreeMap<Key, Value>,
+}
+
+impl Table {
+    fn get(&self, key: &Value) -> Value {
+        Key::of(key)
+            .and_then(|key| self.entries.get(&key).cloned())
+            .unwrap_or(Value::Nil)
+    }
+
+    fn set(&mut self, key: Key, value: Value) {
+        if let Value::Nil = value {
+            self.entries.remove(&key);
+        } else {
+            self.entries.insert(key, value);
+        }
+    }
+
+    fn get_index(&self, index: usize) -> Value {
+        self.entries
+            .get(&Key::Number(index as f64))
+            .cloned()
+            .unwrap_or(Value::Nil)
+    }
+
+    fn set_index(&mut self, index: usize, value: Value) {
+        self.set(Key::Number(index as f64), value);
+    }
+
+    // what # gives, the last of 1, 2, 3... before a nil
+    fn length(&self) -> usize {
+        let mut length = 0;
+        while self.entries.contains_key(&Key::Number((length + 1) as f64)) {
+            length += 1;
+        }
+        length
+    }
+}
+
+struct Closure {
+    function: Rc<Function>,
+    scope: Rc<Scope>,
+}
+
+// a local variable, shared by the closures that see it
+type Local = Rc<RefCell<Value>>;
+
+// the locals of a block, looked for from the innermost block out. each is its own cell, so the
+// closures made in a loop each keep the variable of their own pass
+struct Scope {
+    locals: RefCell<Vec<(Rc<str>, Local)>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    fn new(parent: Option<&Rc<Scope>>) -> Rc<Scope> {
+        Rc::new(Scope {
+            locals: RefCell::new(Vec::new()),
+            parent: parent.cloned(),
+        })
+    }
+
+    fn declare(&self, name: Rc<str>, value: Value) {
+        self.locals
+            .borrow_mut()
+            .push((name, Rc::new(RefCell::new(value))));
+    }
+
+    fn find(&self, name: &str) -> Option<Local> {
+        let mut scope = self;
+        loop {
+            let found = scope
+                .locals
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(local, _)| **local == *name)
+                .map(|(_, cell)| cell.clone());
+            if found.is_some() {
+                return found;
+            }
+            scope = scope.parent.as_deref()?;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Builtin {
+    Print,
+    Type,
+    ToString,
+    ToNumber,
+    Pairs,
+    Next,
+    Ipairs,
+    // what ipairs returns to step with
+    IpairsNext,
+    Error,
+    Assert,
+    Unpack,
+    Floor,
+    Ceil,
+    Abs,
+    Max,
+    Min,
+    Sqrt,
+    Random,
+    RandomSeed,
+    Format,
+    Sub,
+    Len,
+    Upper,
+    Lower,
+    Rep,
+    Byte,
+    Char,
+    Insert,
+    Remove,
+    Concat,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    LeftShift,
+    RightShift,
+    Bit,
+    ReadByte,
+    ReadByteSigned,
+    ReadWord,
+    WriteByte,
+    GetRegister,
+    RegisterRead,
+    RegisterWrite,
+    RegisterBefore,
+    RegisterAfter,
+    FrameCount,
+    Text,
+    Box,
+    Pixel,
+    JoypadRead,
+    JoypadSet,
+}
+
+// the functions a script starts with, by the table they are in. AND, OR, XOR and BIT are the
+// globals fceux has besides bit
+const LIBRARY: [(&str, &str, Builtin); 56] = [
+    ("", "print", Builtin::Print),
+    ("", "type", Builtin::Type),
+    ("", "tostring", Builtin::ToString),
+    ("", "tonumber", Builtin::ToNumber),
+    ("", "pairs", Builtin::Pairs),
+    ("", "next", Builtin::Next),
+    ("", "ipairs", Builtin::Ipairs),
+    ("", "error", Builtin::Error),
+    ("", "assert", Builtin::Assert),
+    ("", "unpack", Builtin::Unpack),
+    ("math", "floor", Builtin::Floor),
+    ("math", "ceil", Builtin::Ceil),
+    ("math", "abs", Builtin::Abs),
+    ("math", "max", Builtin::Max),
+    ("math", "min", Builtin::Min),
+    ("math", "sqrt", Builtin::Sqrt),
+    ("math", "random", Builtin::Random),
+    ("math", "randomseed", Builtin::RandomSeed),
+    ("string", "format", Builtin::Format),
+    ("string", "sub", Builtin::Sub),
+    ("string", "len", Builtin::Len),
+    ("string", "upper", Builtin::Upper),
+    ("string", "lower", Builtin::Lower),
+    ("string", "rep", Builtin::Rep),
+    ("string", "byte", Builtin::Byte),
+    ("string", "char", Builtin::Char),
+    ("table", "insert", Builtin::Insert),
+    ("table", "remove", Builtin::Remove),
+    ("table", "concat", Builtin::Concat),
+    ("bit", "band", Builtin::BitAnd),
+    ("bit", "bor", Builtin::BitOr),
+    ("bit", "bxor", Builtin::BitXor),
+    ("bit", "bnot", Builtin::BitNot),
+    ("bit", "lshift", Builtin::LeftShift),
+    ("bit", "rshift", Builtin::RightShift),
+    ("", "AND", Builtin::BitAnd),
+    ("", "OR", Builtin::BitOr),
+    ("", "XOR", Builtin::BitXor),
+    ("", "BIT", Builtin::Bit),
+    ("memory", "readbyte", Builtin::ReadByte),
+    ("memory", "readbytesigned", Builtin::ReadByteSigned),
+    ("memory", "readword", Builtin::ReadWord),
+    ("memory", "writebyte", Builtin::WriteByte),
+    ("memory", "getregister", Builtin::GetRegister),
+    ("memory", "registerread", Builtin::RegisterRead),
+    ("memory", "registerwrite", Builtin::RegisterWrite),
+    ("emu", "registerbefore", Builtin::RegisterBefore),
+    ("emu", "registerafter", Builtin::RegisterAfter),
+    ("emu", "framecount", Builtin::FrameCount),
+    ("emu", "print", Builtin::Print),
+    ("gui", "text", Builtin::Text),
+    ("gui", "box", Builtin::Box),
+    ("gui", "pixel", Builtin::Pixel),
+    ("joypad", "read", Builtin::JoypadRead),
+    ("joypad", "set", Builtin::JoypadSet),
+    ("", "ipairs", Builtin::IpairsNext),
+];
+
+// the name a builtin is called by in errors, math.floor
+fn builtin_name(builtin: Builtin) -> String {
+    let (table, name, _) = LIBRARY
+        .iter()
+        .find(|(_, _, found)| *found == builtin)
+        .expect("every builtin is in LIBRARY");
+    if table.is_empty() {
+        name.to_string()
+    } else {
+        format!("{table}.{name}")
+    }
+}
+
+// the fields of joypad.read and joypad.set
+const BUTTONS: [(&str, Button); 8] = [
+    ("A", Button::A),
+    ("B", Button::B),
+    ("select", Button::Select),
+    ("start", Button::Start),
+    ("up", Button::Up),
+    ("down", Button::Down),
+    ("left", Button::Left),
+    ("right", Button::Right),
+];
+
+// the colours gui functions take by name, the ones fceux has
+const COLORS: [(&str, Color); 15] = [
+    ("white", [0xFF, 0xFF, 0xFF, 0xFF]),
+    ("black", [0x00, 0x00, 0x00, 0xFF]),
+    ("clear", [0x00, 0x00, 0x00, 0x00]),
+    ("gray", [0x7F, 0x7F, 0x7F, 0xFF]),
+    ("grey", [0x7F, 0x7F, 0x7F, 0xFF]),
+    ("red", [0xFF, 0x00, 0x00, 0xFF]),
+    ("orange", [0xFF, 0x7F, 0x00, 0xFF]),
+    ("yellow", [0xFF, 0xFF, 0x00, 0xFF]),
+    ("chartreuse", [0x7F, 0xFF, 0x00, 0xFF]),
+    ("green", [0x00, 0xFF, 0x00, 0xFF]),
+    ("teal", [0x00, 0xFF, 0x7F, 0xFF]),
+    ("cyan", [0x00, 0xFF, 0xFF, 0xFF]),
+    ("blue", [0x00, 0x00, 0xFF, 0xFF]),
+    ("purple", [0x7F, 0x00, 0xFF, 0xFF]),
+    ("magenta", [0xFF, 0x00, 0xFF, 0xFF]),
+];
+
+const WHITE: Color = [0xFF; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Return(Vec<Value>),
+}
+
+struct Lua {
+    // what runtime errors are reported under, the file name
+    name: String,
+    globals: HashMap<Rc<str>, Value>,
+    // the functions given to emu.registerbefore and emu.registerafter
+    before: Value,
+    after: Value,
+    // memory.registerread and registerwrite calls not made into hooks yet
+    registered: Vec<(Access, RangeInclusive<u16>, Value)>,
+    // the hooks made from them, and the two running before and after
+    memory_hooks: Vec<(Access, RangeInclusive<u16>, HookId)>,
+    frame_hooks: Vec<HookId>,
+    // statements left for what is running, and the calls it is inside
+    budget: u64,
+    depth: usize,
+    // of the statement running, for errors
+    line: usize,
+    // xorshift state of math.random, seeded the same every run so a bot replays
+    random: u64,
+}
+
+impl Lua {
+    fn new(name: &str) -> Self {
+        let mut globals = HashMap::new();
+        for (table, name, builtin) in LIBRARY {
+            if builtin == Builtin::IpairsNext {
+                continue;
+            }
+            let value = Value::Builtin(builtin);
+            if table.is_empty() {
+                globals.insert(name.into(), value);
+                continue;
+            }
+            let library = globals
+                .entry(table.into())
+                .or_insert_with(|| Value::Table(Rc::default()));
+            if let Value::Table(library) = library {
+                library.borrow_mut().set(Key::Str(name.into()), value);
+            }
+        }
+        if let Some(Value::Table(math)) = globals.get("math") {
+            let mut math = math.borrow_mut();
+            math.set(Key::Str("huge".into()), Value::Number(f64::INFINITY));
+            math.set(Key::Str("pi".into()), Value::Number(std::f64::consts::PI));
+        }
+        Lua {
+            name: name.to_string(),
+            globals,
+            before: Value::Nil,
+            after: Value::Nil,
+            registered: Vec::new(),
+            memory_hooks: Vec::new(),
+            frame_hooks: Vec::new(),
+            budget: BUDGET,
+            depth: 0,
+            line: 0,
+            random: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        error(self.line, message)
+    }
+
+    fn exec_block(
+        &mut self,
+        api: &mut ScriptApi,
+        block: &[Statement],
+        scope: &Rc<Scope>,
+    ) -> Result<Flow> {
+        for statement in block {
+            self.line = statement.line;
+            self.tick()?;
+            let flow = self.exec(api, &statement.kind, scope)?;
+            if !matches!(flow, Flow::Normal) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // counts down the budget for a statement or a pass of a loop, even an empty one
+    fn tick(&mut self) -> Result<()> {
+        if self.budget == 0 {
+            return self.error("the script ran for too long without returning");
+        }
+        self.budget -= 1;
+        Ok(())
+    }
+
+    // a Break or Return in a loop's body ends the loop, the return passed on
+    fn exec_loop_body(
+        &mut self,
+        api: &mut ScriptApi,
+        body: &[Statement],
+        scope: &Rc<Scope>,
+    ) -> Result<Option<Flow>> {
+        self.tick()?;
+        Ok(match self.exec_block(api, body, scope)? {
+            Flow::Normal => None,
+            Flow::Break => Some(Flow::Normal),
+            flow => Some(flow),
+        })
+    }
+
+    fn exec(
+        &mut self,
+        api: &mut ScriptApi,
+        statement: &StatementKind,
+        scope: &Rc<Scope>,
+    ) -> Result<Flow> {
+        match statement {
+            StatementKind::Local(names, values) => {
+                let mut values = self.eval_list(api, values, scope)?.into_iter();
+                for name in names {
+                    scope.declare(name.clone(), values.next().unwrap_or(Value::Nil));
+                }
+            }
+            StatementKind::LocalFunction(name, function) => {
+                scope.declare(name.clone(), Value::Nil);
+                let closure = Value::Function(Rc::new(Closure {
+                    function: function.clone(),
+                    scope: scope.clone(),
+                }));
+                let cell = scope.find(name).expect("just declared");
+                *cell.borrow_mut() = closure;
+            }
+            StatementKind::Assign(targets, values) => {
+                let values = self.eval_list(api, values, scope)?;
+                for (index, target) in targets.iter().enumerate() {
+                    let value = values.get(index).cloned().unwrap_or(Value::Nil);
+                    self.assign(api, target, value, scope)?;
+                }
+            }
+            StatementKind::Call(call) => {
+                self.eval_multiple(api, call, scope)?;
+            }
+            StatementKind::Do(body) => return self.exec_block(api, body, &Scope::new(Some(scope))),
+            StatementKind::While(condition, body) => {
+                while self.eval(api, condition, scope)?.truthy() {
+                    if let Some(flow) = self.exec_loop_body(api, body, &Scope::new(Some(scope)))? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StatementKind::Repeat(body, condition) => loop {
+                // the condition sees the locals of the body
+                let inner = Scope::new(Some(scope));
+                if let Some(flow) = self.exec_loop_body(api, body, &inner)? {
+                    return Ok(flow);
+                }
+                if self.eval(api, condition, &inner)?.truthy() {
+                    break;
+                }
+            },
+            StatementKind::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.eval(api, condition, scope)?.truthy() {
+                        return self.exec_block(api, body, &Scope::new(Some(scope)));
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.exec_block(api, body, &Scope::new(Some(scope)));
+                }
+            }
+            StatementKind::NumericFor(numeric) => return self.numeric_for(api, numeric, scope),
+            StatementKind::GenericFor(names, values, body) => {
+                return self.generic_for(api, names, values, body, scope);
+            }
+            StatementKind::Return(values) => {
+                return Ok(Flow::Return(self.eval_list(api, values, scope)?));
+            }
+            StatementKind::Break => return Ok(Flow::Break),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn numeric_for(
+        &mut self,
+        api: &mut ScriptApi,
+        numeric: &NumericFor,
+        scope: &Rc<Scope>,
+    ) -> Result<Flow> {
+        let mut number = |lua: &mut Self, expression: &Expr, what: &str| match lua
+            .eval(api, expression, scope)?
+            .to_number()
+        {
+            Some(number) => Ok(number),
+            None => lua.error(format!("'for' {what} must be a number")),
+        };
+        let mut value = number(self, &numeric.start, "initial value")?;
+        let limit = number(self, &numeric.limit, "limit")?;
+        let step = match &numeric.step {
+            Some(step) => number(self, step, "step")?,
+            None => 1.0,
+        };
+        if step == 0.0 {
+            return self.error("'for' step is zero");
+        }
+        while (step > 0.0 && value <= limit) || (step < 0.0 && value >= limit) {
+            let inner = Scope::new(Some(scope));
+            inner.declare(numeric.name.clone(), Value::Number(value));
+            if let Some(flow) = self.exec_loop_body(api, &numeric.body, &inner)? {
+                return Ok(flow);
+            }
+            value += step;
+        }
+        Ok(Flow::Normal)
+    }
+
+    // for names in f, state, control: calls f(state, control) until its first value is nil
+    fn generic_for(
+        &mut self,
+        api: &mut ScriptApi,
+        names: &[Rc<str>],
+        values: &[Expr],
+        body: &[Statement],
+        scope: &Rc<Scope>,
+    ) -> Result<Flow> {
+        let mut values = self.eval_list(api, values, scope)?.into_iter();
+        let function = values.next().unwrap_or(Value::Nil);
+        let state = values.next().unwrap_or(Value::Nil);
+        let mut control = values.next().unwrap_or(Value::Nil);
+        loop {
+            let results = self.call(api, &function, vec![state.clone(), control.clone()])?;
+            control = results.first().cloned().unwrap_or(Value::Nil);
+            if let Value::Nil = control {
+                break;
+            }
+            let inner = Scope::new(Some(scope));
+            for (index, name) in names.iter().enumerate() {
+                let value = results.get(index).cloned().unwrap_or(Value::Nil);
+                inner.declare(name.clone(), value);
+            }
+            if let Some(flow) = self.exec_loop_body(api, body, &inner)? {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn assign(
+        &mut self,
+        api: &mut ScriptApi,
+        target: &Expr,
+        value: Value,
+        scope: &Rc<Scope>,
+    ) -> Result<()> {
+        match target {
+            Expr::Name(name) => match scope.find(name) {
+                Some(cell) => *cell.borrow_mut() = value,
+                None if matches!(value, Value::Nil) => {
+                    self.globals.remove(name);
+                }
+                None => {
+                    self.globals.insert(name.clone(), value);
+                }
+            },
+            Expr::Index(object, key) => {
+                let object_value = self.eval(api, object, scope)?;
+                let key = self.eval(api, key, scope)?;
+                let Value::Table(table) = &object_value else {
+                    return self.error(format!(
+                        "attempt to index {}",
+                        describe(object, &object_value)
+                    ));
+                };
+                let Some(key) = Key::of(&key) else {
+                    return self.error(format!("table index is {}", key.type_name()));
+                };
+                table.borrow_mut().set(key, value);
+            }
+            _ => unreachable!("the parser only lets names and indexes be assigned"),
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, api: &mut ScriptApi, expression: &Expr, scope: &Rc<Scope>) -> Result<Value> {
+        Ok(match expression {
+            Expr::Nil => Value::Nil,
+            Expr::Bool(value) => Value::Bool(*value),
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Str(text) => Value::Str(text.clone()),
+            Expr::Name(name) => match scope.find(name) {
+                Some(cell) => cell.borrow().clone(),
+                None => self.globals.get(name).cloned().unwrap_or(Value::Nil),
+            },
+            Expr::Index(object, key) => {
+                let object_value = self.eval(api, object, scope)?;
+                let key = self.eval(api, key, scope)?;
+                match self.index(&object_value, &key) {
+                    Some(value) => value,
+                    None => {
+                        return self.error(format!(
+                            "attempt to index {}",
+                            describe(object, &object_value)
+                        ));
+                    }
+                }
+            }
+            Expr::Call(..) | Expr::Method(..) => {
+                let values = self.eval_multiple(api, expression, scope)?;
+                values.into_iter().next().unwrap_or(Value::Nil)
+            }
+            Expr::Function(function) => Value::Function(Rc::new(Closure {
+                function: function.clone(),
+                scope: scope.clone(),
+            })),
+            Expr::Table(fields) => self.table_constructor(api, fields, scope)?,
+            Expr::Paren(inner) => self.eval(api, inner, scope)?,
+            Expr::Unary(operator, operand) => {
+                let value = self.eval(api, operand, scope)?;
+                self.unary(*operator, value)?
+            }
+            Expr::Binary(BinaryOp::And, left, right) => {
+                let left = self.eval(api, left, scope)?;
+                if !left.truthy() {
+                    return Ok(left);
+                }
+                self.eval(api, right, scope)?
+            }
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                let left = self.eval(api, left, scope)?;
+                if left.truthy() {
+                    return Ok(left);
+                }
+                self.eval(api, right, scope)?
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(api, left, scope)?;
+                let right = self.eval(api, right, scope)?;
+                self.binary(*operator, left, right)?
+            }
+        })
+    }
+
+    fn table_constructor(
+        &mut self,
+        api: &mut ScriptApi,
+        fields: &[Field],
+        scope: &Rc<Scope>,
+    ) -> Result<Value> {
+        let mut table = Table::default();
+        let mut next = 1;
+        for (index, field) in fields.iter().enumerate() {
+            match field {
+                Field::Keyed(key, value) => {
+                    let key = self.eval(api, key, scope)?;
+                    let value = self.eval(api, value, scope)?;
+                    let Some(key) = Key::of(&key) else {
+                        return self.error(format!("table index is {}", key.type_name()));
+                    };
+                    table.set(key, value);
+                }
+                // a call last in the constructor gives all its values
+                Field::Item(value) if index + 1 == fields.len() => {
+                    for value in self.eval_multiple(api, value, scope)? {
+                        table.set_index(next, value);
+                        next += 1;
+                    }
+                }
+                Field::Item(value) => {
+                    let value = self.eval(api, value, scope)?;
+                    table.set_index(next, value);
+                    next += 1;
+                }
+            }
+        }
+        Ok(Value::Table(Rc::new(RefCell::new(table))))
+    }
+
+    // every value a call gives, one value for anything else
+    fn eval_multiple(
+        &mut self,
+        api: &mut ScriptApi,
+        expression: &Expr,
+        scope: &Rc<Scope>,
+    ) -> Result<Vec<Value>> {
+        match expression {
+            Expr::Call(function, arguments) => {
+                let callee = self.eval(api, function, scope)?;
+                let arguments = self.eval_list(api, arguments, scope)?;
+                if !matches!(callee, Value::Function(_) | Value::Builtin(_)) {
+                    return self.error(format!("attempt to call {}", describe(function, &callee)));
+                }
+                self.call(api, &callee, arguments)
+            }
+            Expr::Method(object, name, arguments) => {
+                let object = self.eval(api, object, scope)?;
+                let callee = self.index(&object, &Value::Str(name.clone()));
+                let Some(callee @ (Value::Function(_) | Value::Builtin(_))) = callee else {
+                    return self.error(format!("attempt to call method '{name}'"));
+                };
+                let mut all = vec![object];
+                all.extend(self.eval_list(api, arguments, scope)?);
+                self.call(api, &callee, all)
+            }
+            _ => Ok(vec![self.eval(api, expression, scope)?]),
+        }
+    }
+
+    // one value for each expression but a call last in the list, which gives all of its own
+    fn eval_list(
+        &mut self,
+        api: &mut ScriptApi,
+        expressions: &[Expr],
+        scope: &Rc<Scope>,
+    ) -> Result<Vec<Value>> {
+        let mut values = Vec::with_capacity(expressions.len());
+        for (index, expression) in expressions.iter().enumerate() {
+            if index + 1 == expressions.len() {
+                values.extend(self.eval_multiple(api, expression, scope)?);
+            } else {
+                values.push(self.eval(api, expression, scope)?);
+            }
+        }
+        Ok(values)
+    }
+
+    // a field of a table, or a string function for a string. none for what can't be indexed
+    fn index(&self, object: &Value, key: &Value) -> Option<Value> {
+        match object {
+            Value::Table(table) => Some(table.borrow().get(key)),
+            Value::Str(_) => match self.globals.get("string") {
+                Some(Value::Table(string)) => Some(string.borrow().get(key)),
+                _ => Some(Value::Nil),
+            },
+            _ => None,
+        }
+    }
+
+    fn call(
+        &mut self,
+        api: &mut ScriptApi,
+        function: &Value,
+        arguments: Vec<Value>,
+    ) -> Result<Vec<Value>> {
+        let closure = match function {
+            Value::Builtin(builtin) => return self.builtin(api, *builtin, arguments),
+            Value::Function(closure) => closure.clone(),
+            _ => return self.error(format!("attempt to call a {} value", function.type_name())),
+        };
+        if self.depth == MAX_DEPTH {
+            return self.error("stack overflow");
+        }
+        let scope = Scope::new(Some(&closure.scope));
+        let mut arguments = arguments.into_iter();
+        for name in &closure.function.parameters {
+            scope.declare(name.clone(), arguments.next().unwrap_or(Value::Nil));
+        }
+        let line = self.line;
+        self.depth += 1;
+        let flow = self.exec_block(api, &closure.function.body, &scope);
+        self.depth -= 1;
+        let flow = flow?;
+        self.line = line;
+        Ok(match flow {
+            Flow::Return(values) => values,
+            _ => Vec::new(),
+        })
+    }
+
+    fn unary(&self, operator: UnaryOp, value: Value) -> Result<Value> {
+        Ok(match operator {
+            UnaryOp::Not => Value::Bool(!value.truthy()),
+            UnaryOp::Negate => match value.to_number() {
+                Some(number) => Value::Number(-number),
+                None => return self.arithmetic_error(&value),
+            },
+            UnaryOp::Length => match &value {
+                Value::Str(text) => Value::Number(text.len() as f64),
+                Value::Table(table) => Value::Number(table.borrow().length() as f64),
+                _ => {
+                    return self.error(format!(
+                        "attempt to get length of a {} value",
+                        value.type_name()
+                    ));
+                }
+            },
+        })
+    }
+
+    fn binary(&self, operator: BinaryOp, left: Value, right: Value) -> Result<Value> {
+        use BinaryOp::*;
+        Ok(match operator {
+            Equal => Value::Bool(left.equals(&right)),
+            NotEqual => Value::Bool(!left.equals(&right)),
+            Less | LessEqual | Greater | GreaterEqual => {
+                let ordering = match (&left, &right) {
+                    (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+                    (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+                    _ => {
+                        return self.error(format!(
+                            "attempt to compare {} with {}",
+                            left.type_name(),
+                            right.type_name()
+                        ));
+                    }
+                };
+                // nan is neither less nor more than anything
+                Value::Bool(match operator {
+                    Less => ordering == Some(Ordering::Less),
+                    LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Greater => ordering == Some(Ordering::Greater),
+                    _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                })
+            }
+            Concat => match (left.to_text(), right.to_text()) {
+                (Some(left), Some(right)) => Value::Str(format!("{left}{right}").into()),
+                (None, _) => {
+                    return self.error(format!(
+                        "attempt to concatenate a {} value",
+                        left.type_name()
+                    ));
+                }
+                (_, None) => {
+                    return self.error(format!(
+                        "attempt to concatenate a {} value",
+                        right.type_name()
+                    ));
+                }
+            },
+            _ => {
+                let (Some(a), Some(b)) = (left.to_number(), right.to_number()) else {
+                    let bad = if left.to_number().is_none() {
+                        &left
+                    } else {
+                        &right
+                    };
+                    return self.arithmetic_error(bad);
+                };
+                Value::Number(match operator {
+                    Add => a + b,
+                    Subtract => a - b,
+                    Multiply => a * b,
+                    Divide => a / b,
+                    Modulo => a - (a / b).floor() * b,
+                    Power => a.powf(b),
+                    _ => unreachable!("and, or and the comparisons are above"),
+                })
+            }
+        })
+    }
+
+    fn arithmetic_error<T>(&self, value: &Value) -> Result<T> {
+        self.error(format!(
+            "attempt to perform arithmetic on a {} value",
+            value.type_name()
+        ))
+    }
+
+    fn bad_argument<T>(&self, builtin: Builtin, index: usize, message: &str) -> Result<T> {
+        self.error(format!(
+            "bad argument #{} to '{}' ({message})",
+            index + 1,
+            builtin_name(builtin)
+        ))
+    }
+
+    fn number(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<f64> {
+        let value = arguments.get(index).unwrap_or(&Value::Nil);
+        match value.to_number() {
+            Some(number) => Ok(number),
+            None => self.bad_argument(
+                builtin,
+                index,
+                &format!("number expected, got {}", value.type_name()),
+            ),
+        }
+    }
+
+    fn integer(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<i64> {
+        Ok(self.number(builtin, arguments, index)?.floor() as i64)
+    }
+
+    // numbers are taken as the low 16 bits like fceux does
+    fn address(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<u16> {
+        Ok(self.integer(builtin, arguments, index)? as u16)
+    }
+
+    fn optional_integer(
+        &self,
+        builtin: Builtin,
+        arguments: &[Value],
+        index: usize,
+        default: i64,
+    ) -> Result<i64> {
+        match arguments.get(index) {
+            None | Some(Value::Nil) => Ok(default),
+            Some(_) => self.integer(builtin, arguments, index),
+        }
+    }
+
+    fn string(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<Rc<str>> {
+        let value = arguments.get(index).unwrap_or(&Value::Nil);
+        match value.to_text() {
+            Some(text) => Ok(text),
+            None => self.bad_argument(
+                builtin,
+                index,
+                &format!("string expected, got {}", value.type_name()),
+            ),
+        }
+    }
+
+    fn table(
+        &self,
+        builtin: Builtin,
+        arguments: &[Value],
+        index: usize,
+    ) -> Result<Rc<RefCell<Table>>> {
+        match arguments.get(index) {
+            Some(Value::Table(table)) => Ok(table.clone()),
+            value => self.bad_argument(
+                builtin,
+                index,
+                &format!(
+                    "table expected, got {}",
+                    value.unwrap_or(&Value::Nil).type_name()
+                ),
+            ),
+        }
+    }
+
+    // a function or nil, to register or to stop
+    fn callback(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<Value> {
+        match arguments.get(index) {
+            None => Ok(Value::Nil),
+            Some(value @ (Value::Nil | Value::Function(_) | Value::Builtin(_))) => {
+                Ok(value.clone())
+            }
+            Some(value) => self.bad_argument(
+                builtin,
+                index,
+                &format!("function expected, got {}", value.type_name()),
+            ),
+        }
+    }
+
+    fn player(&self, builtin: Builtin, arguments: &[Value], index: usize) -> Result<Player> {
+        Ok(match self.integer(builtin, arguments, index)? {
+            1 => Player::One,
+            2 => Player::Two,
+            3 => Player::Three,
+            4 => Player::Four,
+            _ => return self.bad_argument(builtin, index, "player 1 to 4 expected"),
+        })
+    }
+
+    // a name, "#RRGGBB" or "#RRGGBBAA", 0xRRGGBBAA, or a table of r, g, b and a
+    fn color(
+        &self,
+        builtin: Builtin,
+        arguments: &[Value],
+        index: usize,
+        default: Color,
+    ) -> Result<Color> {
+        let color = match arguments.get(index) {
+            None | Some(Value::Nil) => Some(default),
+            Some(Value::Str(text)) => {
+                let named = COLORS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(text));
+                match (named, text.strip_prefix('#')) {
+                    (Some((_, color)), _) => Some(*color),
+                    (None, Some(digits)) if digits.len() == 6 || digits.len() == 8 => {
+                        u32::from_str_radix(digits, 16).ok().map(|value| {
+                            let value = if digits.len() == 6 {
+                                (value << 8) | 0xFF
+                            } else {
+                                value
+                            };
+                            value.to_be_bytes()
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            Some(Value::Number(number)) => Some((*number as i64 as u32).to_be_bytes()),
+            Some(Value::Table(table)) => {
+                let table = table.borrow();
+                let mut color = WHITE;
+                for (channel, (name, position)) in [("r", 1), ("g", 2), ("b", 3), ("a", 4)]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let value = match table.get(&Value::Str(name.into())) {
+                        Value::Nil => table.get_index(position),
+                        value => value,
+                    };
+                    if let Some(value) = value.to_number() {
+                        color[channel] = value.clamp(0.0, 255.0) as u8;
+                    }
+                }
+                Some(color)
+            }
+            Some(_) => None,
+        };
+        match color {
+            Some(color) => Ok(color),
+            None => self.bad_argument(builtin, index, "colour expected"),
+        }
+    }
+
+    fn next_random(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn builtin(
+        &mut self,
+        api: &mut ScriptApi,
+        builtin: Builtin,
+        arguments: Vec<Value>,
+    ) -> Result<Vec<Value>> {
+        let argument = |index: usize| arguments.get(index).cloned().unwrap_or(Value::Nil);
+        let number = |number: f64| vec![Value::Number(number)];
+        let text = |text: String| vec![Value::Str(text.into())];
+        Ok(match builtin {
+            Builtin::Print => {
+                let line: Vec<String> = arguments.iter().map(Value::to_string).collect();
+                api.emulator.script_output += &line.join("\t");
+                api.emulator.script_output.push('\n');
+                Vec::new()
+            }
+            Builtin::Type => match arguments.first() {
+                Some(value) => text(value.type_name().to_string()),
+                None => return self.bad_argument(builtin, 0, "value expected"),
+            },
+            Builtin::ToString => text(argument(0).to_string()),
+            Builtin::ToNumber => {
+                let value = match arguments.get(1) {
+                    None | Some(Value::Nil) => argument(0).to_number(),
+                    Some(_) => {
+                        let base = self.integer(builtin, &arguments, 1)?;
+                        if !(2..=36).contains(&base) {
+                            return self.bad_argument(builtin, 1, "base out of range");
+                        }
+                        let digits = self.string(builtin, &arguments, 0)?;
+                        i64::from_str_radix(digits.trim(), base as u32)
+                            .ok()
+                            .map(|value| value as f64)
+                    }
+                };
+                vec![value.map_or(Value::Nil, Value::Number)]
+            }
+            Builtin::Pairs => {
+                let table = self.table(builtin, &arguments, 0)?;
+                vec![
+                    Value::Builtin(Builtin::Next),
+                    Value::Table(table),
+                    Value::Nil,
+                ]
+            }
+            Builtin::Next => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let table = table.borrow();
+                let next = match Key::of(&argument(1)) {
+                    None => table.entries.iter().next(),
+                    Some(key) => table
+                        .entries
+                        .range((Bound::Excluded(key), Bound::Unbounded))
+                        .next(),
+                };
+                match next {
+                    Some((key, value)) => vec![key.value(), value.clone()],
+                    None => vec![Value::Nil],
+                }
+            }
+            Builtin::Ipairs => {
+                let table = self.table(builtin, &arguments, 0)?;
+                vec![
+                    Value::Builtin(Builtin::IpairsNext),
+                    Value::Table(table),
+                    Value::Number(0.0),
+                ]
+            }
+            Builtin::IpairsNext => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let index = self.integer(builtin, &arguments, 1)? + 1;
+                match table.borrow().get_index(index as usize) {
+                    Value::Nil => vec![Value::Nil],
+                    value => vec![Value::Number(index as f64), value],
+                }
+            }
+            Builtin::Error => return self.error(argument(0).to_string()),
+            Builtin::Assert => {
+                if !argument(0).truthy() {
+                    return match arguments.get(1) {
+                        Some(message) => self.error(message.to_string()),
+                        None => self.error("assertion failed!"),
+                    };
+                }
+                arguments
+            }
+            Builtin::Unpack => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let table = table.borrow();
+                (1..=table.length())
+                    .map(|index| table.get_index(index))
+                    .collect()
+            }
+            Builtin::Floor => number(self.number(builtin, &arguments, 0)?.floor()),
+            Builtin::Ceil => number(self.number(builtin, &arguments, 0)?.ceil()),
+            Builtin::Abs => number(self.number(builtin, &arguments, 0)?.abs()),
+            Builtin::Sqrt => number(self.number(builtin, &arguments, 0)?.sqrt()),
+            Builtin::Max | Builtin::Min => {
+                let mut best = self.number(builtin, &arguments, 0)?;
+                for index in 1..arguments.len() {
+                    let value = self.number(builtin, &arguments, index)?;
+                    if (builtin == Builtin::Max && value > best)
+                        || (builtin == Builtin::Min && value < best)
+                    {
+                        best = value;
+                    }
+                }
+                number(best)
+            }
+            Builtin::Random => {
+                let fraction = self.next_random();
+                let (low, high) = match arguments.len() {
+                    0 => return Ok(number(fraction)),
+                    1 => (1, self.integer(builtin, &arguments, 0)?),
+                    _ => (
+                        self.integer(builtin, &arguments, 0)?,
+                        self.integer(builtin, &arguments, 1)?,
+                    ),
+                };
+                if low > high {
+                    return self.bad_argument(builtin, arguments.len() - 1, "interval is empty");
+                }
+                number((low as f64 + (fraction * (high - low + 1) as f64).floor()).min(high as f64))
+            }
+            Builtin::RandomSeed => {
+                let seed = self.integer(builtin, &arguments, 0)? as u64;
+                self.random = (seed ^ 0x2545_F491_4F6C_DD1D).max(1);
+                Vec::new()
+            }
+            Builtin::Format => text(self.format(&arguments)?),
+            Builtin::Sub => {
+                let string = self.string(builtin, &arguments, 0)?;
+                let start = self.optional_integer(builtin, &arguments, 1, 1)?;
+                let end = self.optional_integer(builtin, &arguments, 2, -1)?;
+                let bytes = string.as_bytes();
+                let range = byte_range(bytes.len(), start, end);
+                text(String::from_utf8_lossy(&bytes[range]).into_owned())
+            }
+            Builtin::Len => number(self.string(builtin, &arguments, 0)?.len() as f64),
+            Builtin::Upper => text(self.string(builtin, &arguments, 0)?.to_ascii_uppercase()),
+            Builtin::Lower => text(self.string(builtin, &arguments, 0)?.to_ascii_lowercase()),
+            Builtin::Rep => {
+                let string = self.string(builtin, &arguments, 0)?;
+                let count = self.integer(builtin, &arguments, 1)?.max(0) as usize;
+                match string.len().checked_mul(count) {
+                    Some(len) if len <= MAX_STRING => text(string.repeat(count)),
+                    _ => return self.error("resulting string too large"),
+                }
+            }
+            Builtin::Byte => {
+                let string = self.string(builtin, &arguments, 0)?;
+                let start = self.optional_integer(builtin, &arguments, 1, 1)?;
+                let end = self.optional_integer(builtin, &arguments, 2, start)?;
+                let range = byte_range(string.len(), start, end);
+                string.as_bytes()[range]
+                    .iter()
+                    .map(|byte| Value::Number(f64::from(*byte)))
+                    .collect()
+            }
+            Builtin::Char => {
+                let mut string = String::new();
+                for index in 0..arguments.len() {
+                    let code = self.integer(builtin, &arguments, index)?;
+                    let Ok(byte) = u8::try_from(code) else {
+                        return self.bad_argument(builtin, index, "value out of range");
+                    };
+                    string.push(char::from(byte));
+                }
+                text(string)
+            }
+            Builtin::Insert => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let mut table = table.borrow_mut();
+                let length = table.length();
+                let (position, value) = match arguments.len() {
+                    0..=2 => (length + 1, argument(1)),
+                    _ => {
+                        let position = self.integer(builtin, &arguments, 1)?;
+                        if position < 1 || position as usize > length + 1 {
+                            return self.bad_argument(builtin, 1, "position out of bounds");
+                        }
+                        (position as usize, argument(2))
+                    }
+                };
+                for index in (position..=length).rev() {
+                    let moved = table.get_index(index);
+                    table.set_index(index + 1, moved);
+                }
+                table.set_index(position, value);
+                Vec::new()
+            }
+            Builtin::Remove => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let mut table = table.borrow_mut();
+                let length = table.length();
+                if length == 0 {
+                    return Ok(vec![Value::Nil]);
+                }
+                let position = self.optional_integer(builtin, &arguments, 1, length as i64)?;
+                if position < 1 || position as usize > length {
+                    return self.bad_argument(builtin, 1, "position out of bounds");
+                }
+                let position = position as usize;
+                let removed = table.get_index(position);
+                for index in position..length {
+                    let moved = table.get_index(index + 1);
+                    table.set_index(index, moved);
+                }
+                table.set_index(length, Value::Nil);
+                vec![removed]
+            }
+            Builtin::Concat => {
+                let table = self.table(builtin, &arguments, 0)?;
+                let separator = match arguments.get(1) {
+                    None | Some(Value::Nil) => "".into(),
+                    Some(_) => self.string(builtin, &arguments, 1)?,
+                };
+                let table = table.borrow();
+                let mut parts = Vec::new();
+                for index in 1..=table.length() {
+                    match table.get_index(index).to_text() {
+                        Some(part) => parts.push(part),
+                        None => {
+                            return self.error(format!(
+                                "invalid value (at index {index}) in table for 'table.concat'"
+                            ));
+                        }
+                    }
+                }
+                text(parts.join(&separator))
+            }
+            Builtin::BitAnd | Builtin::BitOr | Builtin::BitXor => {
+                let mut result = if builtin == Builtin::BitAnd {
+                    u32::MAX
+                } else {
+                    0
+                };
+                for index in 0..arguments.len().max(1) {
+                    let value = self.integer(builtin, &arguments, index)? as u32;
+                    result = match builtin {
+                        Builtin::BitAnd => result & value,
+                        Builtin::BitOr => result | value,
+                        _ => result ^ value,
+                    };
+                }
+                number(f64::from(result))
+            }
+            Builtin::BitNot => number(f64::from(!(self.integer(builtin, &arguments, 0)? as u32))),
+            Builtin::LeftShift | Builtin::RightShift => {
+                let value = self.integer(builtin, &arguments, 0)? as u32;
+                let shift = self.integer(builtin, &arguments, 1)?;
+                let result = match (builtin, shift) {
+                    (_, 32..) | (_, ..0) => 0,
+                    (Builtin::LeftShift, shift) => value << shift,
+                    (_, shift) => value >> shift,
+                };
+                number(f64::from(result))
+            }
+            Builtin::Bit => {
+                let bit = self.integer(builtin, &arguments, 0)?;
+                number(if (0..32).contains(&bit) {
+                    f64::from(1u32 << bit)
+                } else {
+                    0.0
+                })
+            }
+            Builtin::ReadByte => number(f64::from(api.peek(self.address(builtin, &arguments, 0)?))),
+            Builtin::ReadByteSigned => number(f64::from(
+                api.peek(self.address(builtin, &arguments, 0)?) as i8,
+            )),
+            Builtin::ReadWord => {
+                let address = self.address(builtin, &arguments, 0)?;
+                let low = api.peek(address);
+                let high = api.peek(address.wrapping_add(1));
+                number(f64::from(u16::from_le_bytes([low, high])))
+            }
+            Builtin::WriteByte => {
+                let address = self.address(builtin, &arguments, 0)?;
+                let value = self.integer(builtin, &arguments, 1)? as u8;
+                api.poke(address, value);
+                Vec::new()
+            }
+            Builtin::GetRegister => {
+                let name = self.string(builtin, &arguments, 0)?;
+                let cpu = api.cpu();
+                let value = match name.to_ascii_lowercase().as_str() {
+                    "a" => cpu.reg_a.into(),
+                    "x" => cpu.reg_x.into(),
+                    "y" => cpu.reg_y.into(),
+                    "s" | "sp" => cpu.stack_pointer.into(),
+                    "p" => cpu.flags.to_byte(false).into(),
+                    "pc" => cpu.program_counter,
+                    _ => return self.bad_argument(builtin, 0, "a, x, y, s, p or pc expected"),
+                };
+                number(f64::from(value))
+            }
+            Builtin::RegisterRead | Builtin::RegisterWrite => {
+                let address = self.address(builtin, &arguments, 0)?;
+                let (size, function) = if arguments.len() >= 3 {
+                    (
+                        self.integer(builtin, &arguments, 1)?,
+                        self.callback(builtin, &arguments, 2)?,
+                    )
+                } else {
+                    (1, self.callback(builtin, &arguments, 1)?)
+                };
+                if size < 1 {
+                    return self.bad_argument(builtin, 1, "size must be at least 1");
+                }
+                let last = (i64::from(address) + size - 1).min(0xFFFF) as u16;
+                let access = if builtin == Builtin::RegisterRead {
+                    Access::Read
+                } else {
+                    Access::Write
+                };
+                self.registered.push((access, address..=last, function));
+                Vec::new()
+            }
+            Builtin::RegisterBefore => {
+                self.before = self.callback(builtin, &arguments, 0)?;
+                Vec::new()
+            }
+            Builtin::RegisterAfter => {
+                self.after = self.callback(builtin, &arguments, 0)?;
+                Vec::new()
+            }
+            Builtin::FrameCount => number(api.frame_count() as f64),
+            Builtin::Text => {
+                let x = self.integer(builtin, &arguments, 0)? as i32;
+                let y = self.integer(builtin, &arguments, 1)? as i32;
+                let color = self.color(builtin, &arguments, 3, WHITE)?;
+                api.text(x, y, &argument(2).to_string(), color);
+                Vec::new()
+            }
+            Builtin::Box => {
+                let x1 = self.integer(builtin, &arguments, 0)? as i32;
+                let y1 = self.integer(builtin, &arguments, 1)? as i32;
+                let x2 = self.integer(builtin, &arguments, 2)? as i32;
+                let y2 = self.integer(builtin, &arguments, 3)? as i32;
+                let outline = self.color(builtin, &arguments, 5, WHITE)?;
+                let (x, y) = (x1.min(x2), y1.min(y2));
+                let width = x1.abs_diff(x2) + 1;
+                let height = y1.abs_diff(y2) + 1;
+                if !matches!(argument(4), Value::Nil) {
+                    let fill = self.color(builtin, &arguments, 4, WHITE)?;
+                    api.rect(x, y, width, height, fill, true);
+                }
+                api.rect(x, y, width, height, outline, false);
+                Vec::new()
+            }
+            Builtin::Pixel => {
+                let x = self.integer(builtin, &arguments, 0)? as i32;
+                let y = self.integer(builtin, &arguments, 1)? as i32;
+                let color = self.color(builtin, &arguments, 2, WHITE)?;
+                api.rect(x, y, 1, 1, color, true);
+                Vec::new()
+            }
+            Builtin::JoypadRead => {
+                let buttons = api.buttons(self.player(builtin, &arguments, 0)?);
+                let mut table = Table::default();
+                for (name, button) in BUTTONS {
+                    table.set(
+                        Key::Str(name.into()),
+                        Value::Bool(buttons & button as u8 != 0),
+                    );
+                }
+                vec![Value::Table(Rc::new(RefCell::new(table)))]
+            }
+            // true presses a button and false lets go of it, the others are left as they are
+            Builtin::JoypadSet => {
+                let player = self.player(builtin, &arguments, 0)?;
+                let table = self.table(builtin, &arguments, 1)?;
+                let table = table.borrow();
+                let mut buttons = api.buttons(player);
+                for (name, button) in BUTTONS {
+                    match table.get(&Value::Str(name.into())) {
+                        Value::Nil => {}
+                        value if value.truthy() => buttons |= button as u8,
+                        _ => buttons &= !(button as u8),
+                    }
+                }
+                api.set_buttons(player, buttons);
+                Vec::new()
+            }
+        })
+    }
+
+    // string.format with the flags, width and precision of c's printf for d i x X o c e f g s q
+    fn format(&self, arguments: &[Value]) -> Result<String> {
+        let builtin = Builtin::Format;
+        let pattern = self.string(builtin, arguments, 0)?;
+        let mut output = String::new();
+        let mut next = 1;
+        let mut characters = pattern.chars().peekable();
+        while let Some(character) = characters.next() {
+            if character != '%' {
+                output.push(character);
+                continue;
+            }
+            let mut flags = String::new();
+            while let Some(&flag) = characters.peek()
+                && "-+ #0".contains(flag)
+            {
+                flags.push(flag);
+                characters.next();
+            }
+            let width = read_digits(&mut characters).unwrap_or(0);
+            let precision = if characters.peek() == Some(&'.') {
+                characters.next();
+                Some(read_digits(&mut characters).unwrap_or(0))
+            } else {
+                None
+            };
+            let conversion = characters.next();
+            if conversion == Some('%') {
+                output.push('%');
+                continue;
+            }
+            if next >= arguments.len() && conversion.is_some() {
+                return self.bad_argument(builtin, next, "no value");
+            }
+            let plus = flags.contains('+');
+            let signed = |number: f64, text: String| {
+                if plus && number >= 0.0 {
+                    format!("+{text}")
+                } else {
+                    text
+                }
+            };
+            let (text, numeric) = match conversion {
+                Some('d' | 'i') => {
+                    let value = self.number(builtin, arguments, next)?.trunc();
+                    (signed(value, format!("{}", value as i64)), true)
+                }
+                Some('x') => (
+                    format!("{:x}", self.integer(builtin, arguments, next)?),
+                    true,
+                ),
+                Some('X') => (
+                    format!("{:X}", self.integer(builtin, arguments, next)?),
+                    true,
+                ),
+                Some('o') => (
+                    format!("{:o}", self.integer(builtin, arguments, next)?),
+                    true,
+                ),
+                Some('c') => {
+                    let code = self.integer(builtin, arguments, next)?;
+                    (char::from(code as u8).to_string(), false)
+                }
+                Some('f' | 'F') => {
+                    let value = self.number(builtin, arguments, next)?;
+                    (
+                        signed(value, format!("{value:.*}", precision.unwrap_or(6))),
+                        true,
+                    )
+                }
+                Some('e' | 'E') => {
+                    let value = self.number(builtin, arguments, next)?;
+                    let text = exponent_form(value, precision.unwrap_or(6));
+                    let text = if conversion == Some('E') {
+                        text.to_uppercase()
+                    } else {
+                        text
+                    };
+                    (signed(value, text), true)
+                }
+                Some('g' | 'G') => {
+                    let value = self.number(builtin, arguments, next)?;
+                    let text = general_form(value, precision.unwrap_or(6));
+                    let text = if conversion == Some('G') {
+                        text.to_uppercase()
+                    } else {
+                        text
+                    };
+                    (signed(value, text), true)
+                }
+                Some('s') => {
+                    let text = arguments[next].to_string();
+                    let text = match precision {
+                        Some(precision) => text.chars().take(precision).collect(),
+                        None => text,
+                    };
+                    (text, false)
+                }
+                Some('q') => (format!("{:?}", arguments[next].to_string()), false),
+                _ => {
+                    return self.error("invalid conversion to 'string.format'");
+                }
+            };
+            next += 1;
+            let padding = width.saturating_sub(text.chars().count());
+            if flags.contains('-') {
+                output += &text;
+                output += &" ".repeat(padding);
+            } else if flags.contains('0') && numeric {
+                // the zeros go after the sign
+                let digits_start = usize::from(text.starts_with(['-', '+']));
+                output += &text[..digits_start];
+                output += &"0".repeat(padding);
+                output += &text[digits_start..];
+            } else {
+                output += &" ".repeat(padding);
+                output += &text;
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn read_digits(characters: &mut Peekable<Chars>) -> Option<usize> {
+    let mut value = None;
+    while let Some(digit) = characters
+        .peek()
+        .and_then(|character| character.to_digit(10))
+    {
+        value = Some(value.unwrap_or(0) * 10 + digit as usize);
+        characters.next();
+    }
+    value
+}
+
+// what an expression being called or indexed was, for errors: 'f' (a nil value)
+fn describe(expression: &Expr, value: &Value) -> String {
+    match expression {
+        Expr::Name(name) => format!("'{name}' (a {} value)", value.type_name()),
+        Expr::Index(_, key) => match &**key {
+            Expr::Str(field) => format!("field '{field}' (a {} value)", value.type_name()),
+            _ => format!("a {} value", value.type_name()),
+        },
+        Expr::Method(_, name, _) => {
+            format!("the result of '{name}' (a {} value)", value.type_name())
+        }
+        _ => format!("a {} value", value.type_name()),
+    }
+}
+
+// the bytes from start to end counted from 1, negative from the end, like string.sub takes them
+fn byte_range(length: usize, start: i64, end: i64) -> std::ops::Range<usize> {
+    let length = length as i64;
+    let position = |index: i64| if index < 0 { length + index + 1 } else { index };
+    let start = position(start).max(1);
+    let end = position(end).min(length);
+    if start > end {
+        return 0..0;
+    }
+    (start - 1) as usize..end as usize
+}
+
+// %e, 1.500000e+02
+fn exponent_form(value: f64, precision: usize) -> String {
+    let text = format!("{value:.precision$e}");
+    match text.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let exponent: i32 = exponent.parse().unwrap_or(0);
+            let sign = if exponent < 0 { '-' } else { '+' };
+            format!("{mantissa}e{sign}{:02}", exponent.abs())
+        }
+        None => text,
+    }
+}
+
+// %g, the shorter of %e and %f for precision significant digits without trailing zeros
+fn general_form(value: f64, precision: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return number_to_string(value);
+    }
+    let precision = precision.max(1);
+    let exponent = exponent_form(value, precision - 1);
+    let power: i32 = exponent
+        .split_once('e')
+        .and_then(|(_, power)| power.parse().ok())
+        .unwrap_or(0);
+    let trim = |text: String| {
+        if text.contains('.') {
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            text
+        }
+    };
+    if power < -4 || power >= precision as i32 {
+        let (mantissa, power) = exponent.split_once('e').expect("exponent_form has an e");
+        format!("{}e{power}", trim(mantissa.to_string()))
+    } else {
+        let decimals = (precision as i32 - 1 - power).max(0) as usize;
+        trim(format!("{value:.decimals$}"))
+    }
+}
+
+// calls a function of a script from a hook, then hooks up what it registered. the first error
+// stops the script: it goes to the script output and all the script's hooks are removed
+fn run_callback(
+    api: &mut ScriptApi,
+    lua: &Rc<RefCell<Lua>>,
+    function: &Value,
+    arguments: Vec<Value>,
+) {
+    let result = {
+        let mut state = lua.borrow_mut();
+        state.budget = BUDGET;
+        state.call(api, function, arguments)
+    };
+    match result {
+        Ok(_) => hook_registered(api.emulator, lua),
+        Err(error) => stop(api.emulator, lua, &error),
+    }
+}
+
+// makes hooks of the memory.registerread and registerwrite calls since the last time. a range
+// registered again has its function replaced, nil removes it
+fn hook_registered(emulator: &mut Emulator, lua: &Rc<RefCell<Lua>>) {
+    let registered = std::mem::take(&mut lua.borrow_mut().registered);
+    for (access, range, function) in registered {
+        let mut state = lua.borrow_mut();
+        if let Some(index) = state
+            .memory_hooks
+            .iter()
+            .position(|(found, found_range, _)| *found == access && *found_range == range)
+        {
+            let (_, _, id) = state.memory_hooks.remove(index);
+            emulator.remove_hook(id);
+            emulator.scripts.retain(|hook| *hook != id);
+        }
+        if let Value::Nil = function {
+            continue;
+        }
+        let callback = {
+            let lua = lua.clone();
+            move |api: &mut ScriptApi, address: u16, value: u8| {
+                let arguments = vec![
+                    Value::Number(f64::from(address)),
+                    Value::Number(f64::from(value)),
+                ];
+                run_callback(api, &lua, &function, arguments);
+            }
+        };
+        let id = match access {
+            Access::Read => emulator.on_memory_read(range.clone(), callback),
+            Access::Write => emulator.on_memory_write(range.clone(), callback),
+        };
+        state.memory_hooks.push((access, range, id));
+        emulator.scripts.push(id);
+    }
+}
+
+fn stop(emulator: &mut Emulator, lua: &Rc<RefCell<Lua>>, error: &LuaError) {
+    let state = &mut *lua.borrow_mut();
+    emulator.script_output += &format!("{}: {error}\n", state.name);
+    state.registered.clear();
+    let memory_hooks = state.memory_hooks.drain(..).map(|(_, _, id)| id);
+    for id in state.frame_hooks.drain(..).chain(memory_hooks) {
+        emulator.remove_hook(id);
+        emulator.scripts.retain(|hook| *hook != id);
+    }
+}
+
+impl Emulator {
+    // runs the main chunk of a lua script, which registers the functions called from then on.
+    // name is what the errors of those are printed under in take_script_output
+    pub fn load_lua(&mut self, name: &str, source: &str) -> std::result::Result<(), LuaError> {
+        let chunk = parse(source)?;
+        let lua = Rc::new(RefCell::new(Lua::new(name)));
+        lua.borrow_mut().exec_block(
+            &mut ScriptApi { emulator: self },
+            &chunk,
+            &Scope::new(None),
+        )?;
+        let before = self.on_frame_start({
+            let lua = lua.clone();
+            move |api| {
+                let function = lua.borrow().before.clone();
+                if function.truthy() {
+                    run_callback(api, &lua, &function, Vec::new());
+                }
+            }
+        });
+        let after = self.on_frame({
+            let lua = lua.clone();
+            move |api| {
+                let function = lua.borrow().after.clone();
+                if function.truthy() {
+                    run_callback(api, &lua, &function, Vec::new());
+                }
+            }
+        });
+        lua.borrow_mut().frame_hooks.extend([before, after]);
+        self.scripts.extend([before, after]);
+        hook_registered(self, &lua);
+        Ok(())
+    }
+
+    // what scripts printed, and the errors that stopped them, since the last call
+    pub fn take_script_output(&mut self) -> String {
+        std::mem::take(&mut self.script_output)
+    }
+}
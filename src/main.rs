@@ -1,296 +1,740 @@
-use bytes::BytesMut;
-use num_enum::TryFromPrimitive;
-use std::fs;
-struct Emulator {
-    ram: BytesMut,
-    rom: BytesMut,
-    header: BytesMut,
-    rom_path: String,
-    cpu: Cpu,
+#[cfg(feature = "frontend")]
+mod audio;
+mod capture;
+mod cli;
+mod config;
+#[cfg(feature = "frontend")]
+mod frontend;
+#[cfg(feature = "frontend")]
+mod gamepad;
+mod repl;
+#[cfg(feature = "frontend")]
+mod terminal;
+
+use capture::{Capture, WavWriter};
+#[cfg(feature = "frontend")]
+use cli::NetplayRole;
+use cli::{Command, Options, USAGE};
+use config::Config;
+use ntsc_nes::Emulator;
+use ntsc_nes::analysis::Analysis;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::cheats::{Cheat, parse_cheat_file};
+use ntsc_nes::clock::Region;
+use ntsc_nes::disasm;
+use ntsc_nes::error::EmuError;
+use ntsc_nes::golden::{self, GoldenRun};
+use ntsc_nes::movie::{Movie, MoviePlayer};
+#[cfg(feature = "frontend")]
+use ntsc_nes::netplay::{DEFAULT_DELAY, Netplay};
+use ntsc_nes::nsf;
+use ntsc_nes::palette::Palette;
+use ntsc_nes::patch;
+use ntsc_nes::raw::{self, RawProgram};
+#[cfg(feature = "frontend")]
+use ntsc_nes::rewind::DEFAULT_REWIND_BUDGET;
+use ntsc_nes::stats::Stats;
+use ntsc_nes::symbols::Symbols;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// frames run by --bench without --frames, a bit over 16 seconds of ntsc time
+const BENCH_FRAMES: usize = 1000;
+
+fn exit_on_error(result: io::Result<()>) {
+    if let Err(error) = result {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
 }
 
-struct Cpu {
-    flags: StatusFlags,
-    program_counter: u16,
-    stack_pointer: u16,
-    halted: bool,
-    reg_a: u8,
-    reg_x: u8,
-    reg_y: u8,
+// fceux keeps the labels of a rom next to it in <rom>.ram.nl and <rom>.<bank>.nl for each 16k prg
+// bank, ld65 writes <rom name>.dbg. bank picks the .nl file of one bank instead of every one
+fn symbol_files(rom: &Path, bank: Option<usize>) -> Vec<PathBuf> {
+    let name = rom.file_name().unwrap_or_default().to_string_lossy();
+    let nl = |part: &str| rom.with_file_name(format!("{name}.{part}.nl"));
+    let mut files = vec![nl("ram")];
+    match bank {
+        Some(bank) => files.push(nl(&bank.to_string())),
+        None => {
+            let directory = rom.parent().filter(|parent| !parent.as_os_str().is_empty());
+            let mut banks: Vec<usize> = fs::read_dir(directory.unwrap_or(Path::new(".")))
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| {
+                    let file = entry.ok()?.file_name();
+                    let part = file.to_str()?.strip_prefix(&format!("{name}."))?;
+                    part.strip_suffix(".nl")?.parse().ok()
+                })
+                .collect();
+            banks.sort();
+            files.extend(banks.into_iter().map(|bank| nl(&bank.to_string())));
+        }
+    }
+    files.push(rom.with_extension("dbg"));
+    files.retain(|path| path.exists());
+    files
 }
 
-#[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
-#[repr(u8)]
-enum Opcode {
-    HLT = 0x02,
+fn load_symbol_files(files: &[PathBuf]) -> Result<Symbols, String> {
+    let mut symbols = Symbols::new();
+    for path in files {
+        let loaded = Symbols::load(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        symbols.merge(&loaded);
+    }
+    Ok(symbols)
+}
 
-    PHA = 0x48,
-    PLA = 0x68,
-    BPL = 0x10,
-    BMI = 0x30,
-    BNE = 0xD0,
-    BEQ = 0xF0,
+// prints every 16k prg bank, the last one at $C000 where the vectors live and the others at $8000
+fn disassemble_rom(path: &Path) -> Result<(), String> {
+    let failed = |error: EmuError| format!("{}: {error}", path.display());
+    let data = fs::read(path).map_err(|error| failed(error.into()))?;
+    let cartridge = Cartridge::from_bytes(&data).map_err(|error| failed(error.into()))?;
+    let banks: Vec<&[u8]> = cartridge.prg_rom.chunks(0x4000).collect();
+    let mut stdout = io::stdout().lock();
+    for (index, bank) in banks.iter().enumerate() {
+        let symbols = load_symbol_files(&symbol_files(path, Some(index)))?;
+        let origin = if index == banks.len() - 1 {
+            0xC000
+        } else {
+            0x8000
+        };
+        // written instead of printed so a closed pipe is an error rather than a panic
+        let listing = disasm::listing(&disasm::disassemble(bank, origin), &symbols);
+        writeln!(stdout, "; bank {index}")
+            .and_then(|()| write!(stdout, "{listing}"))
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
 
-    LDY_Immediate = 0xA0,
-    LDX_Immediate = 0xA2,
+// the rom read again for the analysis, with its patch like the emulator loaded it
+fn analyze_rom(path: &Path, patch: Option<&Path>) -> Result<Analysis, String> {
+    let failed = |error: EmuError| format!("{}: {error}", path.display());
+    let data = match patch {
+        Some(patch) => patch::patch_rom(path, patch),
+        None => fs::read(path).map_err(EmuError::from),
+    }
+    .map_err(failed)?;
+    let cartridge = Cartridge::from_bytes(&data).map_err(|error| failed(error.into()))?;
+    Ok(Analysis::new(&cartridge))
+}
 
-    LDA_ZeroPage = 0xA5,
-    LDA_Immediate = 0xA9,
-    LDA_Absolute = 0xAD,
+// the patch given on the command line has to exist, an .ips or .bps named like the rom is optional
+fn patch_file(options: &Options) -> Option<PathBuf> {
+    if options.raw {
+        return None;
+    }
+    options.patch.clone().or_else(|| {
+        ["ips", "bps"]
+            .into_iter()
+            .map(|extension| options.rom.with_extension(extension))
+            .find(|path| path.exists())
+    })
+}
 
-    STA_ZeroPage = 0x85,
-    STA_Absolute = 0x8D,
+// the rom as the game runs it, patched, for what netplay and movies tell roms apart by
+#[cfg(feature = "frontend")]
+fn rom_data(options: &Options) -> Result<Vec<u8>, String> {
+    match patch_file(options) {
+        Some(path) => patch::patch_rom(&options.rom, &path)
+            .map_err(|error| format!("{}: {error}", path.display())),
+        None => {
+            fs::read(&options.rom).map_err(|error| format!("{}: {error}", options.rom.display()))
+        }
+    }
+}
 
-    STX_ZeroPage = 0x86,
-    STX_Absolute = 0x8E,
+fn load_rom(options: &Options) -> Result<Emulator, String> {
+    let failed = |path: &Path, error: EmuError| format!("{}: {error}", path.display());
+    match patch_file(options) {
+        Some(path) => {
+            Emulator::load_patched_rom(&options.rom, &path).map_err(|error| match error {
+                EmuError::BadPatch(_) => failed(&path, error),
+                _ => failed(&options.rom, error),
+            })
+        }
+        None => Emulator::load_rom(&options.rom).map_err(|error| failed(&options.rom, error)),
+    }
+}
 
-    STY_ZeroPage = 0x84,
-    STY_Absolute = 0x8C,
+// the cheat file given on the command line has to exist, the one found by rom name is optional
+fn load_cheats(emulator: &mut Emulator, options: &Options, config: &Config) -> Result<(), String> {
+    let path = match &options.cheats {
+        Some(path) => Some(path.clone()),
+        None => {
+            let name = options.rom.with_extension("cht");
+            let path = match &config.cheat_directory {
+                Some(directory) => directory.join(name.file_name().unwrap_or_default()),
+                None => name,
+            };
+            path.exists().then_some(path)
+        }
+    };
+    if let Some(path) = path {
+        let text =
+            fs::read_to_string(&path).map_err(|error| format!("{}: {error}", path.display()))?;
+        let cheats = parse_cheat_file(&text)
+            .map_err(|(line, error)| format!("{}:{line}: {error}", path.display()))?;
+        for cheat in cheats {
+            emulator.add_cheat(cheat);
+        }
+    }
+    for code in &options.cheat_codes {
+        emulator.add_cheat(Cheat::parse(code).map_err(|error| error.to_string())?);
+    }
+    Ok(())
 }
 
-struct StatusFlags {
-    carry_flag: bool,
-    zero_flag: bool,
-    interrupt_disable_flag: bool,
-    overflow_flag: bool,
-    negative_flag: bool,
+// each script runs its main chunk now, and its callbacks from the first frame on
+fn load_scripts(emulator: &mut Emulator, options: &Options) -> Result<(), String> {
+    for path in &options.scripts {
+        let source =
+            fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        emulator
+            .load_lua(&name, &source)
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+        eprint!("{}", emulator.take_script_output());
+    }
+    Ok(())
 }
 
-impl Emulator {
-    fn new(ram: u16, rom: u16, rom_path: &str) -> Self {
-        Emulator {
-            ram: {
-                let mut buf = BytesMut::with_capacity(ram as usize);
-                buf.resize(ram as usize, 0xFF);
-                buf
-            },
-            rom: BytesMut::zeroed(rom as usize),
-            header: BytesMut::zeroed(16),
-            rom_path: rom_path.to_string(),
-            cpu: Cpu {
-                //interrupt_disable_flag is the only one that is enabled by default
-                flags: StatusFlags {
-                    carry_flag: false,
-                    zero_flag: false,
-                    interrupt_disable_flag: true,
-                    overflow_flag: false,
-                    negative_flag: false,
-                },
-                program_counter: 0,
-                stack_pointer: 0,
-                halted: false,
-                reg_a: 0,
-                reg_x: 0,
-                reg_y: 0,
-            },
-        }
+// the palettes o switches between, and the one to start with: a .pal file from the command line
+// or the config, else one decoded with the configured ntsc parameters, else the built in one
+fn load_palettes(
+    options: &Options,
+    config: &Config,
+) -> Result<(Vec<(String, Palette)>, usize), String> {
+    let parameters = config.ntsc_palette.unwrap_or_default();
+    let mut palettes = vec![
+        ("built in palette".to_string(), Palette::default()),
+        (
+            "ntsc decoded palette".to_string(),
+            Palette::generate(parameters),
+        ),
+    ];
+    let mut start = if config.ntsc_palette.is_some() { 1 } else { 0 };
+    if let Some(path) = &options.palette {
+        let data = fs::read(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        let palette =
+            Palette::from_pal(&data).map_err(|error| format!("{}: {error}", path.display()))?;
+        palettes.push((path.display().to_string(), palette));
+        start = palettes.len() - 1;
     }
+    Ok((palettes, start))
+}
 
-    fn read(&self, address: u16) -> u8 {
-        if address < 0x800 {
-            self.ram[address as usize]
-        } else if address >= 0x8000 {
-            self.rom[(address - 0x8000) as usize]
-        } else {
-            0
+// nsf files are told apart from roms by their signature, whatever they are called
+fn is_nsf(path: &Path) -> bool {
+    let mut magic = [0; 5];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == nsf::MAGIC
+}
+
+fn load_raw(options: &Options) -> Result<Emulator, EmuError> {
+    let mut program = RawProgram::load(&options.rom, options.load_address)?;
+    program.entry = options.entry;
+    program.output_port = options.char_out.then_some(raw::DEFAULT_OUTPUT_PORT);
+    Ok(Emulator::from_raw(program)?)
+}
+
+// the sample rate and the mixer settings of the config
+fn set_up_audio(emulator: &mut Emulator, config: &Config) {
+    if let Some(sample_rate) = config.sample_rate {
+        emulator.set_sample_rate(sample_rate);
+    }
+    for (channel, volume) in &config.channel_volumes {
+        emulator.apu_mut().set_channel_volume(*channel, *volume);
+    }
+    for channel in &config.muted_channels {
+        emulator.apu_mut().set_channel_enabled(*channel, false);
+    }
+    for (chip, volume) in &config.expansion_volumes {
+        emulator.apu_mut().set_expansion_volume(*chip, *volume);
+    }
+}
+
+// plays the songs of an nsf on the terminal, or without one for --frames calls of its play routine,
+// and writes the sound to the .wav given to --record
+fn play_music(options: &Options, config: &Config) -> Result<(), String> {
+    let path = &options.rom;
+    let mut emulator =
+        Emulator::load_nsf(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    if let Some(region) = options.region {
+        emulator.set_region(region);
+    }
+    set_up_audio(&mut emulator, config);
+    let nsf = emulator.nsf().expect("an nsf is loaded");
+    println!("{} - {} ({})", nsf.title, nsf.artist, nsf.copyright);
+    if nsf.expansion_audio & !nsf::EMULATED_EXPANSIONS != 0 {
+        eprintln!(
+            "warning: the vrc7, mmc5, namco 163 or sunsoft 5b sound this music uses is not emulated"
+        );
+    }
+    if let Some(track) = options.track {
+        if track > nsf.songs {
+            return Err(format!("{} has {} songs", path.display(), nsf.songs));
         }
+        // the region override above has to reach the init routine
+        emulator.play_song(track);
+    } else if options.region.is_some() {
+        emulator.play_song(nsf.starting_song);
     }
+    if options.record.is_some() {
+        return Err("an nsf can only be recorded to a .wav file".to_string());
+    }
+    let mut wav = match &options.capture {
+        Some(path)
+            if !path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("wav")) =>
+        {
+            return Err("an nsf can only be recorded to a .wav file".to_string());
+        }
+        Some(path) => Some(
+            WavWriter::create(path, emulator.sample_rate())
+                .map_err(|error| format!("{}: {error}", path.display()))?,
+        ),
+        None => None,
+    };
 
-    fn write(&mut self, address: u16, value: u8) {
-        if address < 0x800 {
-            self.ram[address as usize] = value;
-        } else if address >= 0x8000 {
-            todo!();
-        } else {
-            todo!();
+    #[cfg(feature = "frontend")]
+    if !options.headless {
+        return frontend::play_music(&mut emulator, wav.as_mut())
+            .and_then(|()| wav.map_or(Ok(()), WavWriter::finish))
+            .map_err(|error| error.to_string());
+    }
+    let frames = options
+        .frames
+        .ok_or("playing an nsf without a display needs --frames")?;
+    for _ in 0..frames {
+        emulator.step_nsf();
+        let samples = emulator.take_audio_samples();
+        if let Some(wav) = &mut wav {
+            wav.write(&samples).map_err(|error| error.to_string())?;
         }
     }
+    wav.map_or(Ok(()), WavWriter::finish)
+        .map_err(|error| error.to_string())
+}
 
-    fn push(&mut self, value: u8) {
-        self.write(self.cpu.stack_pointer, value);
-        self.cpu.stack_pointer -= 1;
+// a movie starts from its save state, or from power on in the region it was recorded in
+fn load_movie(emulator: &mut Emulator, path: &Path) -> Result<MoviePlayer, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let movie = Movie::parse(&text).map_err(|error| format!("{}: {error}", path.display()))?;
+    if movie.pal {
+        emulator.set_region(Region::Pal);
+    }
+    if movie.four_score {
+        emulator.connect_four_score(true);
     }
+    if let Some(state) = &movie.savestate {
+        emulator
+            .load_state(state)
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    Ok(MoviePlayer::new(movie))
+}
 
-    fn pull(&mut self) -> u8 {
-        self.cpu.stack_pointer += 1;
-        let stack_byte = self.read(0x100 + self.cpu.stack_pointer);
-        stack_byte
+// a headless run recording checkpoints or checked against them, up to --frames, the end of the movie,
+// a halt or for checking the last checkpoint
+fn run_golden(
+    emulator: &mut Emulator,
+    options: &Options,
+    mut player: Option<MoviePlayer>,
+) -> Result<(), String> {
+    let verify = match &options.golden_verify {
+        Some(path) => {
+            let failed = |error: &dyn std::fmt::Display| format!("{}: {error}", path.display());
+            let text = fs::read_to_string(path).map_err(|error| failed(&error))?;
+            Some(GoldenRun::parse(&text).map_err(|error| failed(&error))?)
+        }
+        None => None,
+    };
+    let mut record = options
+        .golden_record
+        .as_ref()
+        .map(|_| GoldenRun::new(options.golden_interval.unwrap_or(golden::DEFAULT_INTERVAL)));
+    // --frames counts from here, checkpoints are numbered from power on
+    let start = emulator.frame_count();
+    let end = options
+        .frames
+        .map(|frames| start + frames as u64)
+        .or(verify.as_ref().map(GoldenRun::last_frame))
+        .unwrap_or(u64::MAX);
+    while emulator.frame_count() < end {
+        let halted = match &mut player {
+            Some(player) => match emulator.step_frame_with(player) {
+                Some(frame) => frame.halted,
+                None => break,
+            },
+            None => emulator.step_frame().halted,
+        };
+        if let Some(run) = &mut record {
+            run.record(emulator);
+        }
+        if let Some(run) = &verify {
+            run.verify(emulator)
+                .map_err(|divergence| divergence.to_string())?;
+        }
+        if halted {
+            break;
+        }
+    }
+    if let (Some(run), Some(path)) = (&record, &options.golden_record) {
+        fs::write(path, run.to_text()).map_err(|error| format!("{}: {error}", path.display()))?;
+        eprintln!(
+            "{} checkpoints written to {}",
+            run.checkpoints.len(),
+            path.display()
+        );
     }
+    if let Some(run) = &verify {
+        if emulator.frame_count() < run.last_frame() {
+            return Err(format!(
+                "the run stopped at frame {}, before the last checkpoint at frame {}",
+                emulator.frame_count(),
+                run.last_frame()
+            ));
+        }
+        eprintln!("all {} checkpoints match", run.checkpoints.len());
+    }
+    Ok(())
+}
 
-    fn reset(mut self) {
-        self.header = BytesMut::from(&fs::read(&self.rom_path).unwrap()[..]); // load rom file in memory 
-        self.rom.copy_from_slice(&self.header[16..]); // extract the header
-        let pcl = self.read(0xFFFC);
-        let pch = self.read(0xFFFD);
-        self.cpu.program_counter = ((pch as u16) * 0x100) + pcl as u16;
-        self.run();
-        //println!("a : 0x{:02x}\nx : 0x{:02x} \ny : 0x{:02x}", self.cpu.reg_a, self.cpu.reg_x, self.cpu.reg_y);
-        println!("{:02x}", self.ram);
+// connects to the peer, or waits for one to connect
+#[cfg(feature = "frontend")]
+fn start_netplay(emulator: &mut Emulator, options: &Options) -> Result<Option<Netplay>, String> {
+    let Some(role) = &options.netplay else {
+        return Ok(None);
+    };
+    let rom = rom_data(options)?;
+    let failed = |error| format!("netplay: {error}");
+    match role {
+        NetplayRole::Host(port) => {
+            let listener = std::net::TcpListener::bind(("0.0.0.0", *port))
+                .map_err(|error| format!("port {port}: {error}"))?;
+            eprintln!("waiting for a peer on port {port}");
+            let (stream, peer) = listener.accept().map_err(|error| error.to_string())?;
+            let delay = options.netplay_delay.unwrap_or(DEFAULT_DELAY);
+            let netplay = Netplay::host(stream, emulator, &rom, delay, options.netplay_rollback)
+                .map_err(failed)?;
+            eprintln!("{peer} joined as player 2");
+            Ok(Some(netplay))
+        }
+        NetplayRole::Join(address) => {
+            let stream = std::net::TcpStream::connect(address)
+                .map_err(|error| format!("{address}: {error}"))?;
+            let netplay =
+                Netplay::join(stream, emulator, &rom, options.netplay_rollback).map_err(failed)?;
+            eprintln!("joined {address} as player 2");
+            Ok(Some(netplay))
+        }
     }
+}
 
-    fn run(&mut self) {
-        while !self.cpu.halted {
-            self.emulate_cpu();
+// a line of frame timing on stderr once a second
+fn log_stats(emulator: &mut Emulator) {
+    let stats = Stats::attach(emulator);
+    emulator.on_frame(move |_| {
+        if let Some(report) = stats.borrow_mut().take_report() {
+            eprintln!("{report}");
         }
+    });
+}
+
+// video is never converted and audio is thrown away, so this times the core alone
+fn bench(emulator: &mut Emulator, frames: usize) {
+    let start = Instant::now();
+    let mut ran = 0;
+    while ran < frames && !emulator.halted() {
+        emulator.step_frame();
+        emulator.take_audio_samples();
+        ran += 1;
     }
+    let seconds = start.elapsed().as_secs_f64();
+    let fps = ran as f64 / seconds;
+    println!(
+        "{ran} frames in {seconds:.3}s: {fps:.1} fps, {:.1}x real time",
+        fps / emulator.region().frame_rate()
+    );
+}
 
-    fn emulate_cpu(&mut self) {
-        use Opcode::*;
-        let opcode = Opcode::try_from(self.read(self.cpu.program_counter)).unwrap();
-        self.cpu.program_counter += 1;
-        let mut cycles: usize = 0;
-        match opcode {
-            HLT => self.cpu.halted = true,
-            LDY_Immediate => {
-                self.cpu.reg_y = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                cycles = 2;
-            }
-            LDX_Immediate => {
-                self.cpu.reg_x = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                cycles = 2;
-            }
-            LDA_Immediate => {
-                self.cpu.reg_a = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                self.cpu.flags.zero_flag = self.cpu.reg_a == 0;
-                self.cpu.flags.negative_flag = self.cpu.reg_a > 127;
-                cycles = 2;
-            }
-            LDA_ZeroPage => {
-                let operand = self.read(self.cpu.program_counter);
-                self.cpu.reg_a = self.read(operand as u16);
-                self.cpu.program_counter += 1;
-                cycles = 3;
-            }
-            LDA_Absolute => {
-                let operand_l = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                let operand_h = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                self.cpu.reg_a = self.read((operand_h as u16) * 256 + operand_l as u16);
-                cycles = 4;
-            }
-            STY_ZeroPage => {
-                let operand = self.read(self.cpu.program_counter);
-                self.write(operand as u16, self.cpu.reg_y);
-                self.cpu.program_counter += 1;
-                cycles = 3;
-            }
-            STX_ZeroPage => {
-                let operand = self.read(self.cpu.program_counter);
-                self.write(operand as u16, self.cpu.reg_x);
-                self.cpu.program_counter += 1;
-                cycles = 3;
-            }
-            STA_ZeroPage => {
-                let operand = self.read(self.cpu.program_counter);
-                self.write(operand as u16, self.cpu.reg_a);
-                self.cpu.program_counter += 1;
-                cycles = 3;
-            }
-            STY_Absolute => {
-                let operand_l = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                let operand_h = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                self.write((operand_h as u16) * 256 + operand_l as u16, self.cpu.reg_y);
-                cycles = 4;
-            }
-            STX_Absolute => {
-                let operand_l = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                let operand_h = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                self.write((operand_h as u16) * 256 + operand_l as u16, self.cpu.reg_x);
-                cycles = 4;
-            }
-            STA_Absolute => {
-                let operand_l = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                let operand_h = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                self.write((operand_h as u16) * 256 + operand_l as u16, self.cpu.reg_a);
-                cycles = 4;
-            }
-            PHA => {
-                self.push(self.cpu.reg_a);
-                cycles = 3;
-            }
-            PLA => {
-                self.cpu.reg_a = self.pull();
-                self.cpu.flags.zero_flag = self.cpu.reg_a == 0;
-                self.cpu.flags.negative_flag = self.cpu.reg_a >= 0x80;
-                cycles = 4;
+fn main() {
+    let mut options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => *options,
+        Ok(Command::Disassemble(path)) => {
+            if let Err(error) = disassemble_rom(&path) {
+                eprintln!("error: {error}");
+                std::process::exit(1);
             }
-            BNE => {
-                let operand = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                if !self.cpu.flags.zero_flag {
-                    let mut jump_counter = operand as i32;
-                    if jump_counter > 127 {
-                        jump_counter -= 256;
-                    }
-                    self.cpu.program_counter = self.cpu.program_counter + jump_counter as u16;
-                    cycles = 3;
-                } else {
-                    cycles = 2;
+            return;
+        }
+        Ok(Command::Analyze(path, patch)) => {
+            match analyze_rom(&path, patch.as_deref()) {
+                Ok(analysis) => print!("{analysis}"),
+                Err(message) => {
+                    eprintln!("error: {message}");
+                    std::process::exit(1);
                 }
             }
-            BEQ => {
-                let operand = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                if self.cpu.flags.zero_flag {
-                    let mut jump_counter = operand as i32;
-                    if jump_counter > 127 {
-                        jump_counter -= 256;
-                    }
-                    self.cpu.program_counter = self.cpu.program_counter + jump_counter as u16;
-                    cycles = 3;
-                } else {
-                    cycles = 2;
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // an explicit --config has to exist, the default one is optional
+    let config_path = options.config.clone().or_else(config::default_path);
+    let config = match &config_path {
+        Some(path) if options.config.is_some() || path.exists() => config::load(path),
+        _ => Ok(Config::default()),
+    };
+    let config = config.unwrap_or_else(|message| {
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    });
+    config.merge_into(&mut options);
+
+    if !options.raw && is_nsf(&options.rom) {
+        if let Err(message) = play_music(&options, &config) {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let loaded = if options.raw {
+        load_raw(&options).map_err(|error| format!("{}: {error}", options.rom.display()))
+    } else {
+        load_rom(&options)
+    };
+    let mut emulator = loaded.unwrap_or_else(|message| {
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    });
+    if let Err(message) = load_cheats(&mut emulator, &options, &config) {
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    }
+    // the files given on the command line have to exist, the ones found by rom name are optional
+    let symbol_files = if options.symbols.is_empty() {
+        symbol_files(&options.rom, None)
+    } else {
+        options.symbols.clone()
+    };
+    match load_symbol_files(&symbol_files) {
+        Ok(symbols) => *emulator.symbols_mut() = symbols,
+        Err(message) => {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        }
+    }
+    if let Some(region) = options.region {
+        emulator.set_region(region);
+    }
+    if let Some(filter) = options.filter {
+        emulator.set_video_filter(filter);
+    }
+    if options.no_sprite_limit {
+        emulator.set_sprite_limit(false);
+    }
+    // a raw program is already in ram
+    if let Some(init) = options.ram_init
+        && !options.raw
+    {
+        emulator.set_ram_init(init);
+        emulator.power_cycle();
+    }
+    set_up_audio(&mut emulator, &config);
+    let (palettes, palette) = load_palettes(&options, &config).unwrap_or_else(|message| {
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    });
+    emulator.set_palette(palettes[palette].1.clone());
+    emulator.connect_zapper(options.zapper);
+    emulator.connect_four_score(options.four_score);
+    if let Err(message) = load_scripts(&mut emulator, &options) {
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    }
+
+    if options.bench {
+        if options.stats {
+            log_stats(&mut emulator);
+        }
+        bench(&mut emulator, options.frames.unwrap_or(BENCH_FRAMES));
+        return;
+    }
+
+    let player = options.play.as_ref().map(|path| {
+        load_movie(&mut emulator, path).unwrap_or_else(|message| {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        })
+    });
+
+    // after the movie, which may switch the region and so the frame rate
+    let mut capture = options.capture.as_ref().map(|path| {
+        let frame_rate = emulator.region().frame_rate();
+        Capture::create(path, frame_rate, emulator.sample_rate()).unwrap_or_else(|error| {
+            eprintln!("error: {}: {error}", path.display());
+            std::process::exit(1);
+        })
+    });
+
+    if let Some(path) = &options.trace {
+        let output: Box<dyn io::Write> = match path.as_str() {
+            "-" => Box::new(io::stdout()),
+            path => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(error) => {
+                    eprintln!("error: {path}: {error}");
+                    std::process::exit(1);
                 }
+            },
+        };
+        emulator.set_trace(Some(output));
+    }
+
+    if options.debug {
+        if !options.raw {
+            match analyze_rom(&options.rom, patch_file(&options).as_deref()) {
+                Ok(analysis) => emulator.set_analysis(analysis),
+                Err(message) => eprintln!("warning: {message}"),
             }
-            BPL => {
-                let operand = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                if !self.cpu.flags.negative_flag {
-                    let mut jump_counter = operand as i32;
-                    if jump_counter > 127 {
-                        jump_counter -= 256;
-                    }
-                    self.cpu.program_counter = self.cpu.program_counter + jump_counter as u16;
-                    cycles = 3;
-                } else {
-                    cycles = 2;
-                }
+        }
+        let result = repl::run(&mut emulator);
+        exit_on_error(result.and(emulator.flush_save_file()));
+        return;
+    }
+
+    #[cfg(feature = "frontend")]
+    if !options.headless {
+        let rewind_budget = config
+            .rewind_memory
+            .map_or(DEFAULT_REWIND_BUDGET, |megabytes| megabytes << 20);
+        let mut settings = frontend::Settings::new(
+            options.scale.or(config.scale),
+            &config.bindings,
+            rewind_budget,
+        );
+        settings.terminal = options.terminal;
+        settings.turbo_rates = config.turbo_rates.clone();
+        settings.gamepad_map =
+            gamepad::GamepadMap::new(&config.gamepad_bindings, config.gamepad_deadzone);
+        if let Some(devices) = &config.gamepad_devices {
+            settings.gamepad_devices = devices.clone();
+        }
+        settings.config_path = config_path.clone();
+        // c saves screenshots next to the rom
+        settings.screenshot_base = options.rom.with_extension("");
+        settings.raw_screenshots = options.raw_frame;
+        settings.palettes = palettes;
+        settings.palette = palette;
+        settings.stats = options.stats;
+        let mut movie = match (player, &options.record) {
+            (Some(player), _) => frontend::MovieMode::Play(player),
+            (None, Some(_)) => {
+                let rom = rom_data(&options).unwrap_or_default();
+                let name = options.rom.file_name().unwrap_or_default();
+                let mut movie = Movie::new(&name.to_string_lossy(), &rom);
+                movie.pal = emulator.region() == Region::Pal;
+                movie.four_score = emulator.four_score_connected();
+                frontend::MovieMode::Record(movie)
             }
-            BMI => {
-                let operand = self.read(self.cpu.program_counter);
-                self.cpu.program_counter += 1;
-                if self.cpu.flags.negative_flag {
-                    let mut jump_counter = operand as i32;
-                    if jump_counter > 127 {
-                        jump_counter -= 256;
-                    }
-                    self.cpu.program_counter = self.cpu.program_counter + jump_counter as u16;
-                    cycles = 3;
-                } else {
-                    cycles = 2;
+            (None, None) => frontend::MovieMode::Off,
+        };
+        let mut netplay = start_netplay(&mut emulator, &options).unwrap_or_else(|message| {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        });
+        let mut result = frontend::run(
+            &mut emulator,
+            &settings,
+            &mut movie,
+            capture.as_mut(),
+            netplay.as_mut(),
+        );
+        if let Some(capture) = capture {
+            result = result.and(capture.finish());
+        }
+        if let (frontend::MovieMode::Record(movie), Some(path)) = (&movie, &options.record) {
+            result = result.and(fs::write(path, movie.to_fm2()));
+        }
+        if let Some(path) = &options.screenshot {
+            result = result.and(emulator.save_screenshot(path, options.raw_frame));
+        }
+        // write the battery ram back even when the frontend failed
+        exit_on_error(result.and(emulator.flush_save_file()));
+        return;
+    }
+
+    if options.scale.is_some() {
+        eprintln!("warning: --scale does nothing without a display");
+    }
+    if options.stats {
+        log_stats(&mut emulator);
+    }
+    if options.golden_record.is_some() || options.golden_verify.is_some() {
+        if let Err(message) = run_golden(&mut emulator, &options, player) {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        }
+        exit_on_error(emulator.flush_save_file());
+        return;
+    }
+    match (player, options.frames, &mut capture) {
+        // recording goes frame by frame
+        (player, frames, Some(capture)) => {
+            let limit = frames.unwrap_or(usize::MAX);
+            let mut player = player;
+            for _ in 0..limit {
+                let frame = match &mut player {
+                    Some(player) => emulator.step_frame_with(player),
+                    None => Some(emulator.step_frame()),
+                };
+                if frame.is_none_or(|frame| frame.halted) {
+                    break;
                 }
+                let samples = emulator.take_audio_samples();
+                exit_on_error(capture.push_frame(emulator.video_frame(), &samples));
             }
-            _ => todo!(),
         }
+        (Some(mut player), frames, None) => {
+            let limit = frames.unwrap_or(usize::MAX);
+            while player.frame() < limit && emulator.step_frame_with(&mut player).is_some() {}
+        }
+        (None, Some(frames), None) => {
+            emulator.run_until_halt_or(frames);
+        }
+        (None, None, None) => emulator.run(),
     }
-}
-
-fn main() {
-    let emulator = Emulator::new(
-        0x800,
-        0x8000,
-        "/home/este/rust/ntsc-nes/__PatreonRoms/3_Branches.nes",
-    );
-    emulator.reset();
+    if let Some(capture) = capture {
+        exit_on_error(capture.finish());
+    }
+    if let Some(path) = &options.screenshot {
+        exit_on_error(emulator.save_screenshot(path, options.raw_frame));
+    }
+    exit_on_error(emulator.flush_save_file());
+    // what scripts printed goes to stderr, the ram to stdout
+    eprint!("{}", emulator.take_script_output());
+    // what a --raw program printed comes before the ram
+    exit_on_error(io::stdout().write_all(&emulator.take_output()));
+    //println!("a : 0x{:02x}\nx : 0x{:02x} \ny : 0x{:02x}", emulator.cpu().reg_a, emulator.cpu().reg_x, emulator.cpu().reg_y);
+    for byte in emulator.ram() {
+        print!("{byte:02x}");
+    }
+    println!();
 }
@@ -0,0 +1,213 @@
+mod cnrom;
+mod mmc1;
+mod mmc3;
+mod mmc5;
+mod nrom;
+mod nsf;
+mod raw;
+mod uxrom;
+mod vrc6;
+
+use crate::cartridge::{Cartridge, RomError};
+use crate::expansion::ExpansionLevels;
+use crate::ppu::Mirroring;
+use crate::savestate::{Savestate, StateError, StateReader, StateWriter};
+use bytes::BytesMut;
+pub use cnrom::Cnrom;
+pub use mmc1::Mmc1;
+pub use mmc3::Mmc3;
+pub use mmc5::Mmc5;
+pub use nrom::Nrom;
+pub use nsf::NsfBoard;
+pub use raw::RawBoard;
+pub use uxrom::Uxrom;
+pub use vrc6::Vrc6;
+
+// the pattern fetches the ppu is making while it renders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fetch {
+    Background,
+    Sprites,
+}
+
+// cartridge hardware as seen from the cpu ($4020-$FFFF) and the ppu ($0000-$1FFF) buses,
+// rom contents are not part of its save state
+pub trait Mapper: Savestate {
+    // None when nothing on the cartridge drives the data bus, which leaves it open
+    fn prg_read(&mut self, address: u16) -> Option<u8>;
+    fn prg_write(&mut self, address: u16, value: u8);
+    fn chr_read(&mut self, address: u16) -> u8;
+    fn chr_write(&mut self, address: u16, value: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // called once per rendered scanline, at the point where the ppu address line A12 rises
+    fn scanline(&mut self) {}
+
+    // called at dot 1 of the visible and pre-render lines with the scanline while the ppu renders,
+    // and with None on lines it doesn't and from vblank
+    fn ppu_line(&mut self, _scanline: Option<u16>) {}
+
+    // called as the ppu switches between the background and sprite fetches of a line,
+    // for boards that bank chr apart for them
+    fn ppu_fetch(&mut self, _fetch: Fetch, _tall_sprites: bool) {}
+
+    // a nametable byte the board supplies itself instead of console vram, every nametable read
+    // goes through here first
+    fn nametable_read(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    // true when the board took the write and console vram is left alone
+    fn nametable_write(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    // called every cpu cycle, for boards with a counter or sound chip clocked by the cpu
+    fn cpu_cycle(&mut self) {}
+
+    // what the board's own sound chips put out, see expansion.rs
+    fn audio(&self) -> ExpansionLevels {
+        [0.0; 2]
+    }
+
+    // prg ram kept alive by a battery on the cartridge, None for boards without one
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // where in prg rom a cpu address reads from with the banks as they are, None for ram, registers
+    // and boards that don't say. for tools that look at the rom rather than the bus
+    fn prg_rom_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+
+    // what a program wrote to the board's output port since the last call, for boards with one
+    fn take_output(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+pub fn from_cartridge(cartridge: Cartridge) -> Result<Box<dyn Mapper>, RomError> {
+    Ok(match cartridge.header.mapper {
+        0 => Box::new(Nrom::new(cartridge)),
+        1 => Box::new(Mmc1::new(cartridge)),
+        2 => Box::new(Uxrom::new(cartridge)),
+        3 => Box::new(Cnrom::new(cartridge)),
+        4 => Box::new(Mmc3::new(cartridge)),
+        5 => Box::new(Mmc5::new(cartridge)),
+        24 => Box::new(Vrc6::new(cartridge, false)),
+        26 => Box::new(Vrc6::new(cartridge, true)),
+        mapper => return Err(RomError::UnsupportedMapper(mapper)),
+    })
+}
+
+// the prg rom on a board, read through banks of a size the board picks. an image smaller than the
+// window it is mapped into repeats to fill it, the way the address lines the rom doesn't have would,
+// so a 16K nrom shows the same vectors at $BFFA and $FFFA. bank numbers wrap at the banks there are
+pub struct Prg {
+    rom: BytesMut,
+}
+
+impl Prg {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        Prg {
+            rom: cartridge.prg_rom.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rom.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rom.is_empty()
+    }
+
+    // banks of size bytes, a rom smaller than one bank counts as one
+    pub fn bank_count(&self, size: usize) -> usize {
+        (self.rom.len() / size).max(1)
+    }
+
+    pub fn last_bank(&self, size: usize) -> usize {
+        self.bank_count(size) - 1
+    }
+
+    // address inside bank, a power of two size bytes long
+    pub fn read_bank(&self, bank: usize, size: usize, address: u16) -> u8 {
+        self.rom[self.bank_offset(bank, size, address)]
+    }
+
+    // where in the rom read_bank reads from
+    pub fn bank_offset(&self, bank: usize, size: usize, address: u16) -> usize {
+        let bank = bank % self.bank_count(size);
+        self.wrap(bank * size + (address as usize & (size - 1)))
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        self.rom[self.wrap(offset)]
+    }
+
+    // an offset past the end of the rom wrapped back into it
+    pub fn wrap(&self, offset: usize) -> usize {
+        offset % self.rom.len()
+    }
+}
+
+// the pattern memory on a board: chr rom, or chr ram when the cartridge has no rom.
+// offsets wrap at its size, and only ram is part of a save state
+pub struct Chr {
+    memory: BytesMut,
+    writable: bool,
+}
+
+impl Chr {
+    // the ram is the size the header asks for, at least 8K
+    pub fn new(cartridge: &Cartridge) -> Self {
+        if cartridge.chr_rom.is_empty() {
+            Chr {
+                memory: BytesMut::zeroed(cartridge.header.chr_ram_size.max(0x2000)),
+                writable: true,
+            }
+        } else {
+            Chr {
+                memory: cartridge.chr_rom.clone(),
+                writable: false,
+            }
+        }
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        self.memory[offset % self.memory.len()]
+    }
+
+    // writes to rom are dropped
+    pub fn write(&mut self, offset: usize, value: u8) {
+        if self.writable {
+            let length = self.memory.len();
+            self.memory[offset % length] = value;
+        }
+    }
+}
+
+impl Savestate for Chr {
+    fn save(&self, state: &mut StateWriter) {
+        if self.writable {
+            self.memory.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        if self.writable {
+            self.memory.load(state)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::{StateError, ensure, savestate_fields};
+
+// mapper 3: fixed prg like NROM, switchable 8K chr bank
+pub struct Cnrom {
+    prg: Prg,
+    chr: Chr,
+    mirroring: Mirroring,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Cnrom {
+            chr: Chr::new(&cartridge),
+            prg: Prg::new(&cartridge),
+            mirroring: cartridge.header.mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
+            Some(self.prg.read_bank(0, 0x8000, address))
+        } else {
+            None
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        (address >= 0x8000).then(|| self.prg.bank_offset(0, 0x8000, address))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.chr_bank = value as usize;
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_bank * 0x2000 + address as usize)
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr
+            .write(self.chr_bank * 0x2000 + address as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+savestate_fields!(Cnrom { chr_bank, chr } checked);
+
+impl Cnrom {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(self.chr_bank <= 0xFF, "cnrom chr bank")
+    }
+}
@@ -0,0 +1,188 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::{StateError, ensure, savestate_fields};
+use bytes::BytesMut;
+
+// mapper 1: registers are loaded one bit at a time through a 5 bit shift register
+pub struct Mmc1 {
+    prg: Prg,
+    prg_ram: BytesMut,
+    battery: bool,
+    chr: Chr,
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    // cpu cycles since power on and the one the serial port was last written on
+    cycle: u64,
+    last_write: Option<u64>,
+}
+
+impl Mmc1 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Mmc1 {
+            chr: Chr::new(&cartridge),
+            prg_ram: BytesMut::zeroed(0x2000),
+            battery: cartridge.header.battery,
+            prg: Prg::new(&cartridge),
+            shift_register: 0,
+            shift_count: 0,
+            // power on in the "fix last bank at $C000" mode so the reset vector is reachable
+            control: 0x0C,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            cycle: 0,
+            last_write: None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        // 512K boards (SUROM) use chr bank bit 4 to pick the outer 256K half
+        let outer_bank = if self.prg.len() > 0x40000 {
+            (self.chr_bank_0 as usize & 0x10) << 14
+        } else {
+            0
+        };
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let last_bank = self.prg.last_bank(0x4000).min(0x0F);
+        let bank = match ((self.control >> 2) & 0x03, address) {
+            (0 | 1, _) => {
+                return outer_bank + (bank & 0x0E) * 0x4000 + (address & 0x7FFF) as usize;
+            }
+            (2, 0x8000..=0xBFFF) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xBFFF) => bank,
+            (_, _) => last_bank,
+        };
+        outer_bank + bank * 0x4000 + (address & 0x3FFF) as usize
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        if self.control & 0x10 == 0 {
+            (self.chr_bank_0 as usize & 0x1E) * 0x1000 + address as usize
+        } else if address < 0x1000 {
+            self.chr_bank_0 as usize * 0x1000 + address as usize
+        } else {
+            self.chr_bank_1 as usize * 0x1000 + (address & 0x0FFF) as usize
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xFFFF => Some(self.prg.read(self.prg_offset(address))),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        (address >= 0x8000).then(|| self.prg.wrap(self.prg_offset(address)))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            0x8000..=0xFFFF => {
+                // of writes on consecutive cycles only the first counts, so the two writes of a
+                // read-modify-write instruction shift in a single bit
+                let consecutive = self
+                    .last_write
+                    .is_some_and(|last| self.cycle.wrapping_sub(last) == 1);
+                self.last_write = Some(self.cycle);
+                if consecutive {
+                    return;
+                }
+                if value & 0x80 != 0 {
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+                self.shift_register |= (value & 0x01) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    // only the address of the fifth write selects the register
+                    self.write_register(address, self.shift_register);
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_offset(address))
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr.write(self.chr_offset(address), value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn cpu_cycle(&mut self) {
+        self.cycle = self.cycle.wrapping_add(1);
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(&mut self.prg_ram[..])
+    }
+}
+
+savestate_fields!(Mmc1 {
+    prg_ram,
+    shift_register,
+    shift_count,
+    control,
+    chr_bank_0,
+    chr_bank_1,
+    prg_bank,
+    cycle,
+    last_write,
+    chr,
+} checked);
+
+impl Mmc1 {
+    // a shift count past 5 would never write a register and then overflow
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            self.shift_count < 5 && self.shift_register < 1 << self.shift_count,
+            "mmc1 shift register",
+        )
+    }
+}
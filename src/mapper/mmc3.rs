@@ -0,0 +1,180 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::savestate_fields;
+use bytes::BytesMut;
+
+// mapper 4: 8K prg banks, 1K/2K chr banks and a scanline counter wired to the irq line
+pub struct Mmc3 {
+    prg: Prg,
+    prg_ram: BytesMut,
+    battery: bool,
+    chr: Chr,
+    four_screen: bool,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    vertical_mirroring: bool,
+    prg_ram_enabled: bool,
+    prg_ram_write_protected: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mmc3 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Mmc3 {
+            chr: Chr::new(&cartridge),
+            prg_ram: BytesMut::zeroed(0x2000),
+            battery: cartridge.header.battery,
+            four_screen: cartridge.header.mirroring == Mirroring::FourScreen,
+            vertical_mirroring: cartridge.header.mirroring == Mirroring::Vertical,
+            prg: Prg::new(&cartridge),
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_write_protected: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        let last = self.prg.last_bank(0x2000);
+        let second_last = last.saturating_sub(1);
+        let swapped = self.bank_select & 0x40 != 0;
+        match (address >> 13) & 0x03 {
+            0 if swapped => second_last,
+            0 => self.bank_registers[6] as usize,
+            1 => self.bank_registers[7] as usize,
+            2 if swapped => self.bank_registers[6] as usize,
+            2 => second_last,
+            _ => last,
+        }
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        // A12 inversion swaps the 2K and 1K halves of the pattern tables
+        let address = if self.bank_select & 0x80 != 0 {
+            address ^ 0x1000
+        } else {
+            address
+        };
+        let bank = match address >> 10 {
+            0 => self.bank_registers[0] & 0xFE,
+            1 => self.bank_registers[0] | 0x01,
+            2 => self.bank_registers[1] & 0xFE,
+            3 => self.bank_registers[1] | 0x01,
+            slot => self.bank_registers[slot as usize - 2],
+        };
+        bank as usize * 0x0400 + (address & 0x03FF) as usize
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xFFFF => Some(self.prg.read_bank(self.prg_bank(address), 0x2000, address)),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        (address >= 0x8000).then(|| {
+            self.prg
+                .bank_offset(self.prg_bank(address), 0x2000, address)
+        })
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        let even = address & 0x01 == 0;
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protected => {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = value,
+            0x8000..=0x9FFF => self.bank_registers[(self.bank_select & 0x07) as usize] = value,
+            0xA000..=0xBFFF if even => self.vertical_mirroring = value & 0x01 == 0,
+            0xA000..=0xBFFF => {
+                self.prg_ram_enabled = value & 0x80 != 0;
+                self.prg_ram_write_protected = value & 0x40 != 0;
+            }
+            0xC000..=0xDFFF if even => self.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_offset(address))
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr.write(self.chr_offset(address), value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            Mirroring::FourScreen
+        } else if self.vertical_mirroring {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
+    fn scanline(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(&mut self.prg_ram[..])
+    }
+}
+
+savestate_fields!(Mmc3 {
+    prg_ram,
+    bank_select,
+    bank_registers,
+    vertical_mirroring,
+    prg_ram_enabled,
+    prg_ram_write_protected,
+    irq_latch,
+    irq_counter,
+    irq_reload,
+    irq_enabled,
+    irq_pending,
+    chr,
+});
@@ -0,0 +1,447 @@
+use super::{Chr, Fetch, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::{StateError, ensure, savestate_fields};
+use bytes::BytesMut;
+
+// mapper 5: four prg and chr banking modes, prg ram that can be banked in among the rom, a second
+// chr bank set for the background of 8x16 sprite games, 1K of exram usable as a nametable or as
+// per tile attributes, a vertical split screen, a multiplier and a scanline irq
+pub struct Mmc5 {
+    prg: Prg,
+    prg_ram: BytesMut,
+    battery: bool,
+    chr: Chr,
+    exram: [u8; 0x400],
+    prg_mode: u8,
+    chr_mode: u8,
+    // writes to prg ram need $02 at $5102 and $01 at $5103
+    prg_ram_protect: [u8; 2],
+    // 0/1 nametable, 2 ram, 3 read only ram, 1 also turns on the extended attributes
+    exram_mode: u8,
+    // two bits per nametable: console vram page 0 or 1, exram or the fill tile
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    // $5113-$5117, bit 7 of $5114-$5116 maps rom instead of ram
+    prg_banks: [u8; 5],
+    // $5120-$5127 for sprites and everything else, $5128-$512B for the background with 8x16 sprites
+    chr_banks_a: [u16; 8],
+    chr_banks_b: [u16; 4],
+    // $5130, the high bits of the chr banks written after it
+    chr_upper: u8,
+    last_set_b: bool,
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    multiplicand: u8,
+    multiplier: u8,
+
+    // what the ppu is doing, from its hooks
+    in_frame: bool,
+    scanline: u16,
+    fetching_sprites: bool,
+    tall_sprites: bool,
+    // the line background tiles are being fetched for and the tile within it
+    fetch_line: u16,
+    tile: u8,
+    // the exram byte of the tile fetched last in extended attribute mode
+    ext_attribute: u8,
+    split_tile: bool,
+}
+
+impl Mmc5 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let header = &cartridge.header;
+        let prg_ram_size = (header.prg_ram_size + header.prg_nvram_size).max(0x2000);
+        Mmc5 {
+            chr: Chr::new(&cartridge),
+            prg_ram: BytesMut::zeroed(prg_ram_size),
+            battery: header.battery,
+            nametable_mapping: if header.mirroring == Mirroring::Vertical {
+                0x44
+            } else {
+                0x50
+            },
+            prg: Prg::new(&cartridge),
+            exram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0xFF, 0xFF, 0xFF, 0xFF],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            in_frame: false,
+            scanline: 0,
+            fetching_sprites: false,
+            tall_sprites: false,
+            fetch_line: 0,
+            tile: 0,
+            ext_attribute: 0,
+            split_tile: false,
+        }
+    }
+
+    // the 8K bank at $8000-$FFFF and whether it is rom
+    fn prg_bank(&self, address: u16) -> (usize, bool) {
+        let slot = (address as usize - 0x8000) / 0x2000;
+        let (register, bank) = match (self.prg_mode, slot) {
+            (0, _) => (4, self.prg_banks[4] & 0x7C | slot as u8),
+            (1, 0 | 1) | (2, 0 | 1) => (2, self.prg_banks[2] & 0x7E | slot as u8 & 0x01),
+            (1, _) => (4, self.prg_banks[4] & 0x7E | slot as u8 & 0x01),
+            (2, 2) => (3, self.prg_banks[3]),
+            (2, _) => (4, self.prg_banks[4]),
+            _ => (slot + 1, self.prg_banks[slot + 1]),
+        };
+        let rom = register == 4 || self.prg_banks[register] & 0x80 != 0;
+        ((bank & 0x7F) as usize, rom)
+    }
+
+    fn prg_ram_offset(&self, bank: usize, address: u16) -> usize {
+        (bank * 0x2000 + (address & 0x1FFF) as usize) % self.prg_ram.len()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    // background fetches during rendering, where the split and the extended attributes apply
+    fn fetching_background(&self) -> bool {
+        self.in_frame && !self.fetching_sprites
+    }
+
+    // set B banks the background with 8x16 sprites, outside rendering the set written last is used
+    fn uses_set_b(&self) -> bool {
+        if !self.tall_sprites {
+            false
+        } else if self.in_frame {
+            !self.fetching_sprites
+        } else {
+            self.last_set_b
+        }
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        if self.fetching_background() {
+            if self.split_tile {
+                // the split has its own vertical scroll, so the fine y of the ppu is replaced
+                let fine_y = self.split_y() % 8;
+                return self.split_bank as usize * 0x1000 + (address & 0x0FF8) as usize + fine_y;
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.ext_attribute & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                return bank * 0x1000 + (address & 0x0FFF) as usize;
+            }
+        }
+        let slot = (address >> 10) as usize;
+        let (bank, size) = if self.uses_set_b() {
+            let banks = &self.chr_banks_b;
+            match self.chr_mode {
+                0 => (banks[3], 0x2000),
+                1 => (banks[3], 0x1000),
+                2 => (banks[(slot & 0x02) | 0x01], 0x0800),
+                _ => (banks[slot & 0x03], 0x0400),
+            }
+        } else {
+            let banks = &self.chr_banks_a;
+            match self.chr_mode {
+                0 => (banks[7], 0x2000),
+                1 => (banks[slot | 0x03], 0x1000),
+                2 => (banks[slot | 0x01], 0x0800),
+                _ => (banks[slot], 0x0400),
+            }
+        };
+        bank as usize * size + (address as usize & (size - 1))
+    }
+
+    fn nametable_slot(&self, address: u16) -> u8 {
+        let table = (address >> 10) & 0x03;
+        (self.nametable_mapping >> (table * 2)) & 0x03
+    }
+
+    // the line within the split, which scrolls over the 30 rows of exram
+    fn split_y(&self) -> usize {
+        (self.split_scroll as usize + self.fetch_line as usize) % 240
+    }
+
+    fn in_split(&self, column: u8) -> bool {
+        if self.split_control & 0x80 == 0 || self.exram_mode > 1 || column >= 32 {
+            return false;
+        }
+        let threshold = self.split_control & 0x1F;
+        if self.split_control & 0x40 != 0 {
+            column >= threshold
+        } else {
+            column < threshold
+        }
+    }
+
+    fn read_register(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5204 => {
+                let value = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                Some(value)
+            }
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => {
+                Some(self.exram[(address - 0x5C00) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        let chr_bank = value as u16 | (self.chr_upper as u16) << 8;
+        match address {
+            0x5100 => self.prg_mode = value & 0x03,
+            0x5101 => self.chr_mode = value & 0x03,
+            0x5102 | 0x5103 => self.prg_ram_protect[(address - 0x5102) as usize] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113..=0x5117 => self.prg_banks[(address - 0x5113) as usize] = value,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[(address - 0x5120) as usize] = chr_bank;
+                self.last_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[(address - 0x5128) as usize] = chr_bank;
+                self.last_set_b = true;
+            }
+            0x5130 => self.chr_upper = value & 0x03,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let offset = (address - 0x5C00) as usize;
+                match self.exram_mode {
+                    // as a nametable it can only be written while the ppu renders
+                    0 | 1 => self.exram[offset] = if self.in_frame { value } else { 0 },
+                    2 => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5000..=0x5FFF => self.read_register(address),
+            0x6000..=0x7FFF => {
+                let bank = (self.prg_banks[0] & 0x7F) as usize;
+                Some(self.prg_ram[self.prg_ram_offset(bank, address)])
+            }
+            0x8000..=0xFFFF => {
+                let (bank, rom) = self.prg_bank(address);
+                if rom {
+                    Some(self.prg.read_bank(bank, 0x2000, address))
+                } else {
+                    Some(self.prg_ram[self.prg_ram_offset(bank, address)])
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+        let (bank, rom) = self.prg_bank(address);
+        rom.then(|| self.prg.bank_offset(bank, 0x2000, address))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5FFF => self.write_register(address, value),
+            0x6000..=0x7FFF if self.prg_ram_writable() => {
+                let offset = self.prg_ram_offset((self.prg_banks[0] & 0x7F) as usize, address);
+                self.prg_ram[offset] = value;
+            }
+            0x8000..=0xDFFF if self.prg_ram_writable() => {
+                let (bank, rom) = self.prg_bank(address);
+                if !rom {
+                    let offset = self.prg_ram_offset(bank, address);
+                    self.prg_ram[offset] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_offset(address))
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr.write(self.chr_offset(address), value);
+    }
+
+    // pages for the nametables in console vram, the others never get there
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Custom(std::array::from_fn(|table| {
+            (self.nametable_mapping >> (table * 2)) & 0x01
+        }))
+    }
+
+    fn ppu_line(&mut self, scanline: Option<u16>) {
+        let Some(scanline) = scanline else {
+            self.in_frame = false;
+            return;
+        };
+        self.in_frame = true;
+        self.scanline = scanline;
+        if scanline < 240 && scanline == self.irq_compare as u16 && scanline != 0 {
+            self.irq_pending = true;
+        }
+    }
+
+    fn ppu_fetch(&mut self, fetch: Fetch, tall_sprites: bool) {
+        self.tall_sprites = tall_sprites;
+        self.fetching_sprites = fetch == Fetch::Sprites;
+        if fetch == Fetch::Background {
+            // the first two tiles of a line are fetched at the end of the one before
+            self.fetch_line = if self.scanline >= 240 {
+                0
+            } else {
+                self.scanline + 1
+            };
+            self.tile = 0;
+        }
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        let offset = (address & 0x03FF) as usize;
+        let attribute = offset >= 0x3C0;
+        if self.fetching_background() {
+            if !attribute {
+                let column = self.tile;
+                self.tile = self.tile.wrapping_add(1);
+                self.split_tile = self.in_split(column);
+                if self.split_tile {
+                    return Some(self.exram[self.split_y() / 8 * 32 + column as usize]);
+                }
+                self.ext_attribute = self.exram[offset];
+            } else if self.split_tile {
+                let (row, column) = (self.split_y() / 8, self.tile.wrapping_sub(1) as usize);
+                let attribute = self.exram[0x3C0 + row / 4 * 8 + column / 4];
+                let shift = (row & 0x02) << 1 | (column & 0x02);
+                // the same palette in every quadrant, whichever the ppu picks
+                return Some(((attribute >> shift) & 0x03) * 0x55);
+            } else if self.exram_mode == 1 {
+                return Some((self.ext_attribute >> 6) * 0x55);
+            }
+        }
+        match self.nametable_slot(address) {
+            2 if self.exram_mode <= 1 => Some(self.exram[offset]),
+            2 => Some(0),
+            3 if attribute => Some(self.fill_attribute * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, address: u16, value: u8) -> bool {
+        match self.nametable_slot(address) {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[(address & 0x03FF) as usize] = value;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(&mut self.prg_ram[..])
+    }
+}
+
+savestate_fields!(Mmc5 {
+    prg_ram,
+    chr,
+    exram,
+    prg_mode,
+    chr_mode,
+    prg_ram_protect,
+    exram_mode,
+    nametable_mapping,
+    fill_tile,
+    fill_attribute,
+    prg_banks,
+    chr_banks_a,
+    chr_banks_b,
+    chr_upper,
+    last_set_b,
+    split_control,
+    split_scroll,
+    split_bank,
+    irq_compare,
+    irq_enabled,
+    irq_pending,
+    multiplicand,
+    multiplier,
+    in_frame,
+    scanline,
+    fetching_sprites,
+    tall_sprites,
+    fetch_line,
+    tile,
+    ext_attribute,
+    split_tile,
+} checked);
+
+impl Mmc5 {
+    // the registers written through a mask
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            [
+                self.prg_mode,
+                self.chr_mode,
+                self.exram_mode,
+                self.chr_upper,
+            ]
+            .into_iter()
+            .chain(self.prg_ram_protect)
+            .all(|value| value < 4),
+            "mmc5 mode",
+        )?;
+        ensure(self.fill_attribute < 4, "mmc5 fill attribute")
+    }
+}
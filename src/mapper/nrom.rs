@@ -0,0 +1,68 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::savestate_fields;
+use bytes::BytesMut;
+
+// mapper 0: up to 32K of prg rom and 8K of chr, no bank switching
+pub struct Nrom {
+    prg: Prg,
+    prg_ram: BytesMut,
+    battery: bool,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Nrom {
+            chr: Chr::new(&cartridge),
+            prg_ram: BytesMut::zeroed(0x2000),
+            battery: cartridge.header.battery,
+            prg: Prg::new(&cartridge),
+            mirroring: cartridge.header.mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => Some(self.prg_ram[(address - 0x6000) as usize]),
+            0x8000..=0xFFFF => Some(self.prg.read_bank(0, 0x8000, address)),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        (address >= 0x8000).then(|| self.prg.bank_offset(0, 0x8000, address))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram[(address - 0x6000) as usize] = value;
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(address as usize)
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr.write(address as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(&mut self.prg_ram[..])
+    }
+}
+
+savestate_fields!(Nrom { prg_ram, chr });
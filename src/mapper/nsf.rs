@@ -0,0 +1,130 @@
+use super::Mapper;
+use crate::expansion::{ExpansionLevels, FdsAudio, Vrc6Audio};
+use crate::nsf::{DRIVER, Nsf, VRC6};
+use crate::ppu::Mirroring;
+use crate::savestate::savestate_fields;
+use bytes::BytesMut;
+
+const BANK_SIZE: usize = 0x1000;
+
+// the board an nsf player runs on: the music data in eight 4K banks at $8000-$FFFF, switched through
+// $5FF8-$5FFF when the file asks for it, 8K of ram at $6000 and the idle loop routines return to.
+// music for the disk system runs from 32K of ram at $6000-$DFFF instead, which the bank registers
+// and $5FF6-$5FF7 for $6000-$7FFF copy banks into. the header's sound chips come with it
+pub struct NsfBoard {
+    // the data padded so it starts at its load address within a bank
+    prg_rom: BytesMut,
+    prg_ram: BytesMut,
+    banks: [u8; 8],
+    fds_ram: bool,
+    vrc6: Option<Vrc6Audio>,
+    fds: Option<FdsAudio>,
+}
+
+impl NsfBoard {
+    pub fn new(nsf: &Nsf) -> Self {
+        let base = if nsf.fds() { 0x6000 } else { 0x8000 };
+        let padding = if nsf.bankswitched() {
+            nsf.load_address & 0x0FFF
+        } else {
+            // a plain image from $8000 or $6000 to $FFFF
+            nsf.load_address.saturating_sub(base)
+        } as usize;
+        let mut prg_rom = BytesMut::zeroed(padding);
+        prg_rom.extend_from_slice(&nsf.data);
+        let size = prg_rom.len().div_ceil(BANK_SIZE).max(8) * BANK_SIZE;
+        prg_rom.resize(size, 0);
+        NsfBoard {
+            prg_rom,
+            prg_ram: BytesMut::zeroed(if nsf.fds() { 0x8000 } else { 0x2000 }),
+            banks: nsf.initial_banks(),
+            fds_ram: nsf.fds(),
+            vrc6: (nsf.expansion_audio & VRC6 != 0).then(Vrc6Audio::default),
+            fds: nsf.fds().then(FdsAudio::default),
+        }
+    }
+
+    // a bank into the disk system's ram, slot 0 being $6000
+    fn copy_bank(&mut self, slot: usize, bank: u8) {
+        let start = bank as usize * BANK_SIZE % self.prg_rom.len();
+        self.prg_ram[slot * BANK_SIZE..(slot + 1) * BANK_SIZE]
+            .copy_from_slice(&self.prg_rom[start..start + BANK_SIZE]);
+    }
+}
+
+impl Mapper for NsfBoard {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            // JMP DRIVER
+            DRIVER => Some(0x4C),
+            0x4101 => Some(DRIVER as u8),
+            0x4102 => Some((DRIVER >> 8) as u8),
+            0x4040..=0x4092 => self.fds.as_ref().and_then(|fds| fds.read(address)),
+            0x6000..=0x7FFF => Some(self.prg_ram[(address - 0x6000) as usize]),
+            0x8000..=0xDFFF if self.fds_ram => Some(self.prg_ram[(address - 0x6000) as usize]),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(address as usize - 0x8000) / BANK_SIZE] as usize;
+                let offset = bank * BANK_SIZE + (address as usize & (BANK_SIZE - 1));
+                Some(self.prg_rom[offset % self.prg_rom.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.write(address, value);
+        }
+        match address {
+            0x4040..=0x408A => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write(address, value);
+                }
+            }
+            0x5FF6..=0x5FF7 if self.fds_ram => self.copy_bank((address - 0x5FF6) as usize, value),
+            0x5FF8..=0x5FFF => {
+                let index = (address - 0x5FF8) as usize;
+                self.banks[index] = value;
+                if self.fds_ram && index < 6 {
+                    self.copy_bank(index + 2, value);
+                }
+            }
+            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize] = value,
+            0x8000..=0xDFFF if self.fds_ram => self.prg_ram[(address - 0x6000) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, _address: u16) -> u8 {
+        0
+    }
+
+    fn chr_write(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn cpu_cycle(&mut self) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.clock();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+    }
+
+    fn audio(&self) -> ExpansionLevels {
+        [
+            self.vrc6.as_ref().map_or(0.0, Vrc6Audio::output),
+            self.fds.as_ref().map_or(0.0, FdsAudio::output),
+        ]
+    }
+}
+
+savestate_fields!(NsfBoard {
+    prg_ram,
+    banks,
+    vrc6,
+    fds
+});
@@ -0,0 +1,67 @@
+use super::Mapper;
+use crate::ppu::Mirroring;
+use crate::raw::RawProgram;
+use crate::savestate::savestate_fields;
+use bytes::BytesMut;
+
+// the whole cartridge space from $4020 is ram
+const RAM_START: usize = 0x4000;
+
+// the board a raw program runs on: ram from $4020 to $FFFF with the program loaded into it, 8K of
+// chr ram, and an output port that collects what is written to it instead of storing it
+pub struct RawBoard {
+    ram: BytesMut,
+    chr: BytesMut,
+    output_port: Option<u16>,
+    output: Vec<u8>,
+}
+
+impl RawBoard {
+    pub fn new(program: &RawProgram) -> Self {
+        let mut ram = BytesMut::zeroed(0x10000 - RAM_START);
+        let start = program.load_address as usize;
+        for (address, byte) in (start..).zip(&program.data) {
+            if address >= RAM_START {
+                ram[address - RAM_START] = *byte;
+            }
+        }
+        RawBoard {
+            ram,
+            chr: BytesMut::zeroed(0x2000),
+            output_port: program.output_port,
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Mapper for RawBoard {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        Some(self.ram[address as usize - RAM_START])
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if self.output_port == Some(address) {
+            self.output.push(value);
+        } else {
+            self.ram[address as usize - RAM_START] = value;
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize & 0x1FFF]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr[address as usize & 0x1FFF] = value;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+savestate_fields!(RawBoard { ram, chr });
@@ -0,0 +1,65 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::ppu::Mirroring;
+use crate::savestate::{StateError, ensure, savestate_fields};
+
+// mapper 2: switchable 16K bank at $8000, the last bank is fixed at $C000
+pub struct Uxrom {
+    prg: Prg,
+    chr: Chr,
+    mirroring: Mirroring,
+    prg_bank: usize,
+}
+
+impl Uxrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Uxrom {
+            chr: Chr::new(&cartridge),
+            prg: Prg::new(&cartridge),
+            mirroring: cartridge.header.mirroring,
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        let offset = self.prg_rom_offset(address)?;
+        Some(self.prg.read(offset))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => self.prg.last_bank(0x4000),
+            _ => return None,
+        };
+        Some(self.prg.bank_offset(bank, 0x4000, address))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.prg_bank = value as usize;
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(address as usize)
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr.write(address as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+savestate_fields!(Uxrom { prg_bank, chr } checked);
+
+impl Uxrom {
+    fn check(&self) -> Result<(), StateError> {
+        ensure(self.prg_bank <= 0xFF, "uxrom prg bank")
+    }
+}
@@ -0,0 +1,211 @@
+use super::{Chr, Mapper, Prg};
+use crate::cartridge::Cartridge;
+use crate::expansion::{ExpansionLevels, Vrc6Audio};
+use crate::ppu::Mirroring;
+use crate::savestate::savestate_fields;
+use bytes::BytesMut;
+
+// mappers 24 and 26: konami's vrc6, a 16K and an 8K prg bank with the last 8K fixed, eight 1K chr
+// banks, an irq counter clocked by the cpu and three sound channels of its own. 26 has the two low
+// address lines swapped, which is undone before decoding
+pub struct Vrc6 {
+    prg: Prg,
+    prg_ram: BytesMut,
+    battery: bool,
+    chr: Chr,
+    swapped_lines: bool,
+    prg_banks: [u8; 2],
+    chr_banks: [u8; 8],
+    // $B003: bits 2-3 pick the mirroring, bit 7 enables the prg ram
+    ppu_control: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    // counts down 3 a cpu cycle from 341 so the counter goes up once a scanline, unless it
+    // is in cycle mode and goes up every cycle
+    irq_prescaler: u16,
+    irq_cycle_mode: bool,
+    irq_enabled: bool,
+    // what irq_enabled becomes when the irq is acknowledged
+    irq_enabled_after: bool,
+    irq_pending: bool,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(cartridge: Cartridge, swapped_lines: bool) -> Self {
+        Vrc6 {
+            chr: Chr::new(&cartridge),
+            prg_ram: BytesMut::zeroed(0x2000),
+            battery: cartridge.header.battery,
+            prg: Prg::new(&cartridge),
+            swapped_lines,
+            prg_banks: [0, 0],
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            ppu_control: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_prescaler: 341,
+            irq_cycle_mode: false,
+            irq_enabled: false,
+            irq_enabled_after: false,
+            irq_pending: false,
+            audio: Vrc6Audio::default(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.ppu_control & 0x80 != 0
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq_pending = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn prg_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xBFFF => Some(self.prg.read_bank(
+                self.prg_banks[0] as usize,
+                0x4000,
+                address,
+            )),
+            0xC000..=0xDFFF => Some(self.prg.read_bank(
+                self.prg_banks[1] as usize,
+                0x2000,
+                address,
+            )),
+            0xE000..=0xFFFF => Some(self.prg.read_bank(
+                self.prg.last_bank(0x2000),
+                0x2000,
+                address,
+            )),
+            _ => None,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        let (bank, size) = match address {
+            0x8000..=0xBFFF => (self.prg_banks[0] as usize, 0x4000),
+            0xC000..=0xDFFF => (self.prg_banks[1] as usize, 0x2000),
+            0xE000..=0xFFFF => (self.prg.last_bank(0x2000), 0x2000),
+            _ => return None,
+        };
+        Some(self.prg.bank_offset(bank, size, address))
+    }
+
+    fn prg_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled() {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            return;
+        }
+        let address = if self.swapped_lines {
+            (address & !0x03) | ((address & 0x01) << 1) | ((address >> 1) & 0x01)
+        } else {
+            address
+        };
+        match address & 0xF003 {
+            0x8000..=0x8003 => self.prg_banks[0] = value & 0x0F,
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => self.audio.write(address, value),
+            0xB003 => self.ppu_control = value,
+            0xC000..=0xC003 => self.prg_banks[1] = value & 0x1F,
+            register @ (0xD000..=0xD003 | 0xE000..=0xE003) => {
+                let slot = ((register >> 12) - 0x0D) * 4 + (register & 0x03);
+                self.chr_banks[slot as usize] = value;
+            }
+            0xF000 => self.irq_latch = value,
+            0xF001 => {
+                self.irq_enabled_after = value & 0x01 != 0;
+                self.irq_enabled = value & 0x02 != 0;
+                self.irq_cycle_mode = value & 0x04 != 0;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_latch;
+                    self.irq_prescaler = 341;
+                }
+                self.irq_pending = false;
+            }
+            0xF002 => {
+                self.irq_pending = false;
+                self.irq_enabled = self.irq_enabled_after;
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07] as usize;
+        self.chr.read(bank * 0x0400 + (address & 0x03FF) as usize)
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07] as usize;
+        self.chr
+            .write(bank * 0x0400 + (address & 0x03FF) as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.ppu_control >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_cycle(&mut self) {
+        self.audio.clock();
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_cycle_mode {
+            self.clock_irq_counter();
+        } else if self.irq_prescaler <= 3 {
+            self.irq_prescaler += 341 - 3;
+            self.clock_irq_counter();
+        } else {
+            self.irq_prescaler -= 3;
+        }
+    }
+
+    fn audio(&self) -> ExpansionLevels {
+        [self.audio.output(), 0.0]
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(&mut self.prg_ram[..])
+    }
+}
+
+savestate_fields!(Vrc6 {
+    prg_ram,
+    prg_banks,
+    chr_banks,
+    ppu_control,
+    irq_latch,
+    irq_counter,
+    irq_prescaler,
+    irq_cycle_mode,
+    irq_enabled,
+    irq_enabled_after,
+    irq_pending,
+    audio,
+    chr,
+});
@@ -0,0 +1,227 @@
+// side effect free access to every memory of the console for memory viewers and hex editors,
+// with byte pattern search, ram search and addresses frozen to a value
+use crate::Emulator;
+
+pub const RAM_SIZE: usize = 0x800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemorySpace {
+    // the cpu address space, io registers read as the open bus
+    Cpu,
+    // the ppu address space: pattern tables, nametables and palette up to $3FFF
+    Ppu,
+    Oam,
+    Palette,
+}
+
+impl MemorySpace {
+    pub const ALL: [MemorySpace; 4] = [
+        MemorySpace::Cpu,
+        MemorySpace::Ppu,
+        MemorySpace::Oam,
+        MemorySpace::Palette,
+    ];
+
+    // addresses wrap around at the size of the space
+    pub fn size(self) -> usize {
+        match self {
+            MemorySpace::Cpu => 0x10000,
+            MemorySpace::Ppu => 0x4000,
+            MemorySpace::Oam => 0x100,
+            MemorySpace::Palette => 0x20,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemorySpace::Cpu => "cpu",
+            MemorySpace::Ppu => "ppu",
+            MemorySpace::Oam => "oam",
+            MemorySpace::Palette => "pal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MemorySpace::ALL
+            .into_iter()
+            .find(|space| space.name() == name.to_ascii_lowercase())
+    }
+
+    fn wrap(self, address: u16) -> u16 {
+        (address as usize % self.size()) as u16
+    }
+
+    // folds the mirrors of cpu ram onto the first 2K, so each byte has one address
+    fn canonical(self, address: u16) -> u16 {
+        match (self, self.wrap(address)) {
+            (MemorySpace::Cpu, address @ 0x0000..=0x1FFF) => address & 0x07FF,
+            (_, address) => address,
+        }
+    }
+
+    fn is_mirror(self, address: u16) -> bool {
+        self.canonical(address) != self.wrap(address)
+    }
+}
+
+// how a ram search narrows down its addresses, None compares with the value at the last snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFilter {
+    Equal(Option<u8>),
+    NotEqual(Option<u8>),
+    Greater(Option<u8>),
+    Less(Option<u8>),
+    // changed by exactly this much since the last snapshot, wrapping around like the byte
+    IncreasedBy(u8),
+    DecreasedBy(u8),
+}
+
+impl RamFilter {
+    fn keeps(self, previous: u8, current: u8) -> bool {
+        match self {
+            RamFilter::Equal(value) => current == value.unwrap_or(previous),
+            RamFilter::NotEqual(value) => current != value.unwrap_or(previous),
+            RamFilter::Greater(value) => current > value.unwrap_or(previous),
+            RamFilter::Less(value) => current < value.unwrap_or(previous),
+            RamFilter::IncreasedBy(step) => current == previous.wrapping_add(step),
+            RamFilter::DecreasedBy(step) => current == previous.wrapping_sub(step),
+        }
+    }
+}
+
+// finds where a game keeps a number like the lives or the health: start with every address of work
+// ram, then keep filtering by how the values behaved since the snapshot before
+pub struct RamSearch {
+    snapshot: [u8; RAM_SIZE],
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    pub fn new(emulator: &Emulator) -> Self {
+        RamSearch {
+            snapshot: emulator.ram_snapshot(),
+            candidates: (0..RAM_SIZE as u16).collect(),
+        }
+    }
+
+    // keeps the addresses that pass filter, then snapshots ram for the next one.
+    // returns how many are left
+    pub fn filter(&mut self, emulator: &Emulator, filter: RamFilter) -> usize {
+        let ram = emulator.ram();
+        self.candidates.retain(|&address| {
+            filter.keeps(self.snapshot[address as usize], ram[address as usize])
+        });
+        self.snapshot.copy_from_slice(ram);
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // the value at address when the last snapshot was taken
+    pub fn previous(&self, address: u16) -> u8 {
+        self.snapshot[address as usize % RAM_SIZE]
+    }
+
+    // freezes every address left at value, or at what it holds now
+    pub fn freeze(&self, emulator: &mut Emulator, value: Option<u8>) {
+        for &address in &self.candidates {
+            let value = value.unwrap_or(emulator.ram()[address as usize]);
+            emulator.freeze(MemorySpace::Cpu, address, value);
+        }
+    }
+}
+
+impl Emulator {
+    // a copy of the 2K of work ram, for comparing against later
+    pub fn ram_snapshot(&self) -> [u8; RAM_SIZE] {
+        self.ram
+    }
+
+    // reads without touching any latch, buffer or shift register, unlike a read by the cpu or $2007
+    pub fn read_memory(&mut self, space: MemorySpace, address: u16) -> u8 {
+        let address = space.wrap(address);
+        match space {
+            MemorySpace::Cpu => self.peek(address),
+            MemorySpace::Ppu => self.ppu.peek_vram(&mut *self.mapper, address),
+            MemorySpace::Oam => self.ppu.oam()[address as usize],
+            MemorySpace::Palette => self.ppu.peek_vram(&mut *self.mapper, 0x3F00 | address),
+        }
+    }
+
+    // writes without side effects, so io registers and cartridge rom, whose writes
+    // would reach mapper registers, are left alone
+    pub fn write_memory(&mut self, space: MemorySpace, address: u16, value: u8) {
+        let address = space.wrap(address);
+        match space {
+            MemorySpace::Cpu => match address {
+                0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize] = value,
+                0x6000..=0x7FFF => self.mapper.prg_write(address, value),
+                _ => {}
+            },
+            MemorySpace::Ppu => self.ppu.poke_vram(&mut *self.mapper, address, value),
+            MemorySpace::Oam => self.ppu.oam_mut()[address as usize] = value,
+            MemorySpace::Palette => self
+                .ppu
+                .poke_vram(&mut *self.mapper, 0x3F00 | address, value),
+        }
+    }
+
+    // every address where pattern starts, None in the pattern matches any byte. mirrors of ram are skipped
+    pub fn search_memory(&mut self, space: MemorySpace, pattern: &[Option<u8>]) -> Vec<u16> {
+        if pattern.is_empty() || pattern.len() > space.size() {
+            return Vec::new();
+        }
+        let memory: Vec<u8> = (0..space.size())
+            .map(|address| self.read_memory(space, address as u16))
+            .collect();
+        memory
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(address, _)| !space.is_mirror(*address as u16))
+            .filter(|(_, window)| {
+                window
+                    .iter()
+                    .zip(pattern)
+                    .all(|(byte, expected)| expected.is_none_or(|expected| *byte == expected))
+            })
+            .map(|(address, _)| address as u16)
+            .collect()
+    }
+
+    // keeps address at value: cpu writes to it are undone straight away and
+    // everything frozen is written again before every frame
+    pub fn freeze(&mut self, space: MemorySpace, address: u16, value: u8) {
+        let address = space.canonical(address);
+        self.frozen.insert((space, address), value);
+        self.write_memory(space, address, value);
+    }
+
+    pub fn unfreeze(&mut self, space: MemorySpace, address: u16) -> bool {
+        self.frozen
+            .remove(&(space, space.canonical(address)))
+            .is_some()
+    }
+
+    pub fn frozen(&self) -> impl Iterator<Item = (MemorySpace, u16, u8)> + '_ {
+        self.frozen
+            .iter()
+            .map(|(&(space, address), &value)| (space, address, value))
+    }
+
+    pub(crate) fn apply_freezes(&mut self) {
+        let frozen: Vec<_> = self.frozen().collect();
+        for (space, address, value) in frozen {
+            self.write_memory(space, address, value);
+        }
+    }
+
+    // after a cpu write, in case it hit a frozen address
+    pub(crate) fn refreeze(&mut self, address: u16) {
+        let address = MemorySpace::Cpu.canonical(address);
+        if let Some(&value) = self.frozen.get(&(MemorySpace::Cpu, address)) {
+            self.write_memory(MemorySpace::Cpu, address, value);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+// input movies in fceux's text fm2 format, so runs can be replayed here and in fceux.
+// a movie is a header of "key value" lines followed by one |commands|pad 1|pad 2|| line per frame,
+// with two more pads when the movie was made with a four score
+use crate::controller::{FrameInput, InputSource};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the pad columns of an input line, right is the high bit of the pad and a the low one
+const BUTTON_LETTERS: &[u8; 8] = b"RLDUTSBA";
+const SOFT_RESET: u32 = 0x01;
+const HARD_RESET: u32 = 0x02;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    UnsupportedVersion(String),
+    // the binary variant of fm2 packs input lines into bytes
+    Binary,
+    // 1 based number of a line that is not a valid input line
+    BadInput(usize),
+    BadSavestate,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "fm2 version {version} is not supported")
+            }
+            MovieError::Binary => write!(f, "binary fm2 movies are not supported"),
+            MovieError::BadInput(line) => write!(f, "line {line} is not a valid input line"),
+            MovieError::BadSavestate => write!(f, "the savestate header is not valid base64"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_filename: String,
+    // md5 of the prg and chr rom, which fceux checks before playing
+    pub rom_checksum: Option<[u8; 16]>,
+    pub guid: String,
+    pub pal: bool,
+    // input lines carry four pads instead of two
+    pub four_score: bool,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    // a movie recorded from a save state starts by loading it instead of from power on
+    pub savestate: Option<Vec<u8>>,
+    pub frames: Vec<FrameInput>,
+}
+
+impl Movie {
+    // an empty movie for the rom in rom_file, a whole .nes file with its header
+    pub fn new(rom_filename: &str, rom_file: &[u8]) -> Self {
+        // fceux hashes the rom data, which starts after the header and the optional trainer
+        let trainer = rom_file.get(6).is_some_and(|flags| flags & 0x04 != 0);
+        let start = (16 + if trainer { 512 } else { 0 }).min(rom_file.len());
+        Movie {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum: Some(md5(&rom_file[start..])),
+            guid: new_guid(),
+            ..Movie::default()
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Movie::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                let pads = if movie.four_score { 4 } else { 2 };
+                let input = parse_input(line, pads).ok_or(MovieError::BadInput(index + 1))?;
+                movie.frames.push(input);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => {
+                    return Err(MovieError::UnsupportedVersion(value.to_string()));
+                }
+                "binary" if value == "1" => return Err(MovieError::Binary),
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    movie.rom_checksum = value
+                        .strip_prefix("base64:")
+                        .and_then(base64_decode)
+                        .and_then(|digest| digest.try_into().ok());
+                }
+                "guid" => movie.guid = value.to_string(),
+                "palFlag" => movie.pal = value == "1",
+                "fourscore" => movie.four_score = value == "1",
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or(0),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let state = value.strip_prefix("base64:").unwrap_or(value);
+                    movie.savestate = Some(base64_decode(state).ok_or(MovieError::BadSavestate)?);
+                }
+                // microphone, port types and the like are fixed for now
+                _ => {}
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        let mut header = |key: &str, value: &str| {
+            text.push_str(key);
+            text.push(' ');
+            text.push_str(value);
+            text.push('\n');
+        };
+        header("version", "3");
+        header("emuVersion", "22020");
+        header("rerecordCount", &self.rerecord_count.to_string());
+        header("palFlag", if self.pal { "1" } else { "0" });
+        header("romFilename", &self.rom_filename);
+        if let Some(checksum) = &self.rom_checksum {
+            header(
+                "romChecksum",
+                &format!("base64:{}", base64_encode(checksum)),
+            );
+        }
+        header("guid", &self.guid);
+        header("fourscore", if self.four_score { "1" } else { "0" });
+        header("microphone", "0");
+        header("port0", "1");
+        header("port1", "1");
+        header("port2", "0");
+        header("FDS", "0");
+        header("NewPPU", "0");
+        for comment in &self.comments {
+            header("comment", comment);
+        }
+        if let Some(state) = &self.savestate {
+            header("savestate", &format!("base64:{}", base64_encode(state)));
+        }
+        for frame in &self.frames {
+            let commands = if frame.power {
+                HARD_RESET
+            } else if frame.reset {
+                SOFT_RESET
+            } else {
+                0
+            };
+            let pads = if self.four_score { 4 } else { 2 };
+            text.push_str(&format!("|{commands}|"));
+            for buttons in &frame.buttons[..pads] {
+                text.push_str(&pad_column(*buttons));
+                text.push('|');
+            }
+            text.push_str("|\n");
+        }
+        text
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+fn pad_column(buttons: u8) -> String {
+    BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(index, &letter)| {
+            if buttons & (0x80 >> index) != 0 {
+                letter as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// any character other than a space or a dot marks the button pressed, a missing pad presses nothing
+fn parse_input(line: &str, pads: usize) -> Option<FrameInput> {
+    let mut fields = line.strip_prefix('|')?.split('|');
+    let commands: u32 = fields.next()?.trim().parse().ok()?;
+    let mut input = FrameInput {
+        reset: commands & SOFT_RESET != 0,
+        power: commands & HARD_RESET != 0,
+        ..FrameInput::default()
+    };
+    for buttons in &mut input.buttons[..pads] {
+        let column = fields.next().unwrap_or("");
+        if !column.is_empty() && column.len() != 8 {
+            return None;
+        }
+        *buttons = column
+            .bytes()
+            .enumerate()
+            .filter(|(_, letter)| *letter != b' ' && *letter != b'.')
+            .fold(0, |buttons, (index, _)| buttons | 0x80 >> index);
+    }
+    Some(input)
+}
+
+// plays a movie back frame by frame
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, frame: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    // frames played so far
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+}
+
+impl InputSource for MoviePlayer {
+    fn next_frame(&mut self) -> Option<FrameInput> {
+        let input = self.movie.frames.get(self.frame).copied()?;
+        self.frame += 1;
+        Some(input)
+    }
+}
+
+// passes the input of another source through and appends it to a movie
+pub struct Recorder<'a, S: InputSource + ?Sized> {
+    pub source: &'a mut S,
+    pub movie: &'a mut Movie,
+}
+
+impl<S: InputSource + ?Sized> InputSource for Recorder<'_, S> {
+    fn next_frame(&mut self) -> Option<FrameInput> {
+        let input = self.source.next_frame()?;
+        self.movie.frames.push(input);
+        Some(input)
+    }
+}
+
+// fceux only needs this to tell movies apart, so the clock is random enough
+fn new_guid() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+    let hex: String = md5(&nanos.to_le_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for letter in text.trim().bytes().take_while(|&letter| letter != b'=') {
+        let value = BASE64_ALPHABET.iter().position(|&valid| valid == letter)?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
+
+// rfc 1321, for the rom checksum and nothing that needs to be secure
+pub(crate) fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64)
+        .map(|index| ((index + 1) as f64).sin().abs() * 4_294_967_296.0)
+        .map(|constant| constant as u32)
+        .collect();
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block
+            .chunks(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for index in 0..64 {
+            let (mixed, word) = match index / 16 {
+                0 => ((b & c) | (!b & d), index),
+                1 => ((d & b) | (!d & c), (5 * index + 1) % 16),
+                2 => (b ^ c ^ d, (3 * index + 5) % 16),
+                _ => (c ^ (b | !d), (7 * index) % 16),
+            };
+            let rotated = mixed
+                .wrapping_add(a)
+                .wrapping_add(constants[index])
+                .wrapping_add(words[word])
+                .rotate_left(SHIFTS[index]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 16]) -> String {
+        digest.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn md5_gives_the_known_digests() {
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        // longer than a block once padded
+        assert_eq!(
+            hex(md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn movies_read_back_as_they_were_written() {
+        let mut movie = Movie::new("game.nes", &[0; 16 + 0x4000]);
+        movie.four_score = true;
+        movie.rerecord_count = 3;
+        movie.comments.push("author someone".to_string());
+        movie.savestate = Some(vec![0x4E, 0x45, 0x53, 0x53, 0x00]);
+        movie.frames = vec![
+            FrameInput::default(),
+            FrameInput {
+                buttons: [0x81, 0x00, 0xFF, 0x24],
+                ..FrameInput::default()
+            },
+            FrameInput {
+                reset: true,
+                ..FrameInput::default()
+            },
+            FrameInput {
+                power: true,
+                ..FrameInput::default()
+            },
+        ];
+        let text = movie.to_fm2();
+        assert!(text.contains("\n|0|R......A|........|RLDUTSBA|..D..S..||\n"));
+        assert_eq!(Movie::parse(&text), Ok(movie));
+    }
+
+    #[test]
+    fn fceux_input_lines_are_read() {
+        let movie = Movie::parse("version 3\n|0|R  U   A|........||\r\n|1|||\n").unwrap();
+        assert_eq!(movie.frames.len(), 2);
+        assert_eq!(movie.frames[0].buttons, [0x91, 0x00, 0x00, 0x00]);
+        assert!(movie.frames[1].reset && movie.frames[1].buttons == [0; 4]);
+    }
+
+    #[test]
+    fn malformed_movies_are_refused() {
+        let bad_pad = Movie::parse("version 3\n|0|........|........||\n|0|RLDU|........||\n");
+        assert_eq!(bad_pad, Err(MovieError::BadInput(3)));
+        let bad_command = Movie::parse("|x|........|........||");
+        assert_eq!(bad_command, Err(MovieError::BadInput(1)));
+        assert_eq!(
+            Movie::parse("version 2"),
+            Err(MovieError::UnsupportedVersion("2".to_string()))
+        );
+        assert_eq!(Movie::parse("binary 1"), Err(MovieError::Binary));
+        assert_eq!(
+            Movie::parse("savestate base64:@@@@"),
+            Err(MovieError::BadSavestate)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+// two player netplay over a tcp connection. the host is player 1 and sends its save state so both
+// consoles start alike, after that only input goes over the wire: each side sends its pad for the
+// frame delay frames ahead, and a frame runs once the other side's pad for it is known. with a
+// rollback window the frame runs on a guess instead, the last pad heard of, and when the real one
+// turns out different the console goes back to the state before and runs the frames again
+use crate::Emulator;
+use crate::clock::Region;
+use crate::controller::{FrameInput, Player};
+use crate::movie::md5;
+use crate::savestate::StateError;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+pub const DEFAULT_DELAY: u8 = 2;
+const MAGIC: [u8; 4] = *b"NESN";
+const PROTOCOL: u16 = 1;
+// a peer that sends nothing for this long is taken to be gone
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+// frame number, pad, flags
+const MESSAGE_SIZE: usize = 6;
+const RESET_FLAG: u8 = 0x01;
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    BadMagic,
+    Protocol(u16),
+    // the peer runs a different rom
+    RomMismatch,
+    BadState(StateError),
+    // the host's state is longer than a state of this rom
+    StateTooLarge(usize),
+    Disconnected,
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetplayError::Io(error) => write!(f, "{error}"),
+            NetplayError::BadMagic => write!(f, "the peer is not an ntsc-nes netplay session"),
+            NetplayError::Protocol(version) => write!(
+                f,
+                "the peer speaks netplay protocol {version}, this build speaks {PROTOCOL}"
+            ),
+            NetplayError::RomMismatch => write!(f, "the peer is running a different rom"),
+            NetplayError::BadState(error) => write!(f, "the host's save state: {error}"),
+            NetplayError::StateTooLarge(length) => write!(
+                f,
+                "the host sent a {length} byte save state, more than this rom's"
+            ),
+            NetplayError::Disconnected => write!(f, "the peer disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock => NetplayError::Disconnected,
+            _ => NetplayError::Io(error),
+        }
+    }
+}
+
+impl From<StateError> for NetplayError {
+    fn from(error: StateError) -> Self {
+        NetplayError::BadState(error)
+    }
+}
+
+// what one side presses in a frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Pad {
+    buttons: u8,
+    reset: bool,
+}
+
+pub struct Netplay {
+    stream: TcpStream,
+    player: Player,
+    delay: u64,
+    rollback: u64,
+    // the next frame to run
+    frame: u64,
+    // frames before this one ran on the peer's real input
+    confirmed: u64,
+    local: BTreeMap<u64, Pad>,
+    remote: BTreeMap<u64, Pad>,
+    // the guesses frames from confirmed on ran with, and the state before each of them
+    predictions: VecDeque<Pad>,
+    states: VecDeque<Vec<u8>>,
+    // bytes of a message that hasn't fully arrived
+    received: Vec<u8>,
+    rollbacks: u64,
+}
+
+impl Netplay {
+    // starts a session as player 1 on a connection a peer made, with delay frames of input delay
+    // for both sides and rollback frames of input the peer may fall behind without stalling
+    pub fn host(
+        mut stream: TcpStream,
+        emulator: &Emulator,
+        rom: &[u8],
+        delay: u8,
+        rollback: u8,
+    ) -> Result<Self, NetplayError> {
+        handshake(&mut stream, rom)?;
+        let state = emulator.save_state();
+        let region = match emulator.region() {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 2,
+        };
+        let mut setup = vec![delay, region];
+        setup.extend((state.len() as u32).to_le_bytes());
+        setup.extend(state);
+        stream.write_all(&setup)?;
+        Self::new(stream, Player::One, delay, rollback)
+    }
+
+    // joins the session of a host as player 2, taking over its state, region and delay
+    pub fn join(
+        mut stream: TcpStream,
+        emulator: &mut Emulator,
+        rom: &[u8],
+        rollback: u8,
+    ) -> Result<Self, NetplayError> {
+        handshake(&mut stream, rom)?;
+        let mut setup = [0; 6];
+        stream.read_exact(&mut setup)?;
+        let length = u32::from_le_bytes(setup[2..].try_into().expect("4 bytes")) as usize;
+        // both run the same rom, so the host's state can be no longer than this side's
+        if length > emulator.save_state().len() {
+            return Err(NetplayError::StateTooLarge(length));
+        }
+        let mut state = vec![0; length];
+        stream.read_exact(&mut state)?;
+        let region = emulator.region();
+        emulator.set_region(match setup[1] {
+            1 => Region::Pal,
+            2 => Region::Dendy,
+            _ => Region::Ntsc,
+        });
+        // a state that doesn't load ends the session before any input is sent
+        if let Err(error) = emulator.load_state(&state) {
+            emulator.set_region(region);
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(NetplayError::BadState(error));
+        }
+        Self::new(stream, Player::Two, setup[0], rollback)
+    }
+
+    fn new(
+        stream: TcpStream,
+        player: Player,
+        delay: u8,
+        rollback: u8,
+    ) -> Result<Self, NetplayError> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+        Ok(Netplay {
+            stream,
+            player,
+            delay: delay as u64,
+            rollback: rollback as u64,
+            frame: 0,
+            confirmed: 0,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            predictions: VecDeque::new(),
+            states: VecDeque::new(),
+            received: Vec::new(),
+            rollbacks: 0,
+        })
+    }
+
+    // the port this side's pad is plugged into
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay as u8
+    }
+
+    // frames run so far in the session
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // times a guess was wrong and frames were run again
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    // runs the next frame with the pad of player 1 in input as this side's, waiting for the peer
+    // when its input is further behind than the rollback window allows
+    pub fn step_frame(
+        &mut self,
+        emulator: &mut Emulator,
+        input: FrameInput,
+    ) -> Result<(), NetplayError> {
+        let pad = Pad {
+            buttons: input.buttons[0],
+            reset: input.reset || input.power,
+        };
+        let frame = self.frame + self.delay;
+        self.local.insert(frame, pad);
+        let mut message = [0; MESSAGE_SIZE];
+        message[..4].copy_from_slice(&(frame as u32).to_le_bytes());
+        message[4] = pad.buttons;
+        message[5] = if pad.reset { RESET_FLAG } else { 0 };
+        self.stream.write_all(&message)?;
+
+        self.receive(false)?;
+        self.confirm(emulator)?;
+        while self.remote_pad(self.frame).is_none() && self.frame - self.confirmed >= self.rollback
+        {
+            self.receive(true)?;
+            self.confirm(emulator)?;
+        }
+        self.run_frame(emulator, self.frame);
+        self.frame += 1;
+        Ok(())
+    }
+
+    // blocks until every frame run so far is confirmed, running any that were guessed wrong again
+    pub fn wait_for_peer(&mut self, emulator: &mut Emulator) -> Result<(), NetplayError> {
+        self.confirm(emulator)?;
+        while self.confirmed < self.frame {
+            self.receive(true)?;
+            self.confirm(emulator)?;
+        }
+        Ok(())
+    }
+
+    // the first frames have no input from either side
+    fn remote_pad(&self, frame: u64) -> Option<Pad> {
+        if frame < self.delay {
+            Some(Pad::default())
+        } else {
+            self.remote.get(&frame).copied()
+        }
+    }
+
+    fn local_pad(&self, frame: u64) -> Pad {
+        self.local.get(&frame).copied().unwrap_or_default()
+    }
+
+    // reads what has arrived, with wait blocks for at least one message or the timeout
+    fn receive(&mut self, wait: bool) -> Result<(), NetplayError> {
+        self.stream.set_nonblocking(!wait)?;
+        let mut buffer = [0; 256];
+        let result = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Err(NetplayError::Disconnected),
+                Ok(length) => {
+                    self.received.extend_from_slice(&buffer[..length]);
+                    if wait && self.received.len() >= MESSAGE_SIZE {
+                        break Ok(());
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock && !wait => break Ok(()),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => break Err(error.into()),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        result?;
+
+        let messages = self.received.len() / MESSAGE_SIZE;
+        for message in self.received[..messages * MESSAGE_SIZE].chunks(MESSAGE_SIZE) {
+            let frame = u32::from_le_bytes(message[..4].try_into().expect("4 bytes")) as u64;
+            let pad = Pad {
+                buttons: message[4],
+                reset: message[5] & RESET_FLAG != 0,
+            };
+            self.remote.insert(frame, pad);
+        }
+        self.received.drain(..messages * MESSAGE_SIZE);
+        Ok(())
+    }
+
+    // moves confirmed up over frames whose guess was right, and runs the rest again from the first
+    // wrong one
+    fn confirm(&mut self, emulator: &mut Emulator) -> Result<(), NetplayError> {
+        while self.confirmed < self.frame
+            && let Some(actual) = self.remote_pad(self.confirmed)
+        {
+            if self.predictions.front() == Some(&actual) {
+                self.predictions.pop_front();
+                self.states.pop_front();
+                self.confirmed += 1;
+                continue;
+            }
+            let state = self
+                .states
+                .pop_front()
+                .expect("a state for every guessed frame");
+            emulator.load_state(&state)?;
+            self.states.clear();
+            self.predictions.clear();
+            self.rollbacks += 1;
+            // the frames were seen and heard as they were guessed, so the hooks, the trace and the
+            // scripts are left out of running them again and the sound they make is dropped
+            let samples = emulator.apu.samples().len();
+            let hooks = std::mem::take(&mut emulator.hooks);
+            for frame in self.confirmed..self.frame {
+                self.run_frame(emulator, frame);
+            }
+            emulator.hooks = hooks;
+            emulator.apu.truncate_samples(samples);
+            break;
+        }
+        // the last pad heard of stays for guessing
+        let keep = self.confirmed.saturating_sub(1);
+        self.remote = self.remote.split_off(&keep);
+        self.local = self.local.split_off(&self.confirmed);
+        Ok(())
+    }
+
+    fn run_frame(&mut self, emulator: &mut Emulator, frame: u64) {
+        let local = self.local_pad(frame);
+        let remote = match self.remote_pad(frame) {
+            Some(remote) if frame == self.confirmed => {
+                self.confirmed += 1;
+                remote
+            }
+            known => {
+                let guess = known
+                    .or_else(|| self.remote.values().next_back().copied())
+                    .unwrap_or_default();
+                self.states.push_back(emulator.save_state());
+                self.predictions.push_back(guess);
+                guess
+            }
+        };
+        let (one, two) = if self.player == Player::One {
+            (local, remote)
+        } else {
+            (remote, local)
+        };
+        emulator.apply_input(FrameInput {
+            buttons: [one.buttons, two.buttons, 0, 0],
+            reset: one.reset || two.reset,
+            power: false,
+        });
+        emulator.step_frame();
+    }
+}
+
+// both sides say who they are and what they run
+fn handshake(stream: &mut TcpStream, rom: &[u8]) -> Result<(), NetplayError> {
+    stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+    let mut hello = MAGIC.to_vec();
+    hello.extend(PROTOCOL.to_le_bytes());
+    hello.extend(md5(rom));
+    stream.write_all(&hello)?;
+    let mut peer = [0; 22];
+    stream.read_exact(&mut peer)?;
+    if peer[..4] != MAGIC {
+        return Err(NetplayError::BadMagic);
+    }
+    let protocol = u16::from_le_bytes([peer[4], peer[5]]);
+    if protocol != PROTOCOL {
+        return Err(NetplayError::Protocol(protocol));
+    }
+    if peer[6..] != md5(rom) {
+        return Err(NetplayError::RomMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn join_refuses_a_state_longer_than_its_own() {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+        rom.resize(16 + 0x6000, 0);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // a host that says it sends a 4G state
+        let host = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handshake(&mut stream, b"rom").unwrap();
+            let mut setup = vec![DEFAULT_DELAY, 0];
+            setup.extend(u32::MAX.to_le_bytes());
+            stream.write_all(&setup).unwrap();
+            stream
+        });
+        let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+        let stream = TcpStream::connect(address).unwrap();
+        let error = Netplay::join(stream, &mut emulator, b"rom", 0).err();
+        assert!(matches!(
+            error,
+            Some(NetplayError::StateTooLarge(length)) if length == u32::MAX as usize
+        ));
+        host.join().unwrap();
+    }
+
+    #[test]
+    fn join_drops_a_host_whose_state_does_not_load() {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0];
+        rom.resize(16 + 0x6000, 0);
+        let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+        let before = emulator.save_state();
+        // the ppu clock ahead of the master clock, the pads and the open bus come after it
+        let mut state = before.clone();
+        let ppu_clock = state.len() - 16 - 8;
+        state[ppu_clock + 7] = 0x01;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            handshake(&mut stream, b"rom").unwrap();
+            let mut setup = vec![DEFAULT_DELAY, 1];
+            setup.extend((state.len() as u32).to_le_bytes());
+            setup.extend(state);
+            stream.write_all(&setup).unwrap();
+            // the joiner hangs up rather than sending input
+            stream.read(&mut [0; MESSAGE_SIZE]).unwrap()
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        let error = Netplay::join(stream, &mut emulator, b"rom", 0).err();
+        assert!(matches!(
+            error,
+            Some(NetplayError::BadState(StateError::Invalid("ppu clock")))
+        ));
+        assert_eq!(host.join().unwrap(), 0);
+        assert_eq!(emulator.region(), Region::Ntsc);
+        assert_eq!(emulator.save_state(), before);
+    }
+}
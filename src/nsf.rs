@@ -0,0 +1,297 @@
+// nsf music files: the sound code and data ripped from a game, with routines to start a song and to
+// play it on. they run on the cpu and apu alone, the play routine is called on a timer instead of
+// from the nmi, and nothing on the ppu is used
+use crate::Emulator;
+use crate::cartridge::Timing;
+use crate::clock::Region;
+use crate::crc::crc32;
+use crate::error::EmuError;
+use crate::mapper::NsfBoard;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const MAGIC: [u8; 5] = *b"NESM\x1A";
+const HEADER_SIZE: usize = 0x80;
+// where the routines return to: a JMP to itself that the board maps in the unused $4100-$4102
+pub const DRIVER: u16 = 0x4100;
+// a routine that hasn't returned after about a second is given up on
+const CALL_LIMIT: u64 = 2_000_000;
+
+// the bits of Nsf::expansion_audio, and the chips of them that are emulated
+pub const VRC6: u8 = 0x01;
+pub const VRC7: u8 = 0x02;
+pub const FDS: u8 = 0x04;
+pub const MMC5: u8 = 0x08;
+pub const NAMCO_163: u8 = 0x10;
+pub const SUNSOFT_5B: u8 = 0x20;
+pub const EMULATED_EXPANSIONS: u8 = VRC6 | FDS;
+
+#[derive(Debug)]
+pub enum NsfError {
+    TooShort(usize),
+    BadMagic,
+    NoSongs,
+}
+
+impl fmt::Display for NsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NsfError::TooShort(length) => write!(
+                f,
+                "file is {length} bytes, too short for a 128 byte nsf header"
+            ),
+            NsfError::BadMagic => write!(f, "missing \"NESM\\x1A\" signature"),
+            NsfError::NoSongs => write!(f, "header declares no songs"),
+        }
+    }
+}
+
+impl std::error::Error for NsfError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nsf {
+    pub version: u8,
+    pub songs: u8,
+    // songs are numbered from 1 like players show them
+    pub starting_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // microseconds between calls of the play routine
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // the 4K banks at $8000-$FFFF to start with, all zero when the music isn't bank switched
+    pub banks: [u8; 8],
+    // Ntsc, Pal or MultiRegion
+    pub timing: Timing,
+    // one bit each for the vrc6, vrc7, fds, mmc5, namco 163 and sunsoft 5b sound chips
+    pub expansion_audio: u8,
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, NsfError> {
+        if data.len() < HEADER_SIZE {
+            return Err(NsfError::TooShort(data.len()));
+        }
+        if data[..5] != MAGIC {
+            return Err(NsfError::BadMagic);
+        }
+        if data[0x06] == 0 {
+            return Err(NsfError::NoSongs);
+        }
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        // 32 byte fields padded with zeros
+        let text = |offset: usize| {
+            let field = &data[offset..offset + 32];
+            let length = field.iter().position(|&byte| byte == 0).unwrap_or(32);
+            String::from_utf8_lossy(&field[..length]).into_owned()
+        };
+        Ok(Nsf {
+            version: data[0x05],
+            songs: data[0x06],
+            starting_song: data[0x07].clamp(1, data[0x06]),
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            title: text(0x0E),
+            artist: text(0x2E),
+            copyright: text(0x4E),
+            ntsc_speed: word(0x6E),
+            banks: data[0x70..0x78].try_into().expect("8 bytes"),
+            pal_speed: word(0x78),
+            timing: match data[0x7A] & 0x03 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                _ => Timing::MultiRegion,
+            },
+            expansion_audio: data[0x7B],
+            data: data[HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    pub fn bankswitched(&self) -> bool {
+        self.banks.iter().any(|&bank| bank != 0)
+    }
+
+    // music for the disk system runs from its ram at $6000-$DFFF
+    pub fn fds(&self) -> bool {
+        self.expansion_audio & FDS != 0
+    }
+
+    // what $5FF8-$5FFF hold when a song starts, music that isn't bank switched sees its data in
+    // order from $8000, or from $6000 on the disk system
+    pub fn initial_banks(&self) -> [u8; 8] {
+        if self.bankswitched() {
+            self.banks
+        } else if self.fds() {
+            [2, 3, 4, 5, 6, 7, 8, 9]
+        } else {
+            [0, 1, 2, 3, 4, 5, 6, 7]
+        }
+    }
+
+    // what the disk system's $5FF6-$5FF7 for $6000-$7FFF hold when a song starts
+    pub fn initial_fds_banks(&self) -> [u8; 2] {
+        if self.bankswitched() {
+            [self.banks[6], self.banks[7]]
+        } else {
+            [0, 1]
+        }
+    }
+
+    // calls of the play routine per second
+    pub fn play_rate(&self, region: Region) -> f64 {
+        let speed = match region {
+            Region::Pal => self.pal_speed,
+            Region::Ntsc | Region::Dendy => self.ntsc_speed,
+        };
+        if speed == 0 {
+            region.frame_rate()
+        } else {
+            1_000_000.0 / speed as f64
+        }
+    }
+}
+
+// the song being played, host side like the region
+pub(crate) struct NsfPlayer {
+    nsf: Nsf,
+    song: u8,
+    // the cpu cycle the next play call is due on
+    next_play: f64,
+}
+
+impl NsfPlayer {
+    pub(crate) fn play_rate(&self, region: Region) -> f64 {
+        self.nsf.play_rate(region)
+    }
+}
+
+impl Emulator {
+    // an emulator playing the starting song of nsf, driven by step_nsf instead of step_frame
+    pub fn from_nsf(nsf: Nsf) -> Self {
+        let region = Region::from_timing(nsf.timing);
+        let rom_crc = crc32(&nsf.data);
+        let mut emulator = Self::with_mapper(Box::new(NsfBoard::new(&nsf)), region, rom_crc);
+        let song = nsf.starting_song;
+        emulator.nsf = Some(Box::new(NsfPlayer {
+            nsf,
+            song,
+            next_play: 0.0,
+        }));
+        emulator.play_song(song);
+        emulator
+    }
+
+    pub fn load_nsf(path: impl AsRef<Path>) -> Result<Self, EmuError> {
+        let nsf = Nsf::from_bytes(&fs::read(path)?)?;
+        Ok(Self::from_nsf(nsf))
+    }
+
+    pub fn nsf(&self) -> Option<&Nsf> {
+        self.nsf.as_ref().map(|player| &player.nsf)
+    }
+
+    // the song playing, counted from 1
+    pub fn song(&self) -> Option<u8> {
+        self.nsf.as_ref().map(|player| player.song)
+    }
+
+    // starts song over from a cleared console, songs past either end are clamped
+    pub fn play_song(&mut self, song: u8) {
+        let Some(player) = &mut self.nsf else {
+            return;
+        };
+        let song = song.clamp(1, player.nsf.songs);
+        player.song = song;
+        let (banks, init) = (player.nsf.initial_banks(), player.nsf.init_address);
+        let fds_banks = player.nsf.fds().then(|| player.nsf.initial_fds_banks());
+
+        self.ram.fill(0);
+        for address in 0x6000..=0x7FFF {
+            self.mapper.prg_write(address, 0);
+        }
+        if let Some(fds_banks) = fds_banks {
+            for (index, bank) in fds_banks.into_iter().enumerate() {
+                self.mapper.prg_write(0x5FF6 + index as u16, bank);
+            }
+        }
+        for (index, bank) in banks.into_iter().enumerate() {
+            self.mapper.prg_write(0x5FF8 + index as u16, bank);
+        }
+        // silence every channel and keep the frame counter irq off
+        for address in 0x4000..=0x4013 {
+            self.write_untimed(address, 0);
+        }
+        self.write_untimed(0x4015, 0);
+        self.write_untimed(0x4015, 0x0F);
+        self.write_untimed(0x4017, 0x40);
+
+        self.cpu.reg_a = song - 1;
+        self.cpu.reg_x = (self.region == Region::Pal) as u8;
+        self.cpu.reg_y = 0;
+        self.cpu.stack_pointer = 0xFF;
+        self.cpu.flags.interrupt_disable_flag = true;
+        self.cpu.halted = false;
+        self.call_routine(init);
+        let now = self.cycles() as f64;
+        if let Some(player) = &mut self.nsf {
+            player.next_play = now;
+        }
+    }
+
+    pub fn next_song(&mut self) {
+        if let Some(song) = self.song() {
+            self.play_song(song.saturating_add(1));
+        }
+    }
+
+    pub fn previous_song(&mut self) {
+        if let Some(song) = self.song() {
+            self.play_song(song.saturating_sub(1));
+        }
+    }
+
+    // calls the play routine and idles until the next call is due, returns the cpu cycles that took.
+    // the audio comes out through take_audio_samples as usual
+    pub fn step_nsf(&mut self) -> usize {
+        let Some(player) = &self.nsf else {
+            return 0;
+        };
+        let play = player.nsf.play_address;
+        let period = self.region.cpu_clock_rate() / player.play_rate(self.region);
+        let start = self.cycles();
+        self.call_routine(play);
+        let Some(player) = &mut self.nsf else {
+            return 0;
+        };
+        player.next_play += period;
+        let next_play = player.next_play;
+        while (self.cycles() as f64) < next_play && !self.cpu.halted {
+            self.step_instruction();
+        }
+        (self.cycles() - start) as usize
+    }
+
+    // runs the routine at address as if the idle loop had called it with JSR
+    fn call_routine(&mut self, address: u16) {
+        self.push_word(DRIVER.wrapping_sub(1));
+        self.cpu.program_counter = address;
+        let start = self.cycles();
+        while self.cpu.program_counter != DRIVER
+            && !self.cpu.halted
+            && self.cycles() - start < CALL_LIMIT
+        {
+            self.step_instruction();
+        }
+        if self.cpu.program_counter != DRIVER {
+            self.cpu.program_counter = DRIVER;
+            self.cpu.stack_pointer = 0xFF;
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// text and rectangles drawn over the picture by scripts and the frontend, in screen pixels.
+// shapes are kept until cleared, which the emulator does at the start of every frame
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// glyphs are 3x5 pixels, drawn with a pixel of space after them and a line of space below
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+
+// RGBA, with alpha blending the shape over the picture
+pub type Color = [u8; 4];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Shape {
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+        color: Color,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+        filled: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    shapes: Vec<Shape>,
+}
+
+impl Overlay {
+    // newlines start a new line below x, lowercase letters are drawn as capitals
+    pub fn text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        self.shapes.push(Shape::Text {
+            x,
+            y,
+            text: text.to_string(),
+            color,
+        });
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color, filled: bool) {
+        self.shapes.push(Shape::Rect {
+            x,
+            y,
+            width,
+            height,
+            color,
+            filled,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    // draws every shape in the order it was added onto a 256x240 RGBA8 frame
+    pub fn draw(&self, rgba: &mut [u8]) {
+        for shape in &self.shapes {
+            match shape {
+                Shape::Text { x, y, text, color } => {
+                    let (mut column, mut row) = (*x, *y);
+                    for letter in text.bytes() {
+                        if letter == b'\n' {
+                            column = *x;
+                            row += GLYPH_HEIGHT as i32;
+                            continue;
+                        }
+                        let bits = glyph(letter.to_ascii_uppercase());
+                        for index in 0..15 {
+                            if bits & (1 << (14 - index)) != 0 {
+                                blend(rgba, column + index % 3, row + index / 3, *color);
+                            }
+                        }
+                        column += GLYPH_WIDTH as i32;
+                    }
+                }
+                &Shape::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    color,
+                    filled,
+                } => {
+                    let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+                    for row in y.max(0)..=bottom.min(SCREEN_HEIGHT as i32 - 1) {
+                        for column in x.max(0)..=right.min(SCREEN_WIDTH as i32 - 1) {
+                            let edge = row == y || row == bottom || column == x || column == right;
+                            if filled || edge {
+                                blend(rgba, column, row, color);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// pixels off the screen are skipped
+fn blend(rgba: &mut [u8], x: i32, y: i32, color: Color) {
+    if !(0..SCREEN_WIDTH as i32).contains(&x) || !(0..SCREEN_HEIGHT as i32).contains(&y) {
+        return;
+    }
+    let pixel = &mut rgba[(y as usize * SCREEN_WIDTH + x as usize) * 4..][..3];
+    let alpha = color[3] as u16;
+    for (channel, &source) in pixel.iter_mut().zip(&color[..3]) {
+        *channel = ((source as u16 * alpha + *channel as u16 * (255 - alpha)) / 255) as u8;
+    }
+}
+
+// 5 rows of 3 pixels from the top left, the high bit first, unknown characters are a filled box
+fn glyph(letter: u8) -> u16 {
+    match letter {
+        b' ' => 0b000_000_000_000_000,
+        b'0' => 0b111_101_101_101_111,
+        b'1' => 0b010_110_010_010_111,
+        b'2' => 0b111_001_111_100_111,
+        b'3' => 0b111_001_111_001_111,
+        b'4' => 0b101_101_111_001_001,
+        b'5' => 0b111_100_111_001_111,
+        b'6' => 0b111_100_111_101_111,
+        b'7' => 0b111_001_001_001_001,
+        b'8' => 0b111_101_111_101_111,
+        b'9' => 0b111_101_111_001_111,
+        b'A' => 0b010_101_111_101_101,
+        b'B' => 0b110_101_110_101_110,
+        b'C' => 0b011_100_100_100_011,
+        b'D' => 0b110_101_101_101_110,
+        b'E' => 0b111_100_110_100_111,
+        b'F' => 0b111_100_110_100_100,
+        b'G' => 0b011_100_101_101_011,
+        b'H' => 0b101_101_111_101_101,
+        b'I' => 0b111_010_010_010_111,
+        b'J' => 0b001_001_001_101_010,
+        b'K' => 0b101_101_110_101_101,
+        b'L' => 0b100_100_100_100_111,
+        b'M' => 0b101_111_111_101_101,
+        b'N' => 0b110_101_101_101_101,
+        b'O' => 0b010_101_101_101_010,
+        b'P' => 0b110_101_110_100_100,
+        b'Q' => 0b010_101_101_110_011,
+        b'R' => 0b110_101_110_101_101,
+        b'S' => 0b011_100_010_001_110,
+        b'T' => 0b111_010_010_010_010,
+        b'U' => 0b101_101_101_101_111,
+        b'V' => 0b101_101_101_101_010,
+        b'W' => 0b101_101_111_111_101,
+        b'X' => 0b101_101_010_101_101,
+        b'Y' => 0b101_101_010_010_010,
+        b'Z' => 0b111_001_010_100_111,
+        b':' => 0b000_010_000_010_000,
+        b'.' => 0b000_000_000_000_010,
+        b',' => 0b000_000_000_010_100,
+        b'-' => 0b000_000_111_000_000,
+        b'+' => 0b000_010_111_010_000,
+        b'=' => 0b000_111_000_111_000,
+        b'/' => 0b001_001_010_100_100,
+        b'%' => 0b101_001_010_100_101,
+        b'!' => 0b010_010_010_000_010,
+        b'?' => 0b111_001_010_000_010,
+        b'(' => 0b001_010_010_010_001,
+        b')' => 0b100_010_010_010_100,
+        b'#' => 0b101_111_101_111_101,
+        b'_' => 0b000_000_000_000_111,
+        b'*' => 0b000_101_010_101_000,
+        b'\'' => 0b010_010_000_000_000,
+        b'<' => 0b001_010_100_010_001,
+        b'>' => 0b100_010_001_010_100,
+        b'[' => 0b011_010_010_010_011,
+        b']' => 0b110_010_010_010_110,
+        _ => 0b111_111_111_111_111,
+    }
+}
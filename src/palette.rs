@@ -0,0 +1,247 @@
+use crate::video;
+use std::f32::consts::PI;
+use std::fmt;
+
+// rgb approximation of the 2C02 output for each of the 64 colour indices
+pub const NTSC_PALETTE: [[u8; 3]; 64] = [
+    [84, 84, 84],
+    [0, 30, 116],
+    [8, 16, 144],
+    [48, 0, 136],
+    [68, 0, 100],
+    [92, 0, 48],
+    [84, 4, 0],
+    [60, 24, 0],
+    [32, 42, 0],
+    [8, 58, 0],
+    [0, 64, 0],
+    [0, 60, 0],
+    [0, 50, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [152, 150, 152],
+    [8, 76, 196],
+    [48, 50, 236],
+    [92, 30, 228],
+    [136, 20, 176],
+    [160, 20, 100],
+    [152, 34, 32],
+    [120, 60, 0],
+    [84, 90, 0],
+    [40, 114, 0],
+    [8, 124, 0],
+    [0, 118, 40],
+    [0, 102, 120],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [76, 154, 236],
+    [120, 124, 236],
+    [176, 98, 236],
+    [228, 84, 236],
+    [236, 88, 180],
+    [236, 106, 100],
+    [212, 136, 32],
+    [160, 170, 0],
+    [116, 196, 0],
+    [76, 208, 32],
+    [56, 204, 108],
+    [56, 180, 204],
+    [60, 60, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [168, 204, 236],
+    [188, 188, 236],
+    [212, 178, 236],
+    [236, 174, 236],
+    [236, 174, 212],
+    [236, 180, 176],
+    [228, 196, 144],
+    [204, 210, 120],
+    [180, 222, 120],
+    [168, 226, 144],
+    [152, 226, 180],
+    [160, 214, 228],
+    [160, 162, 160],
+    [0, 0, 0],
+    [0, 0, 0],
+];
+
+// converts a framebuffer entry (colour index plus emphasis bits 6-8) to rgb with the built in palette
+pub fn rgb(color: u16) -> [u8; 3] {
+    emphasize(NTSC_PALETTE[(color & 0x3F) as usize], (color >> 6) & 0x07)
+}
+
+// emphasis darkens the channels that are not emphasized by about a fifth
+fn emphasize([mut red, mut green, mut blue]: [u8; 3], emphasis: u16) -> [u8; 3] {
+    if emphasis != 0 {
+        let dim = |channel: u8| (channel as u16 * 13 / 16) as u8;
+        if emphasis & 0x01 == 0 {
+            red = dim(red);
+        }
+        if emphasis & 0x02 == 0 {
+            green = dim(green);
+        }
+        if emphasis & 0x04 == 0 {
+            blue = dim(blue);
+        }
+    }
+    [red, green, blue]
+}
+
+// every framebuffer entry, colour index plus emphasis bits
+pub const PALETTE_ENTRIES: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteError {
+    // .pal files hold 64 or 512 rgb triples
+    BadSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::BadSize(size) => {
+                write!(f, "a palette is 192 or 1536 bytes of rgb, not {size} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+// how a palette is decoded from the composite signal, the defaults match the ntsc filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscParameters {
+    // degrees the decoded hue is rotated by
+    pub hue: f32,
+    // scales the chroma, 0 is greyscale
+    pub saturation: f32,
+    // added to the luma, -1 to 1
+    pub brightness: f32,
+}
+
+impl Default for NtscParameters {
+    fn default() -> Self {
+        NtscParameters {
+            hue: 0.0,
+            saturation: 1.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+// the colour of every framebuffer entry, swapped at runtime to change how the picture is coloured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Box<[[u8; 3]; PALETTE_ENTRIES]>,
+}
+
+impl Default for Palette {
+    // NTSC_PALETTE, emphasized as rgb does it
+    fn default() -> Self {
+        let mut colors = Box::new([[0; 3]; PALETTE_ENTRIES]);
+        for (color, entry) in colors.iter_mut().enumerate() {
+            *entry = rgb(color as u16);
+        }
+        Palette { colors }
+    }
+}
+
+impl Palette {
+    // a .pal file: 64 colours, which get emphasis the way the built in palette does, or all 512
+    pub fn from_pal(data: &[u8]) -> Result<Self, PaletteError> {
+        let entries = match data.len() {
+            192 => 64,
+            1536 => PALETTE_ENTRIES,
+            size => return Err(PaletteError::BadSize(size)),
+        };
+        let mut colors = Box::new([[0; 3]; PALETTE_ENTRIES]);
+        for (color, entry) in colors.iter_mut().enumerate() {
+            let base = &data[(color % entries) * 3..][..3];
+            *entry = [base[0], base[1], base[2]];
+            if entries == 64 {
+                *entry = emphasize(*entry, (color >> 6) as u16);
+            }
+        }
+        Ok(Palette { colors })
+    }
+
+    // decodes each entry from the signal the ppu would output for it, averaged over a subcarrier period
+    pub fn generate(parameters: NtscParameters) -> Self {
+        let hue = parameters.hue.to_radians();
+        let mut colors = Box::new([[0; 3]; PALETTE_ENTRIES]);
+        for (color, entry) in colors.iter_mut().enumerate() {
+            let (mut luma, mut i, mut q) = (0.0, 0.0, 0.0);
+            for phase in 0..video::PHASES {
+                let level = video::composite_level(color as u16, phase);
+                let angle = PI * (phase as f32 + video::HUE_OFFSET) / 6.0 + hue;
+                luma += level;
+                i += level * angle.cos();
+                q += level * angle.sin();
+            }
+            let periods = video::PHASES as f32;
+            let luma = luma / periods + parameters.brightness;
+            // synchronous demodulation halves the amplitude
+            let i = i * 2.0 / periods * parameters.saturation;
+            let q = q * 2.0 / periods * parameters.saturation;
+            *entry = video::yiq_to_rgb(luma, i, q);
+        }
+        Palette { colors }
+    }
+
+    pub fn rgb(&self, color: u16) -> [u8; 3] {
+        self.colors[color as usize % PALETTE_ENTRIES]
+    }
+
+    // all 512 entries as a .pal file
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.colors.iter().flatten().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pal_files_of_64_colours_get_emphasis_and_512_are_taken_as_they_are() {
+        let built_in: Vec<u8> = NTSC_PALETTE.iter().flatten().copied().collect();
+        let palette = Palette::from_pal(&built_in).unwrap();
+        assert_eq!(palette, Palette::default());
+        // $16 with red emphasised keeps its red and dims the rest
+        let [red, green, blue] = NTSC_PALETTE[0x16];
+        assert_eq!(
+            palette.rgb(0x56),
+            [
+                red,
+                (green as u16 * 13 / 16) as u8,
+                (blue as u16 * 13 / 16) as u8
+            ]
+        );
+
+        let full: Vec<u8> = (0..PALETTE_ENTRIES * 3)
+            .map(|byte| (byte * 5) as u8)
+            .collect();
+        let palette = Palette::from_pal(&full).unwrap();
+        assert_eq!(palette.rgb(0x1C5), full[0x1C5 * 3..][..3]);
+        assert_eq!(palette.to_pal(), full);
+    }
+
+    #[test]
+    fn pal_files_of_other_sizes_are_refused() {
+        for size in [0, 3, 191, 193, 576, 1535, 1537] {
+            assert_eq!(
+                Palette::from_pal(&vec![0; size]),
+                Err(PaletteError::BadSize(size))
+            );
+        }
+        assert_eq!(
+            PaletteError::BadSize(100).to_string(),
+            "a palette is 192 or 1536 bytes of rgb, not 100 bytes"
+        );
+    }
+}
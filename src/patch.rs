@@ -0,0 +1,265 @@
+// ips and bps patches, applied to a rom file as it loads so hacks and translations can be played
+// from the original rom. both work on the whole file, ines header included. ips is a list of byte
+// runs to overwrite, bps describes the new file in terms of the old one and carries crc32s of the
+// rom it expects, the rom it makes and itself, which are all checked
+use crate::Emulator;
+use crate::crc::crc32;
+use crate::error::EmuError;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// the three crc32s at the end of a bps patch
+const BPS_FOOTER: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    // neither an ips nor a bps patch
+    UnknownFormat,
+    Truncated,
+    // the rom is not the one the patch was made for
+    SourceMismatch { expected: u32, actual: u32 },
+    TargetMismatch { expected: u32, actual: u32 },
+    // the patch itself is damaged
+    PatchMismatch { expected: u32, actual: u32 },
+    // a bps copy reaching outside the rom or what has been made so far
+    OutOfRange,
+    // a bps command making more than the size the patch declares
+    PastTarget,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an ips or bps patch"),
+            PatchError::Truncated => write!(f, "the patch is cut short"),
+            PatchError::SourceMismatch { expected, actual } => write!(
+                f,
+                "the patch is for a rom with crc32 {expected:08X}, this one has {actual:08X}"
+            ),
+            PatchError::TargetMismatch { expected, actual } => write!(
+                f,
+                "the patched rom should have crc32 {expected:08X} but has {actual:08X}"
+            ),
+            PatchError::PatchMismatch { expected, actual } => write!(
+                f,
+                "the patch should have crc32 {expected:08X} but has {actual:08X}, it is damaged"
+            ),
+            PatchError::OutOfRange => write!(f, "the patch copies from outside the rom"),
+            PatchError::PastTarget => write!(f, "the patch writes past the size it declares"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// the rom with patch applied, told apart by its signature
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(patch, rom)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(patch, rom)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+// records of a 3 byte offset and a 2 byte length followed by the bytes, a length of 0 is a run of
+// one byte repeated. after EOF may come a 3 byte length to cut the file down to
+pub fn apply_ips(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+    let mut output = rom.to_vec();
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_END {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let length = u16::from_be_bytes(reader.array()?) as usize;
+        let run = if length == 0 {
+            let count = u16::from_be_bytes(reader.array()?) as usize;
+            vec![reader.byte()?; count]
+        } else {
+            reader.bytes(length)?.to_vec()
+        };
+        if output.len() < offset + run.len() {
+            output.resize(offset + run.len(), 0);
+        }
+        output[offset..offset + run.len()].copy_from_slice(&run);
+    }
+    if let Ok(length) = reader.bytes(3) {
+        output.truncate(u32::from_be_bytes([0, length[0], length[1], length[2]]) as usize);
+    }
+    Ok(output)
+}
+
+// two sizes, metadata, then commands each copying a run from the
+// rom or the patch, or from elsewhere in either file by a relative offset
+pub fn apply_bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - BPS_FOOTER..];
+    let checksum = |index: usize| {
+        u32::from_le_bytes(
+            footer[index * 4..index * 4 + 4]
+                .try_into()
+                .expect("4 bytes"),
+        )
+    };
+    let (source_crc, target_crc, patch_crc) = (checksum(0), checksum(1), checksum(2));
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc {
+        return Err(PatchError::PatchMismatch {
+            expected: patch_crc,
+            actual,
+        });
+    }
+    let actual = crc32(rom);
+    if actual != source_crc {
+        return Err(PatchError::SourceMismatch {
+            expected: source_crc,
+            actual,
+        });
+    }
+
+    let mut reader = Reader::new(&patch[..patch.len() - BPS_FOOTER], BPS_MAGIC.len());
+    // the size of the rom is covered by its crc32, what it becomes bounds every command
+    reader.number()?;
+    let target_size = usize::try_from(reader.number()?).map_err(|_| PatchError::PastTarget)?;
+    let metadata_size = usize::try_from(reader.number()?).map_err(|_| PatchError::Truncated)?;
+    reader.bytes(metadata_size)?;
+    let mut output = Vec::new();
+    let (mut source_offset, mut target_offset) = (0usize, 0usize);
+    while !reader.done() {
+        let command = reader.number()?;
+        let length = usize::try_from(command >> 2)
+            .ok()
+            .and_then(|length| length.checked_add(1))
+            .filter(|&length| length <= target_size - output.len())
+            .ok_or(PatchError::PastTarget)?;
+        match command & 3 {
+            // source read, the rom at the same place
+            0 => {
+                let start = output.len();
+                let run = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::OutOfRange)?;
+                output.extend_from_slice(run);
+            }
+            // target read, bytes from the patch
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // source copy
+            2 => {
+                source_offset = relative(source_offset, reader.number()?)?;
+                let end = source_offset
+                    .checked_add(length)
+                    .ok_or(PatchError::OutOfRange)?;
+                let run = rom.get(source_offset..end).ok_or(PatchError::OutOfRange)?;
+                output.extend_from_slice(run);
+                source_offset += length;
+            }
+            // target copy, byte by byte since the run may overlap what it makes
+            _ => {
+                target_offset = relative(target_offset, reader.number()?)?;
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or(PatchError::OutOfRange)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    let actual = crc32(&output);
+    if actual != target_crc {
+        return Err(PatchError::TargetMismatch {
+            expected: target_crc,
+            actual,
+        });
+    }
+    Ok(output)
+}
+
+// the low bit of a copy's offset is its sign
+fn relative(offset: usize, data: u64) -> Result<usize, PatchError> {
+    let distance = usize::try_from(data >> 1).map_err(|_| PatchError::OutOfRange)?;
+    if data & 1 != 0 {
+        offset.checked_sub(distance)
+    } else {
+        offset.checked_add(distance)
+    }
+    .ok_or(PatchError::OutOfRange)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Reader { data, position }
+    }
+
+    fn done(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .ok_or(PatchError::Truncated)?;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or(PatchError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PatchError> {
+        Ok(self.bytes(N)?.try_into().expect("N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // 7 bits a byte with the top bit ending it, each further byte counting from where the ones
+    // before leave off so every number has one encoding
+    fn number(&mut self) -> Result<u64, PatchError> {
+        let (mut number, mut shift) = (0u64, 1u64);
+        loop {
+            let byte = self.byte()?;
+            number = number.wrapping_add((byte & 0x7F) as u64 * shift);
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::Truncated)?;
+            number = number.wrapping_add(shift);
+        }
+    }
+}
+
+// the rom file at rom with the patch file at patch applied
+pub fn patch_rom(rom: impl AsRef<Path>, patch: impl AsRef<Path>) -> Result<Vec<u8>, EmuError> {
+    let rom = fs::read(rom)?;
+    Ok(apply(&fs::read(patch)?, &rom)?)
+}
+
+impl Emulator {
+    // like load_rom, with the battery ram of the hack kept apart from the original's next to the patch
+    pub fn load_patched_rom(
+        rom: impl AsRef<Path>,
+        patch: impl AsRef<Path>,
+    ) -> Result<Self, EmuError> {
+        let patch = patch.as_ref();
+        let mut emulator = Self::from_rom_bytes(&patch_rom(rom, patch)?)?;
+        emulator.set_save_file(patch.with_extension("sav"))?;
+        Ok(emulator)
+    }
+}
@@ -0,0 +1,150 @@
+// the reset button, turning the console off and on again and swapping the cartridge while it runs,
+// so a frontend can go from game to game without making a new emulator and with it a new window
+// and audio stream. what ram holds at power on differs between consoles, and some games read it
+// before writing it, so the pattern it starts with can be picked
+use crate::Emulator;
+use crate::battery;
+use crate::cartridge::Cartridge;
+use crate::clock::Region;
+use crate::cpu::RESET_VECTOR;
+use crate::error::EmuError;
+use crate::mapper;
+use crate::symbols::Symbols;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamInit {
+    // every byte the same
+    Fill(u8),
+    // runs of 4 bytes of $00 and 4 of $FF, close to what many consoles power up with
+    Alternating,
+    // noise from a seed, the same seed gives the same ram so movies still play back
+    Random(u64),
+}
+
+impl Default for RamInit {
+    fn default() -> Self {
+        RamInit::Fill(0xFF)
+    }
+}
+
+impl RamInit {
+    pub fn fill(self, ram: &mut [u8]) {
+        match self {
+            RamInit::Fill(value) => ram.fill(value),
+            RamInit::Alternating => {
+                for (index, byte) in ram.iter_mut().enumerate() {
+                    *byte = if index & 4 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            RamInit::Random(seed) => {
+                // splitmix64, 8 bytes a step
+                let mut state = seed;
+                for chunk in ram.chunks_mut(8) {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut value = state;
+                    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    value ^= value >> 31;
+                    chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+impl Emulator {
+    // the reset button: ram, the cartridge and the ppu's memories are kept. the reset sequence runs
+    // the stack pushes of an interrupt as reads, so only SP moves, and takes 7 cycles like one
+    pub fn soft_reset(&mut self) {
+        self.ppu.reset();
+        self.apu.write_register(0x4015, 0);
+        self.read(self.cpu.program_counter);
+        self.read(self.cpu.program_counter);
+        for _ in 0..3 {
+            self.stack_read();
+            self.cpu.stack_pointer = self.cpu.stack_pointer.wrapping_sub(1);
+        }
+        self.cpu.flags.interrupt_disable_flag = true;
+        self.cpu.poll_interrupt_disable = true;
+        self.cpu.program_counter = self.read_word(RESET_VECTOR);
+        self.cpu.halted = false;
+    }
+
+    // off and on again: everything is as it was when the rom was loaded but the battery ram, and
+    // ram starts out in the pattern of set_ram_init. the host side settings stay as they are
+    pub fn power_cycle(&mut self) {
+        let battery_ram = self.mapper.battery_ram().map(<[u8]>::to_vec);
+        let power_on = std::mem::take(&mut self.power_on);
+        self.load_state(&power_on)
+            .expect("the power on state is our own");
+        self.power_on = power_on;
+        if let (Some(saved), Some(ram)) = (battery_ram, self.mapper.battery_ram_mut()) {
+            ram.copy_from_slice(&saved);
+        }
+        self.ram_init.fill(&mut self.ram);
+        self.mid_frame = false;
+        self.power_cycles += 1;
+        self.soft_reset();
+        if let Some(song) = self.song() {
+            self.play_song(song);
+        }
+    }
+
+    pub fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    // takes effect from the next power_cycle or load_new_rom on
+    pub fn set_ram_init(&mut self, init: RamInit) {
+        self.ram_init = init;
+    }
+
+    // swaps in another cartridge and powers on, keeping the video, audio, input and hook setup.
+    // the battery ram of the game before is written out, and the cheats, frozen addresses and
+    // labels and analysis that were for it are dropped. on error the game before keeps running
+    pub fn load_new_rom_bytes(&mut self, rom: &[u8]) -> Result<(), EmuError> {
+        let cartridge = Cartridge::from_bytes(rom)?;
+        let region = Region::from_timing(cartridge.header.timing);
+        let rom_crc = cartridge.crc32();
+        let mapper = mapper::from_cartridge(cartridge)?;
+        self.flush_save_file()?;
+        self.save_file = None;
+        self.flushed_battery_ram.clear();
+        // back to power on with the old cartridge, so the new one goes into a console fresh from it
+        let power_on = std::mem::take(&mut self.power_on);
+        self.load_state(&power_on)
+            .expect("the power on state is our own");
+        self.mapper = mapper;
+        self.rom_crc = rom_crc;
+        self.nsf = None;
+        self.cheats.clear();
+        self.frozen.clear();
+        self.symbols = Symbols::new();
+        self.analysis = None;
+        self.set_region(region);
+        self.take_power_on_state();
+        self.power_cycle();
+        Ok(())
+    }
+
+    // like load_new_rom_bytes, with the .sav next to the rom like load_rom. the save is read
+    // before the swap, so one that can't be read also leaves the game before running
+    pub fn load_new_rom(&mut self, path: impl AsRef<Path>) -> Result<(), EmuError> {
+        let path = path.as_ref();
+        let rom = fs::read(path)?;
+        let save_file = path.with_extension("sav");
+        let save = battery::read_save_file(&save_file)?;
+        self.load_new_rom_bytes(&rom)?;
+        self.attach_save_file(save_file, save);
+        Ok(())
+    }
+
+    // what power_cycle goes back to, taken before the first reset
+    pub(crate) fn take_power_on_state(&mut self) {
+        let mut state = std::mem::take(&mut self.power_on);
+        self.save_state_into(&mut state);
+        self.power_on = state;
+    }
+}
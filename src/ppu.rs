@@ -0,0 +1,834 @@
+use crate::clock::Region;
+use crate::mapper::{Fetch, Mapper};
+use crate::savestate::{StateError, ensure, savestate_fields};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+// sprites the ppu shows on one scanline, the rest are dropped unless the limit is off
+pub const SPRITES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+    // the page of console vram each of the four nametables shows
+    Custom([u8; 4]),
+}
+
+// a sprite selected for the next scanline, pattern bits already flipped horizontally
+#[derive(Debug, Clone, Copy, Default)]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    is_sprite_zero: bool,
+}
+
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_address: u8,
+    oam: [u8; 256],
+    // loopy registers: current vram address, temporary address, fine x scroll and the shared write toggle
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    write_toggle: bool,
+    read_buffer: u8,
+    io_latch: u8,
+    // 2K of console vram, four screen cartridges add the other 2K
+    vram: [u8; 0x1000],
+    palette: [u8; 32],
+    scanline: u16,
+    dot: u16,
+    odd_frame: bool,
+    // frames that have reached vblank since power on
+    frame: u64,
+    pub(crate) frame_complete: bool,
+    region: Region,
+    // the /NMI output is vblank && PPUCTRL bit 7, the cpu only sees its rising edge
+    nmi_output: bool,
+    nmi_edge: bool,
+
+    next_tile_id: u8,
+    next_tile_attribute: u8,
+    next_tile_low: u8,
+    next_tile_high: u8,
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+
+    // the copies of the oam entries evaluation picked for the next line, $FF where none was found,
+    // whether the first one evaluated is among them, the dot the overflow flag goes up on, if it
+    // does, and the sprite after the last one picked
+    secondary_oam: [u8; 32],
+    secondary_count: usize,
+    sprite_zero_next: bool,
+    overflow_dot: Option<u16>,
+    next_sprite: usize,
+
+    line_sprites: [LineSprite; 64],
+    line_sprite_count: usize,
+    // more than 8 sprites a line are drawn when off, host side rather than state
+    sprite_limit: bool,
+
+    // one NES colour index per pixel, bits 6-8 hold the colour emphasis bits of PPUMASK
+    frame_buffer: Box<[u16]>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_address: 0,
+            oam: [0; 256],
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            write_toggle: false,
+            read_buffer: 0,
+            io_latch: 0,
+            vram: [0; 0x1000],
+            palette: [0; 32],
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            frame: 0,
+            frame_complete: false,
+            region: Region::Ntsc,
+            nmi_output: false,
+            nmi_edge: false,
+            next_tile_id: 0,
+            next_tile_attribute: 0,
+            next_tile_low: 0,
+            next_tile_high: 0,
+            pattern_shift_low: 0,
+            pattern_shift_high: 0,
+            attribute_shift_low: 0,
+            attribute_shift_high: 0,
+            secondary_oam: [0xFF; 32],
+            secondary_count: 0,
+            sprite_zero_next: false,
+            overflow_dot: None,
+            next_sprite: 0,
+            line_sprites: [LineSprite::default(); 64],
+            line_sprite_count: 0,
+            sprite_limit: true,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    pub fn frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    // the scanline and dot about to be rendered, scanline 261 is the pre-render line
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    // vram as seen from the ppu bus without going through $2007, palette entries unaffected by greyscale
+    pub fn peek_vram(&self, mapper: &mut dyn Mapper, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+        if address >= 0x3F00 {
+            self.palette[Self::palette_index(address)]
+        } else {
+            self.read_vram(mapper, address)
+        }
+    }
+
+    pub fn poke_vram(&mut self, mapper: &mut dyn Mapper, address: u16, value: u8) {
+        self.write_vram(mapper, address, value);
+    }
+
+    pub fn ctrl(&self) -> u8 {
+        self.ctrl
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    // the scroll the next frame starts at, in pixels into the 512x480 nametable area
+    pub fn scroll(&self) -> (usize, usize) {
+        let x = (self.t & 0x1F) << 3 | self.fine_x as u16 | (self.t & 0x0400) >> 2;
+        let y = ((self.t & 0x03E0) >> 2 | (self.t & 0x7000) >> 12) as usize;
+        // nametables are 240 lines high
+        let y = y + if self.t & 0x0800 != 0 {
+            SCREEN_HEIGHT
+        } else {
+            0
+        };
+        (x as usize, y)
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    pub fn oam_mut(&mut self) -> &mut [u8; 256] {
+        &mut self.oam
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn sprite_limit(&self) -> bool {
+        self.sprite_limit
+    }
+
+    // off draws every sprite on a line, which stops the flicker of games cycling through sprites
+    // but also shows ones a game hides behind the limit. the overflow flag works as before
+    pub fn set_sprite_limit(&mut self, limit: bool) {
+        self.sprite_limit = limit;
+    }
+
+    pub fn odd_frame(&self) -> bool {
+        self.odd_frame
+    }
+
+    // true once for every rising edge of the nmi output
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_edge)
+    }
+
+    fn update_nmi_output(&mut self) {
+        let output = self.status & 0x80 != 0 && self.ctrl & 0x80 != 0;
+        if output && !self.nmi_output {
+            self.nmi_edge = true;
+        }
+        self.nmi_output = output;
+    }
+
+    // register is the cpu address already reduced to 0..=7
+    pub fn read_register(&mut self, mapper: &mut dyn Mapper, register: u16) -> u8 {
+        match register {
+            2 => {
+                // the low bits are whatever was last driven on the ppu data bus
+                let value = (self.status & 0xE0) | (self.io_latch & 0x1F);
+                self.status &= !0x80;
+                self.update_nmi_output();
+                self.write_toggle = false;
+                self.io_latch = value;
+            }
+            4 => {
+                // secondary oam is being cleared, and reads see the $FF written to it
+                let clearing =
+                    self.rendering_enabled() && self.scanline < 240 && (1..=64).contains(&self.dot);
+                self.io_latch = if clearing {
+                    0xFF
+                } else {
+                    self.oam[self.oam_address as usize]
+                };
+            }
+            7 => {
+                let address = self.v & 0x3FFF;
+                if address >= 0x3F00 {
+                    // palette reads are immediate, the buffer gets the nametable byte underneath
+                    self.io_latch = (self.read_palette(address) & 0x3F) | (self.io_latch & 0xC0);
+                    self.read_buffer = self.read_vram(mapper, address - 0x1000);
+                } else {
+                    self.io_latch = self.read_buffer;
+                    self.read_buffer = self.read_vram(mapper, address);
+                }
+                self.increment_vram_address();
+            }
+            _ => {}
+        }
+        self.io_latch
+    }
+
+    // what the reset line clears: PPUCTRL, PPUMASK, the scroll, the write toggle and the read
+    // buffer. vram, oam, the palette and the address in v are kept
+    pub(crate) fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.t = 0;
+        self.fine_x = 0;
+        self.write_toggle = false;
+        self.read_buffer = 0;
+        self.update_nmi_output();
+    }
+
+    pub fn write_register(&mut self, mapper: &mut dyn Mapper, register: u16, value: u8) {
+        self.io_latch = value;
+        match register {
+            0 => {
+                self.ctrl = value;
+                self.t = (self.t & 0xF3FF) | ((value as u16 & 0x03) << 10);
+                // enabling nmi during vblank triggers one straight away
+                self.update_nmi_output();
+            }
+            1 => self.mask = value,
+            3 => self.oam_address = value,
+            4 => {
+                // bits 2-4 of the attribute byte do not exist
+                self.oam[self.oam_address as usize] = if self.oam_address & 3 == 2 {
+                    value & 0xE3
+                } else {
+                    value
+                };
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            5 => {
+                if !self.write_toggle {
+                    self.t = (self.t & !0x001F) | (value as u16 >> 3);
+                    self.fine_x = value & 0x07;
+                } else {
+                    self.t = (self.t & 0x8C1F)
+                        | ((value as u16 & 0x07) << 12)
+                        | ((value as u16 >> 3) << 5);
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            6 => {
+                if !self.write_toggle {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            7 => {
+                self.write_vram(mapper, self.v & 0x3FFF, value);
+                self.increment_vram_address();
+            }
+            _ => {}
+        }
+    }
+
+    fn increment_vram_address(&mut self) {
+        let step = if self.ctrl & 0x04 != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn nametable_index(mirroring: Mirroring, address: u16) -> usize {
+        let offset = (address & 0x0FFF) as usize;
+        let table = offset / 0x400;
+        let physical_table = match mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+            Mirroring::Custom(pages) => pages[table] as usize & 0x03,
+        };
+        physical_table * 0x400 + offset % 0x400
+    }
+
+    fn palette_index(address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+        // the backdrop entries of the sprite palettes mirror the background ones
+        if index & 0x13 == 0x10 {
+            index & !0x10
+        } else {
+            index
+        }
+    }
+
+    fn read_palette(&self, address: u16) -> u8 {
+        let value = self.palette[Self::palette_index(address)];
+        if self.mask & 0x01 != 0 {
+            value & 0x30
+        } else {
+            value
+        }
+    }
+
+    fn read_vram(&self, mapper: &mut dyn Mapper, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+        if address < 0x2000 {
+            mapper.chr_read(address)
+        } else if address < 0x3F00 {
+            match mapper.nametable_read(address) {
+                Some(value) => value,
+                None => self.vram[Self::nametable_index(mapper.mirroring(), address)],
+            }
+        } else {
+            self.read_palette(address)
+        }
+    }
+
+    fn write_vram(&mut self, mapper: &mut dyn Mapper, address: u16, value: u8) {
+        let address = address & 0x3FFF;
+        if address < 0x2000 {
+            mapper.chr_write(address, value);
+        } else if address < 0x3F00 {
+            if !mapper.nametable_write(address, value) {
+                self.vram[Self::nametable_index(mapper.mirroring(), address)] = value;
+            }
+        } else {
+            self.palette[Self::palette_index(address)] = value & 0x3F;
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask & 0x18 != 0
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & 0x20 != 0 { 16 } else { 8 }
+    }
+
+    fn increment_scroll_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_scroll_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // rows 30 and 31 hold attribute data, scrolling into them wraps without switching tables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal_bits(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical_bits(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    fn load_background_shifters(&mut self) {
+        self.pattern_shift_low = (self.pattern_shift_low & 0xFF00) | self.next_tile_low as u16;
+        self.pattern_shift_high = (self.pattern_shift_high & 0xFF00) | self.next_tile_high as u16;
+        let low_fill = if self.next_tile_attribute & 0x01 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        let high_fill = if self.next_tile_attribute & 0x02 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        self.attribute_shift_low = (self.attribute_shift_low & 0xFF00) | low_fill;
+        self.attribute_shift_high = (self.attribute_shift_high & 0xFF00) | high_fill;
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_shift_low <<= 1;
+        self.pattern_shift_high <<= 1;
+        self.attribute_shift_low <<= 1;
+        self.attribute_shift_high <<= 1;
+    }
+
+    fn fetch_background(&mut self, mapper: &mut dyn Mapper) {
+        let background_table = if self.ctrl & 0x10 != 0 { 0x1000 } else { 0 };
+        let fine_y = (self.v >> 12) & 0x07;
+        match (self.dot - 1) % 8 {
+            0 => {
+                self.load_background_shifters();
+                self.next_tile_id = self.read_vram(mapper, 0x2000 | (self.v & 0x0FFF));
+            }
+            2 => {
+                let address =
+                    0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+                let mut attribute = self.read_vram(mapper, address);
+                // pick the 2 bit quadrant of the 32x32 pixel attribute area
+                if self.v & 0x0040 != 0 {
+                    attribute >>= 4;
+                }
+                if self.v & 0x0002 != 0 {
+                    attribute >>= 2;
+                }
+                self.next_tile_attribute = attribute & 0x03;
+            }
+            4 => {
+                let address = background_table + self.next_tile_id as u16 * 16 + fine_y;
+                self.next_tile_low = self.read_vram(mapper, address);
+            }
+            6 => {
+                let address = background_table + self.next_tile_id as u16 * 16 + fine_y + 8;
+                self.next_tile_high = self.read_vram(mapper, address);
+            }
+            7 => self.increment_scroll_x(),
+            _ => {}
+        }
+    }
+
+    fn in_range(&self, y: u8) -> bool {
+        self.scanline.wrapping_sub(y as u16) < self.sprite_height()
+    }
+
+    // evaluation proper runs over dots 65-256, done here in one go at dot 65: it starts at the sprite
+    // oamaddr points to, reads one byte of oam for each sprite out of range and all four for each in
+    // range, two dots a byte, copying the first 8 in range to secondary oam. after that the ppu goes
+    // on looking for a ninth but also steps through the bytes of each entry as it moves to the
+    // next, so it takes tiles, attributes and x positions for y, and the overflow flag is set or
+    // missed by accident
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam = [0xFF; 32];
+        self.secondary_count = 0;
+        self.sprite_zero_next = false;
+        self.overflow_dot = None;
+        let start = self.oam_address as usize / 4;
+        let mut dot = 65;
+        let mut n = start;
+        while n < 64 && self.secondary_count < SPRITES_PER_LINE {
+            let entry = &self.oam[n * 4..n * 4 + 4];
+            if self.in_range(entry[0]) {
+                let slot = self.secondary_count * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(entry);
+                self.secondary_count += 1;
+                self.sprite_zero_next |= n == start;
+                dot += 8;
+            } else {
+                dot += 2;
+            }
+            n += 1;
+        }
+        self.next_sprite = n;
+        let mut m = 0;
+        while n < 64 {
+            if self.in_range(self.oam[n * 4 + m]) {
+                self.overflow_dot = Some(dot);
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+            dot += 2;
+        }
+    }
+
+    // the pattern rows of the sprites in secondary oam, and with the limit off of every other
+    // sprite on the line
+    fn fetch_sprites(&mut self, mapper: &mut dyn Mapper) {
+        self.line_sprite_count = 0;
+        if self.scanline >= 240 {
+            return;
+        }
+        for slot in 0..self.secondary_count {
+            let entry: [u8; 4] = self.secondary_oam[slot * 4..slot * 4 + 4]
+                .try_into()
+                .expect("4 bytes");
+            self.fetch_sprite(mapper, entry, slot == 0 && self.sprite_zero_next);
+        }
+        if !self.sprite_limit {
+            for n in self.next_sprite..64 {
+                let entry: [u8; 4] = self.oam[n * 4..n * 4 + 4].try_into().expect("4 bytes");
+                if self.in_range(entry[0]) {
+                    self.fetch_sprite(mapper, entry, false);
+                }
+            }
+        }
+    }
+
+    fn fetch_sprite(&mut self, mapper: &mut dyn Mapper, entry: [u8; 4], is_sprite_zero: bool) {
+        let [y, tile, attributes, x] = entry;
+        let height = self.sprite_height();
+        // only the low bits count, for a sprite height changed since evaluation
+        let row = self.scanline.wrapping_sub(y as u16) & (height - 1);
+        let row = if attributes & 0x80 != 0 {
+            height - 1 - row
+        } else {
+            row
+        };
+        let address = if height == 16 {
+            let table = (tile as u16 & 0x01) * 0x1000;
+            let tile = (tile as u16 & 0xFE) + row / 8;
+            table + tile * 16 + row % 8
+        } else {
+            let table = if self.ctrl & 0x08 != 0 { 0x1000 } else { 0 };
+            table + tile as u16 * 16 + row
+        };
+        let mut pattern_low = self.read_vram(mapper, address);
+        let mut pattern_high = self.read_vram(mapper, address + 8);
+        if attributes & 0x40 == 0 {
+            pattern_low = pattern_low.reverse_bits();
+            pattern_high = pattern_high.reverse_bits();
+        }
+        self.line_sprites[self.line_sprite_count] = LineSprite {
+            x,
+            attributes,
+            pattern_low,
+            pattern_high,
+            is_sprite_zero,
+        };
+        self.line_sprite_count += 1;
+    }
+
+    fn render_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+        let y = self.scanline as usize;
+
+        let mut background_pixel = 0;
+        let mut background_palette = 0;
+        if self.mask & 0x08 != 0 && (x >= 8 || self.mask & 0x02 != 0) {
+            let bit = 0x8000 >> self.fine_x;
+            background_pixel = ((self.pattern_shift_high & bit != 0) as u8) << 1
+                | (self.pattern_shift_low & bit != 0) as u8;
+            background_palette = ((self.attribute_shift_high & bit != 0) as u8) << 1
+                | (self.attribute_shift_low & bit != 0) as u8;
+        }
+
+        let mut sprite_pixel = 0;
+        let mut sprite_palette = 0;
+        let mut sprite_behind = false;
+        let mut sprite_zero = false;
+        if self.mask & 0x10 != 0 && (x >= 8 || self.mask & 0x04 != 0) {
+            for sprite in &self.line_sprites[..self.line_sprite_count] {
+                let offset = x.wrapping_sub(sprite.x as usize);
+                if offset >= 8 {
+                    continue;
+                }
+                let pixel = ((sprite.pattern_high >> offset) & 0x01) << 1
+                    | (sprite.pattern_low >> offset) & 0x01;
+                if pixel == 0 {
+                    continue;
+                }
+                sprite_pixel = pixel;
+                sprite_palette = (sprite.attributes & 0x03) + 4;
+                sprite_behind = sprite.attributes & 0x20 != 0;
+                sprite_zero = sprite.is_sprite_zero;
+                break;
+            }
+        }
+
+        let (pixel, palette) = match (background_pixel, sprite_pixel) {
+            (0, 0) => (0, 0),
+            (0, _) => (sprite_pixel, sprite_palette),
+            (_, 0) => (background_pixel, background_palette),
+            _ => {
+                if sprite_zero && x != 255 {
+                    self.status |= 0x40;
+                }
+                if sprite_behind {
+                    (background_pixel, background_palette)
+                } else {
+                    (sprite_pixel, sprite_palette)
+                }
+            }
+        };
+
+        let color = self.read_palette(0x3F00 + (palette as u16) * 4 + pixel as u16);
+        let mut emphasis = (self.mask as u16 >> 5) << 6;
+        // the pal ppu swaps the red and green emphasis bits
+        if self.region != Region::Ntsc {
+            emphasis = (emphasis & 0x100) | (emphasis & 0x40) << 1 | (emphasis & 0x80) >> 1;
+        }
+        self.frame_buffer[y * SCREEN_WIDTH + x] = color as u16 | emphasis;
+    }
+
+    // dot at which the pattern fetches first switch to the $1000 table, which is what MMC3 counts
+    fn a12_rise_dot(&self) -> Option<u16> {
+        let background_high = self.ctrl & 0x10 != 0;
+        let sprites_high = self.ctrl & 0x08 != 0 || self.sprite_height() == 16;
+        match (background_high, sprites_high) {
+            (false, true) => Some(260),
+            (true, false) => Some(324),
+            _ => None,
+        }
+    }
+
+    pub fn step(&mut self, mapper: &mut dyn Mapper) {
+        self.run(mapper, 1);
+    }
+
+    // runs several dots in one call, which keeps the position in registers between them
+    pub fn run(&mut self, mapper: &mut dyn Mapper, dots: u32) {
+        for _ in 0..dots {
+            self.step_dot(mapper);
+        }
+    }
+
+    fn step_dot(&mut self, mapper: &mut dyn Mapper) {
+        let visible_line = self.scanline < 240;
+        let pre_render_line = self.scanline == self.region.scanlines() - 1;
+
+        if pre_render_line && self.dot == 1 {
+            self.status &= !0xE0;
+            self.update_nmi_output();
+        }
+
+        if (visible_line || pre_render_line) && self.dot == 1 {
+            mapper.ppu_line(self.rendering_enabled().then_some(self.scanline));
+        }
+
+        if (visible_line || pre_render_line) && self.rendering_enabled() {
+            // the first background fetch of the next line is on this dot
+            if self.dot == 321 {
+                mapper.ppu_fetch(Fetch::Background, self.sprite_height() == 16);
+            }
+            if (2..=257).contains(&self.dot) || (321..=337).contains(&self.dot) {
+                self.shift_background();
+                self.fetch_background(mapper);
+            }
+            if self.dot == 256 {
+                self.increment_scroll_y();
+            }
+            if visible_line && self.dot == 65 {
+                self.evaluate_sprites();
+            }
+            if visible_line && self.overflow_dot == Some(self.dot) {
+                self.status |= 0x20;
+            }
+            if self.dot == 257 {
+                self.load_background_shifters();
+                self.copy_horizontal_bits();
+                mapper.ppu_fetch(Fetch::Sprites, self.sprite_height() == 16);
+                self.fetch_sprites(mapper);
+            }
+            // oamaddr is cleared while the sprite tiles are fetched
+            if (257..=320).contains(&self.dot) {
+                self.oam_address = 0;
+            }
+            if self.a12_rise_dot() == Some(self.dot) {
+                mapper.scanline();
+            }
+            if pre_render_line && (280..=304).contains(&self.dot) {
+                self.copy_vertical_bits();
+            }
+        }
+
+        // nothing is evaluated with rendering off, and nothing is left over for the fetches either
+        if visible_line && self.dot == 65 && !self.rendering_enabled() {
+            self.secondary_count = 0;
+            self.overflow_dot = None;
+        }
+
+        if visible_line && (1..=256).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.dot == 1 {
+            mapper.ppu_line(None);
+            self.status |= 0x80;
+            self.update_nmi_output();
+            self.frame += 1;
+            self.frame_complete = true;
+        }
+
+        self.dot += 1;
+        // odd frames skip the last dot of the pre-render line while rendering, on ntsc only
+        if pre_render_line
+            && self.dot == 340
+            && self.odd_frame
+            && self.rendering_enabled()
+            && self.region == Region::Ntsc
+        {
+            self.dot = 341;
+        }
+        if self.dot > 340 {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.region.scanlines() {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+}
+
+savestate_fields!(LineSprite {
+    x,
+    attributes,
+    pattern_low,
+    pattern_high,
+    is_sprite_zero
+});
+// the frame buffer is output, not state, and is redrawn by the next frame
+savestate_fields!(Ppu {
+    ctrl,
+    mask,
+    status,
+    oam_address,
+    oam,
+    v,
+    t,
+    fine_x,
+    write_toggle,
+    read_buffer,
+    io_latch,
+    vram,
+    palette,
+    scanline,
+    dot,
+    odd_frame,
+    frame,
+    frame_complete,
+    nmi_output,
+    nmi_edge,
+    next_tile_id,
+    next_tile_attribute,
+    next_tile_low,
+    next_tile_high,
+    pattern_shift_low,
+    pattern_shift_high,
+    attribute_shift_low,
+    attribute_shift_high,
+    secondary_oam,
+    secondary_count,
+    sprite_zero_next,
+    overflow_dot,
+    next_sprite,
+    line_sprites,
+    line_sprite_count,
+} checked);
+
+impl Ppu {
+    // where the ppu is and the counts of the sprite buffers, a loaded state is held to what
+    // rendering can get them to
+    fn check(&self) -> Result<(), StateError> {
+        ensure(
+            self.scanline < self.region.scanlines() && self.dot <= 340,
+            "ppu position",
+        )?;
+        ensure(
+            self.v <= 0x7FFF && self.t <= 0x7FFF && self.fine_x < 8,
+            "ppu scroll",
+        )?;
+        let line_sprites = if self.sprite_limit {
+            SPRITES_PER_LINE
+        } else {
+            self.line_sprites.len()
+        };
+        ensure(
+            self.secondary_count <= SPRITES_PER_LINE
+                && self.next_sprite <= 64
+                && self.line_sprite_count <= line_sprites,
+            "sprite count",
+        )
+    }
+}
@@ -0,0 +1,122 @@
+// raw 6502 programs without an ines header, so the emulator doubles as a test rig for code outside
+// a game. the bytes go into memory at their load address, which can be the console's 2K of ram or
+// anywhere from $4020 up where the board has ram, and run from an entry point. with an output port
+// every byte written to it is collected as text for printing
+use crate::Emulator;
+use crate::clock::Region;
+use crate::crc::crc32;
+use crate::error::EmuError;
+use crate::mapper::RawBoard;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// where print routines of 6502 simulators usually write their characters
+pub const DEFAULT_OUTPUT_PORT: u16 = 0xF001;
+const RAM_END: usize = 0x0800;
+const BOARD_START: usize = 0x4020;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawError {
+    Empty,
+    // a .prg too short to hold its load address
+    NoLoadAddress,
+    // the program runs past $FFFF by this many bytes
+    TooLarge(usize),
+    // the first address it covers in the ram mirrors and io registers from $0800 to $401F
+    Overlaps(u16),
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawError::Empty => write!(f, "the program is empty"),
+            RawError::NoLoadAddress => write!(f, "a .prg starts with a 2 byte load address"),
+            RawError::TooLarge(excess) => {
+                write!(f, "the program runs {excess} bytes past $FFFF")
+            }
+            RawError::Overlaps(address) => write!(
+                f,
+                "the program covers ${address:04X}, only $0000-$07FF and $4020-$FFFF hold memory"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RawError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawProgram {
+    pub data: Vec<u8>,
+    pub load_address: u16,
+    // where it starts running, the reset vector in the loaded memory when None
+    pub entry: Option<u16>,
+    // writes to this address are collected instead of stored, see Emulator::take_output
+    pub output_port: Option<u16>,
+}
+
+impl RawProgram {
+    pub fn new(data: Vec<u8>, load_address: u16) -> Self {
+        RawProgram {
+            data,
+            load_address,
+            entry: None,
+            output_port: None,
+        }
+    }
+
+    // a flat .prg, the program after its load address, low byte first
+    pub fn from_prg(data: &[u8]) -> Result<Self, RawError> {
+        if data.len() < 2 {
+            return Err(RawError::NoLoadAddress);
+        }
+        let load_address = u16::from_le_bytes([data[0], data[1]]);
+        Ok(Self::new(data[2..].to_vec(), load_address))
+    }
+
+    // the whole file loaded at load_address, or a flat .prg without one
+    pub fn load(path: impl AsRef<Path>, load_address: Option<u16>) -> Result<Self, EmuError> {
+        let data = fs::read(path)?;
+        Ok(match load_address {
+            Some(load_address) => Self::new(data, load_address),
+            None => Self::from_prg(&data)?,
+        })
+    }
+
+    fn check(&self) -> Result<(), RawError> {
+        if self.data.is_empty() {
+            return Err(RawError::Empty);
+        }
+        let start = self.load_address as usize;
+        let end = start + self.data.len();
+        if end > 0x10000 {
+            return Err(RawError::TooLarge(end - 0x10000));
+        }
+        if start < BOARD_START && end > RAM_END {
+            return Err(RawError::Overlaps(start.max(RAM_END) as u16));
+        }
+        Ok(())
+    }
+}
+
+impl Emulator {
+    // an ntsc console with program loaded, reset and about to run its first instruction
+    pub fn from_raw(program: RawProgram) -> Result<Self, RawError> {
+        program.check()?;
+        let board = Box::new(RawBoard::new(&program));
+        let mut emulator = Self::with_mapper(board, Region::Ntsc, crc32(&program.data));
+        let start = program.load_address as usize;
+        for (address, byte) in (start..RAM_END).zip(&program.data) {
+            emulator.ram[address] = *byte;
+        }
+        if let Some(entry) = program.entry {
+            emulator.cpu.program_counter = entry;
+        }
+        Ok(emulator)
+    }
+
+    // the characters a raw program wrote to its output port since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.mapper.take_output()
+    }
+}
@@ -0,0 +1,381 @@
+// line based debugger over stdin, numbers are hex with an optional $ or 0x prefix.
+// memory addresses may name a space first: ppu:2000, oam:10 or pal:00, the cpu bus otherwise.
+// cpu addresses may also be labels, which win over hex numbers without a prefix
+use ntsc_nes::Emulator;
+use ntsc_nes::debugger::{Access, StopReason};
+use ntsc_nes::disasm::Line;
+use ntsc_nes::memory::{MemorySpace, RamFilter, RamSearch};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+b <addr>            break when pc reaches addr
+rw <addr>           break when the cpu reads addr
+ww <addr>           break when the cpu writes addr
+d <addr>            delete every break and watchpoint at addr
+l                   list break and watchpoints
+s [count]           step instructions
+f                   run to the end of the frame
+c                   continue
+r                   show registers
+set <reg> <value>   set a, x, y, p, sp or pc
+m <addr> [length]   dump memory
+u <addr> [count]    disassemble, the rom data the analysis found shows as .db
+w <addr> <byte>...  write memory, cpu bus writes reach the io registers
+find [space] <byte|??>...
+                    search cpu, ppu, oam or pal memory for bytes, ?? matches any
+fz <addr> [byte]    freeze addr at byte or its current value
+uf <addr>           unfreeze addr
+sn                  start a ram search with every address of work ram
+sf <op> [byte]      keep the addresses whose value is = != > or < byte, or the value at the
+                    search before without one, or that went + or - byte since then
+sl                  list the addresses left in the search
+sfz [byte]          freeze the addresses left at byte or their current values
+lb <addr> [name]    label addr, or remove its label without a name
+q                   quit";
+
+// searches with this few addresses left print them straight away
+const SEARCH_LIST_LIMIT: usize = 16;
+
+fn parse_number(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn parse_address(emulator: &Emulator, text: &str) -> Option<u16> {
+    if text.starts_with('$') || text.starts_with("0x") {
+        return parse_number(text);
+    }
+    emulator
+        .symbols()
+        .address(text)
+        .or_else(|| parse_number(text))
+}
+
+fn parse_location(emulator: &Emulator, text: &str) -> Option<(MemorySpace, u16)> {
+    match text.split_once(':') {
+        Some((space, address)) => Some((MemorySpace::from_name(space)?, parse_number(address)?)),
+        None => Some((MemorySpace::Cpu, parse_address(emulator, text)?)),
+    }
+}
+
+// $address followed by its label when it has one
+fn named(emulator: &Emulator, address: u16) -> String {
+    match emulator.symbols().label(address) {
+        Some(label) => format!("${address:04X} {label}"),
+        None => format!("${address:04X}"),
+    }
+}
+
+fn format_location(space: MemorySpace, address: u16) -> String {
+    match space {
+        MemorySpace::Cpu => format!("${address:04X}"),
+        space => format!("{}:{address:04X}", space.name()),
+    }
+}
+
+// registers, then the instruction about to run
+fn print_registers(emulator: &mut Emulator) {
+    let cpu = emulator.cpu();
+    print!(
+        "pc:{:04X} a:{:02X} x:{:02X} y:{:02X} p:{:02X} sp:{:02X} cyc:{}",
+        cpu.program_counter,
+        cpu.reg_a,
+        cpu.reg_x,
+        cpu.reg_y,
+        cpu.flags.to_byte(false),
+        cpu.stack_pointer,
+        emulator.cycles()
+    );
+    let next = emulator.disassemble_at(emulator.cpu().program_counter);
+    println!("  {}", next.format_with(emulator.symbols()));
+}
+
+fn print_stop(emulator: &mut Emulator, reason: StopReason) {
+    match reason {
+        StopReason::Breakpoint(address) => println!("breakpoint at {}", named(emulator, address)),
+        StopReason::Watchpoint {
+            access,
+            address,
+            value,
+        } => {
+            let verb = match access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            println!("{verb} of ${value:02X} at {}", named(emulator, address));
+        }
+        StopReason::FrameComplete => {}
+        StopReason::Halted => println!("cpu halted"),
+    }
+    print_registers(emulator);
+}
+
+fn dump_memory(emulator: &mut Emulator, space: MemorySpace, start: u16, length: u16) {
+    for row in (0..length).step_by(16) {
+        let address = start.wrapping_add(row);
+        let bytes: Vec<String> = (0..(length - row).min(16))
+            .map(|offset| {
+                let value = emulator.read_memory(space, address.wrapping_add(offset));
+                format!("{value:02X}")
+            })
+            .collect();
+        println!("{address:04X}: {}", bytes.join(" "));
+    }
+}
+
+fn parse_filter(operator: &str, value: Option<&str>) -> Result<RamFilter, String> {
+    let value = match value {
+        Some(text) => Some(parse_number(text).ok_or("expected a byte")? as u8),
+        None => None,
+    };
+    let step = || value.ok_or_else(|| format!("{operator} needs a byte"));
+    Ok(match operator {
+        "=" => RamFilter::Equal(value),
+        "!=" => RamFilter::NotEqual(value),
+        ">" => RamFilter::Greater(value),
+        "<" => RamFilter::Less(value),
+        "+" => RamFilter::IncreasedBy(step()?),
+        "-" => RamFilter::DecreasedBy(step()?),
+        _ => {
+            return Err(format!(
+                "unknown comparison {operator}, one of = != > < + -"
+            ));
+        }
+    })
+}
+
+fn list_search(emulator: &Emulator, search: &RamSearch) {
+    for &address in search.candidates() {
+        println!("${address:04X}: {:02X}", emulator.ram()[address as usize]);
+    }
+}
+
+// returns false when the command asked to quit
+fn execute(
+    emulator: &mut Emulator,
+    search: &mut Option<RamSearch>,
+    words: &[&str],
+) -> Result<bool, String> {
+    let no_search = || "no ram search, sn starts one".to_string();
+    // worked out up front, the commands need the emulator mutably
+    let addresses: Vec<Option<u16>> = words
+        .iter()
+        .map(|word| parse_address(emulator, word))
+        .collect();
+    let locations: Vec<Option<(MemorySpace, u16)>> = words
+        .iter()
+        .map(|word| parse_location(emulator, word))
+        .collect();
+    let address = |index: usize| {
+        addresses
+            .get(index)
+            .copied()
+            .flatten()
+            .ok_or_else(|| "expected an address".to_string())
+    };
+    let location = |index: usize| {
+        locations
+            .get(index)
+            .copied()
+            .flatten()
+            .ok_or_else(|| "expected an address".to_string())
+    };
+    match words {
+        [] => {}
+        ["b", ..] => emulator.debugger_mut().add_breakpoint(address(1)?),
+        ["rw", ..] => emulator
+            .debugger_mut()
+            .add_watchpoint(Access::Read, address(1)?),
+        ["ww", ..] => emulator
+            .debugger_mut()
+            .add_watchpoint(Access::Write, address(1)?),
+        ["d", ..] => {
+            let address = address(1)?;
+            let debugger = emulator.debugger_mut();
+            debugger.remove_breakpoint(address);
+            debugger.remove_watchpoint(Access::Read, address);
+            debugger.remove_watchpoint(Access::Write, address);
+        }
+        ["l"] => {
+            let debugger = emulator.debugger();
+            for address in debugger.breakpoints() {
+                println!("break {}", named(emulator, address));
+            }
+            for address in debugger.watchpoints(Access::Read) {
+                println!("read  {}", named(emulator, address));
+            }
+            for address in debugger.watchpoints(Access::Write) {
+                println!("write {}", named(emulator, address));
+            }
+            for (space, address, value) in emulator.frozen() {
+                println!("fz    {} = {value:02X}", format_location(space, address));
+            }
+        }
+        ["s", ..] => {
+            let count = if words.len() > 1 { address(1)? } else { 1 };
+            let mut stopped = false;
+            for _ in 0..count {
+                if let Some(reason) = emulator.debug_step() {
+                    print_stop(emulator, reason);
+                    stopped = true;
+                    break;
+                }
+            }
+            if !stopped {
+                print_registers(emulator);
+            }
+        }
+        ["f"] => {
+            let reason = emulator.debug_frame();
+            print_stop(emulator, reason);
+        }
+        ["c"] => {
+            let reason = emulator.debug_continue();
+            print_stop(emulator, reason);
+        }
+        ["r"] => print_registers(emulator),
+        ["set", register, value] => {
+            let value = parse_number(value).ok_or("expected a value")?;
+            let cpu = emulator.cpu_mut();
+            match *register {
+                "a" => cpu.reg_a = value as u8,
+                "x" => cpu.reg_x = value as u8,
+                "y" => cpu.reg_y = value as u8,
+                "p" => cpu.flags.set_from_byte(value as u8),
+                "sp" => cpu.stack_pointer = value as u8,
+                "pc" => cpu.program_counter = value,
+                _ => return Err(format!("unknown register {register}")),
+            }
+        }
+        ["m", ..] => {
+            let length = if words.len() > 2 { address(2)? } else { 0x40 };
+            let (space, start) = location(1)?;
+            dump_memory(emulator, space, start, length);
+        }
+        ["u", ..] => {
+            let count = if words.len() > 2 { address(2)? } else { 16 };
+            let start = address(1)?;
+            for line in emulator.disassemble_lines(start, count as usize) {
+                match line {
+                    Line::Instruction(instruction) => {
+                        let pc = instruction.address;
+                        if let Some(name) = emulator.symbols().address_label(pc) {
+                            println!("{name}:");
+                        }
+                        println!("{pc:04X}  {}", instruction.format_with(emulator.symbols()));
+                    }
+                    Line::Data { address, bytes } => {
+                        let bytes: Vec<String> =
+                            bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+                        println!("{address:04X}  .db {}", bytes.join(","));
+                    }
+                }
+            }
+        }
+        ["w", _, bytes @ ..] if !bytes.is_empty() => {
+            let (space, start) = location(1)?;
+            for (offset, byte) in bytes.iter().enumerate() {
+                let value = parse_number(byte).ok_or("expected a byte")? as u8;
+                let address = start.wrapping_add(offset as u16);
+                match space {
+                    MemorySpace::Cpu => emulator.poke(address, value),
+                    space => emulator.write_memory(space, address, value),
+                }
+            }
+        }
+        ["find", rest @ ..] => {
+            let named = rest.first().and_then(|name| MemorySpace::from_name(name));
+            let bytes = if named.is_some() { &rest[1..] } else { rest };
+            let space = named.unwrap_or(MemorySpace::Cpu);
+            let pattern = bytes
+                .iter()
+                .map(|byte| match *byte {
+                    "??" => Ok(None),
+                    byte => parse_number(byte)
+                        .map(|value| Some(value as u8))
+                        .ok_or("expected a byte or ??"),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if pattern.is_empty() {
+                return Err("expected bytes to search for".to_string());
+            }
+            let matches = emulator.search_memory(space, &pattern);
+            for address in &matches {
+                println!("{}", format_location(space, *address));
+            }
+            println!("{} found", matches.len());
+        }
+        ["fz", ..] => {
+            let (space, address) = location(1)?;
+            let value = match words.get(2) {
+                Some(byte) => parse_number(byte).ok_or("expected a byte")? as u8,
+                None => emulator.read_memory(space, address),
+            };
+            emulator.freeze(space, address, value);
+        }
+        ["uf", ..] => {
+            let (space, address) = location(1)?;
+            if !emulator.unfreeze(space, address) {
+                return Err(format!("{} is not frozen", format_location(space, address)));
+            }
+        }
+        ["sn"] => *search = Some(RamSearch::new(emulator)),
+        ["sf", operator, rest @ ..] if rest.len() <= 1 => {
+            let filter = parse_filter(operator, rest.first().copied())?;
+            let search = search.as_mut().ok_or_else(no_search)?;
+            let left = search.filter(emulator, filter);
+            if left <= SEARCH_LIST_LIMIT {
+                list_search(emulator, search);
+            }
+            println!("{left} left");
+        }
+        ["sl"] => list_search(emulator, search.as_ref().ok_or_else(no_search)?),
+        ["sfz", rest @ ..] if rest.len() <= 1 => {
+            let value = match rest.first() {
+                Some(byte) => Some(parse_number(byte).ok_or("expected a byte")? as u8),
+                None => None,
+            };
+            search
+                .as_ref()
+                .ok_or_else(no_search)?
+                .freeze(emulator, value);
+        }
+        ["lb", _] => {
+            let address = address(1)?;
+            if !emulator.symbols_mut().remove(address) {
+                return Err(format!("${address:04X} has no label"));
+            }
+        }
+        ["lb", _, name] => {
+            let address = address(1)?;
+            emulator.add_label(address, name);
+        }
+        ["q"] => return Ok(false),
+        ["h" | "help"] => println!("{HELP}"),
+        _ => return Err("unknown command, h for help".to_string()),
+    }
+    Ok(true)
+}
+
+pub fn run(emulator: &mut Emulator) -> io::Result<()> {
+    print_registers(emulator);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut search = None;
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(());
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match execute(emulator, &mut search, &words) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(message) => println!("{message}"),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::Emulator;
+use crate::savestate::StateError;
+use std::collections::VecDeque;
+
+pub const DEFAULT_REWIND_BUDGET: usize = 32 << 20;
+
+// a history of save states, one per frame, for stepping the emulation backwards.
+// only the newest state is kept whole, every older one is stored as the run length encoded
+// xor against the state after it, which is mostly zeros since little changes in a frame.
+// power_cycle and load_new_rom start the history over
+pub struct Rewind {
+    budget: usize,
+    // the emulator's power cycles when the history began
+    power_cycles: u64,
+    latest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+    scratch: Vec<u8>,
+}
+
+impl Rewind {
+    // budget is the most memory in bytes the history may use, the oldest frames are dropped past it
+    pub fn new(budget: usize) -> Self {
+        Rewind {
+            budget,
+            power_cycles: 0,
+            latest: Vec::new(),
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    // records the current state, call it before running each frame
+    pub fn push(&mut self, emulator: &Emulator) {
+        emulator.save_state_into(&mut self.scratch);
+        if self.power_cycles != emulator.power_cycles || self.latest.len() != self.scratch.len() {
+            // the states before a power cycle or of another layout are not gone back to
+            self.clear();
+            self.power_cycles = emulator.power_cycles;
+        } else {
+            let delta = encode_delta(&self.scratch, &self.latest);
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        std::mem::swap(&mut self.latest, &mut self.scratch);
+        while self.memory_used() > self.budget
+            && let Some(oldest) = self.deltas.pop_front()
+        {
+            self.deltas_size -= oldest.len();
+        }
+    }
+
+    // drops the newest state and loads the one before it, false when there is nothing to go back to.
+    // running a frame afterwards redraws the picture and ends where the dropped state was. a state
+    // that doesn't load leaves the emulator as it was and the history empty
+    pub fn rewind(&mut self, emulator: &mut Emulator) -> Result<bool, StateError> {
+        if self.power_cycles != emulator.power_cycles {
+            self.clear();
+        }
+        let Some(delta) = self.deltas.pop_back() else {
+            return Ok(false);
+        };
+        self.deltas_size -= delta.len();
+        apply_delta(&mut self.latest, &delta);
+        emulator
+            .load_state(&self.latest)
+            .inspect_err(|_| self.clear())?;
+        Ok(true)
+    }
+
+    // how many frames back the history goes
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.latest.len() + self.deltas_size
+    }
+
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(DEFAULT_REWIND_BUDGET)
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        output.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(input: &mut &[u8]) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = input.split_first() {
+        *input = rest;
+        length |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    length
+}
+
+// pairs of a run of unchanged bytes and a run of changed ones, each as a length
+// followed by the changed bytes xored with the old ones
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut position = 0;
+    while position < new.len() {
+        let unchanged = new[position..]
+            .iter()
+            .zip(&old[position..])
+            .take_while(|(new, old)| new == old)
+            .count();
+        position += unchanged;
+        let changed = new[position..]
+            .iter()
+            .zip(&old[position..])
+            .take_while(|(new, old)| new != old)
+            .count();
+        write_length(&mut output, unchanged);
+        write_length(&mut output, changed);
+        output.extend(
+            new[position..position + changed]
+                .iter()
+                .zip(&old[position..])
+                .map(|(new, old)| new ^ old),
+        );
+        position += changed;
+    }
+    output
+}
+
+fn apply_delta(state: &mut [u8], mut delta: &[u8]) {
+    let mut position = 0;
+    while !delta.is_empty() {
+        position += read_length(&mut delta);
+        let changed = read_length(&mut delta);
+        let (bytes, rest) = delta.split_at(changed);
+        for (byte, change) in state[position..position + changed].iter_mut().zip(bytes) {
+            *byte ^= change;
+        }
+        position += changed;
+        delta = rest;
+    }
+}
@@ -0,0 +1,354 @@
+use crate::Emulator;
+use bytes::BytesMut;
+use std::error::Error;
+use std::fmt;
+
+// "NESS" followed by a little endian version, bumped whenever the layout of any section changes,
+// and the crc32 of the rom the state is of
+pub const MAGIC: [u8; 4] = *b"NESS";
+pub const VERSION: u16 = 11;
+const HEADER_SIZE: usize = MAGIC.len() + 2 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    Truncated,
+    // a buffer in the state does not match the size of the loaded cartridge's
+    SizeMismatch { expected: usize, actual: usize },
+    // the state is of another rom, by crc32
+    WrongRom { expected: u32, actual: u32 },
+    // a field holds a value the console could never have put there, named as the error says it
+    Invalid(&'static str),
+    // bytes left over after the last field
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic(magic) => write!(f, "not a save state (magic {magic:02x?})"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {version}")
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::SizeMismatch { expected, actual } => write!(
+                f,
+                "save state buffer is {actual} bytes but the cartridge has {expected}"
+            ),
+            StateError::WrongRom { expected, actual } => write!(
+                f,
+                "save state is of the rom with crc32 {actual:08X}, this one has {expected:08X}"
+            ),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {field}"),
+            StateError::TrailingBytes(count) => {
+                write!(f, "save state has {count} bytes past its end")
+            }
+        }
+    }
+}
+
+impl Error for StateError {}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter::default()
+    }
+
+    // writes into buffer's allocation, dropping what it held
+    pub fn with_buffer(mut buffer: Vec<u8>) -> Self {
+        buffer.clear();
+        StateWriter { data: buffer }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn read(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < length {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.read(N)?.try_into().unwrap())
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+}
+
+// the error for a loaded field unless valid, states come from files and netplay peers and a value
+// out of its range would index past a table or stop the clocks
+pub(crate) fn ensure(valid: bool, field: &'static str) -> Result<(), StateError> {
+    if valid {
+        Ok(())
+    } else {
+        Err(StateError::Invalid(field))
+    }
+}
+
+// a piece of emulator state that can be written to and restored from a save state
+pub trait Savestate {
+    fn save(&self, state: &mut StateWriter);
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+// implements Savestate for a struct by visiting the listed fields in order. with checked after the
+// fields, loading ends with the struct's own check of the values it got
+macro_rules! savestate_fields {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        $crate::savestate::savestate_fields!($type { $($field),* } then $crate::savestate::unchecked);
+    };
+    ($type:ty { $($field:ident),* $(,)? } checked) => {
+        $crate::savestate::savestate_fields!($type { $($field),* } then Self::check);
+    };
+    ($type:ty { $($field:ident),* } then $check:path) => {
+        impl $crate::savestate::Savestate for $type {
+            fn save(&self, state: &mut $crate::savestate::StateWriter) {
+                $($crate::savestate::Savestate::save(&self.$field, state);)*
+            }
+
+            fn load(
+                &mut self,
+                state: &mut $crate::savestate::StateReader,
+            ) -> Result<(), $crate::savestate::StateError> {
+                $($crate::savestate::Savestate::load(&mut self.$field, state)?;)*
+                $check(self)
+            }
+        }
+    };
+}
+pub(crate) use savestate_fields;
+
+pub(crate) fn unchecked<T>(_: &T) -> Result<(), StateError> {
+    Ok(())
+}
+
+macro_rules! savestate_numbers {
+    ($($type:ty),*) => {
+        $(
+            impl Savestate for $type {
+                fn save(&self, state: &mut StateWriter) {
+                    state.write(&self.to_le_bytes());
+                }
+
+                fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+                    *self = <$type>::from_le_bytes(state.read_array()?);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+savestate_numbers!(u8, u16, u32, u64, f32, f64);
+
+impl Savestate for usize {
+    fn save(&self, state: &mut StateWriter) {
+        (*self as u64).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut value = 0u64;
+        value.load(state)?;
+        *self = value as usize;
+        Ok(())
+    }
+}
+
+impl Savestate for bool {
+    fn save(&self, state: &mut StateWriter) {
+        (*self as u8).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = state.read_array::<1>()?[0] != 0;
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Option<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.is_some().save(state);
+        if let Some(value) = self {
+            value.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut present = false;
+        present.load(state)?;
+        *self = if present {
+            let mut value = T::default();
+            value.load(state)?;
+            Some(value)
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
+impl<A: Savestate, B: Savestate> Savestate for (A, B) {
+    fn save(&self, state: &mut StateWriter) {
+        self.0.save(state);
+        self.1.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.0.load(state)?;
+        self.1.load(state)
+    }
+}
+
+impl<T: Savestate, const N: usize> Savestate for [T; N] {
+    fn save(&self, state: &mut StateWriter) {
+        for value in self {
+            value.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for value in self {
+            value.load(state)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Savestate + ?Sized> Savestate for Box<T> {
+    fn save(&self, state: &mut StateWriter) {
+        (**self).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        (**self).load(state)
+    }
+}
+
+// memory buffers keep the size the cartridge gave them, so a state only loads into the same board
+impl Savestate for BytesMut {
+    fn save(&self, state: &mut StateWriter) {
+        (self.len() as u32).save(state);
+        state.write(self);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut length = 0u32;
+        length.load(state)?;
+        if length as usize != self.len() {
+            return Err(StateError::SizeMismatch {
+                expected: self.len(),
+                actual: length as usize,
+            });
+        }
+        self.copy_from_slice(state.read(length as usize)?);
+        Ok(())
+    }
+}
+
+// the host side (video filter, sample rate) is left alone so a state loads into any frontend setup
+savestate_fields!(Emulator {
+    ram,
+    cpu,
+    ppu,
+    apu,
+    mapper,
+    interrupts,
+    dma,
+    master_clock,
+    ppu_clock,
+    open_bus,
+    controllers,
+    four_score,
+} checked);
+
+// thousands of years of emulated time, a clock past it would overflow
+const MAX_CLOCK: u64 = 1 << 62;
+
+impl Emulator {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.save_state_into(&mut buffer);
+        buffer
+    }
+
+    // save_state into an existing buffer, so taking a state every frame does not allocate
+    pub fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        let mut state = StateWriter::with_buffer(std::mem::take(buffer));
+        state.write(&MAGIC);
+        VERSION.save(&mut state);
+        self.rom_crc.save(&mut state);
+        self.save(&mut state);
+        *buffer = state.into_bytes();
+    }
+
+    // a state that fails to load leaves the emulator as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data);
+        let magic = state.read_array()?;
+        if magic != MAGIC {
+            return Err(StateError::BadMagic(magic));
+        }
+        let mut version = 0u16;
+        version.load(&mut state)?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let mut rom_crc = 0u32;
+        rom_crc.load(&mut state)?;
+        if rom_crc != self.rom_crc {
+            return Err(StateError::WrongRom {
+                expected: self.rom_crc,
+                actual: rom_crc,
+            });
+        }
+        let backup = self.save_state();
+        let result = self
+            .load(&mut state)
+            .and_then(|()| match state.remaining() {
+                0 => Ok(()),
+                count => Err(StateError::TrailingBytes(count)),
+            });
+        result.inspect_err(|_| {
+            let mut state = StateReader::new(&backup[HEADER_SIZE..]);
+            self.load(&mut state)
+                .expect("restoring our own state cannot fail");
+        })
+    }
+
+    // the ppu catches up with the master clock on every tick, so a state always has it less than a
+    // dot behind. further behind it would run a frame's worth of dots at once, ahead not at all
+    fn check(&self) -> Result<(), StateError> {
+        let behind = self.master_clock.checked_sub(self.ppu_clock);
+        ensure(
+            self.master_clock <= MAX_CLOCK
+                && behind.is_some_and(|behind| behind < self.region.ppu_divider()),
+            "ppu clock",
+        )
+    }
+}
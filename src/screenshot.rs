@@ -0,0 +1,189 @@
+// screenshots as png files, and the frame as plain rgba for anything outside the emulator.
+// the png encoder only writes stored deflate blocks: a frame is small and nothing reads it on a hot path
+use crate::Emulator;
+use crate::crc::crc32;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// largest payload of a stored deflate block
+const STORED_BLOCK: usize = 0xFFFF;
+
+impl Emulator {
+    // the current frame as 256x240 RGBA8 through the palette alone, so it does not change with
+    // the video filter, the odd frame phase or the overlay
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        let palette = self.video.palette();
+        for &color in self.ppu.frame_buffer() {
+            let [red, green, blue] = palette.rgb(color);
+            rgba.extend_from_slice(&[red, green, blue, 0xFF]);
+        }
+        rgba
+    }
+
+    // writes frame_rgba to path as a png, and with raw the framebuffer next to it in a .raw file:
+    // one little endian u16 per pixel holding the colour index and the emphasis bits
+    pub fn save_screenshot(&self, path: &Path, raw: bool) -> io::Result<()> {
+        let png = encode_png(SCREEN_WIDTH, SCREEN_HEIGHT, &self.frame_rgba());
+        fs::write(path, png)?;
+        if raw {
+            let indices: Vec<u8> = self
+                .ppu
+                .frame_buffer()
+                .iter()
+                .flat_map(|color| color.to_le_bytes())
+                .collect();
+            fs::write(path.with_extension("raw"), indices)?;
+        }
+        Ok(())
+    }
+}
+
+// an 8 bit RGBA png of width x height pixels
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        rgba.len(),
+        width * height * 4,
+        "rgba does not match the size"
+    );
+    // every row starts with filter type 0, no filtering
+    let mut scanlines = Vec::with_capacity(rgba.len() + height);
+    for row in rgba.chunks(width * 4) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, colour type 6 (rgba), default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(STORED_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // deflate with a 32K window, and the check bits that make the header a multiple of 31
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        stream.push(last as u8);
+        let length = chunk.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(chunk);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the chunks of png in order, checking each crc on the way
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, crc) = rest[4..].split_at(4 + length);
+            assert_eq!(
+                crc32(body),
+                u32::from_be_bytes(crc[..4].try_into().unwrap())
+            );
+            chunks.push((body[..4].try_into().unwrap(), &body[4..]));
+            rest = &crc[4..];
+        }
+        chunks
+    }
+
+    // the data of a zlib stream of stored blocks, checking the block lengths and the adler32
+    fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        let mut data = Vec::new();
+        let mut at = 2;
+        loop {
+            let last = stream[at] & 0x01 != 0;
+            assert_eq!(stream[at] >> 1, 0, "not a stored block");
+            let length = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+            assert_eq!(
+                !length,
+                u16::from_le_bytes([stream[at + 3], stream[at + 4]])
+            );
+            data.extend_from_slice(&stream[at + 5..][..length as usize]);
+            at += 5 + length as usize;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(stream[at..], adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn pngs_hold_the_pixels_they_were_given() {
+        // a whole frame is too big for one stored block
+        for (width, height) in [(3, 2), (SCREEN_WIDTH, SCREEN_HEIGHT)] {
+            let rgba: Vec<u8> = (0..width * height * 4)
+                .map(|byte| (byte * 7) as u8)
+                .collect();
+            let png = encode_png(width, height, &rgba);
+            let chunks = chunks(&png);
+            let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+            assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+
+            let header = chunks[0].1;
+            assert_eq!(header[..4], (width as u32).to_be_bytes());
+            assert_eq!(header[4..8], (height as u32).to_be_bytes());
+            assert_eq!(header[8..], [8, 6, 0, 0, 0]);
+            assert!(chunks[2].1.is_empty());
+
+            // rows of filter type 0 then the pixels as they were
+            let scanlines = inflate_stored(chunks[1].1);
+            assert_eq!(scanlines.len(), (width * 4 + 1) * height);
+            for (row, line) in scanlines.chunks(width * 4 + 1).enumerate() {
+                assert_eq!(line[0], 0);
+                assert_eq!(line[1..], rgba[row * width * 4..][..width * 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn adler32_matches_its_known_values() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+}
@@ -0,0 +1,112 @@
+// scripts written against the emulator: bots, trackers and practice tools.
+// a script gets callbacks at the start and end of every frame and on every cpu memory access,
+// through the hooks in hooks.rs, and pokes at the machine through ScriptApi. scripts are host side
+// and not part of a save state
+use crate::Emulator;
+use crate::controller::{Button, Player};
+use crate::cpu::Cpu;
+use crate::overlay::{Color, Overlay};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub trait Script {
+    // before the first instruction of a frame, after the input of the frame is applied
+    fn frame_start(&mut self, _api: &mut ScriptApi) {}
+    // once the ppu enters vblank
+    fn frame_end(&mut self, _api: &mut ScriptApi) {}
+    // after the cpu read value from address
+    fn memory_read(&mut self, _api: &mut ScriptApi, _address: u16, _value: u8) {}
+    // before the cpu writes value to address
+    fn memory_write(&mut self, _api: &mut ScriptApi, _address: u16, _value: u8) {}
+}
+
+// what a script or a hook may do from a callback, nothing here runs cycles or calls other hooks
+pub struct ScriptApi<'a> {
+    pub(crate) emulator: &'a mut Emulator,
+}
+
+impl ScriptApi<'_> {
+    // reads memory without side effects, see Emulator::peek
+    pub fn peek(&mut self, address: u16) -> u8 {
+        self.emulator.peek(address)
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.emulator.poke(address, value);
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.emulator.cpu
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.emulator.ppu.scanline()
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.emulator.ppu.dot()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.emulator.frame_count()
+    }
+
+    pub fn text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        self.emulator.overlay.text(x, y, text, color);
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color, filled: bool) {
+        self.emulator
+            .overlay
+            .rect(x, y, width, height, color, filled);
+    }
+
+    // replaces what the pad reports from now on, set from frame_start to override the player
+    pub fn set_buttons(&mut self, player: Player, buttons: u8) {
+        self.emulator.set_buttons(player, buttons);
+    }
+
+    pub fn set_button(&mut self, player: Player, button: Button, pressed: bool) {
+        self.emulator.set_button(player, button, pressed);
+    }
+
+    pub fn buttons(&self, player: Player) -> u8 {
+        self.emulator.controllers[player.port()].buttons()
+    }
+}
+
+impl Emulator {
+    // the script's callbacks become hooks, shared by the script through a RefCell
+    pub fn add_script(&mut self, script: Box<dyn Script>) {
+        let script = Rc::new(RefCell::new(script));
+        let hooks = [
+            self.on_frame_start({
+                let script = script.clone();
+                move |api| script.borrow_mut().frame_start(api)
+            }),
+            self.on_frame({
+                let script = script.clone();
+                move |api| script.borrow_mut().frame_end(api)
+            }),
+            self.on_memory_read(0x0000..=0xFFFF, {
+                let script = script.clone();
+                move |api, address, value| script.borrow_mut().memory_read(api, address, value)
+            }),
+            self.on_memory_write(0x0000..=0xFFFF, move |api, address, value| {
+                script.borrow_mut().memory_write(api, address, value)
+            }),
+        ];
+        self.scripts.extend(hooks);
+    }
+
+    pub fn clear_scripts(&mut self) {
+        for id in std::mem::take(&mut self.scripts) {
+            self.remove_hook(id);
+        }
+    }
+
+    // shapes drawn over the next frames by video_frame, cleared when a frame starts
+    pub fn overlay_mut(&mut self) -> &mut Overlay {
+        &mut self.overlay
+    }
+}
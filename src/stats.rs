@@ -0,0 +1,154 @@
+// frame timing for finding stutter and following performance between releases: how many frames a
+// second are emulated, how long the host takes over each, how much sound is waiting to be taken,
+// the cpu and ppu cycles a frame ran and the frames that came too late to be shown in time.
+// collected by frame hooks over the last second of frames
+use crate::Emulator;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// how much history the averages are taken over, and how often take_report has a line
+const WINDOW: Duration = Duration::from_secs(1);
+// a frame ending this much of a frame after it was due missed its chance to be shown
+const LATE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub frame: u64,
+    // when the frame ended, what the host spent emulating it and the time since the frame before
+    pub end: Instant,
+    pub host_time: Duration,
+    pub interval: Option<Duration>,
+    pub cpu_cycles: u64,
+    pub ppu_dots: u64,
+    // the sound made and not yet taken when the frame ended
+    pub buffered_audio: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    window: VecDeque<FrameStats>,
+    dropped: u64,
+    // what the frame underway started at
+    start: Option<(Instant, u64, u64)>,
+    last_report: Option<Instant>,
+}
+
+impl Stats {
+    // collects from the frames emulator runs from now on, through the hooks sharing what it returns
+    pub fn attach(emulator: &mut Emulator) -> Rc<RefCell<Stats>> {
+        let stats = Rc::new(RefCell::new(Stats::default()));
+        emulator.on_frame_start({
+            let stats = stats.clone();
+            move |api| {
+                let emulator = &*api.emulator;
+                stats.borrow_mut().start =
+                    Some((Instant::now(), emulator.cycles(), emulator.ppu_dots()));
+            }
+        });
+        emulator.on_frame({
+            let stats = stats.clone();
+            move |api| stats.borrow_mut().end_frame(api.emulator)
+        });
+        stats
+    }
+
+    fn end_frame(&mut self, emulator: &Emulator) {
+        let Some((start, cycles, dots)) = self.start.take() else {
+            return;
+        };
+        let end = Instant::now();
+        let interval = self.window.back().map(|last| end - last.end);
+        // a gap of more than the window is a pause rather than a stutter
+        if let (Some(interval), Some(duration)) = (interval, emulator.frame_duration())
+            && interval.as_secs_f64() > duration.as_secs_f64() * (1.0 + LATE)
+            && interval < WINDOW
+        {
+            self.dropped += 1;
+        }
+        let samples = emulator.apu.samples().len();
+        self.window.push_back(FrameStats {
+            frame: emulator.frame_count(),
+            end,
+            host_time: end - start,
+            interval,
+            cpu_cycles: emulator.cycles() - cycles,
+            ppu_dots: emulator.ppu_dots() - dots,
+            buffered_audio: Duration::from_secs_f64(samples as f64 / emulator.sample_rate() as f64),
+        });
+        while self
+            .window
+            .front()
+            .is_some_and(|first| end - first.end > WINDOW)
+        {
+            self.window.pop_front();
+        }
+    }
+
+    pub fn last(&self) -> Option<&FrameStats> {
+        self.window.back()
+    }
+
+    // frames emulated a second of host time
+    pub fn fps(&self) -> f64 {
+        match (self.window.front(), self.window.back()) {
+            (Some(first), Some(last)) if last.end > first.end => {
+                (self.window.len() - 1) as f64 / (last.end - first.end).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn average_host_time(&self) -> Duration {
+        let total: Duration = self.window.iter().map(|frame| frame.host_time).sum();
+        total / self.window.len().max(1) as u32
+    }
+
+    pub fn max_host_time(&self) -> Duration {
+        self.window
+            .iter()
+            .map(|frame| frame.host_time)
+            .max()
+            .unwrap_or_default()
+    }
+
+    // frames since attaching that ended more than half a frame after they were due
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // a line of the stats once a second, for logging them
+    pub fn take_report(&mut self) -> Option<String> {
+        let now = Instant::now();
+        let last_report = *self.last_report.get_or_insert(now);
+        if now - last_report < WINDOW {
+            return None;
+        }
+        self.last_report = Some(now);
+        Some(self.to_string())
+    }
+}
+
+// frame 600: 60.0 fps, host 2.31ms (max 4.10ms), audio 16.7ms, cpu 29780 ppu 89342, 0 dropped
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(last) = self.last() else {
+            return write!(f, "no frames yet");
+        };
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "frame {}: {:.1} fps, host {:.2}ms (max {:.2}ms), audio {:.1}ms, cpu {} ppu {}, {} dropped",
+            last.frame,
+            self.fps(),
+            milliseconds(self.average_host_time()),
+            milliseconds(self.max_host_time()),
+            milliseconds(last.buffered_audio),
+            last.cpu_cycles,
+            last.ppu_dots,
+            self.dropped
+        )
+    }
+}
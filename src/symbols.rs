@@ -0,0 +1,205 @@
+// names for addresses, so the debugger, the trace log and the disassembly can say nmi_handler or
+// player_x instead of $C0F3. they come from fceux .nl label files, the debug info ld65 writes with
+// --dbgfile, or are added one by one. labels are by cpu address, with bank switching the labels of
+// every bank at an address are all loaded and the first one wins
+use crate::Emulator;
+use crate::error::EmuError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolError {
+    // 1 based number of a line that is not a valid label
+    BadLine(usize),
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolError::BadLine(line) => write!(f, "line {line} is not a valid label"),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    // each label with the bytes it covers, more than one for arrays
+    labels: BTreeMap<u16, (String, u16)>,
+    addresses: BTreeMap<String, u16>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // .dbg files by their extension, anything else is taken for an .nl file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EmuError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let dbg = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("dbg"));
+        Ok(if dbg {
+            Self::parse_dbg(&text)?
+        } else {
+            Self::parse_nl(&text)?
+        })
+    }
+
+    // lines of $address#name#comment, or $address/size#name#comment for an array of size bytes.
+    // both numbers are hex, lines without a name only comment on the address
+    pub fn parse_nl(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = || SymbolError::BadLine(index + 1);
+            let mut fields = line.strip_prefix('$').ok_or_else(bad)?.split('#');
+            let location = fields.next().ok_or_else(bad)?;
+            let (address, size) = location.split_once('/').unwrap_or((location, "1"));
+            let address = u16::from_str_radix(address, 16).map_err(|_| bad())?;
+            let size = u16::from_str_radix(size, 16).map_err(|_| bad())?;
+            match fields.next().map(str::trim) {
+                Some("") | None => {}
+                Some(name) => symbols.add_range(address, size.max(1), name),
+            }
+        }
+        Ok(symbols)
+    }
+
+    // the labels of a ca65 debug info file, its sym lines of type lab. imports have no value and
+    // equates are left out since most of them are constants rather than addresses
+    pub fn parse_dbg(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let Some(attributes) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+            let bad = || SymbolError::BadLine(index + 1);
+            let mut name = None;
+            let mut value = None;
+            let mut size = 1;
+            let mut label = false;
+            for attribute in attributes.split(',') {
+                let (key, text) = attribute.split_once('=').ok_or_else(bad)?;
+                match key {
+                    "name" => name = Some(text.trim_matches('"')),
+                    "val" => value = Some(parse_dbg_number(text).ok_or_else(bad)?),
+                    "size" => size = parse_dbg_number(text).ok_or_else(bad)?,
+                    "type" => label = text == "lab",
+                    _ => {}
+                }
+            }
+            if let (true, Some(name), Some(value)) = (label, name, value) {
+                symbols.add_range(value as u16, (size as u16).max(1), name);
+            }
+        }
+        Ok(symbols)
+    }
+
+    // names address, replacing a label it had and moving name here from where it was
+    pub fn add(&mut self, address: u16, name: &str) {
+        self.remove(address);
+        if let Some(old) = self.addresses.remove(name) {
+            self.labels.remove(&old);
+        }
+        self.labels.insert(address, (name.to_string(), 1));
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    pub fn remove(&mut self, address: u16) -> bool {
+        match self.labels.remove(&address) {
+            Some((name, _)) => {
+                self.addresses.remove(&name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // keeps what is already there, for labels coming from more than one file
+    fn add_range(&mut self, address: u16, size: u16, name: &str) {
+        if self.labels.contains_key(&address) || self.addresses.contains_key(name) {
+            return;
+        }
+        self.labels.insert(address, (name.to_string(), size));
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    // the labels of other that don't clash with these
+    pub fn merge(&mut self, other: &Symbols) {
+        for (address, (name, size)) in &other.labels {
+            self.add_range(*address, *size, name);
+        }
+    }
+
+    // the label of address, name+offset inside an array
+    pub fn label(&self, address: u16) -> Option<String> {
+        let (start, (name, size)) = self.labels.range(..=address).next_back()?;
+        match address - start {
+            0 => Some(name.clone()),
+            offset if offset < *size => Some(format!("{name}+{offset}")),
+            _ => None,
+        }
+    }
+
+    // the label starting at address, nothing for the middle of an array
+    pub fn address_label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|(name, _)| name.as_str())
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    // every label by address
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.labels
+            .iter()
+            .map(|(address, (name, _))| (*address, name.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+// ld65 writes numbers as 0x hex, or decimal
+fn parse_dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(digits) => u32::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Emulator {
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut Symbols {
+        &mut self.symbols
+    }
+
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.symbols.add(address, name);
+    }
+
+    // adds the labels of an .nl or .dbg file to the ones already loaded
+    pub fn load_symbols(&mut self, path: impl AsRef<Path>) -> Result<(), EmuError> {
+        let symbols = Symbols::load(path)?;
+        self.symbols.merge(&symbols);
+        Ok(())
+    }
+}
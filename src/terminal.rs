@@ -0,0 +1,182 @@
+// the picture drawn in the terminal itself, for ssh sessions and consoles without a framebuffer.
+// every character cell is an upper half block with the top pixel as its foreground colour and the
+// one below as its background, in 24 bit colour when COLORTERM says the terminal has it and in the
+// 256 colour palette otherwise. only the rows that changed since the last frame are sent again
+use ntsc_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// how often the terminal is asked for its size, to follow it being resized
+const RESIZE_INTERVAL: Duration = Duration::from_secs(1);
+const HALF_BLOCK: char = '\u{2580}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Colors {
+    TrueColor,
+    Palette256,
+}
+
+pub struct TerminalScreen {
+    colors: Colors,
+    // rows and columns of the terminal
+    size: (usize, usize),
+    // the cells the picture covers, each two pixels high, and where it starts on the screen
+    columns: usize,
+    rows: usize,
+    left: usize,
+    top: usize,
+    last_resize_check: Instant,
+    // what each row was last drawn as
+    drawn: Vec<String>,
+}
+
+impl TerminalScreen {
+    pub fn open() -> io::Result<Self> {
+        let colors = match env::var("COLORTERM") {
+            Ok(value) if value.contains("truecolor") || value.contains("24bit") => {
+                Colors::TrueColor
+            }
+            _ => Colors::Palette256,
+        };
+        let mut screen = TerminalScreen {
+            colors,
+            size: (0, 0),
+            columns: 0,
+            rows: 0,
+            left: 0,
+            top: 0,
+            last_resize_check: Instant::now(),
+            drawn: Vec::new(),
+        };
+        screen.fit(terminal_size()?);
+        // the cursor stays hidden while the picture is up
+        print!("\x1B[?25l\x1B[2J");
+        io::stdout().flush()?;
+        Ok(screen)
+    }
+
+    // the largest picture that fits, its aspect kept, leaving the bottom line for messages
+    fn fit(&mut self, size: (usize, usize)) {
+        let (rows, columns) = size;
+        self.size = size;
+        let scale = (columns as f64 / SCREEN_WIDTH as f64)
+            .min(rows.saturating_sub(1) as f64 * 2.0 / SCREEN_HEIGHT as f64)
+            .min(1.0);
+        self.columns = ((SCREEN_WIDTH as f64 * scale) as usize).max(1);
+        self.rows = ((SCREEN_HEIGHT as f64 * scale / 2.0) as usize).max(1);
+        self.left = columns.saturating_sub(self.columns) / 2;
+        self.top = rows.saturating_sub(1).saturating_sub(self.rows) / 2;
+        self.drawn.clear();
+        print!("\x1B[2J");
+    }
+
+    // frame is 256x240 RGBA8
+    pub fn present(&mut self, frame: &[u8]) -> io::Result<()> {
+        if self.last_resize_check.elapsed() >= RESIZE_INTERVAL {
+            self.last_resize_check = Instant::now();
+            let size = terminal_size()?;
+            if size != self.size {
+                self.fit(size);
+            }
+        }
+        self.drawn.resize(self.rows, String::new());
+        let mut output = String::new();
+        for row in 0..self.rows {
+            let line = self.row(frame, row);
+            if line != self.drawn[row] {
+                let _ = write!(
+                    output,
+                    "\x1B[{};{}H{line}\x1B[0m",
+                    self.top + row + 1,
+                    self.left + 1
+                );
+                self.drawn[row] = line;
+            }
+        }
+        // back to the message line
+        let _ = write!(output, "\x1B[{};1H", self.top + self.rows + 1);
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()
+    }
+
+    // one line of cells, the colours only given where they change
+    fn row(&self, frame: &[u8], row: usize) -> String {
+        let pixel = |x: usize, y: usize| {
+            let x = x * SCREEN_WIDTH / self.columns;
+            let y = (y * SCREEN_HEIGHT / (self.rows * 2)).min(SCREEN_HEIGHT - 1);
+            let offset = (y * SCREEN_WIDTH + x) * 4;
+            [frame[offset], frame[offset + 1], frame[offset + 2]]
+        };
+        let mut line = String::new();
+        let mut last = None;
+        for x in 0..self.columns {
+            let colors = (pixel(x, row * 2), pixel(x, row * 2 + 1));
+            if last != Some(colors) {
+                let (top, bottom) = colors;
+                match self.colors {
+                    Colors::TrueColor => {
+                        let _ = write!(
+                            line,
+                            "\x1B[38;2;{};{};{};48;2;{};{};{}m",
+                            top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                        );
+                    }
+                    Colors::Palette256 => {
+                        let _ = write!(
+                            line,
+                            "\x1B[38;5;{};48;5;{}m",
+                            palette_index(top),
+                            palette_index(bottom)
+                        );
+                    }
+                }
+                last = Some(colors);
+            }
+            line.push(HALF_BLOCK);
+        }
+        line
+    }
+}
+
+impl Drop for TerminalScreen {
+    fn drop(&mut self) {
+        print!("\x1B[0m\x1B[?25h\x1B[2J\x1B[H");
+        let _ = io::stdout().flush();
+    }
+}
+
+// the nearest colour of the 6x6x6 cube or the grey ramp of the 256 colour palette
+fn palette_index([red, green, blue]: [u8; 3]) -> u8 {
+    let level = |value: u8| ((value as u16 * 5 + 127) / 255) as u8;
+    let cube = 16 + 36 * level(red) + 6 * level(green) + level(blue);
+    let (max, min) = (red.max(green).max(blue), red.min(green).min(blue));
+    if max - min < 16 {
+        // 24 greys from 8 to 238 in steps of 10
+        let grey = (red as u16 + green as u16 + blue as u16) / 3;
+        match grey {
+            0..=3 => 16,
+            244.. => 231,
+            grey => 232 + ((grey - 3) / 10).min(23) as u8,
+        }
+    } else {
+        cube
+    }
+}
+
+// rows and columns, as stty reports them for the terminal on stdin
+fn terminal_size() -> io::Result<(usize, usize)> {
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut numbers = text.split_whitespace().map(str::parse::<usize>);
+    match (numbers.next(), numbers.next()) {
+        (Some(Ok(rows)), Some(Ok(columns))) if rows > 0 && columns > 0 => Ok((rows, columns)),
+        _ => Err(io::Error::other("could not get the size of the terminal")),
+    }
+}
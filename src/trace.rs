@@ -0,0 +1,215 @@
+use crate::Emulator;
+use crate::disasm::{Instruction, Operand};
+use std::io::{self, Write};
+
+impl Emulator {
+    // logs every instruction before it runs in the format of nestest.log, None stops tracing
+    pub fn set_trace(&mut self, output: Option<Box<dyn Write>>) {
+        if let Some(id) = self.trace.take() {
+            self.remove_hook(id);
+        }
+        if let Some(mut output) = output {
+            let id = self.on_instruction(move |api| {
+                if api.emulator.write_trace(&mut output).is_err()
+                    && let Some(id) = api.emulator.trace.take()
+                {
+                    api.emulator.remove_hook(id);
+                }
+            });
+            self.trace = Some(id);
+        }
+    }
+
+    // the nestest.log line for the instruction at pc, addresses with a label named by it, e.g.
+    // C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+    pub fn trace_line(&mut self) -> String {
+        let instruction = self.disassemble_at(self.cpu.program_counter);
+        let bytes: Vec<String> = instruction
+            .bytes()
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let marker = if instruction.official { ' ' } else { '*' };
+        let text = format!(
+            "{:04X}  {:<8} {marker}{} {}",
+            instruction.address,
+            bytes.join(" "),
+            instruction.mnemonic,
+            self.annotated_operand(&instruction)
+        );
+        format!(
+            "{:<47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            text.trim_end(),
+            self.cpu.reg_a,
+            self.cpu.reg_x,
+            self.cpu.reg_y,
+            self.cpu.flags.to_byte(false),
+            self.cpu.stack_pointer,
+            self.ppu.scanline(),
+            self.ppu.dot(),
+            self.cycles()
+        )
+    }
+
+    // the operand followed by the effective address and the value there, as nestest prints it
+    fn annotated_operand(&mut self, instruction: &Instruction) -> String {
+        let label = instruction
+            .operand_address()
+            .and_then(|address| self.symbols.label(address));
+        let operand = instruction.format_operand(label.as_deref());
+        let value = instruction.value;
+        let x = self.cpu.reg_x;
+        let y = self.cpu.reg_y;
+        match instruction.operand {
+            Operand::ZeroPage => format!("{operand} = {:02X}", self.peek(value)),
+            Operand::ZeroPageX | Operand::ZeroPageY => {
+                let index = if instruction.operand == Operand::ZeroPageX {
+                    x
+                } else {
+                    y
+                };
+                let address = (value as u8).wrapping_add(index) as u16;
+                format!("{operand} @ {address:02X} = {:02X}", self.peek(address))
+            }
+            Operand::Absolute if matches!(instruction.mnemonic, "JMP" | "JSR") => operand,
+            Operand::Absolute => format!("{operand} = {:02X}", self.peek(value)),
+            Operand::AbsoluteX | Operand::AbsoluteY => {
+                let index = if instruction.operand == Operand::AbsoluteX {
+                    x
+                } else {
+                    y
+                };
+                let address = value.wrapping_add(index as u16);
+                format!("{operand} @ {address:04X} = {:02X}", self.peek(address))
+            }
+            Operand::Indirect => {
+                // the pointer's high byte comes from the same page, like JMP ($xxFF) on the cpu
+                let high = (value & 0xFF00) | (value.wrapping_add(1) & 0x00FF);
+                let target = u16::from_le_bytes([self.peek(value), self.peek(high)]);
+                format!("{operand} = {target:04X}")
+            }
+            Operand::IndirectX => {
+                let pointer = (value as u8).wrapping_add(x);
+                let address = self.peek_zero_page_word(pointer);
+                format!(
+                    "{operand} @ {pointer:02X} = {address:04X} = {:02X}",
+                    self.peek(address)
+                )
+            }
+            Operand::IndirectY => {
+                let base = self.peek_zero_page_word(value as u8);
+                let address = base.wrapping_add(y as u16);
+                format!(
+                    "{operand} = {base:04X} @ {address:04X} = {:02X}",
+                    self.peek(address)
+                )
+            }
+            _ => operand,
+        }
+    }
+
+    fn peek_zero_page_word(&mut self, pointer: u8) -> u16 {
+        u16::from_le_bytes([
+            self.peek(pointer as u16),
+            self.peek(pointer.wrapping_add(1) as u16),
+        ])
+    }
+
+    // a failed write stops tracing rather than the emulation. a label at pc gets a line of its own
+    // before the instruction, which keeps to the nestest columns with labels spelled in the operand
+    fn write_trace(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        let line = self.trace_line();
+        match self.symbols.address_label(self.cpu.program_counter) {
+            Some(name) => writeln!(output, "{name}:\n{line}"),
+            None => writeln!(output, "{line}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    // an nrom console with the bytes of pieces at their addresses from $C000, nops elsewhere
+    fn console(pieces: &[(u16, &[u8])]) -> Emulator {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0];
+        rom.resize(16, 0);
+        let mut prg = vec![0xEA; 0x4000];
+        for &(address, bytes) in pieces {
+            let offset = (address - 0xC000) as usize;
+            prg[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        rom.extend(prg);
+        let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+        emulator.ram_mut().fill(0);
+        emulator
+    }
+
+    #[test]
+    fn lines_match_the_start_of_nestest_log() {
+        let mut emulator = console(&[
+            (0xC000, &[0x4C, 0xF5, 0xC5]),
+            (
+                0xC5F5,
+                &[
+                    0xA2, 0x00, 0x86, 0x00, 0x86, 0x10, 0x86, 0x11, 0x20, 0x2D, 0xC7,
+                ],
+            ),
+            (0xC72D, &[0xEA, 0x38, 0xB0, 0x04]),
+        ]);
+        let golden = "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12
+C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 45 CYC:15
+C5FB  86 11     STX $11 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 54 CYC:18
+C5FD  20 2D C7  JSR $C72D                       A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 63 CYC:21
+C72D  EA        NOP                             A:00 X:00 Y:00 P:26 SP:FB PPU:  0, 81 CYC:27
+C72E  38        SEC                             A:00 X:00 Y:00 P:26 SP:FB PPU:  0, 87 CYC:29
+C72F  B0 04     BCS $C735                       A:00 X:00 Y:00 P:27 SP:FB PPU:  0, 93 CYC:31";
+        for line in golden.lines() {
+            assert_eq!(emulator.trace_line(), line);
+            emulator.step_instruction();
+        }
+    }
+
+    #[test]
+    fn operands_show_their_effective_address_and_value() {
+        // LDX #$02, LDY #$03, then each indexed and indirect mode reading around $0300
+        let mut emulator = console(&[(
+            0xC000,
+            &[
+                0xA2, 0x02, 0xA0, 0x03, 0xB5, 0xFF, 0x96, 0x10, 0xBD, 0xFE, 0x02, 0xB9, 0xFE, 0x02,
+                0xA1, 0x7E, 0xB1, 0x80, 0xA7, 0x13, 0x6C, 0xFF, 0x02,
+            ],
+        )]);
+        let ram = emulator.ram_mut();
+        ram[0x01] = 0x11;
+        ram[0x13] = 0x13;
+        ram[0x80..0x82].copy_from_slice(&[0x00, 0x03]);
+        ram[0x300..0x302].copy_from_slice(&[0x5A, 0x89]);
+        ram[0x303] = 0x77;
+        ram[0x2FF] = 0x34;
+        ram[0x200] = 0x12;
+        let golden = [
+            "B5 FF     LDA $FF,X @ 01 = 11",
+            "96 10     STX $10,Y @ 13 = 13",
+            "BD FE 02  LDA $02FE,X @ 0300 = 5A",
+            "B9 FE 02  LDA $02FE,Y @ 0301 = 89",
+            "A1 7E     LDA ($7E,X) @ 80 = 0300 = 5A",
+            "B1 80     LDA ($80),Y = 0300 @ 0303 = 77",
+            "A7 13    *LAX $13 = 02",
+            // the pointer's high byte from $0200 rather than $0300
+            "6C FF 02  JMP ($02FF) = 1234",
+        ];
+        emulator.step_instruction();
+        emulator.step_instruction();
+        for text in golden {
+            let line = emulator.trace_line();
+            assert_eq!(line[6..47].trim_end(), text);
+            emulator.step_instruction();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::palette::Palette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoFilter {
+    // straight palette lookup
+    #[default]
+    Rgb,
+    // luma and chroma share one signal, so edges fringe and the chroma crawls
+    Ntsc,
+    // luma and chroma travel separately, only the chroma bandwidth limit remains
+    SVideo,
+}
+
+// the signal is sampled at 12 times the colour subcarrier, 8 samples per pixel
+const SAMPLES_PER_PIXEL: usize = 8;
+pub(crate) const PHASES: usize = 12;
+
+// composite levels of the 2C02 relative to sync, for the 4 luma rows
+const LEVEL_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const LEVEL_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const BLACK: f32 = 0.312;
+const WHITE: f32 = 1.100;
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+// rotates the decoded hue so colour 8 lines up with the colour burst
+pub(crate) const HUE_OFFSET: f32 = 3.9;
+
+pub struct VideoOutput {
+    filter: VideoFilter,
+    // colours the rgb filter looks up, the ntsc and s-video filters decode their own
+    palette: Palette,
+    rgba: Vec<u8>,
+    // the composite signal of one scanline, with the luma-only copy used by s-video
+    signal: Vec<f32>,
+    luma: Vec<f32>,
+    carrier: [(f32, f32); PHASES],
+}
+
+impl Default for VideoOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VideoOutput {
+    pub fn new() -> Self {
+        let mut carrier = [(0.0, 0.0); PHASES];
+        for (phase, entry) in carrier.iter_mut().enumerate() {
+            let angle = PI * (phase as f32 + HUE_OFFSET) / 6.0;
+            *entry = (angle.cos(), angle.sin());
+        }
+        VideoOutput {
+            filter: VideoFilter::default(),
+            palette: Palette::default(),
+            rgba: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            signal: vec![0.0; SCREEN_WIDTH * SAMPLES_PER_PIXEL],
+            luma: vec![0.0; SCREEN_WIDTH * SAMPLES_PER_PIXEL],
+            carrier,
+        }
+    }
+
+    pub fn filter(&self) -> VideoFilter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: VideoFilter) {
+        self.filter = filter;
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    // converts a frame of colour indices into RGBA8, odd_frame picks the subcarrier phase of the frame
+    pub fn render(&mut self, framebuffer: &[u16], odd_frame: bool) -> &mut [u8] {
+        match self.filter {
+            VideoFilter::Rgb => {
+                for (pixel, &color) in self.rgba.chunks_exact_mut(4).zip(framebuffer) {
+                    let [red, green, blue] = self.palette.rgb(color);
+                    pixel.copy_from_slice(&[red, green, blue, 0xFF]);
+                }
+            }
+            VideoFilter::Ntsc | VideoFilter::SVideo => {
+                for y in 0..SCREEN_HEIGHT {
+                    // a scanline is 341 * 8 samples, which moves the phase by 4 every line,
+                    // and the dot skipped on odd frames moves it by another 4 every other frame
+                    let phase = (y * 4 + if odd_frame { 4 } else { 0 }) % PHASES;
+                    let line = &framebuffer[y * SCREEN_WIDTH..][..SCREEN_WIDTH];
+                    self.encode_line(line, phase);
+                    self.decode_line(y, phase);
+                }
+            }
+        }
+        &mut self.rgba
+    }
+
+    fn encode_line(&mut self, line: &[u16], phase: usize) {
+        for (x, &color) in line.iter().enumerate() {
+            let mut average = 0.0;
+            for k in 0..PHASES {
+                average += composite_level(color, phase + x * SAMPLES_PER_PIXEL + k);
+            }
+            for k in 0..SAMPLES_PER_PIXEL {
+                let sample = x * SAMPLES_PER_PIXEL + k;
+                self.signal[sample] = composite_level(color, phase + sample);
+                self.luma[sample] = average / PHASES as f32;
+            }
+        }
+    }
+
+    fn decode_line(&mut self, y: usize, phase: usize) {
+        let separate_luma = self.filter == VideoFilter::SVideo;
+        let samples = self.signal.len() as isize;
+        for x in 0..SCREEN_WIDTH {
+            // integrate one subcarrier period around the centre of the pixel
+            let centre = (x * SAMPLES_PER_PIXEL + SAMPLES_PER_PIXEL / 2) as isize;
+            let (mut luma, mut i, mut q) = (0.0, 0.0, 0.0);
+            for offset in -(PHASES as isize / 2)..PHASES as isize / 2 {
+                let sample = (centre + offset).clamp(0, samples - 1) as usize;
+                let mut level = self.signal[sample];
+                if separate_luma {
+                    level -= self.luma[sample];
+                } else {
+                    luma += level;
+                }
+                let (cos, sin) = self.carrier[(phase + sample) % PHASES];
+                i += level * cos;
+                q += level * sin;
+            }
+            let luma = if separate_luma {
+                self.luma[centre as usize]
+            } else {
+                luma / PHASES as f32
+            };
+            // synchronous demodulation halves the amplitude
+            let (i, q) = (i * 2.0 / PHASES as f32, q * 2.0 / PHASES as f32);
+            let [red, green, blue] = yiq_to_rgb(luma, i, q);
+            let pixel = &mut self.rgba[(y * SCREEN_WIDTH + x) * 4..][..4];
+            pixel.copy_from_slice(&[red, green, blue, 0xFF]);
+        }
+    }
+}
+
+pub(crate) fn yiq_to_rgb(luma: f32, i: f32, q: f32) -> [u8; 3] {
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    [
+        to_byte(luma + 0.946_882 * i + 0.623_557 * q),
+        to_byte(luma - 0.274_788 * i - 0.635_691 * q),
+        to_byte(luma - 1.108_545 * i + 1.709_007 * q),
+    ]
+}
+
+// the square wave the ppu outputs for a colour at a given subcarrier phase, 0 is black and 1 is white
+pub(crate) fn composite_level(color: u16, phase: usize) -> f32 {
+    let hue = (color & 0x0F) as usize;
+    // columns $E and $F are always black
+    let row = if hue > 13 {
+        1
+    } else {
+        (color >> 4) as usize & 0x03
+    };
+    let in_phase = |hue: usize| (hue + phase) % PHASES < 6;
+    let mut level = match hue {
+        0 => LEVEL_HIGH[row],
+        13.. => LEVEL_LOW[row],
+        _ if in_phase(hue) => LEVEL_HIGH[row],
+        _ => LEVEL_LOW[row],
+    };
+    let emphasis = color >> 6;
+    if (emphasis & 0x01 != 0 && in_phase(0))
+        || (emphasis & 0x02 != 0 && in_phase(4))
+        || (emphasis & 0x04 != 0 && in_phase(8))
+    {
+        level *= EMPHASIS_ATTENUATION;
+    }
+    (level - BLACK) / (WHITE - BLACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(filter: VideoFilter, framebuffer: &[u16], odd_frame: bool) -> Vec<u8> {
+        let mut output = VideoOutput::new();
+        output.set_filter(filter);
+        output.render(framebuffer, odd_frame).to_vec()
+    }
+
+    fn rgb(frame: &[u8], x: usize, y: usize) -> [u8; 3] {
+        let pixel = &frame[(y * SCREEN_WIDTH + x) * 4..][..3];
+        [pixel[0], pixel[1], pixel[2]]
+    }
+
+    fn neutral([red, green, blue]: [u8; 3]) -> bool {
+        red == green && green == blue
+    }
+
+    #[test]
+    fn flat_fields_decode_to_their_colours() {
+        let field = |color: u16| vec![color; SCREEN_WIDTH * SCREEN_HEIGHT];
+        // rgb is the palette itself
+        let frame = render(VideoFilter::Rgb, &field(0x16), false);
+        assert_eq!(rgb(&frame, 100, 100), Palette::default().rgb(0x16));
+
+        for filter in [VideoFilter::Ntsc, VideoFilter::SVideo] {
+            let decoded = |color: u16| rgb(&render(filter, &field(color), false), 100, 100);
+            // the columns without chroma are greys getting lighter a row at a time, $0F is black
+            assert_eq!(decoded(0x0F), [0, 0, 0]);
+            assert_eq!(decoded(0x30), [255, 255, 255]);
+            let greys = [decoded(0x00), decoded(0x10), decoded(0x20)];
+            assert!(greys.into_iter().all(neutral), "{greys:?}");
+            assert!(
+                greys[0][0] < greys[1][0] && greys[1][0] < greys[2][0],
+                "{greys:?}"
+            );
+
+            // red, blue and green come out strongest in their own channel
+            let [red, green, blue] = decoded(0x16);
+            assert!(red > 2 * green && red > 2 * blue);
+            let [red, green, blue] = decoded(0x12);
+            assert!(blue > 2 * red && blue > 2 * green);
+            let [red, green, blue] = decoded(0x1A);
+            assert!(green > 2 * red && green > 2 * blue);
+            // red emphasis darkens the other two
+            let ([_, green, blue], [_, emphasised_green, emphasised_blue]) =
+                (decoded(0x16), decoded(0x16 | 0x40));
+            assert!(emphasised_green < green && emphasised_blue <= blue);
+        }
+    }
+
+    #[test]
+    fn composite_fringes_edges_and_s_video_does_not() {
+        // black on the left half and white on the right
+        let edge: Vec<u16> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|index| {
+                if index % SCREEN_WIDTH < 128 {
+                    0x0F
+                } else {
+                    0x30
+                }
+            })
+            .collect();
+        let even = render(VideoFilter::Ntsc, &edge, false);
+        let odd = render(VideoFilter::Ntsc, &edge, true);
+        // the luma step leaks into the chroma either side of the edge, away from it there is none
+        assert!(!neutral(rgb(&even, 127, 10)) && !neutral(rgb(&even, 128, 10)));
+        assert_eq!(rgb(&even, 124, 10), [0, 0, 0]);
+        assert_eq!(rgb(&even, 131, 10), [255, 255, 255]);
+        // the fringe's colour crawls with the subcarrier phase from line to line and frame to frame
+        assert_ne!(rgb(&even, 127, 10), rgb(&odd, 127, 10));
+        assert_ne!(rgb(&even, 127, 10), rgb(&even, 127, 11));
+        assert_eq!(rgb(&even, 127, 10), rgb(&even, 127, 13));
+
+        let separate = render(VideoFilter::SVideo, &edge, false);
+        assert_eq!(rgb(&separate, 127, 10), [0, 0, 0]);
+        assert_eq!(rgb(&separate, 128, 10), [255, 255, 255]);
+    }
+}
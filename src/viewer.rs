@@ -0,0 +1,205 @@
+// pictures of the ppu state for graphics debugging: the nametables, the pattern tables,
+// the sprites in oam and the palette. everything is read without side effects
+use crate::Emulator;
+use crate::palette::Palette;
+
+// NES colour indices like the framebuffer, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u16>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: u16) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn to_rgba(&self, palette: &Palette) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&color| {
+                let [red, green, blue] = palette.rgb(color);
+                [red, green, blue, 0xFF]
+            })
+            .collect()
+    }
+}
+
+// an oam entry decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    // the sprite is drawn from the line below y
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    // 0-3, the sprite palettes follow the four background ones
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl Emulator {
+    // the four nametables as 512x480, laid out as at $2000, $2400, $2800 and $2C00 after mirroring
+    pub fn render_nametables(&mut self) -> Image {
+        let table = if self.ppu.ctrl() & 0x10 != 0 {
+            0x1000
+        } else {
+            0
+        };
+        let mut image = Image::new(512, 480);
+        for nametable in 0..4u16 {
+            let base = 0x2000 + nametable * 0x400;
+            let (left, top) = (
+                (nametable as usize & 1) * 256,
+                (nametable as usize >> 1) * 240,
+            );
+            for row in 0..30u16 {
+                for column in 0..32u16 {
+                    let tile = self
+                        .ppu
+                        .peek_vram(&mut *self.mapper, base + row * 32 + column);
+                    let attribute = self
+                        .ppu
+                        .peek_vram(&mut *self.mapper, base + 0x3C0 + row / 4 * 8 + column / 4);
+                    let quadrant = (row & 0x02) << 1 | (column & 0x02);
+                    let palette = (attribute >> quadrant) & 0x03;
+                    self.draw_tile(
+                        &mut image,
+                        (left + column as usize * 8, top + row as usize * 8),
+                        table + tile as u16 * 16,
+                        palette,
+                        (false, false),
+                    );
+                }
+            }
+        }
+        image
+    }
+
+    // both pattern tables side by side as 256x128, in palette 0-7 (sprite palettes are 4-7)
+    pub fn render_pattern_tables(&mut self, palette: u8) -> Image {
+        let mut image = Image::new(256, 128);
+        for tile in 0..512u16 {
+            let table = tile / 256;
+            let (column, row) = ((tile % 16) as usize, (tile % 256 / 16) as usize);
+            self.draw_tile(
+                &mut image,
+                (table as usize * 128 + column * 8, row * 8),
+                tile * 16,
+                palette & 0x07,
+                (false, false),
+            );
+        }
+        image
+    }
+
+    // the scroll the next frame starts at, in pixels into the 512x480 of render_nametables
+    pub fn scroll(&self) -> (usize, usize) {
+        self.ppu.scroll()
+    }
+
+    pub fn sprites(&self) -> [Sprite; 64] {
+        let oam = self.ppu.oam();
+        std::array::from_fn(|index| {
+            let entry = &oam[index * 4..index * 4 + 4];
+            Sprite {
+                y: entry[0],
+                tile: entry[1],
+                palette: entry[2] & 0x03,
+                behind_background: entry[2] & 0x20 != 0,
+                flip_horizontal: entry[2] & 0x40 != 0,
+                flip_vertical: entry[2] & 0x80 != 0,
+                x: entry[3],
+            }
+        })
+    }
+
+    // the 64 sprites in oam order, 16 to a row in 8x16 cells so 8x16 sprites fit,
+    // with the backdrop colour where they are transparent and under 8x8 ones
+    pub fn render_sprites(&mut self) -> Image {
+        let mut image = Image::new(128, 64);
+        let backdrop = self.ppu.peek_vram(&mut *self.mapper, 0x3F00);
+        image.pixels.fill(backdrop as u16);
+        let tall = self.ppu.ctrl() & 0x20 != 0;
+        let table = if self.ppu.ctrl() & 0x08 != 0 {
+            0x1000
+        } else {
+            0
+        };
+        for (index, sprite) in self.sprites().into_iter().enumerate() {
+            let (left, top) = (index % 16 * 8, index / 16 * 16);
+            let flip = (sprite.flip_horizontal, sprite.flip_vertical);
+            if tall {
+                // the low bit of the tile picks the table, a vertical flip swaps the two halves
+                let first = (sprite.tile as u16 & 0x01) * 0x1000 + (sprite.tile as u16 & 0xFE) * 16;
+                let (upper, lower) = if sprite.flip_vertical {
+                    (first + 16, first)
+                } else {
+                    (first, first + 16)
+                };
+                self.draw_tile(&mut image, (left, top), upper, sprite.palette + 4, flip);
+                self.draw_tile(&mut image, (left, top + 8), lower, sprite.palette + 4, flip);
+            } else {
+                let address = table + sprite.tile as u16 * 16;
+                self.draw_tile(&mut image, (left, top), address, sprite.palette + 4, flip);
+            }
+        }
+        image
+    }
+
+    // the 32 palette entries as they are read back through $2007, in two rows of 16
+    pub fn palette_ram(&mut self) -> [u8; 32] {
+        std::array::from_fn(|index| self.ppu.peek_vram(&mut *self.mapper, 0x3F00 + index as u16))
+    }
+
+    // palette_ram as a 16x2 image, one pixel per entry
+    pub fn render_palette(&mut self) -> Image {
+        Image {
+            width: 16,
+            height: 2,
+            pixels: self
+                .palette_ram()
+                .iter()
+                .map(|&entry| entry as u16)
+                .collect(),
+        }
+    }
+
+    // an 8x8 tile at address in pattern memory, colour 0 is drawn as the backdrop
+    fn draw_tile(
+        &mut self,
+        image: &mut Image,
+        (left, top): (usize, usize),
+        address: u16,
+        palette: u8,
+        (flip_horizontal, flip_vertical): (bool, bool),
+    ) {
+        for row in 0..8 {
+            let source = if flip_vertical { 7 - row } else { row };
+            let low = self.ppu.peek_vram(&mut *self.mapper, address + source);
+            let high = self.ppu.peek_vram(&mut *self.mapper, address + source + 8);
+            for column in 0..8 {
+                let bit = if flip_horizontal { column } else { 7 - column };
+                let pixel = ((high >> bit) & 0x01) << 1 | (low >> bit) & 0x01;
+                let entry = if pixel == 0 {
+                    0x3F00
+                } else {
+                    0x3F00 + palette as u16 * 4 + pixel as u16
+                };
+                let color = self.ppu.peek_vram(&mut *self.mapper, entry) as u16;
+                image.set(left + column as usize, top + row as usize, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// javascript bindings for the browser frontend in web/, made with wasm-bindgen. build them with
+//   cargo rustc --lib --release --target wasm32-unknown-unknown --no-default-features --features wasm --crate-type cdylib
+//   wasm-bindgen --target web --out-dir web target/wasm32-unknown-unknown/release/ntsc_nes.wasm
+// which leaves ntsc_nes.js and ntsc_nes_bg.wasm next to the page. the rom comes in as the bytes of
+// an ArrayBuffer, nothing on this path touches a file system the browser doesn't have
+use crate::Emulator;
+use crate::controller::Player;
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+// one console, the page makes one per rom it runs
+#[wasm_bindgen]
+pub struct Nes {
+    emulator: Emulator,
+}
+
+fn js_error(error: impl ToString) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+#[wasm_bindgen]
+impl Nes {
+    // the console with rom in it powered on, or an error saying why the rom can't run
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &ArrayBuffer) -> Result<Nes, JsError> {
+        let emulator =
+            Emulator::from_rom_bytes(&Uint8Array::new(rom).to_vec()).map_err(js_error)?;
+        Ok(Nes { emulator })
+    }
+
+    // swaps the cartridge of the running console, which keeps its settings
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &ArrayBuffer) -> Result<(), JsError> {
+        self.emulator
+            .load_new_rom_bytes(&Uint8Array::new(rom).to_vec())
+            .map_err(js_error)
+    }
+
+    // the audio context's rate, which the samples are resampled to
+    #[wasm_bindgen(js_name = setSampleRate)]
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.emulator.set_sample_rate(sample_rate);
+    }
+
+    #[wasm_bindgen(js_name = stepFrame)]
+    pub fn step_frame(&mut self) {
+        self.emulator.step_frame();
+    }
+
+    pub fn reset(&mut self) {
+        self.emulator.soft_reset();
+    }
+
+    #[wasm_bindgen(js_name = powerCycle)]
+    pub fn power_cycle(&mut self) {
+        self.emulator.power_cycle();
+    }
+
+    // 256x240 RGBA8 through the video filter, a Uint8Array for ImageData
+    pub fn framebuffer(&mut self) -> Vec<u8> {
+        self.emulator.video_frame().to_vec()
+    }
+
+    // the mono samples of the frames run since the last call, a Float32Array
+    #[wasm_bindgen(js_name = takeAudio)]
+    pub fn take_audio(&mut self) -> Vec<f32> {
+        self.emulator.take_audio_samples()
+    }
+
+    // player counts from 0, buttons in shift order: A, B, Select, Start, Up, Down, Left, Right from
+    // bit 0. players 2 and 3 are the four score's, others are ignored
+    #[wasm_bindgen(js_name = setButtons)]
+    pub fn set_buttons(&mut self, player: u32, buttons: u8) {
+        let player = match player {
+            0 => Player::One,
+            1 => Player::Two,
+            2 => Player::Three,
+            3 => Player::Four,
+            _ => return,
+        };
+        self.emulator.set_buttons(player, buttons);
+    }
+}
@@ -0,0 +1,157 @@
+// the zapper light gun on port 2. its photodiode sees light while the beam has just drawn a bright
+// pixel under the aim, so the light bit is worked out from the part of the framebuffer already drawn
+use crate::Emulator;
+use crate::palette::Palette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// pixels around the aim point the sensor picks up
+const SENSOR_RADIUS: i32 = 3;
+// scanlines the photodiode stays lit after the beam passes
+const LIGHT_SCANLINES: u16 = 20;
+// average of the rgb channels a pixel needs to register as light
+const BRIGHTNESS_THRESHOLD: u16 = 85;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Zapper {
+    // the pixel aimed at, None while pointing away from the screen
+    pub aim: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+impl Zapper {
+    // bit 3 is 0 while light is sensed, bit 4 is 1 while the trigger is pulled
+    // brightness is judged in the colours of palette
+    pub fn read(&self, frame_buffer: &[u16], palette: &Palette, scanline: u16, dot: u16) -> u8 {
+        let mut value = if self.trigger { 0x10 } else { 0 };
+        if !self.senses_light(frame_buffer, palette, scanline, dot) {
+            value |= 0x08;
+        }
+        value
+    }
+
+    fn senses_light(
+        &self,
+        frame_buffer: &[u16],
+        palette: &Palette,
+        scanline: u16,
+        dot: u16,
+    ) -> bool {
+        let Some((x, y)) = self.aim else {
+            return false;
+        };
+        for row in y as i32 - SENSOR_RADIUS..=y as i32 + SENSOR_RADIUS {
+            if !(0..SCREEN_HEIGHT as i32).contains(&row) {
+                continue;
+            }
+            let row = row as u16;
+            // rows below the beam still hold the last frame, and the light fades a while after it passed
+            if scanline < row || scanline - row > LIGHT_SCANLINES {
+                continue;
+            }
+            for column in x as i32 - SENSOR_RADIUS..=x as i32 + SENSOR_RADIUS {
+                if !(0..SCREEN_WIDTH as i32).contains(&column) {
+                    continue;
+                }
+                // pixel x of a line is output on dot x + 1
+                if scanline == row && dot <= column as u16 + 1 {
+                    continue;
+                }
+                let color = frame_buffer[row as usize * SCREEN_WIDTH + column as usize];
+                let [red, green, blue] = palette.rgb(color);
+                if (red as u16 + green as u16 + blue as u16) / 3 >= BRIGHTNESS_THRESHOLD {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl Emulator {
+    // plugs a zapper into port 2 in place of the second pad, or takes it out again
+    pub fn connect_zapper(&mut self, connected: bool) {
+        self.zapper = connected.then(Zapper::default);
+    }
+
+    pub fn zapper_connected(&self) -> bool {
+        self.zapper.is_some()
+    }
+
+    // where the gun points in screen pixels and whether the trigger is held, ignored without a zapper
+    pub fn set_zapper(&mut self, aim: Option<(usize, usize)>, trigger: bool) {
+        if let Some(zapper) = &mut self.zapper {
+            zapper.aim = aim.filter(|&(x, y)| x < SCREEN_WIDTH && y < SCREEN_HEIGHT);
+            zapper.trigger = trigger;
+        }
+    }
+
+    pub(crate) fn read_zapper(&self) -> u8 {
+        match &self.zapper {
+            Some(zapper) => zapper.read(
+                self.ppu.frame_buffer(),
+                self.video.palette(),
+                self.ppu.scanline(),
+                self.ppu.dot(),
+            ),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // black everywhere but white at (x, y)
+    fn lit(x: usize, y: usize) -> Vec<u16> {
+        let mut frame_buffer = vec![0x0F; SCREEN_WIDTH * SCREEN_HEIGHT];
+        frame_buffer[y * SCREEN_WIDTH + x] = 0x30;
+        frame_buffer
+    }
+
+    fn aimed(x: usize, y: usize) -> Zapper {
+        Zapper {
+            aim: Some((x, y)),
+            trigger: false,
+        }
+    }
+
+    #[test]
+    fn light_is_seen_from_when_the_beam_passes_for_a_while() {
+        let palette = Palette::default();
+        let frame_buffer = lit(100, 50);
+        let light = |zapper: Zapper, scanline: u16, dot: u16| {
+            zapper.read(&frame_buffer, &palette, scanline, dot) & 0x08 == 0
+        };
+        let zapper = aimed(100, 50);
+        // the beam draws x 100 on dot 101, so the light comes after it
+        assert!(!light(zapper, 50, 101));
+        assert!(light(zapper, 50, 102));
+        assert!(light(zapper, 70, 0));
+        assert!(!light(zapper, 71, 0));
+        // above the pixel the framebuffer still holds the frame before
+        assert!(!light(zapper, 49, 340));
+
+        // the sensor takes in 3 pixels each way and no more
+        assert!(light(aimed(103, 47), 60, 0));
+        assert!(!light(aimed(104, 50), 60, 0));
+        assert!(!light(aimed(100, 54), 60, 0));
+        assert!(!light(Zapper::default(), 60, 0));
+
+        // $00 is a grey just short of bright enough
+        let frame_buffer = vec![0x00; SCREEN_WIDTH * SCREEN_HEIGHT];
+        assert_ne!(zapper.read(&frame_buffer, &palette, 60, 0) & 0x08, 0);
+    }
+
+    #[test]
+    fn the_trigger_sets_bit_4() {
+        let palette = Palette::default();
+        let frame_buffer = lit(0, 0);
+        let zapper = Zapper {
+            aim: None,
+            trigger: true,
+        };
+        assert_eq!(zapper.read(&frame_buffer, &palette, 0, 0), 0x18);
+        assert_eq!(Zapper::default().read(&frame_buffer, &palette, 0, 0), 0x08);
+    }
+}
@@ -0,0 +1,93 @@
+// the analysis following code from the vectors through the banks
+mod common;
+
+use ntsc_nes::Emulator;
+use ntsc_nes::analysis::{Analysis, ByteKind, Reason};
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::disasm::Line;
+
+#[test]
+fn analysis_follows_the_code_into_the_banks_it_fits() {
+    // uxrom with 4 banks, the fixed one calling $8000, which is an RTS only in bank 1
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 4, 0, 0x20, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0xFF; 0x10000];
+    prg[0x0000] = 0x02;
+    prg[0x4000] = 0x60;
+    prg[0x8000] = 0x00;
+    let fixed = 0xC000;
+    // JSR $8000, an unofficial NOP $00, LDA $C100,X, JMP to itself
+    prg[fixed..fixed + 11].copy_from_slice(&[
+        0x20, 0x00, 0x80, 0x04, 0x00, 0xBD, 0x00, 0xC1, 0x4C, 0x08, 0xC0,
+    ]);
+    prg[fixed + 0x100..fixed + 0x102].copy_from_slice(&[0x02, 0x02]);
+    prg[0xFFFA..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0, 0x00, 0xC0]);
+    rom.extend(prg);
+    let analysis = Analysis::new(&Cartridge::from_bytes(&rom).unwrap());
+
+    assert_eq!(analysis.entry_points[1].address, 0xC000);
+    assert_eq!(analysis.entry_points[1].offset, Some(fixed));
+    assert_eq!(analysis.banked_entries.get(&0x8000), Some(&1));
+    assert_eq!(analysis.unreachable_banks(), [0, 2]);
+    assert_eq!(analysis.kind(fixed), ByteKind::Code);
+    assert_eq!(analysis.kind(fixed + 1), ByteKind::Operand);
+    assert_eq!(analysis.kind(fixed + 0x100), ByteKind::Data);
+    assert_eq!(analysis.kind(0x4000), ByteKind::Code);
+    assert_eq!(analysis.kind(0x0000), ByteKind::Unknown);
+    assert!(
+        analysis
+            .suspicious
+            .iter()
+            .any(|found| found.address == 0xC003 && found.reason == Reason::Unofficial)
+    );
+
+    // the debugger shows the table and a jump into an operand as data
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    emulator.set_analysis(analysis);
+    let lines = emulator.disassemble_lines(0xC100, 1);
+    assert_eq!(
+        lines,
+        [Line::Data {
+            address: 0xC100,
+            bytes: vec![0x02]
+        }]
+    );
+    assert!(matches!(
+        emulator.disassemble_lines(0xC001, 1)[..],
+        [Line::Data {
+            address: 0xC001,
+            ..
+        }]
+    ));
+    let starts: Vec<u16> = emulator
+        .disassemble_lines(0xC000, 3)
+        .iter()
+        .map(|line| match line {
+            Line::Instruction(instruction) => instruction.address,
+            Line::Data { address, .. } => panic!("data at {address:04X}"),
+        })
+        .collect();
+    assert_eq!(starts, [0xC000, 0xC003, 0xC005]);
+}
+
+#[test]
+fn boards_without_a_known_layout_get_a_guess() {
+    // mapper 7 switches all 32K at once, which the guess of a fixed last 16K bank gets wrong
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 0, 0x70, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0xFF; 0x8000];
+    // LDA #$00, then a jam
+    prg[0x4000..0x4003].copy_from_slice(&[0xA9, 0x00, 0x02]);
+    prg[0x7FFA..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0, 0x00, 0xC0]);
+    rom.extend(prg);
+    let analysis = Analysis::new(&Cartridge::from_bytes(&rom).unwrap());
+    assert!(!analysis.known_layout);
+    assert_eq!(analysis.kind(0x4000), ByteKind::Code);
+    assert!(
+        analysis
+            .suspicious
+            .iter()
+            .any(|found| found.address == 0xC002 && found.reason == Reason::Jam)
+    );
+    assert!(analysis.to_string().contains("mapper 7"));
+}
@@ -0,0 +1,169 @@
+// the mixer and expansion sound
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::apu::Channel;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::clock::{Region, Speed};
+use ntsc_nes::expansion::Expansion;
+use ntsc_nes::nsf::Nsf;
+
+#[test]
+fn mixer_scales_and_mutes_channels() {
+    // a constant volume square wave on pulse 1 at about 440Hz
+    let mut program = store(0x4015, 0x01);
+    program.extend(store(0x4000, 0xBF));
+    program.extend(store(0x4002, 0xFD));
+    program.extend(store(0x4003, 0x00));
+    let [low, high] = (0xC000 + program.len() as u16).to_le_bytes();
+    program.extend([0x4C, low, high]);
+    let peak = |emulator: &mut Emulator| {
+        emulator.step_frame();
+        let samples = emulator.take_audio_samples();
+        samples
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()))
+    };
+
+    let mut emulator = program_rom(&program);
+    emulator.apu_mut().set_record_levels(true);
+    emulator.step_frame();
+    let full = peak(&mut emulator);
+    assert!(full > 0.01);
+    let frequency = emulator.apu().channel_frequency(Channel::Pulse1).unwrap();
+    assert!((frequency - 440.0).abs() < 1.0);
+    assert_eq!(emulator.apu().channel_frequency(Channel::Triangle), None);
+    let levels = emulator.apu_mut().take_levels();
+    assert!(levels.iter().any(|level| level[0] == 15));
+    assert!(levels.iter().all(|level| level[1] == 0));
+
+    emulator.apu_mut().set_channel_volume(Channel::Pulse1, 0.5);
+    peak(&mut emulator);
+    let half = peak(&mut emulator);
+    assert!(half > 0.01 && half < full * 0.75);
+
+    emulator
+        .apu_mut()
+        .set_channel_enabled(Channel::Pulse1, false);
+    for _ in 0..10 {
+        emulator.step_frame();
+    }
+    emulator.take_audio_samples();
+    assert!(peak(&mut emulator) < 0.001);
+    assert_eq!(emulator.apu().channel_volume(Channel::Pulse1), 0.5);
+}
+
+#[test]
+fn vrc6_and_disk_system_sound_mixes_in() {
+    // a mapper 24 cartridge running from the fixed bank at $E000, a square wave on the first vrc6
+    // pulse at about 440Hz
+    let mut program = store(0x9000, 0x7F);
+    program.extend(store(0x9001, 0xFF));
+    program.extend(store(0x9002, 0x80));
+    let [low, high] = (0xE000 + program.len() as u16).to_le_bytes();
+    program.extend([0x4C, low, high]);
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x80, 0x10];
+    rom.resize(16, 0);
+    let mut prg = vec![0xEA; 0x4000];
+    prg[0x2000..0x2000 + program.len()].copy_from_slice(&program);
+    prg[0x3FFC..].copy_from_slice(&[0x00, 0xE0, 0x00, 0xE0]);
+    rom.extend(prg);
+    rom.resize(rom.len() + 0x2000, 0);
+    let peak = |emulator: &mut Emulator| {
+        emulator.step_frame();
+        let samples = emulator.take_audio_samples();
+        samples
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()))
+    };
+
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    emulator.step_frame();
+    assert!(peak(&mut emulator) > 0.01);
+    emulator
+        .apu_mut()
+        .set_expansion_volume(Expansion::Vrc6, 0.0);
+    for _ in 0..10 {
+        emulator.step_frame();
+    }
+    emulator.take_audio_samples();
+    assert!(peak(&mut emulator) < 0.001);
+
+    // disk system music loads into ram from $6000, so it can write over its own code
+    let mut nsf = b"NESM\x1A\x01\x01\x01".to_vec();
+    nsf.extend([0x00, 0x80, 0x00, 0x80, 0x10, 0x80]);
+    nsf.resize(0x6E, 0);
+    nsf.extend(16_667u16.to_le_bytes());
+    nsf.resize(0x7B, 0);
+    nsf.push(0x04);
+    nsf.resize(0x80, 0);
+    // init: LDA #$42, STA $8100, LDA $8100, STA $10, LDA $4092, STA $11, RTS
+    nsf.extend([
+        0xA9, 0x42, 0x8D, 0x00, 0x81, 0xAD, 0x00, 0x81, 0x85, 0x10, 0xAD, 0x92, 0x40, 0x85, 0x11,
+        0x60,
+    ]);
+    nsf.extend([0x60]);
+    let mut emulator = Emulator::from_nsf(Nsf::from_bytes(&nsf).unwrap());
+    emulator.play_song(1);
+    emulator.step_nsf();
+    assert_eq!(emulator.ram()[0x10], 0x42);
+    // the modulation gain, with the open bus of the high bits
+    assert_eq!(emulator.ram()[0x11], 0x40);
+}
+
+#[test]
+fn volumes_stay_between_silent_and_full() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    emulator.apu_mut().set_channel_volume(Channel::Noise, 2.0);
+    assert_eq!(emulator.apu().channel_volume(Channel::Noise), 1.0);
+    emulator.apu_mut().set_channel_volume(Channel::Noise, -1.0);
+    assert_eq!(emulator.apu().channel_volume(Channel::Noise), 0.0);
+    emulator.apu_mut().set_expansion_volume(Expansion::Fds, 1.5);
+    assert_eq!(emulator.apu().expansion_volume(Expansion::Fds), 1.0);
+    // nothing playing is silence, once the filters have settled from power on
+    for _ in 0..10 {
+        emulator.step_frame();
+    }
+    emulator.take_audio_samples();
+    emulator.step_frame();
+    let samples = emulator.take_audio_samples();
+    assert!(samples.iter().all(|sample| sample.abs() < 0.001));
+}
+
+#[test]
+fn each_frame_is_resampled_to_its_share_of_the_output_rate() {
+    // the samples a frame has at the output rate: the rate over the frames a second at the speed
+    let cases = [
+        (Region::Ntsc, 44_100, 1.0, 733.8),
+        (Region::Pal, 44_100, 1.0, 881.9),
+        (Region::Ntsc, 48_000, 1.0, 798.7),
+        (Region::Pal, 48_000, 1.0, 959.9),
+        (Region::Ntsc, 44_100, 2.0, 366.9),
+        (Region::Pal, 48_000, 0.5, 1919.7),
+    ];
+    for (region, sample_rate, speed, per_frame) in cases {
+        let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+        emulator.set_region(region);
+        emulator.set_sample_rate(sample_rate);
+        emulator.set_speed(Speed::Multiplier(speed));
+        emulator.step_frame();
+        emulator.take_audio_samples();
+        let counts: Vec<usize> = (0..60)
+            .map(|_| {
+                emulator.step_frame();
+                emulator.take_audio_samples().len()
+            })
+            .collect();
+        let case = format!("{region:?} at {sample_rate}Hz and {speed}x");
+        // a frame gets the samples that end in it, one more or less than its share
+        for count in &counts {
+            assert!((*count as f64 - per_frame).abs() < 1.0, "{case}: {count}");
+        }
+        let total: usize = counts.iter().sum();
+        assert!(
+            (total as f64 - 60.0 * per_frame).abs() < 6.0,
+            "{case}: {total}"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// roms and patches built from a few bytes of program, shared by the tests. each test file is its
+// own crate and uses some of these
+#![allow(dead_code)]
+
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+
+// a 16K nrom cartridge running program from $C000
+pub fn program_rom(program: &[u8]) -> Emulator {
+    nrom(program, 1)
+}
+
+// the same with chr_banks 8K chr roms, none gives the board chr ram
+pub fn nrom(program: &[u8], chr_banks: u8) -> Emulator {
+    Emulator::new(Cartridge::from_bytes(&nrom_file(program, chr_banks)).unwrap()).unwrap()
+}
+
+pub fn nrom_file(program: &[u8], chr_banks: u8) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, chr_banks, 0, 0];
+    rom.resize(16, 0);
+    let mut prg = program.to_vec();
+    prg.resize(0x4000, 0xEA);
+    prg[0x3FFC..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0]);
+    rom.extend(prg);
+    rom.resize(rom.len() + chr_banks as usize * 0x2000, 0);
+    rom
+}
+
+// LDA #value, STA address
+pub fn store(address: u16, value: u8) -> Vec<u8> {
+    let [low, high] = address.to_le_bytes();
+    vec![0xA9, value, 0x8D, low, high]
+}
+
+// the crc32 of zip and png, that bps patches carry
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// 7 bits a byte, the top bit set on the last
+pub fn bps_number(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte | 0x80);
+            return bytes;
+        }
+        bytes.push(byte);
+        value -= 1;
+    }
+}
@@ -0,0 +1,92 @@
+// the debugger: breakpoints, watchpoints and stepping
+mod common;
+
+use common::program_rom;
+use ntsc_nes::Emulator;
+use ntsc_nes::debugger::{Access, StopReason};
+
+// counts X from 1 to 3 into $10, then reads it back and halts: LDX #$00, INX, STX $10, CPX #$03,
+// BNE back to the INX, LDA $10, HLT
+fn counting_loop() -> Emulator {
+    program_rom(&[
+        0xA2, 0x00, 0xE8, 0x86, 0x10, 0xE0, 0x03, 0xD0, 0xF9, 0xA5, 0x10, 0x02,
+    ])
+}
+
+#[test]
+fn continuing_stops_at_each_breakpoint_until_the_cpu_halts() {
+    let mut emulator = counting_loop();
+    emulator.debugger_mut().add_breakpoint(0xC003);
+    for x in 1..=3 {
+        assert_eq!(emulator.debug_continue(), StopReason::Breakpoint(0xC003));
+        assert_eq!(emulator.cpu().program_counter, 0xC003);
+        assert_eq!(emulator.cpu().reg_x, x);
+    }
+    assert!(emulator.debugger_mut().remove_breakpoint(0xC003));
+    assert!(!emulator.debugger_mut().remove_breakpoint(0xC003));
+    assert_eq!(emulator.debugger().breakpoints().count(), 0);
+    assert_eq!(emulator.debug_continue(), StopReason::Halted);
+    assert_eq!(emulator.ram()[0x10], 3);
+    assert_eq!(emulator.cpu().reg_a, 3);
+}
+
+#[test]
+fn stepping_runs_one_instruction_at_a_time() {
+    let mut emulator = counting_loop();
+    for pc in [0xC002, 0xC003, 0xC005] {
+        assert_eq!(emulator.debug_step(), None);
+        assert_eq!(emulator.cpu().program_counter, pc);
+    }
+    // a breakpoint on the next instruction ends the step with it
+    emulator.debugger_mut().add_breakpoint(0xC007);
+    assert_eq!(emulator.debug_step(), Some(StopReason::Breakpoint(0xC007)));
+    // and continuing from a breakpoint runs past it, back round the loop to it again
+    assert_eq!(emulator.debug_continue(), StopReason::Breakpoint(0xC007));
+    assert_eq!(emulator.cpu().reg_x, 2);
+}
+
+#[test]
+fn watchpoints_stop_after_the_instruction_touching_their_address() {
+    let mut emulator = counting_loop();
+    emulator
+        .debugger_mut()
+        .add_watchpoint(Access::Write, 0x0010);
+    emulator.debugger_mut().add_watchpoint(Access::Read, 0x0010);
+    let write = |value| StopReason::Watchpoint {
+        access: Access::Write,
+        address: 0x0010,
+        value,
+    };
+    assert_eq!(emulator.debug_continue(), write(1));
+    assert_eq!(emulator.cpu().program_counter, 0xC005);
+    assert_eq!(emulator.debug_continue(), write(2));
+
+    assert!(
+        emulator
+            .debugger_mut()
+            .remove_watchpoint(Access::Write, 0x0010)
+    );
+    let read = StopReason::Watchpoint {
+        access: Access::Read,
+        address: 0x0010,
+        value: 3,
+    };
+    assert_eq!(emulator.debug_continue(), read);
+    assert_eq!(emulator.cpu().program_counter, 0xC00B);
+    let watched: Vec<u16> = emulator.debugger().watchpoints(Access::Read).collect();
+    assert_eq!(watched, [0x0010]);
+    assert_eq!(emulator.debug_continue(), StopReason::Halted);
+}
+
+#[test]
+fn a_frame_stopped_at_a_breakpoint_carries_on_with_the_next_call() {
+    // INC $10, JMP back to it
+    let mut emulator = program_rom(&[0xE6, 0x10, 0x4C, 0x00, 0xC0]);
+    assert_eq!(emulator.debug_frame(), StopReason::FrameComplete);
+    assert_eq!(emulator.frame_count(), 1);
+    emulator.debugger_mut().add_breakpoint(0xC002);
+    assert_eq!(emulator.debug_frame(), StopReason::Breakpoint(0xC002));
+    emulator.debugger_mut().remove_breakpoint(0xC002);
+    assert_eq!(emulator.debug_frame(), StopReason::FrameComplete);
+    assert_eq!(emulator.frame_count(), 2);
+}
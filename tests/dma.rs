@@ -0,0 +1,93 @@
+// cycles the cpu loses to oam and dmc dma
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::memory::MemorySpace;
+
+// the dmc playing 17 bytes from $C000 at its fastest rate, a byte every 432 cycles
+fn dmc_on() -> Vec<u8> {
+    let mut program = store(0x4010, 0x0F);
+    program.extend(store(0x4012, 0x00));
+    program.extend(store(0x4013, 0x01));
+    program.extend(store(0x4015, 0x10));
+    program
+}
+
+// the cycles of each instruction run until a HLT
+fn instruction_cycles(emulator: &mut Emulator) -> Vec<usize> {
+    let mut cycles = Vec::new();
+    while !emulator.halted() {
+        cycles.push(emulator.step_instruction());
+    }
+    cycles
+}
+
+#[test]
+fn oam_dma_stalls_the_cpu_513_cycles_or_514_from_an_odd_one() {
+    // LDA #$02, STA $4014 with the write on an even cycle, after 7 of reset and 2 of the LDA
+    let mut program = store(0x4014, 0x02);
+    program.push(0x02);
+    let mut emulator = program_rom(&program);
+    for (offset, value) in emulator.ram_mut()[0x200..0x300].iter_mut().enumerate() {
+        *value = offset as u8 ^ 0x5A;
+    }
+    assert_eq!(emulator.step_instruction(), 2);
+    assert_eq!(emulator.cycles(), 9);
+    assert_eq!(emulator.step_instruction(), 4 + 513);
+    for address in 0..0x100 {
+        let expected = address as u8 ^ 0x5A;
+        // bits 2-4 of the attribute bytes are not there to keep
+        let expected = if address % 4 == 2 {
+            expected & 0xE3
+        } else {
+            expected
+        };
+        assert_eq!(emulator.read_memory(MemorySpace::Oam, address), expected);
+    }
+
+    // LDA $00 first puts the write on an odd cycle, and the copy waits one more for a read cycle
+    let mut program = vec![0xA5, 0x00];
+    program.extend(store(0x4014, 0x02));
+    program.push(0x02);
+    let mut emulator = program_rom(&program);
+    assert_eq!(instruction_cycles(&mut emulator)[..3], [3, 2, 4 + 514]);
+}
+
+#[test]
+fn dmc_fetches_steal_4_cycles_from_the_cpu() {
+    let mut emulator = program_rom(&dmc_on());
+    let mut fetches = 0;
+    let mut cycles = 0;
+    // the four stores, then nops until the 17 bytes are played
+    for _ in 0..8 {
+        cycles += emulator.step_instruction();
+    }
+    while emulator.cycles() < 10_000 {
+        let taken = emulator.step_instruction();
+        match taken {
+            2 => {}
+            6 => fetches += 1,
+            _ => panic!("a nop took {taken} cycles"),
+        }
+        cycles += taken;
+    }
+    assert_eq!(fetches, 17);
+    assert_eq!(emulator.cycles(), 7 + cycles as u64);
+}
+
+#[test]
+fn a_dmc_fetch_during_oam_dma_takes_2_cycles() {
+    // the first byte is fetched right after the dmc starts, the second some 720 cycles later is
+    // pushed into an oam dma started 280 nops on
+    let mut program = dmc_on();
+    program.extend([0xEA; 280]);
+    program.extend(store(0x4014, 0x02));
+    program.push(0x02);
+    let mut emulator = program_rom(&program);
+    let cycles = instruction_cycles(&mut emulator);
+    assert_eq!(cycles.iter().filter(|&&taken| taken == 6).count(), 1);
+    // the copy starts on an even cycle, and the loaded byte costs a cycle to read and one to
+    // realign to the writes
+    assert_eq!(cycles[cycles.len() - 2], 4 + 513 + 2);
+}
@@ -0,0 +1,29 @@
+// step_frame, one frame at a time with what it made
+mod common;
+
+use common::program_rom;
+
+#[test]
+fn step_frame_runs_one_frame() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    let first = emulator.step_frame().frame;
+    let frame = emulator.step_frame();
+    assert_eq!(frame.frame, first + 1);
+    // 341 * 262 / 3 cpu cycles to an ntsc frame, give or take the JMP that runs into vblank
+    assert!(frame.cycles.abs_diff(29781) <= 3);
+    assert!(!frame.samples.is_empty());
+    assert!(!frame.halted);
+
+    let mut emulator = program_rom(&[0xEA, 0x02]);
+    assert!(emulator.step_frame().halted);
+}
+
+#[test]
+fn a_halted_console_runs_no_more_frames() {
+    let mut emulator = program_rom(&[0x02]);
+    assert!(emulator.step_frame().halted);
+    let frame = emulator.step_frame();
+    assert!(frame.halted);
+    assert_eq!(frame.cycles, 0);
+    assert!(frame.samples.is_empty());
+}
@@ -0,0 +1,107 @@
+// golden runs recorded and checked against
+mod common;
+
+use common::{nrom_file, program_rom, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::golden::{GoldenError, GoldenRun};
+
+#[test]
+fn golden_runs_catch_the_first_frame_that_differs() {
+    // counts frames in $10 from the nmi, and from frame 100 on $11 too when told to
+    let program = |diverge: bool| {
+        let mut program = store(0x2000, 0x80);
+        // spin: JMP spin
+        let [low, high] = (0xC000 + program.len() as u16).to_le_bytes();
+        program.extend([0x4C, low, high]);
+        program.resize(0x100, 0xEA);
+        // nmi: INC $10, LDA $10, CMP #100, BCC done, INC $11 when diverging, done: RTI
+        program.extend([0xE6, 0x10, 0xA5, 0x10, 0xC9, 100, 0x90, 0x02]);
+        program.extend(if diverge { [0xE6, 0x11] } else { [0xEA, 0xEA] });
+        program.push(0x40);
+        let mut rom = nrom_file(&program, 1);
+        let vectors = rom.len() - 0x2000 - 6;
+        rom[vectors..vectors + 2].copy_from_slice(&[0x00, 0xC1]);
+        Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap()
+    };
+
+    let mut emulator = program(false);
+    let mut run = GoldenRun::new(30);
+    for _ in 0..240 {
+        emulator.step_frame();
+        run.record(&emulator);
+    }
+    assert_eq!(run.checkpoints.len(), 8);
+    let run = GoldenRun::parse(&run.to_text()).unwrap();
+    assert_eq!(run.last_frame(), 240);
+
+    let mut emulator = program(false);
+    for _ in 0..240 {
+        emulator.step_frame();
+        assert!(run.verify(&mut emulator).is_ok());
+    }
+    let mut emulator = program(true);
+    let mut diverged = None;
+    for _ in 0..240 {
+        emulator.step_frame();
+        if let Err(divergence) = run.verify(&mut emulator) {
+            diverged = Some(divergence);
+            break;
+        }
+    }
+    let divergence = diverged.unwrap();
+    assert_eq!(divergence.actual.frame, 120);
+    assert_ne!(divergence.expected.ram, divergence.actual.ram);
+    assert_eq!(divergence.expected.picture, divergence.actual.picture);
+    assert!(divergence.to_string().contains("* ram"));
+    assert_eq!(GoldenRun::parse("frame=1"), Err(GoldenError::NotAGoldenRun));
+}
+
+#[test]
+fn golden_files_point_at_the_line_they_cannot_read() {
+    let header = "ntsc-nes golden run 1\n";
+    assert_eq!(GoldenRun::parse(""), Err(GoldenError::NotAGoldenRun));
+    assert_eq!(GoldenRun::parse(header), Err(GoldenError::BadLine(2)));
+    assert_eq!(
+        GoldenRun::parse(&format!("{header}every many\n")),
+        Err(GoldenError::BadLine(2))
+    );
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    emulator.step_frame();
+    let mut run = GoldenRun::new(1);
+    run.record(&emulator);
+    let text = run.to_text();
+    // a field misnamed, one given twice in place of another, one too many and one that isn't a
+    // number
+    let checkpoint = text.lines().nth(2).unwrap();
+    for broken in [
+        checkpoint.replace(" dot=", " dots="),
+        checkpoint.replace(" dot=", " x="),
+        format!("{checkpoint} extra=1"),
+        checkpoint.replace("frame=1", "frame=one"),
+    ] {
+        assert_eq!(
+            GoldenRun::parse(&format!("{header}every 1\n\n{broken}\n")),
+            Err(GoldenError::BadLine(4))
+        );
+    }
+    // checkpoints every 0 frames, and ones that repeat or go back a frame
+    assert_eq!(
+        GoldenRun::parse(&format!("{header}every 0\n")),
+        Err(GoldenError::BadLine(2))
+    );
+    let second = checkpoint.replace("frame=1", "frame=2");
+    for (lines, error) in [
+        ([checkpoint, checkpoint], GoldenError::OutOfOrder(4)),
+        ([second.as_str(), checkpoint], GoldenError::OutOfOrder(4)),
+    ] {
+        let text = format!("{header}every 1\n{}\n{}\n", lines[0], lines[1]);
+        assert_eq!(GoldenRun::parse(&text), Err(error));
+    }
+    let text = format!("{header}every 1\n{checkpoint}\n{second}\n");
+    assert_eq!(GoldenRun::parse(&text).unwrap().last_frame(), 2);
+
+    // a run with nothing recorded checks nothing
+    assert_eq!(GoldenRun::new(0).interval, 1);
+    assert!(GoldenRun::new(60).verify(&mut emulator).is_ok());
+}
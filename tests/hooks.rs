@@ -0,0 +1,76 @@
+// hooks on frames, nmis, memory accesses and scanlines
+mod common;
+
+use common::program_rom;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+#[test]
+fn hooks_see_frames_nmis_writes_and_scanlines() {
+    // turns the nmi on and counts $10 up, the nmi vector starts it over: LDA #$80, STA $2000,
+    // INC $10, JMP $C005
+    let mut emulator = program_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xE6, 0x10, 0x4C, 0x05, 0xC0]);
+    let frames = Rc::new(Cell::new(0));
+    let nmis = Rc::new(Cell::new(0));
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let lines = Rc::new(Cell::new(0));
+    let frame_hook = emulator.on_frame({
+        let frames = frames.clone();
+        move |_| frames.set(frames.get() + 1)
+    });
+    emulator.on_nmi({
+        let nmis = nmis.clone();
+        move |api| {
+            assert_eq!(api.scanline(), 241);
+            nmis.set(nmis.get() + 1)
+        }
+    });
+    emulator.on_memory_write(0x0010..=0x0010, {
+        let writes = writes.clone();
+        move |_, address, value| writes.borrow_mut().push((address, value))
+    });
+    emulator.on_scanline(100, {
+        let lines = lines.clone();
+        move |api| {
+            assert_eq!(api.scanline(), 100);
+            lines.set(lines.get() + 1)
+        }
+    });
+    for _ in 0..3 {
+        emulator.step_frame();
+    }
+    assert_eq!(frames.get(), 3);
+    // the nmi of each vblank is taken as the next frame starts
+    assert_eq!(nmis.get(), 2);
+    assert_eq!(lines.get(), 3);
+    // INC writes the old value back before the new one, and nothing outside the range is seen
+    let written = writes.borrow().clone();
+    assert!(written.iter().all(|&(address, _)| address == 0x0010));
+    assert_eq!(written[..2], [(0x0010, 0xFF), (0x0010, 0x00)]);
+    assert_eq!(written.last(), Some(&(0x0010, emulator.peek(0x0010))));
+
+    emulator.remove_hook(frame_hook);
+    emulator.step_frame();
+    assert_eq!(frames.get(), 3);
+    assert_eq!(lines.get(), 4);
+}
+
+#[test]
+fn hooks_only_see_their_range_and_removing_one_twice_does_nothing() {
+    // LDA $10, LDA $20, JMP $C000
+    let mut emulator = program_rom(&[0xA5, 0x10, 0xA5, 0x20, 0x4C, 0x00, 0xC0]);
+    let reads = Rc::new(RefCell::new(Vec::new()));
+    let hook = emulator.on_memory_read(0x0020..=0x0020, {
+        let reads = reads.clone();
+        move |_, address, _| reads.borrow_mut().push(address)
+    });
+    emulator.step_frame();
+    assert!(!reads.borrow().is_empty());
+    assert!(reads.borrow().iter().all(|&address| address == 0x0020));
+
+    emulator.remove_hook(hook);
+    emulator.remove_hook(hook);
+    reads.borrow_mut().clear();
+    emulator.step_frame();
+    assert!(reads.borrow().is_empty());
+}
@@ -0,0 +1,97 @@
+// the four score, autofire and the zapper
+mod common;
+
+use common::program_rom;
+use ntsc_nes::controller::{Autofire, Button, FrameInput};
+use ntsc_nes::memory::MemorySpace;
+
+// strobes the pads and stores 24 reads of $4016 at $00 and of $4017 at $20
+const FOUR_SCORE_READS: [u8; 34] = [
+    0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xA2, 0x00, 0xAD, 0x16, 0x40, 0x29,
+    0x01, 0x95, 0x00, 0xAD, 0x17, 0x40, 0x29, 0x01, 0x95, 0x20, 0xE8, 0xE0, 0x18, 0xD0, 0xED, 0x4C,
+    0x1F, 0xC0,
+];
+
+#[test]
+fn four_score_reads_out_all_four_pads_and_its_signature() {
+    let mut emulator = program_rom(&FOUR_SCORE_READS);
+    emulator.connect_four_score(true);
+    emulator.apply_input(FrameInput {
+        buttons: [0x01, 0x02, 0x80, 0x01],
+        ..FrameInput::default()
+    });
+    emulator.step_frame();
+    let ones = |start: usize| -> Vec<usize> {
+        (0..24)
+            .filter(|bit| emulator.ram()[start + bit] == 1)
+            .collect()
+    };
+    assert_eq!(ones(0x00), [0, 15, 19]);
+    assert_eq!(ones(0x20), [1, 8, 18]);
+}
+
+#[test]
+fn autofire_alternates_held_turbo_buttons() {
+    let mut autofire = Autofire::new(60.0);
+    autofire.set_rate(Button::B, 30.0);
+    let held = [0x03, 0, 0, 0x01];
+    let frames: Vec<[u8; 4]> = (0..4).map(|_| autofire.next_frame(held)).collect();
+    // A at 15 presses a second is down for 2 frames and up for 2, B at 30 every other frame
+    assert_eq!(
+        frames,
+        [
+            [0x03, 0, 0, 0x01],
+            [0x01, 0, 0, 0x01],
+            [0x02, 0, 0, 0],
+            [0, 0, 0, 0]
+        ]
+    );
+    assert_eq!(autofire.rate(Button::A), 15.0);
+}
+
+#[test]
+fn without_the_four_score_the_pads_read_1_after_their_eight_buttons() {
+    let mut emulator = program_rom(&FOUR_SCORE_READS);
+    emulator.set_input([0x01, 0x02, 0x80, 0x01]);
+    emulator.step_frame();
+    let ones = |start: usize| -> Vec<usize> {
+        (0..24)
+            .filter(|bit| emulator.ram()[start + bit] == 1)
+            .collect()
+    };
+    // players 3 and 4 and the signature are not there
+    let after = |first: usize| -> Vec<usize> { [first].into_iter().chain(8..24).collect() };
+    assert_eq!(ones(0x00), after(0));
+    assert_eq!(ones(0x20), after(1));
+}
+
+#[test]
+fn the_zapper_takes_port_2_and_sees_a_white_screen() {
+    // shows the background, then reads $4017 into $11 over and over and counts the reads with
+    // light at $10: LDA $4017, STA $11, AND #$08, BNE over the INC $10, JMP back
+    let program = [
+        0xA9, 0x08, 0x8D, 0x01, 0x20, 0xAD, 0x17, 0x40, 0x85, 0x11, 0x29, 0x08, 0xD0, 0x02, 0xE6,
+        0x10, 0x4C, 0x05, 0xC0,
+    ];
+    let light_reads = |backdrop: u8, aim: Option<(usize, usize)>| {
+        let mut emulator = program_rom(&program);
+        emulator.write_memory(MemorySpace::Palette, 0x00, backdrop);
+        emulator.connect_zapper(true);
+        emulator.set_zapper(aim, true);
+        emulator.step_frame();
+        emulator.ram_mut()[0x10] = 0;
+        emulator.step_frame();
+        assert_eq!(emulator.ram()[0x11] & 0x10, 0x10);
+        emulator.ram()[0x10]
+    };
+    assert!(light_reads(0x30, Some((128, 120))) > 0);
+    assert_eq!(light_reads(0x0F, Some((128, 120))), 0);
+    assert_eq!(light_reads(0x30, None), 0);
+
+    // without it port 2 is a pad again
+    let mut emulator = program_rom(&program);
+    emulator.set_zapper(Some((128, 120)), true);
+    assert!(!emulator.zapper_connected());
+    emulator.step_frame();
+    assert_eq!(emulator.ram()[0x11] & 0x18, 0);
+}
@@ -0,0 +1,213 @@
+// lua scripts: the language, the callbacks they register and what they do to the machine
+mod common;
+
+use common::program_rom;
+use ntsc_nes::Emulator;
+
+// turns the nmi on and counts $10 up, the nmi vector starts it over: LDA #$80, STA $2000, INC $10,
+// JMP $C005
+fn counting_rom() -> Emulator {
+    program_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xE6, 0x10, 0x4C, 0x05, 0xC0])
+}
+
+// what the main chunk of source printed
+fn run(source: &str) -> String {
+    let mut emulator = counting_rom();
+    emulator.load_lua("test.lua", source).unwrap();
+    emulator.take_script_output()
+}
+
+#[test]
+fn scripts_have_locals_closures_tables_and_loops() {
+    let source = r#"
+        local function counter()
+            local count = 0
+            return function() count = count + 1; return count end
+        end
+        local next_one = counter()
+        next_one()
+        print(next_one(), type(next_one), 7 // 1 == nil)
+    "#;
+    // // is not lua 5.1, the error names the line
+    let mut emulator = counting_rom();
+    let error = emulator.load_lua("test.lua", source).unwrap_err();
+    assert_eq!(error.line, 8);
+
+    let source = r#"
+        local function counter()
+            local count = 0
+            return function() count = count + 1; return count end
+        end
+        local next_one = counter()
+        next_one()
+        print(next_one(), type(next_one), -2 ^ 2, 7 % 3, 1 .. 2)
+
+        local squares = {}
+        for i = 1, 5 do squares[#squares + 1] = i * i end
+        print(#squares, table.concat(squares, ","))
+        local total = 0
+        for _, square in ipairs(squares) do total = total + square end
+        local keys = {}
+        for key, value in pairs({b = 2, a = 1, c = 3}) do keys[#keys + 1] = key .. value end
+        print(total, table.concat(keys, " "))
+
+        local i, found = 10, nil
+        while true do
+            i = i - 3
+            if i < 0 then break elseif i == 4 then found = i end
+        end
+        repeat local done = true until done
+        print(i, found, not found, found and "yes" or "no")
+
+        local point = {x = 1, y = 2}
+        function point:move(dx) self.x = self.x + dx; return self end
+        print(point:move(4).x, ("%02X %5.2f|%-3s|%d"):format(171, 3.14159, "a", -7))
+        print(string.sub("hello", 2, -2), ("abc"):upper(), tostring(nil), tonumber("0x1F"),
+            tonumber("ff", 16), math.max(3, 9, 4), math.floor(-1.5), AND(0xF0, 0x3C),
+            bit.lshift(1, 4), BIT(7))
+        table.insert(squares, 1, 0)
+        print(table.remove(squares), squares[1], #squares, select)
+    "#;
+    assert_eq!(
+        run(source),
+        "2\tfunction\t-4\t1\t12\n\
+         5\t1,4,9,16,25\n\
+         55\ta1 b2 c3\n\
+         -2\t4\tfalse\tyes\n\
+         5\tAB  3.14|a  |-7\n\
+         ell\tABC\tnil\t31\t255\t9\t-2\t48\t16\t128\n\
+         25\t0\t5\tnil\n"
+    );
+}
+
+#[test]
+fn errors_name_their_line() {
+    let mut emulator = counting_rom();
+    let error = emulator
+        .load_lua("test.lua", "local x = 1\nif x then\nprint(x)\n")
+        .unwrap_err();
+    assert_eq!(error.line, 4);
+    assert!(error.message.contains("'end' expected"), "{error}");
+
+    let error = emulator
+        .load_lua("test.lua", "local t = nil\n\nprint(t.field)")
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 3: attempt to index 't' (a nil value)"
+    );
+
+    let error = emulator
+        .load_lua("test.lua", "memory.readbyte('x')")
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "bad argument #1 to 'memory.readbyte' (number expected, got string)"
+    );
+
+    let error = emulator
+        .load_lua("test.lua", "while true do end")
+        .unwrap_err();
+    assert!(error.message.contains("too long"), "{error}");
+    let error = emulator
+        .load_lua("test.lua", "local function f() return f() + 1 end f()")
+        .unwrap_err();
+    assert_eq!(error.message, "stack overflow");
+    // a string no host has memory for, and one whose length does not fit in a usize
+    for count in ["1e9", "2^63"] {
+        let source = format!("print(#string.rep('ab', {count}))");
+        let error = emulator.load_lua("test.lua", &source).unwrap_err();
+        assert_eq!(error.message, "resulting string too large");
+    }
+    assert_eq!(run("print(#string.rep('ab', 1000))"), "2000\n");
+    assert_eq!(emulator.take_script_output(), "");
+}
+
+#[test]
+fn callbacks_run_on_frames_and_memory_writes() {
+    let mut emulator = counting_rom();
+    let source = r#"
+        writes = 0
+        starts = 0
+        memory.registerwrite(0x10, function(address, value)
+            writes = writes + 1
+            last = value
+        end)
+        emu.registerbefore(function() starts = starts + 1 end)
+        emu.registerafter(function()
+            print(emu.framecount(), starts, last == memory.readbyte(0x10), writes > 0)
+        end)
+    "#;
+    emulator.load_lua("test.lua", source).unwrap();
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(
+        emulator.take_script_output(),
+        "1\t1\ttrue\ttrue\n2\t2\ttrue\ttrue\n"
+    );
+
+    emulator.clear_scripts();
+    emulator.step_frame();
+    assert_eq!(emulator.take_script_output(), "");
+
+    // registering nil takes the hook away again
+    let source = r#"
+        memory.registerwrite(0x10, function()
+            print("write")
+            memory.registerwrite(0x10, nil)
+        end)
+    "#;
+    emulator.load_lua("test.lua", source).unwrap();
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(emulator.take_script_output(), "write\n");
+}
+
+#[test]
+fn scripts_poke_memory_read_registers_and_set_input() {
+    let mut emulator = counting_rom();
+    let source = r##"
+        emu.registerbefore(function()
+            memory.writebyte(0x300, 0x42)
+            joypad.set(1, {A = true, start = true})
+            joypad.set(2, {right = true})
+        end)
+        emu.registerafter(function()
+            local pad = joypad.read(1)
+            local pc = memory.getregister("pc")
+            print(memory.readbyte(0x300), pad.A, pad.start, pad.B, pc >= 0xC000 and pc <= 0xC009)
+            gui.box(10, 20, 13, 22, "#FF000080", "red")
+            gui.text(0, 100, "hi")
+        end)
+    "##;
+    emulator.load_lua("test.lua", source).unwrap();
+    emulator.step_frame();
+    assert_eq!(
+        emulator.take_script_output(),
+        "66\ttrue\ttrue\tfalse\ttrue\n"
+    );
+    assert_eq!(emulator.ram()[0x300], 0x42);
+    let pixel = |emulator: &mut Emulator, x: usize, y: usize| {
+        let offset = (y * 256 + x) * 4;
+        let frame = emulator.video_frame();
+        [frame[offset], frame[offset + 1], frame[offset + 2]]
+    };
+    // the outline is drawn over the half transparent fill
+    assert_eq!(pixel(&mut emulator, 10, 20), [0xFF, 0x00, 0x00]);
+    assert_eq!(pixel(&mut emulator, 13, 22), [0xFF, 0x00, 0x00]);
+    let inside = pixel(&mut emulator, 11, 21);
+    let outside = pixel(&mut emulator, 30, 21);
+    assert_ne!(inside, outside);
+    assert!(inside[0] > inside[1]);
+}
+
+#[test]
+fn an_error_in_a_callback_stops_the_script() {
+    let mut emulator = counting_rom();
+    let source = "calls = 0\nemu.registerafter(function()\ncalls = calls + 1\nprint(calls)\nerror('boom')\nend)";
+    emulator.load_lua("test.lua", source).unwrap();
+    for _ in 0..3 {
+        emulator.step_frame();
+    }
+    assert_eq!(emulator.take_script_output(), "1\ntest.lua: line 5: boom\n");
+}
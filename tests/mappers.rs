@@ -0,0 +1,224 @@
+// the boards: prg banking and mirroring, mmc1 and mmc3, chr ram and mmc5
+mod common;
+
+use common::{nrom, program_rom, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::error::EmuError;
+use ntsc_nes::memory::MemorySpace;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn small_prg_roms_are_mirrored_and_vectors_come_from_the_last_bank() {
+    // a 16K nrom shows up at both $8000 and $C000
+    let mut emulator = program_rom(&[0xEA, 0x02]);
+    assert_eq!(emulator.cpu().program_counter, 0xC000);
+    assert_eq!(emulator.peek(0x8000), 0xEA);
+    assert_eq!(emulator.peek(0xBFFD), 0xC0);
+    assert!(emulator.run_until_halt_or(10));
+
+    // three 16K uxrom banks filled with their numbers, the last one selects bank 4 and halts
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 3, 0, 0x20, 0];
+    rom.resize(16, 0);
+    for bank in 0..3 {
+        rom.extend(vec![bank; 0x4000]);
+    }
+    let last = rom.len() - 0x4000;
+    rom[last..last + 6].copy_from_slice(&[0xA9, 0x04, 0x8D, 0x00, 0x80, 0x02]);
+    rom[last + 0x3FFC..last + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    assert_eq!(emulator.cpu().program_counter, 0xC000);
+    assert_eq!(emulator.peek(0x8000), 0);
+    // bank numbers past the last wrap around
+    assert!(emulator.run_until_halt_or(10));
+    assert_eq!(emulator.peek(0x8000), 1);
+    assert_eq!(emulator.peek(0xFFFF), 2);
+}
+
+// an mmc1 board of eight 16K prg banks and eight 4K chr banks, each filled with its number.
+// program is in every prg bank, so it carries on whichever is switched in
+fn mmc1(program: &[u8]) -> Emulator {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 8, 4, 0x10, 0];
+    rom.resize(16, 0);
+    for bank in 0..8 {
+        let mut prg = vec![bank; 0x4000];
+        prg[..program.len()].copy_from_slice(program);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        rom.extend(prg);
+    }
+    for bank in 0..8 {
+        rom.extend(vec![bank; 0x1000]);
+    }
+    Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap()
+}
+
+// the five writes loading an mmc1 register a bit at a time
+fn serial_write(address: u16, value: u8) -> Vec<u8> {
+    (0..5)
+        .flat_map(|bit| store(address, value >> bit & 0x01))
+        .collect()
+}
+
+#[test]
+fn mmc1_switches_prg_and_chr_in_each_bank_mode() {
+    // control, prg bank, the two chr banks, then the banks at $A000, $E000, ppu $0000 and $1000
+    let cases = [
+        // $8000 switched and the last bank at $C000, one 8K chr bank with the low bit dropped
+        (0x0C, 2, 3, 5, [2, 7, 2, 3]),
+        // the first bank at $8000 and $C000 switched
+        (0x08, 5, 3, 5, [0, 5, 2, 3]),
+        // 32K at a time, the low bit of the prg bank dropped
+        (0x00, 5, 3, 5, [4, 5, 2, 3]),
+        (0x04, 2, 3, 5, [2, 3, 2, 3]),
+        // two 4K chr banks
+        (0x1C, 2, 3, 5, [2, 7, 3, 5]),
+    ];
+    for (control, prg, chr_0, chr_1, expected) in cases {
+        let mut program = serial_write(0x8000, control);
+        program.extend(serial_write(0xA000, chr_0));
+        program.extend(serial_write(0xC000, chr_1));
+        program.extend(serial_write(0xE000, prg));
+        program.push(0x02);
+        let mut emulator = mmc1(&program);
+        assert!(emulator.run_until_halt_or(10));
+        let banks = [
+            emulator.peek(0xA000),
+            emulator.peek(0xE000),
+            emulator.read_memory(MemorySpace::Ppu, 0x0000),
+            emulator.read_memory(MemorySpace::Ppu, 0x1000),
+        ];
+        assert_eq!(banks, expected, "control {control:#04x}");
+    }
+
+    // a write with the top bit set drops the bits shifted in so far and fixes the last bank again
+    let mut program = serial_write(0x8000, 0x08);
+    program.extend(store(0xE000, 0x01));
+    program.extend(store(0xE000, 0x80));
+    program.extend(serial_write(0xE000, 2));
+    program.push(0x02);
+    let mut emulator = mmc1(&program);
+    assert!(emulator.run_until_halt_or(10));
+    assert_eq!([emulator.peek(0xA000), emulator.peek(0xE000)], [2, 7]);
+}
+
+#[test]
+fn mmc1_takes_one_bit_from_a_read_modify_write() {
+    // INC $E000 five times, each writes back the 7 there and then 8 on the next cycle. only the
+    // first write counts, so the prg bank register is loaded with %11111: bank 15 wraps to 7
+    let mut program = [0xEE, 0x00, 0xE0].repeat(5);
+    program.extend([0xAD, 0x00, 0xA0, 0x85, 0x00, 0x02]);
+    let mut emulator = mmc1(&program);
+    assert!(emulator.run_until_halt_or(10));
+    assert_eq!(emulator.ram()[0x00], 7);
+
+    // writes a cycle apart or more all count, %00010 selects bank 2
+    let mut program = serial_write(0xE000, 2);
+    program.extend([0xAD, 0x00, 0xA0, 0x85, 0x00, 0x02]);
+    let mut emulator = mmc1(&program);
+    assert!(emulator.run_until_halt_or(10));
+    assert_eq!(emulator.ram()[0x00], 2);
+}
+
+// the scanlines of the acks of an mmc3 irq handler through the first frame rendered with ctrl.
+// the frame counter irq off with LDA #$40, STA $4017, then LDA $2002, BPL to wait for vblank, the
+// irq latch of 10 loaded, reloaded and enabled: LDA #$0A, STA $C000, STA $C001, STA $E001, then
+// LDA #ctrl, STA $2000, LDA #$18, STA $2001, CLI and JMP to itself. the handler at $E023 acks and
+// enables again, and reloads a latch of 20: STA $E000, STA $E001, LDA #$14, STA $C000, STA $C001,
+// RTI
+fn mmc3_irq_lines(ctrl: u8) -> Vec<u16> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0x40, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0xEA; 0x8000];
+    let program = [
+        0xA9, 0x40, 0x8D, 0x17, 0x40, 0xAD, 0x02, 0x20, 0x10, 0xFB, 0xA9, 0x0A, 0x8D, 0x00, 0xC0,
+        0x8D, 0x01, 0xC0, 0x8D, 0x01, 0xE0, 0xA9, ctrl, 0x8D, 0x00, 0x20, 0xA9, 0x18, 0x8D, 0x01,
+        0x20, 0x58, 0x4C, 0x20, 0xE0, 0x8D, 0x00, 0xE0, 0x8D, 0x01, 0xE0, 0xA9, 0x14, 0x8D, 0x00,
+        0xC0, 0x8D, 0x01, 0xC0, 0x40,
+    ];
+    prg[0x6000..0x6000 + program.len()].copy_from_slice(&program);
+    prg[0x7FFC..].copy_from_slice(&[0x00, 0xE0, 0x23, 0xE0]);
+    rom.extend(prg);
+    rom.resize(rom.len() + 0x2000, 0);
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    emulator.on_memory_write(0xE000..=0xE000, {
+        let lines = lines.clone();
+        move |api, _, _| lines.borrow_mut().push(api.scanline())
+    });
+    // the frame of the vblank waited for, then the one rendered
+    emulator.step_frame();
+    lines.borrow_mut().clear();
+    emulator.step_frame();
+    lines.take()
+}
+
+#[test]
+fn mmc3_counts_scanlines_on_a12_and_raises_its_irq_at_zero() {
+    // sprites from $1000 raise A12 once a line. the pre-render line loads the counter with 10 and
+    // line 9 takes it to 0. the reload in the handler has line 10 load 20, which runs out on 30
+    let lines = mmc3_irq_lines(0x08);
+    assert_eq!(lines[..3], [9, 30, 51]);
+    // each ack lowers the line, or the handler would run again at once on the same line
+    assert!(lines.windows(2).all(|pair| pair[1] - pair[0] == 21));
+
+    // A12 rises at dot 324 with the background from $1000, late enough for the acks to fall on
+    // the next line. with both tables at $0000 it never rises
+    assert_eq!(mmc3_irq_lines(0x10)[..3], [10, 31, 52]);
+    assert!(mmc3_irq_lines(0x00).is_empty());
+}
+
+#[test]
+fn ppu_writes_land_in_chr_ram() {
+    // write $5A to $0010 through $2007, then read it back past the read buffer into $00
+    let mut program = [
+        store(0x2006, 0x00),
+        store(0x2006, 0x10),
+        store(0x2007, 0x5A),
+    ]
+    .concat();
+    program.extend([store(0x2006, 0x00), store(0x2006, 0x10)].concat());
+    program.extend([0xAD, 0x07, 0x20, 0xAD, 0x07, 0x20, 0x85, 0x00, 0x02]);
+    let mut emulator = nrom(&program, 0);
+    assert!(emulator.run_until_halt_or(100));
+    assert_eq!(emulator.ram()[0x00], 0x5A);
+
+    // chr rom keeps what it was built with
+    let mut emulator = nrom(&program, 1);
+    assert!(emulator.run_until_halt_or(100));
+    assert_eq!(emulator.ram()[0x00], 0x00);
+}
+
+#[test]
+fn mmc5_multiplies_and_fills_nametables() {
+    // 7 * 6 into $00, then every nametable as the fill tile $42 read back through $2007 into $01
+    let mut program = [store(0x5205, 7), store(0x5206, 6)].concat();
+    program.extend([0xAD, 0x05, 0x52, 0x85, 0x00]);
+    program.extend([store(0x5105, 0xFF), store(0x5106, 0x42)].concat());
+    program.extend([store(0x2006, 0x24), store(0x2006, 0x10)].concat());
+    program.extend([0xAD, 0x07, 0x20, 0xAD, 0x07, 0x20, 0x85, 0x01, 0x02]);
+    // mmc5 powers on with the last 8K bank everywhere
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x50, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0xEA; 0x2000];
+    prg.extend(&program);
+    prg.resize(0x4000, 0xEA);
+    prg[0x3FFC..].copy_from_slice(&[0x00, 0xE0, 0x00, 0xE0]);
+    rom.extend(prg);
+    rom.resize(rom.len() + 0x2000, 0);
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    assert!(emulator.run_until_halt_or(100));
+    assert_eq!(emulator.ram()[0x00], 42);
+    assert_eq!(emulator.ram()[0x01], 0x42);
+}
+
+#[test]
+fn boards_that_are_not_emulated_are_refused() {
+    // mapper 7, axrom
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0x70, 0];
+    rom.resize(16 + 0x4000, 0);
+    let error = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).err();
+    assert!(matches!(error, Some(EmuError::UnsupportedMapper(7))));
+    let error = Emulator::from_rom_bytes(&rom[..16 + 0x2000]).err();
+    assert!(matches!(error, Some(EmuError::RomTooSmall { .. })));
+}
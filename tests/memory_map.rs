@@ -0,0 +1,42 @@
+// the cpu memory map: the ram and ppu register mirrors, and open bus where nothing answers
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::memory::MemorySpace;
+
+#[test]
+fn ram_and_the_ppu_registers_repeat_through_their_mirrors() {
+    // $42 into $1805, then LDA $0005, STA $10 and LDA $0805, STA $11
+    let mut program = store(0x1805, 0x42);
+    program.extend([0xA5, 0x05, 0x85, 0x10, 0xAD, 0x05, 0x08, 0x85, 0x11]);
+    // $2006 through $2806 and $3FFE makes the address $2100, $3007 is $2007
+    program.extend(store(0x2806, 0x21));
+    program.extend(store(0x3FFE, 0x00));
+    program.extend(store(0x3007, 0x99));
+    program.push(0x02);
+    let mut emulator = program_rom(&program);
+    assert!(emulator.run_until_halt_or(5));
+    assert_eq!(emulator.ram()[0x05], 0x42);
+    assert_eq!(emulator.ram()[0x10..0x12], [0x42, 0x42]);
+    assert_eq!(emulator.read_memory(MemorySpace::Ppu, 0x2100), 0x99);
+}
+
+#[test]
+fn reads_of_nothing_see_the_last_byte_on_the_bus() {
+    // LDA $4000, STA $10 and LDA $5000, STA $11: the last byte read was the address's high one
+    let mut program = vec![0xAD, 0x00, 0x40, 0x85, 0x10, 0xAD, 0x00, 0x50, 0x85, 0x11];
+    // the pads only drive the low bits of LDA $4016, STA $12
+    program.extend([0xAD, 0x16, 0x40, 0x85, 0x12]);
+    // and $4015 leaves bit 5 alone. LDA $4015, STA $13 has $40 on the bus before it, while
+    // LDX #$E0, LDA $3F35,X, STA $14 first reads $3F15 as it crosses the page, a ppu register that
+    // answers with the $20 STA $2003 left on the ppu's bus
+    program.extend([0xAD, 0x15, 0x40, 0x85, 0x13]);
+    program.extend(store(0x2003, 0x20));
+    program.extend([0xA2, 0xE0, 0xBD, 0x35, 0x3F, 0x85, 0x14]);
+    program.push(0x02);
+    let mut emulator = program_rom(&program);
+    assert!(emulator.run_until_halt_or(5));
+    assert_eq!(emulator.ram()[0x10..0x12], [0x40, 0x50]);
+    assert_eq!(emulator.ram()[0x12] & 0xE0, 0x40);
+    assert_eq!(emulator.ram()[0x13..0x15], [0x00, 0x20]);
+}
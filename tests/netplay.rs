@@ -0,0 +1,156 @@
+// two consoles running one session over a local tcp connection
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::controller::FrameInput;
+use ntsc_nes::netplay::{Netplay, NetplayError};
+use std::cell::Cell;
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+// counts the frames each pad holds A down into $00 and $01, then waits for the next frame
+fn count_a_presses() -> Vec<u8> {
+    let mut program = [store(0x4016, 1), store(0x4016, 0)].concat();
+    // LDA $4016, AND #1, CLC, ADC $00, STA $00 and the same for $4017 into $01
+    program.extend([0xAD, 0x16, 0x40, 0x29, 0x01, 0x18, 0x65, 0x00, 0x85, 0x00]);
+    program.extend([0xAD, 0x17, 0x40, 0x29, 0x01, 0x18, 0x65, 0x01, 0x85, 0x01]);
+    // wait for vblank: BIT $2002, BPL back to it, JMP to the start
+    program.extend([0x2C, 0x02, 0x20, 0x10, 0xFB, 0x4C, 0x00, 0xC0]);
+    program
+}
+
+#[test]
+fn netplay_peers_run_the_same_frames() {
+    const FRAMES: u64 = 60;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // the host holds A every third frame and waits for the client, which guesses up to 4 frames ahead
+    let host = thread::spawn(move || {
+        let mut emulator = program_rom(&count_a_presses());
+        let (stream, _) = listener.accept().unwrap();
+        let mut netplay = Netplay::host(stream, &emulator, b"rom", 2, 0).unwrap();
+        for frame in 0..FRAMES {
+            let pressed = frame % 3 == 0;
+            let input = FrameInput {
+                buttons: [pressed as u8, 0, 0, 0],
+                ..FrameInput::default()
+            };
+            netplay.step_frame(&mut emulator, input).unwrap();
+        }
+        netplay.wait_for_peer(&mut emulator).unwrap();
+        // the connection stays open until the client is done too
+        (emulator.ram()[..2].to_vec(), netplay)
+    });
+    let mut emulator = program_rom(&count_a_presses());
+    let stream = TcpStream::connect(address).unwrap();
+    let mut netplay = Netplay::join(stream, &mut emulator, b"rom", 4).unwrap();
+    assert_eq!(netplay.delay(), 2);
+    for frame in 0..FRAMES {
+        let input = FrameInput {
+            buttons: [(frame % 5 < 2) as u8, 0, 0, 0],
+            ..FrameInput::default()
+        };
+        netplay.step_frame(&mut emulator, input).unwrap();
+    }
+    netplay.wait_for_peer(&mut emulator).unwrap();
+    let client = emulator.ram()[..2].to_vec();
+
+    // the same input run locally, each pad 2 frames late
+    let mut expected = program_rom(&count_a_presses());
+    for frame in 0..FRAMES {
+        let pad = |pressed: fn(u64) -> bool| (frame >= 2 && pressed(frame - 2)) as u8;
+        expected.apply_input(FrameInput {
+            buttons: [
+                pad(|frame| frame % 3 == 0),
+                pad(|frame| frame % 5 < 2),
+                0,
+                0,
+            ],
+            ..FrameInput::default()
+        });
+        expected.step_frame();
+    }
+    assert_eq!(host.join().unwrap().0, client);
+    assert_eq!(client, expected.ram()[..2]);
+    assert_ne!(client, [0, 0]);
+}
+
+#[test]
+fn netplay_refuses_a_peer_with_another_rom() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let host = thread::spawn(move || {
+        let emulator = program_rom(&count_a_presses());
+        let (stream, _) = listener.accept().unwrap();
+        Netplay::host(stream, &emulator, b"rom", 2, 0).err()
+    });
+    let mut emulator = program_rom(&count_a_presses());
+    let stream = TcpStream::connect(address).unwrap();
+    let error = Netplay::join(stream, &mut emulator, b"another rom", 0).err();
+    assert!(matches!(error, Some(NetplayError::RomMismatch)));
+    assert!(matches!(
+        host.join().unwrap(),
+        Some(NetplayError::RomMismatch)
+    ));
+}
+
+#[test]
+fn rollbacks_run_the_frames_again_without_their_sound_or_hooks() {
+    const FRAMES: u64 = 30;
+    let host_pad = |frame: u64| frame.is_multiple_of(3) as u8;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let host = thread::spawn(move || {
+        let mut emulator = program_rom(&count_a_presses());
+        let (stream, _) = listener.accept().unwrap();
+        let mut netplay = Netplay::host(stream, &emulator, b"rom", 2, 0).unwrap();
+        // the client guesses at the frames it runs meanwhile that the host holds nothing, and the
+        // host's first frame holds A
+        thread::sleep(Duration::from_millis(500));
+        for frame in 0..FRAMES {
+            let input = FrameInput {
+                buttons: [host_pad(frame), 0, 0, 0],
+                ..FrameInput::default()
+            };
+            netplay.step_frame(&mut emulator, input).unwrap();
+        }
+        netplay.wait_for_peer(&mut emulator).unwrap();
+        netplay
+    });
+    let mut emulator = program_rom(&count_a_presses());
+    let frames = Rc::new(Cell::new(0));
+    emulator.on_frame({
+        let frames = frames.clone();
+        move |_| frames.set(frames.get() + 1)
+    });
+    let stream = TcpStream::connect(address).unwrap();
+    let mut netplay = Netplay::join(stream, &mut emulator, b"rom", 4).unwrap();
+    let mut samples = 0;
+    for _ in 0..FRAMES {
+        netplay
+            .step_frame(&mut emulator, FrameInput::default())
+            .unwrap();
+        samples += emulator.take_audio_samples().len();
+    }
+    netplay.wait_for_peer(&mut emulator).unwrap();
+    samples += emulator.take_audio_samples().len();
+    assert!(netplay.rollbacks() > 0);
+    assert_eq!(frames.get(), FRAMES);
+
+    let mut expected = program_rom(&count_a_presses());
+    let mut expected_samples = 0;
+    for frame in 0..FRAMES {
+        expected.apply_input(FrameInput {
+            buttons: [if frame >= 2 { host_pad(frame - 2) } else { 0 }, 0, 0, 0],
+            ..FrameInput::default()
+        });
+        expected.step_frame();
+        expected_samples += expected.take_audio_samples().len();
+    }
+    assert_eq!(samples, expected_samples);
+    assert_eq!(emulator.ram()[..2], expected.ram()[..2]);
+    assert_eq!(emulator.save_state(), expected.save_state());
+    host.join().unwrap();
+}
@@ -0,0 +1,89 @@
+// nsf music files
+mod common;
+
+use ntsc_nes::Emulator;
+use ntsc_nes::nsf::{Nsf, NsfError};
+
+#[test]
+fn nsf_calls_init_with_the_song_and_play_at_its_rate() {
+    let mut nsf = b"NESM\x1A\x01\x03\x01".to_vec();
+    // load and init at $8000, play at $8010
+    nsf.extend([0x00, 0x80, 0x00, 0x80, 0x10, 0x80]);
+    nsf.resize(0x6E, 0);
+    // 60 calls a second
+    nsf.extend(16_667u16.to_le_bytes());
+    nsf.resize(0x80, 0);
+    // init: STA $10, RTS
+    nsf.extend([0x85, 0x10, 0x60]);
+    nsf.resize(0x90, 0xEA);
+    // play: INC $00, RTS
+    nsf.extend([0xE6, 0x00, 0x60]);
+    let mut emulator = Emulator::from_nsf(Nsf::from_bytes(&nsf).unwrap());
+    emulator.play_song(3);
+    let cycles: usize = (0..60).map(|_| emulator.step_nsf()).sum();
+    assert_eq!(emulator.ram()[0x10], 2);
+    assert_eq!(emulator.ram()[0x00], 60);
+    // a second of ntsc cpu time
+    assert!(cycles.abs_diff(1_789_773) < 100);
+}
+
+#[test]
+fn nsf_init_and_play_make_sound() {
+    let mut nsf = b"NESM\x1A\x01\x01\x01".to_vec();
+    // load and init at $8000, play at $8020
+    nsf.extend([0x00, 0x80, 0x00, 0x80, 0x20, 0x80]);
+    nsf.resize(0x6E, 0);
+    nsf.extend(16_667u16.to_le_bytes());
+    nsf.resize(0x80, 0);
+    // init: pulse 1 on at full constant volume and about 440Hz
+    for (register, value) in [
+        (0x4015, 0x01),
+        (0x4000, 0xBF),
+        (0x4002, 0xFD),
+        (0x4003, 0x00),
+    ] {
+        nsf.extend(common::store(register, value));
+    }
+    nsf.push(0x60);
+    nsf.resize(0xA0, 0xEA);
+    // play: RTS
+    nsf.push(0x60);
+    let mut emulator = Emulator::from_nsf(Nsf::from_bytes(&nsf).unwrap());
+    emulator.play_song(1);
+    for _ in 0..10 {
+        emulator.step_nsf();
+    }
+    let samples = emulator.take_audio_samples();
+    assert!(samples.len() > 7000);
+    let low = samples.iter().copied().fold(f32::MAX, f32::min);
+    let high = samples.iter().copied().fold(f32::MIN, f32::max);
+    assert!(high - low > 0.1, "{low} to {high}");
+    // a square wave at 440Hz crosses zero about 880 times a second
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .count();
+    let seconds = samples.len() as f64 / emulator.sample_rate() as f64;
+    assert!(
+        (crossings as f64 / seconds - 880.0).abs() < 60.0,
+        "{crossings}"
+    );
+}
+
+#[test]
+fn nsf_headers_are_checked() {
+    assert!(matches!(
+        Nsf::from_bytes(b"NESM\x1A"),
+        Err(NsfError::TooShort(5))
+    ));
+    let mut nsf = b"NESN\x1A\x01\x01\x01".to_vec();
+    nsf.resize(0x80, 0);
+    assert!(matches!(Nsf::from_bytes(&nsf), Err(NsfError::BadMagic)));
+    nsf[3] = b'M';
+    nsf[6] = 0;
+    assert!(matches!(Nsf::from_bytes(&nsf), Err(NsfError::NoSongs)));
+    // a starting song past the last is the last
+    nsf[6] = 2;
+    nsf[7] = 5;
+    assert_eq!(Nsf::from_bytes(&nsf).unwrap().starting_song, 2);
+}
@@ -0,0 +1,123 @@
+// ips and bps patches applied to a rom
+mod common;
+
+use common::{bps_number, crc32, nrom_file, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::patch::{self, PatchError};
+
+#[test]
+fn ips_and_bps_patches_change_the_rom() {
+    // stores $11 to $0200 and halts, the value is at offset 17 of the file
+    let mut program = store(0x0200, 0x11);
+    program.push(0x02);
+    let rom = nrom_file(&program, 1);
+    let run = |data: &[u8]| {
+        let mut emulator = Emulator::from_rom_bytes(data).unwrap();
+        emulator.run_until_halt_or(1);
+        emulator.peek(0x0200)
+    };
+    assert_eq!(run(&rom), 0x11);
+
+    // one byte, then a run of 4 NOPs at the end of the file, then a truncation to cut them off
+    let mut ips = b"PATCH".to_vec();
+    ips.extend([0x00, 0x00, 0x11, 0x00, 0x01, 0x22]);
+    let end = (rom.len() as u32).to_be_bytes();
+    ips.extend(&end[1..]);
+    ips.extend([0x00, 0x00, 0x00, 0x04, 0xEA]);
+    ips.extend(b"EOF");
+    let patched = patch::apply(&ips, &rom).unwrap();
+    assert_eq!(patched.len(), rom.len() + 4);
+    assert_eq!(run(&patched), 0x22);
+    ips.extend(&end[1..]);
+    assert_eq!(patch::apply(&ips, &rom).unwrap().len(), rom.len());
+
+    // 17 bytes of the rom, $33 from the patch, then the rest of the rom by a source copy
+    let mut bps = b"BPS1".to_vec();
+    let size = rom.len() as u64;
+    for value in [size, size, 0, 16 << 2, 1] {
+        bps.extend(bps_number(value));
+    }
+    bps.push(0x33);
+    bps.extend(bps_number(((size - 18 - 1) << 2) | 2));
+    bps.extend(bps_number(18 << 1));
+    let mut target = rom.clone();
+    target[17] = 0x33;
+    bps.extend(crc32(&rom).to_le_bytes());
+    bps.extend(crc32(&target).to_le_bytes());
+    bps.extend(crc32(&bps).to_le_bytes());
+    let patched = patch::apply(&bps, &rom).unwrap();
+    assert_eq!(patched, target);
+    assert_eq!(run(&patched), 0x33);
+
+    assert!(matches!(
+        patch::apply(&bps, &target),
+        Err(PatchError::SourceMismatch { .. })
+    ));
+    let mut damaged = bps.clone();
+    damaged[10] ^= 0xFF;
+    assert!(matches!(
+        patch::apply(&damaged, &rom),
+        Err(PatchError::PatchMismatch { .. })
+    ));
+    assert_eq!(patch::apply(b"nope", &rom), Err(PatchError::UnknownFormat));
+}
+
+// a bps patch for rom of commands making target_size bytes, with the crc32s for a target of rom
+// unchanged so only each command's checks can fail
+fn bps(rom: &[u8], target_size: u64, commands: &[u8]) -> Vec<u8> {
+    let mut bps = b"BPS1".to_vec();
+    for value in [rom.len() as u64, target_size, 0] {
+        bps.extend(bps_number(value));
+    }
+    bps.extend(commands);
+    bps.extend(crc32(rom).to_le_bytes());
+    bps.extend(crc32(rom).to_le_bytes());
+    bps.extend(crc32(&bps).to_le_bytes());
+    bps
+}
+
+#[test]
+fn bps_commands_stay_inside_the_rom_and_the_declared_size() {
+    let rom = nrom_file(&[0x02], 1);
+    let size = rom.len() as u64;
+    let whole = bps_number((size - 1) << 2);
+    assert_eq!(
+        patch::apply(&bps(&rom, size, &whole), &rom),
+        Ok(rom.clone())
+    );
+
+    // the whole rom is more than a declared half of it
+    assert_eq!(
+        patch::apply(&bps(&rom, size / 2, &whole), &rom),
+        Err(PatchError::PastTarget)
+    );
+    // lengths near the top of a u64 must not wrap around
+    let huge = bps_number(((u64::MAX >> 2) << 2) | 1);
+    assert_eq!(
+        patch::apply(&bps(&rom, size, &huge), &rom),
+        Err(PatchError::PastTarget)
+    );
+    // a source copy from far past the end, and from before the start
+    for offset in [(u64::MAX >> 1) << 1, (1 << 1) | 1] {
+        let mut copy = bps_number(2);
+        copy.extend(bps_number(offset));
+        assert_eq!(
+            patch::apply(&bps(&rom, size, &copy), &rom),
+            Err(PatchError::OutOfRange)
+        );
+    }
+    // a target copy of bytes not made yet
+    let mut copy = bps_number(3);
+    copy.extend(bps_number(0));
+    assert_eq!(
+        patch::apply(&bps(&rom, size, &copy), &rom),
+        Err(PatchError::OutOfRange)
+    );
+    // a target read longer than the patch
+    let read = bps_number((15 << 2) | 1);
+    assert_eq!(
+        patch::apply(&bps(&rom, size, &read), &rom),
+        Err(PatchError::Truncated)
+    );
+    assert_eq!(patch::apply(b"BPS1", &rom), Err(PatchError::Truncated));
+}
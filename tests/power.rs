@@ -0,0 +1,74 @@
+// the reset button, power cycling with a ram pattern and swapping cartridges
+mod common;
+
+use common::{nrom_file, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::power::RamInit;
+use std::fs;
+
+#[test]
+fn reset_keeps_ram_power_cycle_keeps_the_battery_and_new_roms_keep_the_settings() {
+    // INC $6000, INC $10, then spins
+    let mut rom = nrom_file(&[0xEE, 0x00, 0x60, 0xE6, 0x10, 0x4C, 0x05, 0xC0], 1);
+    rom[6] |= 0x02;
+    let mut emulator = Emulator::from_rom_bytes(&rom).unwrap();
+    let counts = |emulator: &mut Emulator| (emulator.peek(0x6000), emulator.ram()[0x10]);
+    emulator.step_frame();
+    assert_eq!(counts(&mut emulator), (1, 0x00));
+    emulator.soft_reset();
+    emulator.step_frame();
+    assert_eq!(counts(&mut emulator), (2, 0x01));
+    emulator.set_ram_init(RamInit::Fill(0x00));
+    emulator.power_cycle();
+    assert_eq!(emulator.frame_count(), 0);
+    emulator.step_frame();
+    assert_eq!(counts(&mut emulator), (3, 0x01));
+
+    let fill = |init: RamInit| {
+        let mut ram = [0; 16];
+        init.fill(&mut ram);
+        ram
+    };
+    assert_eq!(
+        fill(RamInit::Alternating)[..8],
+        [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+    assert_eq!(fill(RamInit::Random(1)), fill(RamInit::Random(1)));
+    assert_ne!(fill(RamInit::Random(1)), fill(RamInit::Random(2)));
+
+    emulator.set_sprite_limit(false);
+    emulator
+        .load_new_rom_bytes(&nrom_file(&store(0x0020, 0x42), 1))
+        .unwrap();
+    emulator.step_frame();
+    assert_eq!((emulator.ram()[0x20], emulator.ram()[0x10]), (0x42, 0x00));
+    assert!(!emulator.sprite_limit());
+    // a bad rom leaves the game running
+    assert!(emulator.load_new_rom_bytes(b"not a rom").is_err());
+    assert_eq!(emulator.ram()[0x20], 0x42);
+}
+
+#[test]
+fn a_save_file_that_cannot_be_read_leaves_the_game_before_running() {
+    let directory = std::env::temp_dir().join(format!("ntsc-nes-power-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    // LDA $6000, STA $20, then spins
+    let mut rom = nrom_file(&[0xAD, 0x00, 0x60, 0x85, 0x20, 0x4C, 0x05, 0xC0], 1);
+    rom[6] |= 0x02;
+    let good = directory.join("good.nes");
+    fs::write(&good, &rom).unwrap();
+    fs::write(directory.join("good.sav"), [0x42]).unwrap();
+    let unreadable = directory.join("unreadable.nes");
+    fs::write(&unreadable, &rom).unwrap();
+    // a directory where the save should be
+    fs::create_dir_all(directory.join("unreadable.sav")).unwrap();
+
+    let mut emulator = Emulator::from_rom_bytes(&nrom_file(&store(0x0020, 0x11), 1)).unwrap();
+    emulator.step_frame();
+    assert!(emulator.load_new_rom(&unreadable).is_err());
+    assert_eq!(emulator.ram()[0x20], 0x11);
+    emulator.load_new_rom(&good).unwrap();
+    emulator.step_frame();
+    assert_eq!(emulator.ram()[0x20], 0x42);
+    fs::remove_dir_all(&directory).unwrap();
+}
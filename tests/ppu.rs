@@ -0,0 +1,187 @@
+// the ppu registers: $2002 status, the $2007 read buffer, scrolling and sprite 0 hits
+mod common;
+
+use common::{nrom, nrom_file, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::memory::MemorySpace;
+
+// waits for vblank with LDA $2002, BPL, makes the register writes, then ors every $2002 read into
+// $00: LDA $2002, ORA $00, STA $00 and JMP back to the load. the board mirrors vertically, tile 1
+// is solid in colour $16 for the background and $30 for sprites, and sits at column 2 of the first
+// nametable's top row and column 0 of the second's. everything else in oam is $FF
+fn render(writes: &[(u16, u8)], sprites: &[(usize, [u8; 4])]) -> Emulator {
+    let mut program = vec![0xAD, 0x02, 0x20, 0x10, 0xFB];
+    for &(address, value) in writes {
+        program.extend(store(address, value));
+    }
+    let [low, high] = (0xC000 + program.len() as u16).to_le_bytes();
+    program.extend([0xAD, 0x02, 0x20, 0x05, 0x00, 0x85, 0x00, 0x4C, low, high]);
+    let mut rom = nrom_file(&program, 0);
+    rom[6] = 0x01;
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    emulator.ram_mut()[0x00] = 0;
+    for address in 0x10..0x18 {
+        emulator.write_memory(MemorySpace::Ppu, address, 0xFF);
+    }
+    emulator.write_memory(MemorySpace::Ppu, 0x2002, 0x01);
+    emulator.write_memory(MemorySpace::Ppu, 0x2400, 0x01);
+    emulator.write_memory(MemorySpace::Palette, 0x00, 0x0F);
+    emulator.write_memory(MemorySpace::Palette, 0x01, 0x16);
+    emulator.write_memory(MemorySpace::Palette, 0x11, 0x30);
+    for address in 0..256 {
+        emulator.write_memory(MemorySpace::Oam, address, 0xFF);
+    }
+    for &(index, entry) in sprites {
+        for (offset, byte) in entry.into_iter().enumerate() {
+            emulator.write_memory(MemorySpace::Oam, (index * 4 + offset) as u16, byte);
+        }
+    }
+    for _ in 0..3 {
+        emulator.step_frame();
+    }
+    emulator
+}
+
+fn pixel(emulator: &Emulator, x: usize, y: usize) -> u16 {
+    emulator.framebuffer()[y * 256 + x]
+}
+
+#[test]
+fn status_reads_clear_vblank_and_the_write_toggle() {
+    // LDA $2002, BPL until vblank and STA $01, then LDA $2002, STA $02. one $2006 write, a $2002
+    // read, then the two writes of $2305 and $AB through $2007
+    let mut program = vec![
+        0xAD, 0x02, 0x20, 0x10, 0xFB, 0x85, 0x01, 0xAD, 0x02, 0x20, 0x85, 0x02,
+    ];
+    program.extend(store(0x2006, 0x21));
+    program.extend([0xAD, 0x02, 0x20]);
+    program.extend(store(0x2006, 0x23));
+    program.extend(store(0x2006, 0x05));
+    program.extend(store(0x2007, 0xAB));
+    program.push(0x02);
+    let mut emulator = nrom(&program, 0);
+    assert!(emulator.run_until_halt_or(5));
+    assert_ne!(emulator.ram()[0x01] & 0x80, 0);
+    assert_eq!(emulator.ram()[0x02] & 0x80, 0);
+    // the read between made $23 the high byte again rather than the low one after $21
+    assert_eq!(emulator.read_memory(MemorySpace::Ppu, 0x2305), 0xAB);
+    assert_eq!(emulator.read_memory(MemorySpace::Ppu, 0x2123), 0x00);
+}
+
+#[test]
+fn data_reads_come_a_read_late_except_from_the_palette() {
+    // LDA $2007, STA into ram, or LDA $2007 alone to skip a byte
+    let read = |into: Option<u8>| match into {
+        Some(address) => vec![0xAD, 0x07, 0x20, 0x85, address],
+        None => vec![0xAD, 0x07, 0x20],
+    };
+    let mut program = store(0x2006, 0x20);
+    program.extend(store(0x2006, 0x00));
+    for into in [0x00, 0x01, 0x02] {
+        program.extend(read(Some(into)));
+    }
+    // the palette at $3F01 straight away, and the nametable byte under it at $2F01 next
+    program.extend(store(0x2006, 0x3F));
+    program.extend(store(0x2006, 0x01));
+    program.extend(read(Some(0x03)));
+    program.extend(store(0x2006, 0x20));
+    program.extend(store(0x2006, 0x00));
+    program.extend(read(Some(0x04)));
+    // with ctrl bit 2 each access steps down a column of 32
+    program.extend(store(0x2000, 0x04));
+    program.extend(store(0x2006, 0x20));
+    program.extend(store(0x2006, 0x00));
+    program.extend(read(None));
+    program.extend(read(Some(0x05)));
+    program.extend(read(Some(0x06)));
+    program.push(0x02);
+
+    let mut emulator = nrom(&program, 0);
+    for (address, value) in [
+        (0x2000, 0x11),
+        (0x2001, 0x22),
+        (0x2020, 0x55),
+        (0x2F01, 0x44),
+    ] {
+        emulator.write_memory(MemorySpace::Ppu, address, value);
+    }
+    emulator.write_memory(MemorySpace::Palette, 0x01, 0x2A);
+    emulator.ram_mut()[0x00] = 0xFF;
+    assert!(emulator.run_until_halt_or(5));
+    assert_eq!(
+        emulator.ram()[..7],
+        [0x00, 0x11, 0x22, 0x2A, 0x44, 0x11, 0x55]
+    );
+}
+
+#[test]
+fn scroll_writes_move_the_background_by_tiles_and_pixels() {
+    let shown = |writes: &[(u16, u8)]| render(&[writes, &[(0x2001, 0x0A)]].concat(), &[]);
+    let solid = |emulator: &Emulator, xs: &[usize], y: usize| {
+        xs.iter().all(|&x| pixel(emulator, x, y) == 0x16)
+    };
+    let clear = |emulator: &Emulator, xs: &[usize], y: usize| {
+        xs.iter().all(|&x| pixel(emulator, x, y) == 0x0F)
+    };
+
+    // unscrolled the tile covers x 16 to 23 of the first 8 lines
+    let emulator = shown(&[]);
+    assert!(solid(&emulator, &[16, 23], 0) && solid(&emulator, &[16, 23], 7));
+    assert!(clear(&emulator, &[15, 24], 0) && clear(&emulator, &[16], 8));
+
+    // fine x: 5 pixels left
+    let emulator = shown(&[(0x2005, 5), (0x2005, 0)]);
+    assert!(solid(&emulator, &[11, 18], 0));
+    assert!(clear(&emulator, &[10, 19], 0));
+
+    // fine and coarse y: 3 lines up
+    let emulator = shown(&[(0x2005, 0), (0x2005, 3)]);
+    assert!(solid(&emulator, &[16], 4));
+    assert!(clear(&emulator, &[16], 5));
+
+    // ctrl picks the second nametable, whose tile is at column 0
+    let emulator = shown(&[(0x2000, 0x01)]);
+    assert!(solid(&emulator, &[0, 7], 0));
+    assert!(clear(&emulator, &[8, 16], 0));
+
+    // 250 pixels right, and the fetches run off the end of the first nametable into the second
+    let emulator = shown(&[(0x2005, 250), (0x2005, 0)]);
+    assert!(solid(&emulator, &[6, 13], 0));
+    assert!(clear(&emulator, &[5, 14], 0));
+
+    // $2006 writes share t with the scroll: $2400 is the second nametable, scrolled to 0, 0
+    let emulator = shown(&[(0x2006, 0x24), (0x2006, 0x00)]);
+    assert!(solid(&emulator, &[0, 7], 0));
+    assert!(clear(&emulator, &[16], 0));
+}
+
+#[test]
+fn sprite_zero_hits_only_where_it_covers_the_background() {
+    let hit = |writes: &[(u16, u8)], sprites: &[(usize, [u8; 4])]| {
+        render(writes, sprites).ram()[0x00] & 0x40 != 0
+    };
+    let shown = [(0x2001, 0x1E)];
+    // over the background tile, drawn a line below its y
+    assert!(hit(&shown, &[(0, [0, 1, 0x00, 16])]));
+    let emulator = render(&shown, &[(0, [0, 1, 0x00, 16])]);
+    assert_eq!(pixel(&emulator, 16, 1), 0x30);
+    // behind the background still hits
+    assert!(hit(&shown, &[(0, [0, 1, 0x20, 16])]));
+    // over the transparent rest of the background, or a sprite other than 0 over the tile
+    assert!(!hit(&shown, &[(0, [0, 1, 0x00, 40])]));
+    assert!(!hit(
+        &shown,
+        &[(0, [0, 1, 0x00, 40]), (1, [0, 1, 0x00, 16])]
+    ));
+
+    // the second nametable puts the tile in the left 8 pixels, which a mask can hide
+    assert!(hit(
+        &[(0x2000, 0x01), (0x2001, 0x1E)],
+        &[(0, [0, 1, 0x00, 0])]
+    ));
+    assert!(!hit(
+        &[(0x2000, 0x01), (0x2001, 0x18)],
+        &[(0, [0, 1, 0x00, 0])]
+    ));
+}
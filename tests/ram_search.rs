@@ -0,0 +1,39 @@
+// the ram search and freezes
+mod common;
+
+use common::program_rom;
+use ntsc_nes::memory::{MemorySpace, RamFilter, RamSearch};
+
+#[test]
+fn ram_search_finds_a_counter() {
+    // INC $10 once a frame
+    let mut emulator = program_rom(&[0xE6, 0x10, 0x2C, 0x02, 0x20, 0x10, 0xFB, 0x4C, 0x00, 0xC0]);
+    emulator.step_frame();
+    let mut search = RamSearch::new(&emulator);
+    emulator.step_frame();
+    assert_eq!(search.filter(&emulator, RamFilter::IncreasedBy(1)), 1);
+    assert_eq!(search.candidates(), [0x10]);
+    emulator.step_frame();
+    assert_eq!(search.filter(&emulator, RamFilter::Greater(None)), 1);
+    assert_eq!(search.filter(&emulator, RamFilter::Equal(Some(0x01))), 0);
+
+    let mut search = RamSearch::new(&emulator);
+    search.filter(&emulator, RamFilter::Equal(Some(emulator.ram()[0x10])));
+    search.freeze(&mut emulator, Some(0x40));
+    emulator.step_frame();
+    assert_eq!(emulator.ram()[0x10], 0x40);
+}
+
+#[test]
+fn filters_that_match_nothing_leave_no_candidates() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    emulator.step_frame();
+    let mut search = RamSearch::new(&emulator);
+    emulator.step_frame();
+    // nothing changes in ram while the cpu spins
+    assert_eq!(search.filter(&emulator, RamFilter::IncreasedBy(1)), 0);
+    assert!(search.candidates().is_empty());
+    assert_eq!(search.filter(&emulator, RamFilter::Equal(None)), 0);
+    assert_eq!(MemorySpace::from_name("PPU"), Some(MemorySpace::Ppu));
+    assert_eq!(MemorySpace::from_name("vram"), None);
+}
@@ -0,0 +1,57 @@
+// headerless programs loaded at an address
+mod common;
+
+use common::store;
+use ntsc_nes::Emulator;
+use ntsc_nes::raw::{DEFAULT_OUTPUT_PORT, RawError, RawProgram};
+
+#[test]
+fn raw_programs_load_anywhere_there_is_memory_and_print() {
+    // prints "hi" through the output port, stores to $0200 and halts
+    let mut program = store(0xF001, b'h');
+    program.extend(store(0xF001, b'i'));
+    program.extend(store(0x0200, 0x42));
+    program.push(0x02);
+    let mut raw = RawProgram::new(program.clone(), 0x8000);
+    raw.entry = Some(0x8000);
+    raw.output_port = Some(DEFAULT_OUTPUT_PORT);
+    let mut emulator = Emulator::from_raw(raw).unwrap();
+    assert_eq!(emulator.cpu().program_counter, 0x8000);
+    assert!(emulator.run_until_halt_or(1));
+    assert_eq!(emulator.take_output(), b"hi");
+    assert_eq!(emulator.peek(0x0200), 0x42);
+
+    // a flat .prg carries its load address, this one in the console's ram
+    let mut prg = vec![0x00, 0x04];
+    prg.extend(&program);
+    let mut raw = RawProgram::from_prg(&prg).unwrap();
+    raw.entry = Some(0x0400);
+    let mut emulator = Emulator::from_raw(raw).unwrap();
+    assert!(emulator.run_until_halt_or(1));
+    assert!(emulator.take_output().is_empty());
+    assert_eq!(emulator.peek(0xF001), b'i');
+
+    assert_eq!(
+        Emulator::from_raw(RawProgram::new(vec![0; 0x100], 0x0780)).err(),
+        Some(RawError::Overlaps(0x0800))
+    );
+    assert_eq!(
+        Emulator::from_raw(RawProgram::new(vec![0; 0x100], 0xFFF0)).err(),
+        Some(RawError::TooLarge(0xF0))
+    );
+}
+
+#[test]
+fn raw_programs_need_bytes_and_a_load_address() {
+    assert_eq!(
+        Emulator::from_raw(RawProgram::new(Vec::new(), 0x8000)).err(),
+        Some(RawError::Empty)
+    );
+    assert_eq!(
+        RawProgram::from_prg(&[0x00]).err(),
+        Some(RawError::NoLoadAddress)
+    );
+    // a load address alone is an empty program
+    let raw = RawProgram::from_prg(&[0x00, 0x80]).unwrap();
+    assert_eq!(Emulator::from_raw(raw).err(), Some(RawError::Empty));
+}
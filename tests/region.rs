@@ -0,0 +1,89 @@
+// pal and dendy: the region the header picks, frame lengths and where vblank starts
+mod common;
+
+use common::{nrom_file, program_rom};
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::clock::Region;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const REGIONS: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+#[test]
+fn the_header_picks_the_region() {
+    let region = |rom: Vec<u8>| {
+        Emulator::new(Cartridge::from_bytes(&rom).unwrap())
+            .unwrap()
+            .region()
+    };
+    let mut ines = nrom_file(&[0x02], 1);
+    assert_eq!(region(ines.clone()), Region::Ntsc);
+    ines[9] = 0x01;
+    assert_eq!(region(ines), Region::Pal);
+
+    // nes 2.0 keeps the timing in byte 12, a multi-region rom runs as ntsc
+    let mut nes2 = nrom_file(&[0x02], 1);
+    nes2[7] = 0x08;
+    for (timing, expected) in [Region::Ntsc, Region::Pal, Region::Ntsc, Region::Dendy]
+        .into_iter()
+        .enumerate()
+    {
+        nes2[12] = timing as u8;
+        assert_eq!(region(nes2.clone()), expected, "timing {timing}");
+    }
+}
+
+#[test]
+fn frames_last_as_long_as_the_region_makes_them() {
+    // with rendering off no dot is skipped: 341 dots a line, 262 lines on ntsc and 312 on the
+    // others, and 12, 16 or 15 master clocks a cpu cycle against 4, 5 and 5 a dot
+    for (region, lines, cycles_per_frame) in [
+        (Region::Ntsc, 262, 29780.67),
+        (Region::Pal, 312, 33247.5),
+        (Region::Dendy, 312, 35464.0),
+    ] {
+        let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+        emulator.set_region(region);
+        emulator.step_frame();
+        let (dots, cycles) = (emulator.ppu_dots(), emulator.cycles());
+        for _ in 0..60 {
+            emulator.step_frame();
+        }
+        // each end lands a JMP or so past vblank
+        let dots = emulator.ppu_dots() - dots;
+        assert!(
+            dots.abs_diff(60 * 341 * lines) <= 20,
+            "{region:?}: {dots} dots"
+        );
+        let cycles = (emulator.cycles() - cycles) as f64;
+        let expected = 60.0 * cycles_per_frame;
+        assert!(
+            (cycles - expected).abs() <= 6.0,
+            "{region:?}: {cycles} cycles"
+        );
+    }
+}
+
+#[test]
+fn vblank_starts_on_the_regions_line() {
+    // LDA #$80, STA $2000 turns the nmi on, JMP $C005 waits for it and the vector starts over
+    for (region, line) in REGIONS.into_iter().zip([241, 241, 291]) {
+        let mut emulator = program_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0xC0]);
+        emulator.set_region(region);
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        emulator.on_nmi({
+            let lines = lines.clone();
+            move |api| lines.borrow_mut().push(api.scanline())
+        });
+        for _ in 0..4 {
+            emulator.step_frame();
+        }
+        let lines = lines.borrow();
+        assert!(lines.len() >= 3, "{region:?}");
+        assert!(
+            lines.iter().all(|&seen| seen == line),
+            "{region:?}: {lines:?}"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// the rewind history going back frame by frame
+mod common;
+
+use common::{nrom_file, program_rom};
+use ntsc_nes::rewind::Rewind;
+
+#[test]
+fn rewinding_goes_back_through_every_frame_pushed() {
+    // INC $10 once a frame
+    let mut emulator = program_rom(&[0xE6, 0x10, 0x2C, 0x02, 0x20, 0x10, 0xFB, 0x4C, 0x00, 0xC0]);
+    let mut rewind = Rewind::new(1 << 20);
+    assert_eq!(rewind.rewind(&mut emulator), Ok(false));
+    let mut states = Vec::new();
+    for _ in 0..10 {
+        rewind.push(&emulator);
+        states.push(emulator.save_state());
+        emulator.step_frame();
+    }
+    assert_eq!(rewind.len(), 9);
+    // the newest state pushed is dropped and the one before it loaded
+    for state in states.iter().rev().skip(1) {
+        assert_eq!(rewind.rewind(&mut emulator), Ok(true));
+        assert_eq!(&emulator.save_state(), state);
+    }
+    assert_eq!(rewind.rewind(&mut emulator), Ok(false));
+    assert_eq!(&emulator.save_state(), &states[0]);
+
+    // a small budget keeps the newest frames
+    let mut rewind = Rewind::new(states[0].len() + 64);
+    for _ in 0..10 {
+        rewind.push(&emulator);
+        emulator.step_frame();
+    }
+    assert!(rewind.len() < 9);
+    assert!(rewind.memory_used() <= states[0].len() + 64);
+}
+
+#[test]
+fn power_cycles_and_new_roms_start_the_history_over() {
+    let mut emulator = program_rom(&[0xE6, 0x10, 0x4C, 0x00, 0xC0]);
+    let mut rewind = Rewind::default();
+    for _ in 0..3 {
+        rewind.push(&emulator);
+        emulator.step_frame();
+    }
+    emulator.power_cycle();
+    assert_eq!(rewind.rewind(&mut emulator), Ok(false));
+    assert!(rewind.is_empty());
+
+    for _ in 0..3 {
+        rewind.push(&emulator);
+        emulator.step_frame();
+    }
+    emulator
+        .load_new_rom_bytes(&nrom_file(&[0xE6, 0x11, 0x4C, 0x00, 0xC0], 1))
+        .unwrap();
+    let ram = emulator.ram().to_vec();
+    assert_eq!(rewind.rewind(&mut emulator), Ok(false));
+    assert_eq!(emulator.ram(), ram);
+    // and what is pushed after is one history again
+    rewind.push(&emulator);
+    emulator.step_frame();
+    rewind.push(&emulator);
+    assert_eq!(rewind.len(), 1);
+}
@@ -0,0 +1,49 @@
+// run_until and the conditions it stops at
+mod common;
+
+use common::program_rom;
+use ntsc_nes::condition::{Condition, Stop};
+
+#[test]
+fn run_until_stops_at_each_kind_of_condition() {
+    // counts $00 up forever: INC $00, JMP $C000
+    let mut emulator = program_rom(&[0xE6, 0x00, 0x4C, 0x00, 0xC0]);
+    assert_eq!(
+        emulator.run_until(Condition::Memory(0x0000, 0x10)),
+        Stop::Met
+    );
+    assert_eq!(emulator.cpu().program_counter, 0xC002);
+    assert_eq!(
+        emulator.run_until(Condition::ProgramCounter(0xC000)),
+        Stop::Met
+    );
+    // a condition that already holds returns without running
+    assert_eq!(
+        emulator.run_until(Condition::ProgramCounter(0xC000)),
+        Stop::Met
+    );
+    assert_eq!(emulator.peek(0x0000), 0x10);
+
+    let frame = emulator.frame_count();
+    assert_eq!(emulator.run_until(Condition::Frames(2)), Stop::Met);
+    assert_eq!(emulator.frame_count(), frame + 2);
+
+    let mut instructions = 0;
+    let condition = Condition::custom(move |_| {
+        instructions += 1;
+        instructions > 100
+    });
+    let cycles = emulator.cycles();
+    assert_eq!(
+        emulator.run_until(Condition::Any(vec![Condition::Frames(1), condition])),
+        Stop::Met
+    );
+    // INC zero page takes 5 cycles and JMP 3
+    assert_eq!(emulator.cycles() - cycles, 50 * 5 + 50 * 3);
+
+    let mut emulator = program_rom(&[0xEA, 0x02]);
+    assert_eq!(
+        emulator.run_until(Condition::ProgramCounter(0x8000)),
+        Stop::Halted
+    );
+}
@@ -0,0 +1,110 @@
+// save states written and loaded back
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::savestate::StateError;
+
+// INC $10 once a frame
+const COUNTER: [u8; 10] = [0xE6, 0x10, 0x2C, 0x02, 0x20, 0x10, 0xFB, 0x4C, 0x00, 0xC0];
+
+#[test]
+fn a_loaded_state_runs_on_like_the_one_saved() {
+    let mut emulator = program_rom(&COUNTER);
+    for _ in 0..5 {
+        emulator.step_frame();
+    }
+    let state = emulator.save_state();
+    for _ in 0..5 {
+        emulator.step_frame();
+    }
+    let later = emulator.save_state();
+    let counter = emulator.ram()[0x10];
+
+    emulator.load_state(&state).unwrap();
+    assert_eq!(emulator.save_state(), state);
+    assert_eq!(emulator.ram()[0x10], counter - 5);
+    for _ in 0..5 {
+        emulator.step_frame();
+    }
+    assert_eq!(emulator.save_state(), later);
+
+    // into another emulator of the same rom too
+    let mut other = program_rom(&COUNTER);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+}
+
+#[test]
+fn states_of_other_roms_and_broken_states_are_refused() {
+    let mut emulator = program_rom(&COUNTER);
+    emulator.step_frame();
+    let before = emulator.save_state();
+
+    let mut other = program_rom(&store(0x0010, 0x42));
+    other.step_frame();
+    assert!(matches!(
+        emulator.load_state(&other.save_state()),
+        Err(StateError::WrongRom { .. })
+    ));
+    assert_eq!(emulator.save_state(), before);
+
+    let mut truncated = before.clone();
+    truncated.truncate(before.len() / 2);
+    assert_eq!(emulator.load_state(&truncated), Err(StateError::Truncated));
+    assert_eq!(emulator.save_state(), before);
+    assert_eq!(
+        emulator.load_state(&before[..8]),
+        Err(StateError::Truncated)
+    );
+
+    let mut state = before.clone();
+    state[4] = 0xFF;
+    assert!(matches!(
+        emulator.load_state(&state),
+        Err(StateError::UnsupportedVersion(_))
+    ));
+    state[0] = b'X';
+    assert!(matches!(
+        emulator.load_state(&state),
+        Err(StateError::BadMagic(_))
+    ));
+    assert_eq!(emulator.save_state(), before);
+}
+
+#[test]
+fn states_with_fields_out_of_range_are_refused() {
+    let mut emulator = program_rom(&COUNTER);
+    emulator.step_frame();
+    let before = emulator.save_state();
+
+    let mut longer = before.clone();
+    longer.push(0);
+    assert_eq!(
+        emulator.load_state(&longer),
+        Err(StateError::TrailingBytes(1))
+    );
+
+    // the ppu clock ahead of the master clock, the four score's 3 bytes, the pads' 12 and the open
+    // bus come after it
+    let mut state = before.clone();
+    let ppu_clock = state.len() - 16 - 8;
+    state[ppu_clock + 7] = 0x01;
+    assert_eq!(
+        emulator.load_state(&state),
+        Err(StateError::Invalid("ppu clock"))
+    );
+    assert_eq!(emulator.save_state(), before);
+
+    // any byte of the state at $FF either fails to load or runs on, a state that loads replaces all
+    // of the one before
+    let mut state = before.clone();
+    for offset in 10..before.len() {
+        state[offset] = 0xFF;
+        if emulator.load_state(&state).is_ok() {
+            for _ in 0..10 {
+                emulator.step_instruction();
+            }
+        }
+        state[offset] = before[offset];
+    }
+}
@@ -0,0 +1,77 @@
+// sprite evaluation, the 8 sprite limit and the overflow flag
+mod common;
+
+use common::nrom;
+use ntsc_nes::Emulator;
+use ntsc_nes::memory::MemorySpace;
+
+// shows sprites and ors every $2002 read into $00: LDA #$14, STA $2001, LDA $2002, ORA $00,
+// STA $00, JMP $C005. the sprites are tile 1, solid in colour $16, everything else in oam is $FF
+fn sprite_line(sprites: &[(usize, [u8; 4])], limit: bool) -> Emulator {
+    let mut emulator = nrom(
+        &[
+            0xA9, 0x14, 0x8D, 0x01, 0x20, 0xAD, 0x02, 0x20, 0x05, 0x00, 0x85, 0x00, 0x4C, 0x05,
+            0xC0,
+        ],
+        0,
+    );
+    emulator.ram_mut()[0x00] = 0;
+    for address in 0x10..0x18 {
+        emulator.write_memory(MemorySpace::Ppu, address, 0xFF);
+    }
+    emulator.write_memory(MemorySpace::Palette, 0x00, 0x0F);
+    emulator.write_memory(MemorySpace::Palette, 0x11, 0x16);
+    for address in 0..256 {
+        emulator.write_memory(MemorySpace::Oam, address, 0xFF);
+    }
+    for &(index, entry) in sprites {
+        for (offset, byte) in entry.into_iter().enumerate() {
+            emulator.write_memory(MemorySpace::Oam, (index * 4 + offset) as u16, byte);
+        }
+    }
+    emulator.set_sprite_limit(limit);
+    for _ in 0..3 {
+        emulator.step_frame();
+    }
+    emulator
+}
+
+#[test]
+fn sprite_evaluation_keeps_8_a_line_and_gets_the_overflow_flag_wrong_like_the_ppu() {
+    let overflow = |emulator: &Emulator| emulator.ram()[0x00] & 0x20 != 0;
+    let on_line = |count: usize| -> Vec<(usize, [u8; 4])> {
+        (0..count)
+            .map(|index| (index, [100, 1, 0x00, index as u8 * 10]))
+            .collect()
+    };
+    assert!(!overflow(&sprite_line(&on_line(8), true)));
+    let emulator = sprite_line(&on_line(9), true);
+    assert!(overflow(&emulator));
+    // the ninth is not drawn
+    assert_eq!(emulator.framebuffer()[102 * 256 + 80], 0x0F);
+    assert_eq!(emulator.framebuffer()[102 * 256 + 70], 0x16);
+
+    // after the eighth the ppu reads sprite 9's tile as its y, and sprite 10's attributes for the
+    // next, so an off screen sprite sets the flag and a ninth on the line is missed
+    let mut sprites = on_line(8);
+    sprites.push((9, [0xFF, 100, 0xFF, 0xFF]));
+    assert!(overflow(&sprite_line(&sprites, true)));
+    let mut sprites = on_line(8);
+    sprites.push((10, [100, 1, 0x00, 90]));
+    assert!(!overflow(&sprite_line(&sprites, true)));
+
+    // without the limit every sprite is drawn, and the flag is the same
+    let emulator = sprite_line(&on_line(9), false);
+    assert!(overflow(&emulator));
+    assert_eq!(emulator.framebuffer()[102 * 256 + 80], 0x16);
+}
+
+#[test]
+fn sprites_below_the_picture_are_never_on_a_line() {
+    let below: Vec<(usize, [u8; 4])> = (0..9)
+        .map(|index| (index, [0xF0 + index as u8, 1, 0x00, index as u8 * 10]))
+        .collect();
+    let emulator = sprite_line(&below, true);
+    assert_eq!(emulator.ram()[0x00] & 0x20, 0);
+    assert!(emulator.framebuffer().iter().all(|&pixel| pixel == 0x0F));
+}
@@ -0,0 +1,50 @@
+// frame timing stats
+mod common;
+
+use common::program_rom;
+use ntsc_nes::stats::Stats;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn stats_count_the_cycles_of_each_frame() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    let stats = Stats::attach(&mut emulator);
+    assert_eq!(stats.borrow().to_string(), "no frames yet");
+    for _ in 0..10 {
+        emulator.step_frame();
+    }
+    let stats = stats.borrow();
+    let last = stats.last().expect("a frame");
+    assert_eq!(last.frame, emulator.frame_count());
+    // 341 dots on 262 lines and a third of that in cpu cycles, give or take the instruction the
+    // frame ends in
+    assert!(last.ppu_dots.abs_diff(341 * 262) <= 9);
+    assert!(last.cpu_cycles.abs_diff(last.ppu_dots / 3) <= 1);
+    assert!(stats.max_host_time() >= stats.average_host_time());
+    assert!(
+        stats
+            .to_string()
+            .starts_with(&format!("frame {}: ", last.frame))
+    );
+}
+
+#[test]
+fn late_frames_count_as_dropped_and_a_pause_does_not() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    let stats = Stats::attach(&mut emulator);
+    assert_eq!(stats.borrow_mut().take_report(), None);
+    emulator.step_frame();
+    assert_eq!(stats.borrow().fps(), 0.0);
+    // more than one and a half ntsc frames late
+    thread::sleep(Duration::from_millis(40));
+    emulator.step_frame();
+    assert_eq!(stats.borrow().dropped(), 1);
+    thread::sleep(Duration::from_millis(1100));
+    emulator.step_frame();
+    let mut stats = stats.borrow_mut();
+    assert_eq!(stats.dropped(), 1);
+    // the frames from before the pause have left the window
+    assert_eq!(stats.fps(), 0.0);
+    assert!(stats.take_report().is_some());
+}
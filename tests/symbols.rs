@@ -0,0 +1,62 @@
+// labels from .nl and ca65 .dbg files in traces and disassembly
+mod common;
+
+use common::program_rom;
+use ntsc_nes::disasm;
+use ntsc_nes::symbols::{SymbolError, Symbols};
+
+#[test]
+fn symbols_name_addresses_in_the_trace_and_the_disassembly() {
+    // INC $10, then JMP $C000
+    let program = [0xE6, 0x10, 0x4C, 0x00, 0xC0];
+    let nl =
+        Symbols::parse_nl("$C000#reset#where it starts\n$000E/4#counters#\n$C002##\n").unwrap();
+    assert_eq!(nl.label(0x0010).as_deref(), Some("counters+2"));
+    assert_eq!(nl.label(0x0012), None);
+    assert_eq!(nl.address("reset"), Some(0xC000));
+    let dbg = Symbols::parse_dbg(
+        "version\tmajor=2,minor=0\n\
+         sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,ref=2,val=0xC002,seg=0,type=lab\n\
+         sym\tid=1,name=\"SPEED\",addrsize=zeropage,scope=0,def=3,val=0x3,type=equ\n",
+    )
+    .unwrap();
+    assert_eq!(dbg.iter().collect::<Vec<_>>(), [(0xC002, "main")]);
+
+    let listing = disasm::listing(&disasm::disassemble(&program, 0xC000), &nl);
+    assert!(listing.starts_with("reset:\n"), "{listing}");
+    assert!(listing.contains("INC counters+2"), "{listing}");
+    assert!(listing.contains("JMP reset"), "{listing}");
+
+    let mut emulator = program_rom(&program);
+    *emulator.symbols_mut() = nl;
+    emulator.add_label(0x0010, "frames");
+    let line = emulator.trace_line();
+    assert!(
+        line.starts_with("C000  E6 10     INC frames = FF"),
+        "{line}"
+    );
+}
+
+#[test]
+fn symbol_files_point_at_the_line_they_cannot_read() {
+    assert_eq!(
+        Symbols::parse_nl("$C000#reset#\nC002#main#\n"),
+        Err(SymbolError::BadLine(2))
+    );
+    assert_eq!(
+        Symbols::parse_nl("\n$C0G0#reset#\n"),
+        Err(SymbolError::BadLine(2))
+    );
+    assert_eq!(
+        Symbols::parse_nl("$000E/X#counters#"),
+        Err(SymbolError::BadLine(1))
+    );
+    assert_eq!(
+        Symbols::parse_dbg("version\tmajor=2\nsym\tid=0,name=\"main\",val=0xZZ,type=lab\n"),
+        Err(SymbolError::BadLine(2))
+    );
+    assert_eq!(
+        Symbols::parse_dbg("sym\tid=0,name\n"),
+        Err(SymbolError::BadLine(1))
+    );
+}
@@ -0,0 +1,162 @@
+// runs blargg's test roms headlessly and checks the result they report through prg ram:
+// $6000 holds the status ($80 running, $81 wants a reset, anything else is the result code),
+// $6001-$6003 the signature DE B0 61 and $6004 a zero terminated text of what happened.
+// the roms are not redistributable, so their tests are ignored by default. put them under
+// tests/roms/ and run cargo test -- --ignored, a rom that is missing then fails its test
+mod common;
+
+use common::{program_rom, store};
+use ntsc_nes::Emulator;
+use std::path::Path;
+
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT: u16 = 0x6004;
+// about 25 seconds of emulated time, the slowest blargg roms finish in under 20
+const FRAME_LIMIT: usize = 1500;
+// blargg asks for the reset button to be held a little while after it sets $81
+const RESET_DELAY: usize = 6;
+
+fn text(emulator: &mut Emulator) -> String {
+    let mut text = String::new();
+    for address in TEXT..0x7000 {
+        match emulator.peek(address) {
+            0 => break,
+            byte => text.push(byte as char),
+        }
+    }
+    text.trim().to_string()
+}
+
+// Ok with the text of a passing rom, Err with why it failed otherwise
+fn run_until_result(emulator: &mut Emulator, frame_limit: usize) -> Result<String, String> {
+    let mut reset_in = None;
+    for _ in 0..frame_limit {
+        if emulator.run_until_halt_or(1) {
+            return Err(format!(
+                "cpu halted at ${:04X}",
+                emulator.cpu().program_counter
+            ));
+        }
+        let signature = [0, 1, 2].map(|offset| emulator.peek(STATUS + 1 + offset));
+        if signature != SIGNATURE {
+            continue;
+        }
+        match emulator.peek(STATUS) {
+            0x80 => {}
+            0x81 => match reset_in {
+                Some(0) => {
+                    emulator.soft_reset();
+                    reset_in = None;
+                }
+                Some(frames) => reset_in = Some(frames - 1),
+                None => reset_in = Some(RESET_DELAY),
+            },
+            0x00 => return Ok(text(emulator)),
+            code => return Err(format!("result code {code}: {}", text(emulator))),
+        }
+    }
+    Err(format!("no result after {frame_limit} frames"))
+}
+
+fn run_test_rom(name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(name);
+    assert!(path.exists(), "{name} needs {}", path.display());
+    let mut emulator = Emulator::load_rom(&path).unwrap();
+    if let Err(message) = run_until_result(&mut emulator, FRAME_LIMIT) {
+        panic!("{name} failed, {message}");
+    }
+}
+
+// a program reporting like a blargg rom, then spinning forever
+fn report(status: u8, message: &str) -> Vec<u8> {
+    let mut program = store(STATUS, 0x80);
+    for (offset, byte) in SIGNATURE.into_iter().enumerate() {
+        program.extend(store(STATUS + 1 + offset as u16, byte));
+    }
+    for (offset, byte) in message.bytes().chain([0]).enumerate() {
+        program.extend(store(TEXT + offset as u16, byte));
+    }
+    program.extend(store(STATUS, status));
+    let [low, high] = (0xC000 + program.len() as u16).to_le_bytes();
+    program.extend([0x4C, low, high]);
+    program
+}
+
+#[test]
+fn harness_reads_a_pass() {
+    let mut emulator = program_rom(&report(0, "Passed\n"));
+    assert_eq!(
+        run_until_result(&mut emulator, 10),
+        Ok("Passed".to_string())
+    );
+}
+
+#[test]
+fn harness_reads_a_failure_code() {
+    let mut emulator = program_rom(&report(3, "BCC failed"));
+    assert_eq!(
+        run_until_result(&mut emulator, 10),
+        Err("result code 3: BCC failed".to_string())
+    );
+}
+
+#[test]
+fn harness_gives_up_on_a_rom_that_never_reports() {
+    let mut emulator = program_rom(&[0x4C, 0x00, 0xC0]);
+    assert_eq!(
+        run_until_result(&mut emulator, 10),
+        Err("no result after 10 frames".to_string())
+    );
+}
+
+#[test]
+fn run_until_halt_or_stops_at_a_hlt() {
+    let mut emulator = program_rom(&[0xEA, 0xEA, 0x02]);
+    assert!(emulator.run_until_halt_or(100));
+    assert_eq!(emulator.cpu().program_counter, 0xC003);
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn cpu_instructions() {
+    run_test_rom("instr_test-v5/official_only.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn cpu_instruction_timing() {
+    run_test_rom("instr_timing/instr_timing.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn cpu_interrupts() {
+    run_test_rom("cpu_interrupts_v2/cpu_interrupts.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn cpu_dummy_reads() {
+    run_test_rom("cpu_dummy_reads.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn ppu_vbl_nmi() {
+    run_test_rom("ppu_vbl_nmi/ppu_vbl_nmi.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn ppu_open_bus() {
+    run_test_rom("ppu_open_bus/ppu_open_bus.nes");
+}
+
+#[test]
+#[ignore = "needs blargg roms in tests/roms"]
+fn apu_test() {
+    run_test_rom("apu_test/apu_test.nes");
+}
@@ -0,0 +1,119 @@
+// the nametable, pattern table, sprite and palette viewers
+mod common;
+
+use common::{nrom_file, store};
+use ntsc_nes::Emulator;
+use ntsc_nes::cartridge::Cartridge;
+use ntsc_nes::memory::MemorySpace;
+use ntsc_nes::palette::Palette;
+use ntsc_nes::viewer::{Image, Sprite};
+
+// a board with chr ram and vertical mirroring running program. tile 1 of the first table has its
+// top left pixel in colour 1 and tile 1 of the second its top right one in colour 2
+fn board(program: &[u8]) -> Emulator {
+    let mut rom = nrom_file(program, 0);
+    rom[6] = 0x01;
+    let mut emulator = Emulator::new(Cartridge::from_bytes(&rom).unwrap()).unwrap();
+    emulator.write_memory(MemorySpace::Ppu, 0x0010, 0x80);
+    emulator.write_memory(MemorySpace::Ppu, 0x1018, 0x01);
+    for (entry, color) in [
+        (0x00, 0x0F),
+        (0x01, 0x16),
+        (0x05, 0x21),
+        (0x12, 0x2A),
+        (0x15, 0x27),
+    ] {
+        emulator.write_memory(MemorySpace::Palette, entry, color);
+    }
+    emulator
+}
+
+#[test]
+fn pattern_tables_sit_side_by_side_in_the_palette_asked_for() {
+    let mut emulator = board(&[0x02]);
+    let image = emulator.render_pattern_tables(0);
+    assert_eq!((image.width, image.height), (256, 128));
+    let pixel = |image: &Image, x: usize, y: usize| image.pixels[y * 256 + x];
+    assert_eq!(pixel(&image, 8, 0), 0x16);
+    assert_eq!(pixel(&image, 9, 0), 0x0F);
+    assert_eq!(pixel(&image, 8, 1), 0x0F);
+    // palette 4 is the first of the sprites'
+    let image = emulator.render_pattern_tables(4);
+    assert_eq!(pixel(&image, 128 + 15, 0), 0x2A);
+    assert_eq!(pixel(&image, 128 + 8, 0), 0x0F);
+}
+
+#[test]
+fn nametables_are_drawn_after_mirroring_with_their_attributes() {
+    let mut emulator = board(&[0x02]);
+    // tile 1 at column 2 of the top row, the attribute byte gives that quadrant palette 1
+    emulator.write_memory(MemorySpace::Ppu, 0x2002, 0x01);
+    emulator.write_memory(MemorySpace::Ppu, 0x23C0, 0x04);
+    let image = emulator.render_nametables();
+    assert_eq!((image.width, image.height), (512, 480));
+    let pixel = |x: usize, y: usize| image.pixels[y * 512 + x];
+    assert_eq!(pixel(16, 0), 0x21);
+    assert_eq!(pixel(17, 0), 0x0F);
+    // $2800 mirrors $2000 and $2400 is a table of its own
+    assert_eq!(pixel(16, 240), 0x21);
+    assert_eq!(pixel(256 + 16, 0), 0x0F);
+}
+
+#[test]
+fn the_scroll_boxes_where_the_next_frame_starts() {
+    // x 12 and y 34, in the second nametable
+    let mut program = store(0x2005, 12);
+    program.extend(store(0x2005, 34));
+    program.extend(store(0x2000, 0x01));
+    program.push(0x02);
+    let mut emulator = board(&program);
+    assert!(emulator.run_until_halt_or(2));
+    assert_eq!(emulator.scroll(), (256 + 12, 34));
+}
+
+#[test]
+fn sprites_decode_their_attributes_and_draw_in_oam_order() {
+    let mut emulator = board(&[0x02]);
+    // sprite 1: palette 1, behind the background and flipped left to right
+    for (offset, byte) in [10, 1, 0x61, 20].into_iter().enumerate() {
+        emulator.write_memory(MemorySpace::Oam, 4 + offset as u16, byte);
+    }
+    assert_eq!(
+        emulator.sprites()[1],
+        Sprite {
+            x: 20,
+            y: 10,
+            tile: 1,
+            palette: 1,
+            behind_background: true,
+            flip_horizontal: true,
+            flip_vertical: false,
+        }
+    );
+    let image = emulator.render_sprites();
+    assert_eq!((image.width, image.height), (128, 64));
+    let pixel = |x: usize, y: usize| image.pixels[y * 128 + x];
+    assert_eq!(pixel(8 + 7, 0), 0x27);
+    assert_eq!(pixel(8, 0), 0x0F);
+    // sprite 0 is all zeros: tile 0, which is blank
+    assert!((0..8).all(|x| pixel(x, 0) == 0x0F));
+}
+
+#[test]
+fn the_palette_reads_back_as_through_2007() {
+    let mut emulator = board(&[0x02]);
+    let palette = emulator.palette_ram();
+    assert_eq!(palette[..6], [0x0F, 0x16, 0x00, 0x00, 0x00, 0x21]);
+    // $3F10 is the backdrop again
+    assert_eq!(palette[0x10], 0x0F);
+    assert_eq!((palette[0x12], palette[0x15]), (0x2A, 0x27));
+
+    let image = emulator.render_palette();
+    assert_eq!((image.width, image.height), (16, 2));
+    let expected: Vec<u16> = palette.iter().map(|&entry| entry as u16).collect();
+    assert_eq!(image.pixels, expected);
+    let rgba = image.to_rgba(&Palette::default());
+    assert_eq!(rgba.len(), 32 * 4);
+    let [red, green, blue] = Palette::default().rgb(0x16);
+    assert_eq!(rgba[4..8], [red, green, blue, 0xFF]);
+}
@@ -0,0 +1,105 @@
+// browser frontend for the wasm build, it drives the Nes of src/wasm.rs through the ntsc_nes.js
+// wasm-bindgen makes
+import init, { Nes } from "./ntsc_nes.js";
+
+const WIDTH = 256;
+const HEIGHT = 240;
+// bits in shift order, as setButtons expects them
+const KEYS = {
+  KeyX: 0x01, KeyZ: 0x02, Tab: 0x04, Space: 0x04, Enter: 0x08,
+  ArrowUp: 0x10, ArrowDown: 0x20, ArrowLeft: 0x40, ArrowRight: 0x80,
+};
+
+const screen = document.getElementById("screen").getContext("2d");
+const image = screen.createImageData(WIDTH, HEIGHT);
+const status = document.getElementById("status");
+let nes = null;
+let running = false;
+let buttons = 0;
+let audio = null;
+let audioTime = 0;
+
+// a rom loaded over another swaps the cartridge of the running console
+function loadRom(buffer) {
+  try {
+    if (nes) {
+      nes.loadRom(buffer);
+    } else {
+      nes = new Nes(buffer);
+    }
+  } catch (error) {
+    status.textContent = error.message;
+    return;
+  }
+  status.textContent = "";
+  // browsers only start audio after a user gesture, picking a file is one
+  if (!audio) {
+    audio = new AudioContext();
+  }
+  nes.setSampleRate(audio.sampleRate);
+  audioTime = audio.currentTime;
+  if (!running) {
+    running = true;
+    requestAnimationFrame(frame);
+  }
+}
+
+// a frame of samples is queued right behind the previous one, late frames start over from now
+function queueAudio() {
+  const samples = nes.takeAudio();
+  const count = samples.length;
+  if (count === 0) {
+    return;
+  }
+  const buffer = audio.createBuffer(1, count, audio.sampleRate);
+  buffer.copyToChannel(samples, 0);
+  const source = audio.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audio.destination);
+  audioTime = Math.max(audioTime, audio.currentTime);
+  source.start(audioTime);
+  audioTime += count / audio.sampleRate;
+}
+
+let last = 0;
+let owed = 0;
+// requestAnimationFrame follows the display, the console runs at about 60.1 frames a second
+function frame(now) {
+  owed = Math.min(owed + (last ? now - last : 0) / (1000 / 60.0988), 4);
+  last = now;
+  if (owed >= 1) {
+    while (owed >= 1) {
+      nes.setButtons(0, buttons);
+      nes.stepFrame();
+      owed -= 1;
+    }
+    queueAudio();
+    image.data.set(nes.framebuffer());
+    screen.putImageData(image, 0, 0);
+  }
+  requestAnimationFrame(frame);
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (file) {
+    loadRom(await file.arrayBuffer());
+  }
+});
+document.getElementById("reset").addEventListener("click", () => nes && nes.reset());
+window.addEventListener("keydown", (event) => {
+  if (event.code in KEYS) {
+    buttons |= KEYS[event.code];
+    event.preventDefault();
+  }
+});
+window.addEventListener("keyup", (event) => {
+  if (event.code in KEYS) {
+    buttons &= ~KEYS[event.code];
+    event.preventDefault();
+  }
+});
+
+init().catch((error) => {
+  status.textContent = "could not load ntsc_nes_bg.wasm: " + error;
+});